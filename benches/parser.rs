@@ -0,0 +1,197 @@
+//! Offline benchmarks for the annotated CSV parser.
+//!
+//! Unlike the other benchmarks, these need no InfluxDB instance: the input
+//! is generated in memory, so they can run anywhere.
+//!
+//! ```bash
+//! cargo bench --bench parser --features devtools
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use influxdb_stream::devtools::{Corpus, FieldType};
+use influxdb_stream::{AnnotatedCsvParser, FluxRecord};
+use tokio::runtime::Runtime;
+
+const ROWS: usize = 10_000;
+
+/// A column of generated data: annotated CSV type and a value for row `i`.
+struct Column {
+    name: String,
+    data_type: &'static str,
+    value: fn(usize) -> String,
+}
+
+impl Column {
+    fn new(name: impl Into<String>, data_type: &'static str, value: fn(usize) -> String) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            value,
+        }
+    }
+}
+
+fn string_value(i: usize) -> String {
+    format!("server{}", i % 16)
+}
+
+fn double_value(i: usize) -> String {
+    format!("{}.{}", i % 100, i % 7)
+}
+
+fn long_value(i: usize) -> String {
+    (i as i64 * 37 - 1_000).to_string()
+}
+
+fn unsigned_value(i: usize) -> String {
+    (i as u64 * 37).to_string()
+}
+
+fn bool_value(i: usize) -> String {
+    (i % 2 == 0).to_string()
+}
+
+fn time_value(i: usize) -> String {
+    let secs = 1_700_000_000 + i as i64;
+    chrono::DateTime::from_timestamp(secs, (i % 1000) as u32 * 1_000_000)
+        .unwrap()
+        .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+fn duration_value(i: usize) -> String {
+    format!("{}m{}s", i % 60, i % 13)
+}
+
+fn binary_value(i: usize) -> String {
+    ["aGVsbG8=", "d29ybGQ=", "Zm9vYmFy"][i % 3].to_string()
+}
+
+/// Render `rows` rows of `columns` as one annotated CSV table.
+fn generate_csv(columns: &[Column], rows: usize) -> Vec<u8> {
+    let mut csv = String::new();
+    let join = |f: &dyn Fn(&Column) -> String| columns.iter().map(f).collect::<Vec<_>>().join(",");
+
+    csv.push_str(&format!(
+        "#datatype,string,long,{}\n",
+        join(&|c| c.data_type.to_string())
+    ));
+    csv.push_str(&format!(
+        "#group,false,false,{}\n",
+        join(&|_| "false".to_string())
+    ));
+    csv.push_str(&format!("#default,_result,,{}\n", join(&|_| String::new())));
+    csv.push_str(&format!(",result,table,{}\n", join(&|c| c.name.clone())));
+    for i in 0..rows {
+        let values: Vec<String> = columns.iter().map(|c| (c.value)(i)).collect();
+        csv.push_str(&format!(",,0,{}\n", values.join(",")));
+    }
+    csv.into_bytes()
+}
+
+/// Columns of a pivoted corpus with one tag: `_start`, `_stop`, `_time`,
+/// `_measurement` and `host`.
+const PIVOT_COLUMNS: usize = 5;
+
+/// A typical pivoted layout of `count` columns: timestamps, a tag and
+/// numeric fields.
+fn mixed_csv(count: usize) -> Vec<u8> {
+    let mut corpus = Corpus::new("bench").tag("host", 1).rows(ROWS).pivot(true);
+    for i in 0..count - PIVOT_COLUMNS {
+        corpus = match i % 2 {
+            0 => corpus.field(format!("field{}", i), FieldType::Double),
+            _ => corpus.field(format!("count{}", i), FieldType::Long),
+        };
+    }
+    corpus.annotated_csv()
+}
+
+async fn parse_all(input: &[u8]) -> usize {
+    let mut parser = AnnotatedCsvParser::new(input);
+    let mut count = 0;
+    while parser.next().await.unwrap().is_some() {
+        count += 1;
+    }
+    count
+}
+
+async fn parse_all_into(input: &[u8]) -> usize {
+    let mut parser = AnnotatedCsvParser::new(input);
+    let mut record = FluxRecord::new(0);
+    let mut count = 0;
+    while parser.next_into(&mut record).await.unwrap() {
+        count += 1;
+    }
+    count
+}
+
+/// Throughput as the number of columns grows.
+fn bench_column_count(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("parser_columns");
+
+    for count in [8, 16, 32, 64] {
+        let input = mixed_csv(count);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &input, |b, input| {
+            b.to_async(&rt).iter(|| parse_all(input));
+        });
+    }
+
+    group.finish();
+}
+
+/// Throughput for each data type, parsed as the only value column.
+fn bench_data_types(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("parser_types");
+
+    let types = [
+        Column::new("_value", "string", string_value),
+        Column::new("_value", "double", double_value),
+        Column::new("_value", "long", long_value),
+        Column::new("_value", "unsignedLong", unsigned_value),
+        Column::new("_value", "boolean", bool_value),
+        Column::new("_value", "dateTime:RFC3339", time_value),
+        Column::new("_value", "duration", duration_value),
+        Column::new("_value", "base64Binary", binary_value),
+    ];
+    for column in types {
+        let data_type = column.data_type;
+        let input = generate_csv(&[column], ROWS);
+        group.throughput(Throughput::Elements(ROWS as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(data_type),
+            &input,
+            |b, input| {
+                b.to_async(&rt).iter(|| parse_all(input));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Allocating a record per row versus reusing one with `next_into`.
+fn bench_record_reuse(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("parser_reuse");
+    let input = mixed_csv(8);
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    group.bench_function("next", |b| {
+        b.to_async(&rt).iter(|| parse_all(&input));
+    });
+    group.bench_function("next_into", |b| {
+        b.to_async(&rt).iter(|| parse_all_into(&input));
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_column_count,
+    bench_data_types,
+    bench_record_reuse
+);
+criterion_main!(benches);
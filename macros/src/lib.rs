@@ -0,0 +1,141 @@
+//! Procedural macros for `influxdb-stream`.
+//!
+//! These are implementation details of the macros exported by
+//! `influxdb-stream`; use them through that crate.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Expand `flux!` with inline captures converted like positional arguments.
+///
+/// Input: `$crate, "format string" [, wrapped positional arguments]*`. Every
+/// `{name}` placeholder in the format string becomes a named argument
+/// `name = $crate::flux::Literal(&name)` of `format!`.
+#[doc(hidden)]
+#[proc_macro]
+pub fn flux_inline(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let krate: Vec<TokenTree> = tokens.by_ref().take_while(|t| !is_comma(t)).collect();
+    let Some(format) = tokens.next() else {
+        return compile_error("flux! requires a format string");
+    };
+    let Some(literal) = string_literal(&format) else {
+        return compile_error("flux! format string must be a string literal");
+    };
+    let rest: Vec<TokenTree> = tokens.collect();
+
+    let mut args = TokenStream::new();
+    args.extend([format]);
+    args.extend(rest);
+    for name in inline_captures(&literal.to_string()) {
+        let ident = Ident::new(&name, literal.span());
+        args.extend([
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+            TokenTree::Ident(ident.clone()),
+            TokenTree::Punct(Punct::new('=', Spacing::Alone)),
+        ]);
+        args.extend(krate.iter().cloned());
+        args.extend(path(&["flux", "Literal"]));
+        let mut reference = TokenStream::new();
+        reference.extend([
+            TokenTree::Punct(Punct::new('&', Spacing::Alone)),
+            TokenTree::Ident(ident),
+        ]);
+        args.extend([TokenTree::Group(Group::new(
+            Delimiter::Parenthesis,
+            reference,
+        ))]);
+    }
+
+    let mut out: TokenStream = path(&["std", "format"]).into_iter().collect();
+    out.extend([
+        TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+        TokenTree::Group(Group::new(Delimiter::Parenthesis, args)),
+    ]);
+    out
+}
+
+fn is_comma(token: &TokenTree) -> bool {
+    matches!(token, TokenTree::Punct(p) if p.as_char() == ',')
+}
+
+/// Get the string literal of `token`, looking through invisible groups.
+fn string_literal(token: &TokenTree) -> Option<Literal> {
+    match token {
+        TokenTree::Literal(l) if l.to_string().trim_end_matches('#').ends_with('"') => {
+            Some(l.clone())
+        }
+        TokenTree::Group(g) if g.delimiter() == Delimiter::None => {
+            let mut inner = g.stream().into_iter();
+            let first = inner.next()?;
+            inner.next().is_none().then(|| string_literal(&first))?
+        }
+        _ => None,
+    }
+}
+
+/// `::a::b`, to append to `$crate` or use as an absolute path.
+fn path(segments: &[&str]) -> Vec<TokenTree> {
+    let mut out = Vec::new();
+    for segment in segments {
+        out.push(TokenTree::Punct(Punct::new(':', Spacing::Joint)));
+        out.push(TokenTree::Punct(Punct::new(':', Spacing::Alone)));
+        out.push(TokenTree::Ident(Ident::new(segment, Span::call_site())));
+    }
+    out
+}
+
+/// Find the names of inline-captured variables in the source of a string literal.
+fn inline_captures(source: &str) -> Vec<String> {
+    let raw = source.starts_with('r');
+    let mut names = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !raw => {
+                // Skip the escaped character, including `\u{...}`.
+                let escaped = chars.next();
+                if escaped == Some('u') && chars.peek() == Some(&'{') {
+                    chars.by_ref().find(|&c| c == '}');
+                }
+            }
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '{' => {
+                let argument: String = chars
+                    .by_ref()
+                    .take_while(|&c| c != '}')
+                    .collect::<String>()
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                if is_identifier(&argument) && !names.contains(&argument) {
+                    names.push(argument);
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && s != "_"
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    let mut out: TokenStream = path(&["core", "compile_error"]).into_iter().collect();
+    out.extend([
+        TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+        TokenTree::Group(Group::new(
+            Delimiter::Parenthesis,
+            TokenTree::Literal(Literal::string(message)).into(),
+        )),
+    ]);
+    out
+}
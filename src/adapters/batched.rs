@@ -0,0 +1,140 @@
+//! Yielding records in small batches.
+//!
+//! Every item of a stream costs a trip through the consumer's async machinery.
+//! [`Batched`] collects the records that are already available into a
+//! [`SmallVec`], so consumers that process records in groups wake up once per
+//! batch instead of once per record.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use smallvec::SmallVec;
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// Records yielded together by [`Batched`], stored inline up to `N`.
+pub type RecordChunk<const N: usize> = SmallVec<[FluxRecord; N]>;
+
+/// Stream returned by [`RecordStreamExt::batched`](super::RecordStreamExt::batched).
+///
+/// The inner stream is boxed because `pin_project!` does not support const
+/// generics; the allocation happens once per stream, not per batch.
+pub struct Batched<S, const N: usize> {
+    stream: Pin<Box<S>>,
+    error: Option<Error>,
+    done: bool,
+}
+
+impl<S, const N: usize> Batched<S, N> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            error: None,
+            done: false,
+        }
+    }
+}
+
+impl<S, const N: usize> Stream for Batched<S, N>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    type Item = Result<RecordChunk<N>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Some(Err(e)));
+        }
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = RecordChunk::<N>::new();
+        while chunk.len() < N.max(1) {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => chunk.push(record),
+                Poll::Ready(Some(Err(e))) => {
+                    // Deliver the records read before the error first.
+                    if chunk.is_empty() {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    this.error = Some(e);
+                    break;
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    break;
+                }
+                Poll::Pending if chunk.is_empty() => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+
+        if chunk.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(Ok(chunk)))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.stream.size_hint();
+        let n = N.max(1);
+        (lower.div_ceil(n), upper.map(|u| u + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::RecordStreamExt;
+    use crate::error::{Error, Result};
+    use crate::types::FluxRecord;
+    use futures::{StreamExt, stream};
+
+    fn records(n: i32) -> Vec<Result<FluxRecord>> {
+        (0..n).map(|i| Ok(FluxRecord::new(i))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batched_groups_ready_records() {
+        let chunks: Vec<_> = stream::iter(records(10)).batched::<4>().collect().await;
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.as_ref().unwrap().len()).collect();
+        assert_eq!(sizes, vec![4, 4, 2]);
+        assert_eq!(chunks[2].as_ref().unwrap()[1].table, 9);
+    }
+
+    #[tokio::test]
+    async fn test_batched_yields_records_before_error() {
+        let mut input = records(3);
+        input.push(Err(Error::Csv("bad row".to_string())));
+        input.extend(records(2));
+
+        let chunks: Vec<_> = stream::iter(input).batched::<8>().collect().await;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].as_ref().unwrap().len(), 3);
+        assert!(matches!(chunks[1], Err(Error::Csv(_))));
+        assert_eq!(chunks[2].as_ref().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batched_does_not_wait_for_full_chunk() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut chunks = Box::pin(from_channel(rx).batched::<16>());
+
+        tx.send(Ok(FluxRecord::new(0))).unwrap();
+        tx.send(Ok(FluxRecord::new(1))).unwrap();
+        assert_eq!(chunks.next().await.unwrap().unwrap().len(), 2);
+
+        drop(tx);
+        assert!(chunks.next().await.is_none());
+    }
+
+    fn from_channel(
+        mut rx: tokio::sync::mpsc::UnboundedReceiver<Result<FluxRecord>>,
+    ) -> impl futures::Stream<Item = Result<FluxRecord>> {
+        futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+    }
+}
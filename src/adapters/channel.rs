@@ -0,0 +1,88 @@
+//! Bridging record streams to Tokio channels.
+
+use futures::future::{self, Either};
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Drive `stream` on a spawned task and forward its items to a bounded channel.
+///
+/// At most `capacity` items (at least one) are buffered ahead of the
+/// receiver; once the channel is full the task stops reading the stream, so
+/// the HTTP response is throttled by the consumer. Dropping the receiver
+/// stops the task and drops the stream, closing the connection.
+///
+/// # Panics
+///
+/// Panics if called outside of a Tokio runtime.
+pub fn into_channel<S>(stream: S, capacity: usize) -> mpsc::Receiver<Result<FluxRecord>>
+where
+    S: Stream<Item = Result<FluxRecord>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    tokio::spawn(drive(stream, tx));
+    rx
+}
+
+async fn drive<S>(stream: S, tx: mpsc::Sender<Result<FluxRecord>>)
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    loop {
+        // Wait for room before reading, so no item is pulled that cannot be sent.
+        let Ok(permit) = tx.reserve().await else {
+            break;
+        };
+        let next = stream.next();
+        let closed = std::pin::pin!(tx.closed());
+        match future::select(closed, next).await {
+            Either::Right((Some(item), _)) => permit.send(item),
+            Either::Right((None, _)) | Either::Left(_) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_into_channel_forwards_all_items() {
+        let records = (0..10).map(|i| Ok(FluxRecord::new(i)));
+        let mut rx = into_channel(stream::iter(records), 2);
+
+        let mut tables = Vec::new();
+        while let Some(record) = rx.recv().await {
+            tables.push(record.unwrap().table);
+        }
+        assert_eq!(tables, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_into_channel_drop_receiver_stops_driver() {
+        let guard = Arc::new(());
+        let held = guard.clone();
+        let input = stream::pending::<Result<FluxRecord>>().map(move |item| {
+            let _ = &held;
+            item
+        });
+
+        let rx = into_channel(input, 1);
+        assert_eq!(Arc::strong_count(&guard), 2);
+        drop(rx);
+
+        for _ in 0..100 {
+            if Arc::strong_count(&guard) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("driver task kept the stream alive");
+    }
+}
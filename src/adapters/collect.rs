@@ -0,0 +1,126 @@
+//! Collecting records into memory with a size limit.
+//!
+//! Collecting a whole result defeats streaming when the result turns out to
+//! be huge. A [`CollectLimit`] makes the collection fail with
+//! [`Error::LimitExceeded`] instead, and drops the stream as soon as the limit
+//! is crossed so nothing more is downloaded.
+
+use futures::{Stream, StreamExt};
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// Upper bounds on the records collected into memory.
+///
+/// The default has no limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollectLimit {
+    max_records: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl CollectLimit {
+    /// Create a limit with no bounds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow at most `n` records.
+    pub fn records(mut self, n: usize) -> Self {
+        self.max_records = Some(n);
+        self
+    }
+
+    /// Allow at most `bytes` of records, as estimated by
+    /// [`FluxRecord::estimated_size`].
+    pub fn bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Returns true if no bound is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_records.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Fail if `records` records totalling `bytes` exceed the limit.
+    pub(crate) fn check(&self, records: usize, bytes: usize) -> Result<()> {
+        if let Some(max) = self.max_records.filter(|max| records > *max) {
+            return Err(Error::LimitExceeded(format!("more than {} records", max)));
+        }
+        if let Some(max) = self.max_bytes.filter(|max| bytes > *max) {
+            return Err(Error::LimitExceeded(format!("more than {} bytes", max)));
+        }
+        Ok(())
+    }
+}
+
+/// Collect every record of `stream`, failing once `limit` is exceeded.
+pub async fn collect_limited<S>(stream: S, limit: &CollectLimit) -> Result<Vec<FluxRecord>>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut records = Vec::new();
+    let mut bytes = 0;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        if limit.max_bytes.is_some() {
+            bytes += record.estimated_size();
+        }
+        limit.check(records.len() + 1, bytes)?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use futures::stream;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn records(n: i32) -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter((0..n).map(|i| Ok(FluxRecord::new(i))))
+    }
+
+    #[tokio::test]
+    async fn test_collect_within_limit() {
+        let limit = CollectLimit::new().records(5);
+        assert_eq!(collect_limited(records(5), &limit).await.unwrap().len(), 5);
+        assert!(CollectLimit::new().is_unlimited());
+    }
+
+    #[tokio::test]
+    async fn test_record_limit_stops_reading() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let counter = pulled.clone();
+        let input = records(100).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let err = input
+            .try_collect_limited(CollectLimit::new().records(3))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(_)));
+        assert_eq!(pulled.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit() {
+        let one = FluxRecord::new(0).estimated_size();
+        let limit = CollectLimit::new().bytes(one * 2);
+
+        assert!(collect_limited(records(2), &limit).await.is_ok());
+        let err = collect_limited(records(3), &limit).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Result limit exceeded: more than {} bytes", one * 2)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Filtering by group key.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Boxed predicate used by [`RecordStreamExt::filter_tags`](super::RecordStreamExt::filter_tags).
+pub type TagPredicate = Box<dyn FnMut(&FluxRecord) -> bool + Send>;
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::filter_group_key`](super::RecordStreamExt::filter_group_key)
+    /// and [`RecordStreamExt::filter_tags`](super::RecordStreamExt::filter_tags).
+    pub struct FilterGroupKey<S, P> {
+        #[pin]
+        stream: S,
+        predicate: P,
+        // Decision for the table currently being read.
+        current: Option<(i32, bool)>,
+    }
+}
+
+impl<S, P> FilterGroupKey<S, P> {
+    pub(crate) fn new(stream: S, predicate: P) -> Self {
+        Self {
+            stream,
+            predicate,
+            current: None,
+        }
+    }
+}
+
+impl<S, P> Stream for FilterGroupKey<S, P>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    P: FnMut(&FluxRecord) -> bool,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            let record = match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(record)) => record,
+                other => return Poll::Ready(other),
+            };
+
+            let keep = match *this.current {
+                Some((table, keep)) if table == record.table => keep,
+                _ => {
+                    let keep = (this.predicate)(&record);
+                    *this.current = Some((record.table, keep));
+                    keep
+                }
+            };
+
+            if keep {
+                return Poll::Ready(Some(Ok(record)));
+            }
+        }
+    }
+}
+
+/// Build a predicate matching records whose string columns equal all `tags`.
+pub(crate) fn tag_predicate(tags: &[(&str, &str)]) -> TagPredicate {
+    let tags: Vec<(String, String)> = tags
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    Box::new(move |record| {
+        tags.iter()
+            .all(|(k, v)| record.get(k).and_then(|x| x.as_string()) == Some(v.as_str()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::RecordStreamExt;
+    use crate::error::{Error, Result};
+    use crate::types::FluxRecord;
+    use crate::value::Value;
+    use futures::{Stream, StreamExt, TryStreamExt, stream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn record(table: i32, host: &str, region: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(table);
+        record.insert("host".to_string(), Value::String(host.to_string()));
+        record.insert("region".to_string(), Value::String(region.to_string()));
+        record
+    }
+
+    fn input() -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter(vec![
+            Ok(record(0, "server1", "us-east")),
+            Ok(record(0, "server1", "us-east")),
+            Ok(record(1, "server2", "us-east")),
+            Ok(record(2, "server1", "eu-west")),
+            Ok(record(2, "server1", "eu-west")),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_filter_tags() {
+        let records: Vec<_> = input()
+            .filter_tags(&[("host", "server1")])
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 4);
+
+        let records: Vec<_> = input()
+            .filter_tags(&[("host", "server1"), ("region", "eu-west")])
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.table == 2));
+    }
+
+    #[tokio::test]
+    async fn test_filter_tags_missing_column() {
+        let records: Vec<_> = input()
+            .filter_tags(&[("rack", "a1")])
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_filter_group_key_evaluates_once_per_table() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        let records: Vec<_> = input()
+            .filter_group_key(move |r| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                r.get_str("region") == Some("us-east")
+            })
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_filter_passes_errors() {
+        let input = stream::iter(vec![
+            Ok(record(0, "server2", "us-east")),
+            Err(Error::Csv("boom".to_string())),
+        ]);
+
+        let items: Vec<_> = input.filter_tags(&[("host", "server1")]).collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_err());
+    }
+}
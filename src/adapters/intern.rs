@@ -0,0 +1,191 @@
+//! Sharing of repeated string values.
+//!
+//! Tag columns repeat the same few strings across millions of records. The
+//! [`Intern`] adapter replaces them with [`Value::SharedString`]s from a
+//! per-stream [`StringInterner`], so records kept in memory share one
+//! allocation per distinct string.
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Pool of shared strings.
+#[derive(Clone, Debug, Default)]
+pub struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    /// Create an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the shared copy of `s`, adding it to the pool if needed.
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(shared) = self.strings.get(s) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = Arc::from(s);
+        self.strings.insert(shared.clone());
+        shared
+    }
+
+    /// Replace a string `value` with its shared copy. Other values are left
+    /// unchanged.
+    pub fn intern_value(&mut self, value: &mut Value) {
+        if let Value::String(s) = value {
+            *value = Value::SharedString(self.intern(s));
+        }
+    }
+
+    /// Get the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns true if the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Remove all strings from the pool.
+    ///
+    /// Values interned earlier keep their strings alive.
+    pub fn clear(&mut self) {
+        self.strings.clear();
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::intern_strings`](super::RecordStreamExt::intern_strings)
+    /// and [`RecordStreamExt::intern_columns`](super::RecordStreamExt::intern_columns).
+    pub struct Intern<S> {
+        #[pin]
+        stream: S,
+        columns: Option<Vec<String>>,
+        interner: StringInterner,
+    }
+}
+
+impl<S> Intern<S> {
+    pub(crate) fn new(stream: S, columns: Option<Vec<String>>) -> Self {
+        Self {
+            stream,
+            columns,
+            interner: StringInterner::new(),
+        }
+    }
+
+    /// Get the pool of strings seen so far.
+    pub fn interner(&self) -> &StringInterner {
+        &self.interner
+    }
+}
+
+impl<S> Stream for Intern<S>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut item = ready!(this.stream.poll_next(cx));
+
+        if let Some(Ok(record)) = &mut item {
+            match this.columns {
+                Some(columns) => {
+                    for column in columns.iter() {
+                        if let Some(value) = record.get_mut(column) {
+                            this.interner.intern_value(value);
+                        }
+                    }
+                }
+                None => {
+                    let schema = record.schema().clone();
+                    for (name, value) in schema.names().iter().zip(record.values_mut()) {
+                        if name != "_value" {
+                            this.interner.intern_value(value);
+                        }
+                    }
+                }
+            }
+        }
+
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use futures::{TryStreamExt, stream};
+
+    fn record(host: &str, value: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("host", Value::String(host.to_string()));
+        record.insert("_value", Value::String(value.to_string()));
+        record
+    }
+
+    fn shared(record: &FluxRecord, column: &str) -> Arc<str> {
+        match record.get(column) {
+            Some(Value::SharedString(s)) => s.clone(),
+            other => panic!("{} is not shared: {:?}", column, other),
+        }
+    }
+
+    #[test]
+    fn test_interner_reuses_allocation() {
+        let mut interner = StringInterner::new();
+        let a = interner.intern("server1");
+        let b = interner.intern("server1");
+        let c = interner.intern("server2");
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(interner.len(), 2);
+
+        let mut value = Value::Long(1);
+        interner.intern_value(&mut value);
+        assert_eq!(value, Value::Long(1));
+    }
+
+    #[tokio::test]
+    async fn test_intern_strings_skips_value_column() {
+        let input = stream::iter(vec![Ok(record("server1", "a")), Ok(record("server1", "b"))]);
+        let records: Vec<_> = input.intern_strings().try_collect().await.unwrap();
+
+        assert!(Arc::ptr_eq(
+            &shared(&records[0], "host"),
+            &shared(&records[1], "host")
+        ));
+        assert!(matches!(records[0].get("_value"), Some(Value::String(_))));
+        assert_eq!(records[1].get_string("host"), Some("server1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_intern_columns() {
+        let input = stream::iter(vec![Ok(record("server1", "a"))]);
+        let mut interned = Box::pin(input.intern_columns(&["_value", "missing"]));
+        let record = interned.try_next().await.unwrap().unwrap();
+
+        assert_eq!(&*shared(&record, "_value"), "a");
+        assert!(matches!(record.get("host"), Some(Value::String(_))));
+        assert_eq!(interned.interner().len(), 1);
+    }
+}
@@ -0,0 +1,365 @@
+//! Client-side join of two record streams on time and tags.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use async_stream::stream;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::{Stream, StreamExt};
+
+use crate::error::Result;
+use crate::types::{FluxRecord, RecordSchema};
+
+/// Options for [`join_by_time`].
+#[derive(Clone, Debug)]
+pub struct JoinOptions {
+    on: Vec<String>,
+    tolerance: TimeDelta,
+    suffixes: (String, String),
+}
+
+impl JoinOptions {
+    /// Join records with exactly equal `_time` and no tag columns.
+    pub fn new() -> Self {
+        Self {
+            on: Vec::new(),
+            tolerance: TimeDelta::zero(),
+            suffixes: ("_left".to_string(), "_right".to_string()),
+        }
+    }
+
+    /// Only join records whose values in `columns` (typically tags) are equal.
+    pub fn on<I, T>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.on = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Join records whose `_time` differs by at most `tolerance` (default: zero).
+    pub fn tolerance(mut self, tolerance: TimeDelta) -> Self {
+        self.tolerance = tolerance.abs();
+        self
+    }
+
+    /// Rename columns present in both records by appending `left` or `right`
+    /// (default: `_left` and `_right`).
+    pub fn suffixes(mut self, left: impl Into<String>, right: impl Into<String>) -> Self {
+        self.suffixes = (left.into(), right.into());
+        self
+    }
+}
+
+impl Default for JoinOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Join two record streams on `_time` and the [`on`](JoinOptions::on) columns.
+///
+/// This is an inner join: each `left` record is paired with the `right`
+/// record of the same tags that is closest in time, within the tolerance, and
+/// records without a partner are dropped. Each `right` record is used at most
+/// once. Both inputs must be sorted by `_time`, as for
+/// [`merge_by_time`](super::merge_by_time); only the `right` records within
+/// the tolerance of the current `left` record are kept in memory.
+///
+/// Joined records hold `_time` and the `on` columns of the left record, in
+/// that order, followed by the remaining columns of the left and then the
+/// right record. Joined records share one schema as long as their inputs do. Columns present in both records
+/// get the [suffixes](JoinOptions::suffixes), so `_value` becomes `_value_left`
+/// and `_value_right`. The first error from either input ends the stream.
+///
+/// # Example
+///
+/// ```ignore
+/// use chrono::TimeDelta;
+/// use influxdb_stream::adapters::{JoinOptions, join_by_time};
+///
+/// let temperature = client.query_stream(temperature_query).await?;
+/// let humidity = client.query_stream(humidity_query).await?;
+/// let options = JoinOptions::new()
+///     .on(["sensor"])
+///     .tolerance(TimeDelta::seconds(1))
+///     .suffixes("_temperature", "_humidity");
+/// let mut joined = join_by_time(temperature, humidity, options);
+/// ```
+pub fn join_by_time<L, R>(
+    left: L,
+    right: R,
+    options: JoinOptions,
+) -> impl Stream<Item = Result<FluxRecord>>
+where
+    L: Stream<Item = Result<FluxRecord>>,
+    R: Stream<Item = Result<FluxRecord>>,
+{
+    stream! {
+        let mut left = std::pin::pin!(left);
+        let mut right = std::pin::pin!(right);
+        let mut window: VecDeque<(DateTime<FixedOffset>, FluxRecord)> = VecDeque::new();
+        let mut lookahead: Option<(DateTime<FixedOffset>, FluxRecord)> = None;
+        let mut right_done = false;
+        let mut layout: Option<Layout> = None;
+
+        while let Some(record) = left.next().await {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            let Some(time) = record.time().copied() else { continue };
+
+            // Pull right records up to the end of this record's window.
+            loop {
+                if let Some((t, _)) = &lookahead {
+                    if *t - time > options.tolerance {
+                        break;
+                    }
+                    window.extend(lookahead.take());
+                }
+                if right_done {
+                    break;
+                }
+                match right.next().await {
+                    Some(Ok(r)) => lookahead = r.time().copied().map(|t| (t, r)),
+                    Some(Err(e)) => {
+                        yield Err(e);
+                        return;
+                    }
+                    None => right_done = true,
+                }
+            }
+            while window.front().is_some_and(|(t, _)| time - *t > options.tolerance) {
+                window.pop_front();
+            }
+            if window.is_empty() && lookahead.is_none() && right_done {
+                break;
+            }
+
+            let best = window
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, r))| same_keys(&record, r, &options.on))
+                .min_by_key(|(_, (t, _))| (*t - time).abs())
+                .map(|(i, _)| i);
+            if let Some((_, partner)) = best.and_then(|i| window.remove(i)) {
+                let joined = layout
+                    .take()
+                    .filter(|l| l.fits(&record, &partner))
+                    .unwrap_or_else(|| Layout::new(&record, &partner, &options));
+                yield Ok(joined.combine(&record, &partner));
+                layout = Some(joined);
+            }
+        }
+    }
+}
+
+/// Compare the `on` columns of two records.
+fn same_keys(a: &FluxRecord, b: &FluxRecord, on: &[String]) -> bool {
+    on.iter().all(|c| a.get(c) == b.get(c))
+}
+
+/// Columns of the records joined from a pair of input schemas.
+struct Layout {
+    left: Arc<RecordSchema>,
+    right: Arc<RecordSchema>,
+    schema: Arc<RecordSchema>,
+    columns: Vec<Column>,
+}
+
+/// Position of a joined column in one of the input records.
+#[derive(Clone, Copy)]
+enum Column {
+    Left(usize),
+    Right(usize),
+}
+
+impl Layout {
+    fn new(left: &FluxRecord, right: &FluxRecord, options: &JoinOptions) -> Self {
+        let is_key = |name: &str| name == "_time" || options.on.iter().any(|c| c == name);
+        let (left_suffix, right_suffix) = &options.suffixes;
+        let (left_schema, right_schema) = (left.schema(), right.schema());
+
+        let mut names = Vec::with_capacity(left.len() + right.len());
+        let mut columns = Vec::with_capacity(left.len() + right.len());
+        for key in std::iter::once("_time").chain(options.on.iter().map(String::as_str)) {
+            if let Some(i) = left_schema.index_of(key) {
+                names.push(key.to_string());
+                columns.push(Column::Left(i));
+            }
+        }
+        for (i, name) in left_schema.names().iter().enumerate() {
+            if is_key(name) {
+                continue;
+            }
+            if right.contains(name) {
+                names.push(format!("{}{}", name, left_suffix));
+            } else {
+                names.push(name.clone());
+            }
+            columns.push(Column::Left(i));
+        }
+        for (i, name) in right_schema.names().iter().enumerate() {
+            if is_key(name) {
+                continue;
+            }
+            if left.contains(name) {
+                names.push(format!("{}{}", name, right_suffix));
+            } else {
+                names.push(name.clone());
+            }
+            columns.push(Column::Right(i));
+        }
+
+        Self {
+            left: left_schema.clone(),
+            right: right_schema.clone(),
+            schema: Arc::new(RecordSchema::new(names)),
+            columns,
+        }
+    }
+
+    /// Returns true if the layout was built for the schemas of `left` and
+    /// `right`.
+    fn fits(&self, left: &FluxRecord, right: &FluxRecord) -> bool {
+        Arc::ptr_eq(&self.left, left.schema()) && Arc::ptr_eq(&self.right, right.schema())
+    }
+
+    /// Build the joined record of `left` and `right`.
+    fn combine(&self, left: &FluxRecord, right: &FluxRecord) -> FluxRecord {
+        let values = self
+            .columns
+            .iter()
+            .map(|column| match *column {
+                Column::Left(i) => left.values()[i].clone(),
+                Column::Right(i) => right.values()[i].clone(),
+            })
+            .collect();
+        FluxRecord::from_parts(left.table, self.schema.clone(), values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::value::Value;
+    use futures::TryStreamExt;
+    use futures::stream;
+    use ordered_float::OrderedFloat;
+
+    fn record(ts: &str, sensor: &str, value: f64) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record.insert("sensor".to_string(), Value::String(sensor.to_string()));
+        record.insert("_value".to_string(), Value::Double(OrderedFloat(value)));
+        record
+    }
+
+    fn input(records: Vec<FluxRecord>) -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter(records.into_iter().map(Ok))
+    }
+
+    #[tokio::test]
+    async fn test_join_on_time_and_tags() {
+        let temperature = input(vec![
+            record("2023-11-14T12:00:00Z", "a", 21.0),
+            record("2023-11-14T12:00:00Z", "b", 19.0),
+            record("2023-11-14T12:00:10Z", "a", 21.5),
+        ]);
+        let humidity = input(vec![
+            record("2023-11-14T12:00:00Z", "b", 60.0),
+            record("2023-11-14T12:00:01Z", "a", 40.0),
+            record("2023-11-14T12:00:12Z", "a", 41.0),
+        ]);
+        let options = JoinOptions::new()
+            .on(["sensor"])
+            .tolerance(TimeDelta::seconds(1))
+            .suffixes("_t", "_h");
+
+        let joined: Vec<_> = join_by_time(temperature, humidity, options)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(joined.len(), 2);
+        assert_eq!(
+            joined[0].columns().collect::<Vec<_>>(),
+            ["_time", "sensor", "_value_t", "_value_h"]
+        );
+        assert_eq!(joined[0].get_str("sensor"), Some("a"));
+        assert_eq!(joined[0].get_double("_value_h"), Some(40.0));
+        assert_eq!(joined[1].get_str("sensor"), Some("b"));
+        assert_eq!(joined[1].get_double("_value_t"), Some(19.0));
+    }
+
+    #[tokio::test]
+    async fn test_join_picks_nearest_once() {
+        let left = input(vec![
+            record("2023-11-14T12:00:02Z", "a", 1.0),
+            record("2023-11-14T12:00:03Z", "a", 2.0),
+        ]);
+        let right = input(vec![
+            record("2023-11-14T12:00:00Z", "a", 10.0),
+            record("2023-11-14T12:00:02Z", "a", 20.0),
+        ]);
+        let options = JoinOptions::new().tolerance(TimeDelta::seconds(5));
+
+        let joined: Vec<_> = join_by_time(left, right, options)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0].get_double("_value_right"), Some(20.0));
+        assert_eq!(joined[1].get_double("_value_right"), Some(10.0));
+        assert_eq!(joined[0].get_str("sensor_left"), Some("a"));
+    }
+
+    #[tokio::test]
+    async fn test_join_orders_keys_and_shares_schema() {
+        let mut left = FluxRecord::new(0);
+        left.insert("_value".to_string(), Value::Double(OrderedFloat(1.0)));
+        left.insert("sensor".to_string(), Value::String("a".to_string()));
+        left.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-11-14T12:00:00Z").unwrap()),
+        );
+        let right = record("2023-11-14T12:00:00Z", "a", 2.0);
+        let options = JoinOptions::new().on(["sensor"]);
+
+        let joined: Vec<_> = join_by_time(
+            input(vec![left.clone(), left]),
+            input(vec![right.clone(), right]),
+            options,
+        )
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(joined.len(), 2);
+        assert_eq!(
+            joined[0].columns().collect::<Vec<_>>(),
+            ["_time", "sensor", "_value_left", "_value_right"]
+        );
+        assert_eq!(joined[0].get_double("_value_right"), Some(2.0));
+        assert!(Arc::ptr_eq(joined[0].schema(), joined[1].schema()));
+    }
+
+    #[tokio::test]
+    async fn test_join_error_ends_stream() {
+        let left = input(vec![record("2023-11-14T12:00:00Z", "a", 1.0)]);
+        let right = stream::iter(vec![Err(Error::Csv("boom".to_string()))]);
+
+        let items: Vec<_> = join_by_time(left, right, JoinOptions::new())
+            .collect()
+            .await;
+        assert_eq!(items.len(), 1);
+        assert!(matches!(items[0], Err(Error::Csv(_))));
+    }
+}
@@ -0,0 +1,204 @@
+//! Ordered k-way merge of record streams.
+
+use std::cmp::Ordering;
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// Merge several record streams into one stream ordered by `_time`.
+///
+/// Each input must already be sorted by `_time`, for example one stream per
+/// bucket or shard of the same measurement. Records with equal timestamps are
+/// ordered by the string values of the `tiebreak` columns (typically tags),
+/// and then by the position of their input stream.
+///
+/// Records without a `_time` column sort before all timestamped records. Errors
+/// are yielded as soon as they occur; the stream that produced the error is
+/// dropped and merging continues with the remaining inputs.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::adapters::merge_by_time;
+///
+/// let east = client.query_stream(east_query).await?;
+/// let west = client.query_stream(west_query).await?;
+/// let mut merged = merge_by_time(vec![east, west], &["host"]);
+/// ```
+pub fn merge_by_time<S>(
+    streams: Vec<S>,
+    tiebreak: &[&str],
+) -> impl Stream<Item = Result<FluxRecord>>
+where
+    S: Stream<Item = Result<FluxRecord>> + Unpin,
+{
+    let tiebreak: Vec<String> = tiebreak.iter().map(|c| c.to_string()).collect();
+
+    stream! {
+        let mut inputs: Vec<Option<S>> = streams.into_iter().map(Some).collect();
+        let mut heads: Vec<Option<FluxRecord>> = inputs.iter().map(|_| None).collect();
+
+        // Prime every input with its first record.
+        for i in 0..inputs.len() {
+            if let Some(e) = advance(&mut inputs[i], &mut heads[i]).await {
+                yield Err(e);
+            }
+        }
+
+        loop {
+            let mut next: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                let Some(candidate) = head else { continue };
+                let earlier = match next.and_then(|j| heads[j].as_ref()) {
+                    Some(best) => compare(candidate, best, &tiebreak) == Ordering::Less,
+                    None => true,
+                };
+                if earlier {
+                    next = Some(i);
+                }
+            }
+
+            let Some(i) = next else { break };
+            if let Some(record) = heads[i].take() {
+                yield Ok(record);
+            }
+            if let Some(e) = advance(&mut inputs[i], &mut heads[i]).await {
+                yield Err(e);
+            }
+        }
+    }
+}
+
+/// Pull the next record of `input` into `head`.
+///
+/// Exhausted or failed inputs are dropped. Returns the error, if any.
+async fn advance<S>(input: &mut Option<S>, head: &mut Option<FluxRecord>) -> Option<Error>
+where
+    S: Stream<Item = Result<FluxRecord>> + Unpin,
+{
+    let stream = input.as_mut()?;
+    match stream.next().await {
+        Some(Ok(record)) => {
+            *head = Some(record);
+            None
+        }
+        Some(Err(e)) => {
+            *input = None;
+            Some(e)
+        }
+        None => {
+            *input = None;
+            None
+        }
+    }
+}
+
+/// Compare two records by `_time`, then by the tiebreak columns.
+fn compare(a: &FluxRecord, b: &FluxRecord, tiebreak: &[String]) -> Ordering {
+    a.time().cmp(&b.time()).then_with(|| {
+        tiebreak
+            .iter()
+            .map(|c| {
+                let a = a.get(c).and_then(|v| v.as_string());
+                let b = b.get(c).and_then(|v| v.as_string());
+                a.cmp(&b)
+            })
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use chrono::DateTime;
+    use futures::TryStreamExt;
+    use futures::stream::{self, BoxStream};
+
+    fn record(ts: &str, host: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record.insert("host".to_string(), Value::String(host.to_string()));
+        record
+    }
+
+    fn input(items: Vec<Result<FluxRecord>>) -> BoxStream<'static, Result<FluxRecord>> {
+        stream::iter(items).boxed()
+    }
+
+    fn hosts(records: &[FluxRecord]) -> Vec<String> {
+        records
+            .iter()
+            .map(|r| r.get_string("host").unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_merge_by_time() {
+        let a = input(vec![
+            Ok(record("2023-11-14T12:00:00Z", "a1")),
+            Ok(record("2023-11-14T12:00:03Z", "a2")),
+        ]);
+        let b = input(vec![
+            Ok(record("2023-11-14T12:00:01Z", "b1")),
+            Ok(record("2023-11-14T12:00:02Z", "b2")),
+            Ok(record("2023-11-14T12:00:04Z", "b3")),
+        ]);
+
+        let merged: Vec<_> = merge_by_time(vec![a, b], &[]).try_collect().await.unwrap();
+        assert_eq!(hosts(&merged), ["a1", "b1", "b2", "a2", "b3"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_tiebreak() {
+        let a = input(vec![Ok(record("2023-11-14T12:00:00Z", "server2"))]);
+        let b = input(vec![Ok(record("2023-11-14T12:00:00Z", "server1"))]);
+
+        let merged: Vec<_> = merge_by_time(vec![a, b], &["host"])
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(hosts(&merged), ["server1", "server2"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_ties_keep_input_order() {
+        let a = input(vec![Ok(record("2023-11-14T12:00:00Z", "server2"))]);
+        let b = input(vec![Ok(record("2023-11-14T12:00:00Z", "server1"))]);
+
+        let merged: Vec<_> = merge_by_time(vec![a, b], &[]).try_collect().await.unwrap();
+        assert_eq!(hosts(&merged), ["server2", "server1"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_error_drops_failed_input() {
+        let a = input(vec![
+            Ok(record("2023-11-14T12:00:00Z", "a1")),
+            Err(Error::Csv("boom".to_string())),
+            Ok(record("2023-11-14T12:00:05Z", "a2")),
+        ]);
+        let b = input(vec![Ok(record("2023-11-14T12:00:01Z", "b1"))]);
+
+        let items: Vec<_> = merge_by_time(vec![a, b], &[]).collect().await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap().get_string("host").unwrap(), "a1");
+        assert!(matches!(items[1], Err(Error::Csv(_))));
+        assert_eq!(items[2].as_ref().unwrap().get_string("host").unwrap(), "b1");
+    }
+
+    #[tokio::test]
+    async fn test_merge_empty() {
+        let merged: Vec<_> =
+            merge_by_time(Vec::<BoxStream<'static, Result<FluxRecord>>>::new(), &[])
+                .collect()
+                .await;
+        assert!(merged.is_empty());
+    }
+}
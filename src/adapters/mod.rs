@@ -0,0 +1,474 @@
+//! Combinators for record streams.
+//!
+//! These adapters operate on any `Stream<Item = Result<FluxRecord>>`, such as
+//! the streams returned by [`Client::query_stream`](crate::Client::query_stream).
+//! Most of them are available as methods through [`RecordStreamExt`].
+
+pub mod batched;
+pub mod channel;
+pub mod collect;
+pub mod filter;
+pub mod intern;
+pub mod join;
+pub mod merge;
+pub mod partition;
+pub mod prefetch;
+pub mod resample;
+pub mod sketch;
+pub mod stats;
+pub mod take_time;
+pub mod tee;
+pub mod throttle;
+pub mod timezone;
+pub mod validate;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncWrite;
+use tokio::sync::mpsc;
+
+use crate::checkpoint::Checkpointed;
+use crate::de::Deserialized;
+use crate::error::Result;
+use crate::sink::{self, CsvOptions, LineProtocolOptions};
+use crate::typed::{FromRecord, Typed};
+use crate::types::FluxRecord;
+
+pub use batched::{Batched, RecordChunk};
+pub use channel::into_channel;
+pub use collect::{CollectLimit, collect_limited};
+pub use filter::{FilterGroupKey, TagPredicate};
+pub use intern::{Intern, StringInterner};
+pub use join::{JoinOptions, join_by_time};
+pub use merge::merge_by_time;
+pub use partition::{PartitionOptions, SeriesKey, partition_by_series};
+pub use prefetch::Prefetch;
+pub use resample::{Fill, Resample, ResampleOptions};
+pub use sketch::QuantileSketch;
+pub use stats::Summary;
+pub use take_time::{TakeUntilTime, TakeWhileTime};
+pub use tee::{TeeBranch, tee};
+pub use throttle::Throttle;
+pub use timezone::InTimezone;
+pub use validate::{ExpectedSchema, SchemaMismatch, Validate};
+
+/// Extension methods for streams of records.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::adapters::RecordStreamExt;
+///
+/// let mut stream = client.query_stream(query).await?.throttle(5_000);
+/// ```
+pub trait RecordStreamExt: Stream<Item = Result<FluxRecord>> + Sized {
+    /// Limit consumption to at most `records_per_sec` records per second.
+    ///
+    /// The underlying stream is not polled again until the budget used by the
+    /// previous record has elapsed, so the HTTP response is read no faster than
+    /// the given rate. A rate of zero disables throttling.
+    fn throttle(self, records_per_sec: u64) -> Throttle<Self, fn(&FluxRecord) -> u64> {
+        Throttle::new(self, records_per_sec, |_| 1)
+    }
+
+    /// Limit consumption to `units_per_sec`, where each record costs `cost(record)` units.
+    ///
+    /// Use this to throttle by an estimate of record size in bytes, or to give
+    /// some records more weight than others.
+    fn throttle_by<F>(self, units_per_sec: u64, cost: F) -> Throttle<Self, F>
+    where
+        F: FnMut(&FluxRecord) -> u64,
+    {
+        Throttle::new(self, units_per_sec, cost)
+    }
+
+    /// Yield records until one has a `_time` at or after `until`.
+    ///
+    /// The underlying stream is dropped as soon as the boundary is crossed,
+    /// which closes the connection instead of downloading the rest of the
+    /// response. Use this when the Flux `range()` cannot be bounded precisely.
+    /// Records without `_time` are passed through.
+    fn take_until_time(self, until: DateTime<FixedOffset>) -> TakeUntilTime<Self> {
+        TakeUntilTime::new(self, until)
+    }
+
+    /// Yield records while `predicate` returns true for their `_time`.
+    ///
+    /// Like [`take_until_time`](Self::take_until_time), the underlying stream is dropped
+    /// at the first record that fails the predicate.
+    fn take_while_time<P>(self, predicate: P) -> TakeWhileTime<Self, P>
+    where
+        P: FnMut(&DateTime<FixedOffset>) -> bool,
+    {
+        TakeWhileTime::new(self, predicate)
+    }
+
+    /// Align records to a fixed time grid, filling gaps within a tolerance.
+    ///
+    /// Each table is treated as one series and must be sorted by `_time`.
+    /// Grid points are multiples of the interval since the Unix epoch; points
+    /// that cannot be filled within the tolerance are omitted.
+    ///
+    /// ```ignore
+    /// use influxdb_stream::adapters::{Fill, ResampleOptions};
+    ///
+    /// let options = ResampleOptions::new(TimeDelta::seconds(10))
+    ///     .fill(Fill::Linear)
+    ///     .tolerance(TimeDelta::minutes(1));
+    /// let stream = client.query_stream(query).await?.resample(options);
+    /// ```
+    fn resample(self, options: ResampleOptions) -> Resample<Self> {
+        Resample::new(self, options)
+    }
+
+    /// Collect all records into a `Vec`, failing with
+    /// [`Error::LimitExceeded`](crate::Error::LimitExceeded) once `limit` is
+    /// exceeded.
+    ///
+    /// The stream is dropped as soon as the limit is crossed, which stops the
+    /// download.
+    fn try_collect_limited(
+        self,
+        limit: CollectLimit,
+    ) -> impl Future<Output = Result<Vec<FluxRecord>>> + Send
+    where
+        Self: Send,
+    {
+        async move { collect_limited(self, &limit).await }
+    }
+
+    /// Yield records in batches of up to `N`.
+    ///
+    /// Each batch holds the records available without waiting, so a batch is
+    /// smaller than `N` when the response arrives slowly; no record is
+    /// delayed to fill one. An error is yielded after the records read
+    /// before it.
+    ///
+    /// ```ignore
+    /// let mut chunks = client.query_stream(query).await?.batched::<64>();
+    /// while let Some(chunk) = chunks.next().await {
+    ///     for record in chunk? { /* ... */ }
+    /// }
+    /// ```
+    fn batched<const N: usize>(self) -> Batched<Self, N> {
+        Batched::new(self)
+    }
+
+    /// Share the allocations of repeated string values.
+    ///
+    /// Every string column except `_value` is interned through a pool owned by
+    /// the stream, turning its values into [`Value::SharedString`](crate::Value::SharedString).
+    /// This pays off when many records are kept in memory. The pool grows with
+    /// the number of distinct strings, so use [`intern_columns`](Self::intern_columns)
+    /// when other string columns have high cardinality.
+    fn intern_strings(self) -> Intern<Self> {
+        Intern::new(self, None)
+    }
+
+    /// Share the allocations of repeated string values in `columns` only.
+    fn intern_columns(self, columns: &[&str]) -> Intern<Self> {
+        Intern::new(self, Some(columns.iter().map(|c| c.to_string()).collect()))
+    }
+
+    /// Convert every record into `T`.
+    ///
+    /// Conversion failures are yielded as errors in place of the record.
+    fn typed<T: FromRecord>(self) -> Typed<Self, T> {
+        Typed::new(self)
+    }
+
+    /// Deserialize every record into `T` with serde; see [`de`](crate::de).
+    ///
+    /// Struct fields are matched to columns once per table, not per record.
+    /// Conversion failures are yielded as errors in place of the record.
+    fn deserialize<T: DeserializeOwned>(self) -> Deserialized<Self, T> {
+        Deserialized::new(self)
+    }
+
+    /// Keep only records whose columns equal all of the given `(column, value)` pairs.
+    ///
+    /// The columns must be part of the group key (tags, `_measurement` and
+    /// `_field` with the default grouping): the match is decided on the first
+    /// record of each table and reused for the rest of that table.
+    ///
+    /// ```ignore
+    /// let stream = client.query_stream(query).await?.filter_tags(&[("host", "server1")]);
+    /// ```
+    fn filter_tags(self, tags: &[(&str, &str)]) -> FilterGroupKey<Self, TagPredicate> {
+        FilterGroupKey::new(self, filter::tag_predicate(tags))
+    }
+
+    /// Keep only records of tables for which `predicate` returns true.
+    ///
+    /// The predicate is called once per table, with its first record, and must
+    /// only inspect group-key columns since the decision applies to every
+    /// record of that table.
+    fn filter_group_key<P>(self, predicate: P) -> FilterGroupKey<Self, P>
+    where
+        P: FnMut(&FluxRecord) -> bool,
+    {
+        FilterGroupKey::new(self, predicate)
+    }
+
+    /// Convert every time value of each record, including `_time`, to `tz`.
+    ///
+    /// The instants are unchanged; only their offset is. `tz` is any
+    /// [`chrono::TimeZone`], such as a [`chrono_tz::Tz`](https://docs.rs/chrono-tz)
+    /// or [`chrono::Local`], and daylight saving time is applied per record.
+    ///
+    /// ```ignore
+    /// let stream = client.query_stream(query).await?.in_timezone(chrono_tz::Europe::Paris);
+    /// ```
+    fn in_timezone<Tz: TimeZone>(self, tz: Tz) -> InTimezone<Self, Tz> {
+        InTimezone::new(self, tz)
+    }
+
+    /// Check each record against `schema`, failing on the first mismatch.
+    ///
+    /// The mismatching record is replaced by an
+    /// [`Error::SchemaMismatch`](crate::Error::SchemaMismatch) and the stream
+    /// ends after it.
+    ///
+    /// ```ignore
+    /// let schema = ExpectedSchema::new()
+    ///     .column("host", DataType::String)
+    ///     .column("_value", DataType::Double);
+    /// let stream = client.query_stream(query).await?.validate(schema);
+    /// ```
+    fn validate(self, schema: ExpectedSchema) -> Validate<Self, fn(&FluxRecord, &SchemaMismatch)> {
+        Validate::new(self, schema, None)
+    }
+
+    /// Check each record against `schema`, calling `report` on mismatches.
+    ///
+    /// Mismatching records are still yielded after being reported.
+    fn validate_with<F>(self, schema: ExpectedSchema, report: F) -> Validate<Self, F>
+    where
+        F: FnMut(&FluxRecord, &SchemaMismatch),
+    {
+        Validate::new(self, schema, Some(report))
+    }
+
+    /// Forward records to a bounded channel from a spawned task.
+    ///
+    /// This decouples reading the response from processing it, for example to
+    /// hand records to a worker task. See [`into_channel`] for details.
+    ///
+    /// ```ignore
+    /// let mut rx = client.query_stream(query).await?.into_channel(1024);
+    /// tokio::spawn(async move {
+    ///     while let Some(record) = rx.recv().await {
+    ///         process(record?);
+    ///     }
+    ///     Ok::<_, influxdb_stream::Error>(())
+    /// });
+    /// ```
+    fn into_channel(self, capacity: usize) -> mpsc::Receiver<Result<FluxRecord>>
+    where
+        Self: Send + 'static,
+    {
+        channel::into_channel(self, capacity)
+    }
+
+    /// Read up to `records` records ahead of the consumer on a spawned task.
+    ///
+    /// Parsing normally proceeds in lock-step with consumption; prefetching
+    /// lets the response keep flowing while the consumer is busy, absorbing
+    /// bursts of slow processing with bounded memory.
+    fn prefetch(self, records: usize) -> Prefetch
+    where
+        Self: Send + 'static,
+    {
+        Prefetch::new(self, records as u64, |_| 1)
+    }
+
+    /// Read ahead up to `budget` units, where each record costs `cost(record)` units.
+    ///
+    /// Use [`FluxRecord::estimated_size`] as the cost to bound the buffer in
+    /// bytes:
+    ///
+    /// ```ignore
+    /// let stream = client
+    ///     .query_stream(query)
+    ///     .await?
+    ///     .prefetch_by(16 * 1024 * 1024, |r| r.estimated_size() as u64);
+    /// ```
+    fn prefetch_by<F>(self, budget: u64, cost: F) -> Prefetch
+    where
+        Self: Send + 'static,
+        F: FnMut(&FluxRecord) -> u64 + Send + 'static,
+    {
+        Prefetch::new(self, budget, cost)
+    }
+
+    /// Duplicate records to `branches` bounded consumers.
+    ///
+    /// The slowest branch governs how fast the response is read. See [`tee()`]
+    /// for details.
+    ///
+    /// ```ignore
+    /// let mut branches = client.query_stream(query).await?.tee(2, 1024);
+    /// let (raw, stats) = (branches.remove(0), branches.remove(0));
+    /// let write = raw.write_csv_file("raw.csv", &CsvOptions::new());
+    /// let count = stats.try_fold(0u64, |n, _| async move { Ok(n + 1) });
+    /// let (written, counted) = tokio::try_join!(write, count)?;
+    /// ```
+    fn tee(self, branches: usize, capacity: usize) -> Vec<TeeBranch>
+    where
+        Self: Send + 'static,
+    {
+        tee::tee(self, branches, capacity)
+    }
+
+    /// Track acknowledged records so progress can be saved with `checkpointer`.
+    ///
+    /// See the [`checkpoint`](crate::checkpoint) module for details.
+    fn checkpointed<C>(self, checkpointer: C) -> Checkpointed<Self, C> {
+        Checkpointed::new(self, checkpointer)
+    }
+
+    /// Compute count, min, max, mean and standard deviation of `column` in one pass.
+    ///
+    /// See [`stats::stats`] for how non-numeric values are handled.
+    ///
+    /// ```ignore
+    /// let summary = client.query_stream(query).await?.stats("_value").await?;
+    /// println!("{} rows, mean {:?}", summary.count(), summary.mean());
+    /// ```
+    fn stats(self, column: &str) -> impl Future<Output = Result<Summary>> + Send
+    where
+        Self: Send,
+    {
+        let column = column.to_string();
+        async move { stats::stats(self, &column).await }
+    }
+
+    /// Compute a [`Summary`] of `column` per distinct value of the `group_by` columns.
+    fn stats_by(
+        self,
+        column: &str,
+        group_by: &[&str],
+    ) -> impl Future<Output = Result<BTreeMap<Vec<String>, Summary>>> + Send
+    where
+        Self: Send,
+    {
+        let column = column.to_string();
+        let group_by: Vec<String> = group_by.iter().map(|s| s.to_string()).collect();
+        async move {
+            let group_by: Vec<&str> = group_by.iter().map(String::as_str).collect();
+            stats::stats_by(self, &column, &group_by).await
+        }
+    }
+
+    /// Build a quantile sketch of `column` with the given relative accuracy.
+    ///
+    /// Percentiles over arbitrarily long ranges are estimated in bounded
+    /// memory; see [`QuantileSketch`].
+    ///
+    /// ```ignore
+    /// let sketch = client.query_stream(query).await?.sketch("_value", 0.01).await?;
+    /// println!("p95 {:?}, p99 {:?}", sketch.quantile(0.95), sketch.quantile(0.99));
+    /// ```
+    fn sketch(
+        self,
+        column: &str,
+        relative_accuracy: f64,
+    ) -> impl Future<Output = Result<QuantileSketch>> + Send
+    where
+        Self: Send,
+    {
+        let column = column.to_string();
+        async move { sketch::sketch(self, &column, relative_accuracy).await }
+    }
+
+    /// Write all records to `writer` as CSV and return the number of rows.
+    ///
+    /// See [`sink::write_csv`] for details.
+    fn write_csv<W>(
+        self,
+        writer: W,
+        options: &CsvOptions,
+    ) -> impl Future<Output = Result<u64>> + Send
+    where
+        Self: Send,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let options = options.clone();
+        async move { sink::write_csv(self, writer, &options).await }
+    }
+
+    /// Write all records to a newly created (or truncated) file as CSV.
+    ///
+    /// Returns the number of rows written.
+    fn write_csv_file(
+        self,
+        path: impl AsRef<Path>,
+        options: &CsvOptions,
+    ) -> impl Future<Output = Result<u64>> + Send
+    where
+        Self: Send,
+    {
+        let path = path.as_ref().to_path_buf();
+        let options = options.clone();
+        async move {
+            let file = tokio::fs::File::create(path).await?;
+            sink::write_csv(self, file, &options).await
+        }
+    }
+
+    /// Write all records to `writer` as line protocol and return the number
+    /// of lines.
+    ///
+    /// See [`sink::write_line_protocol`] for details.
+    fn write_line_protocol<W>(
+        self,
+        writer: W,
+        options: &LineProtocolOptions,
+    ) -> impl Future<Output = Result<u64>> + Send
+    where
+        Self: Send,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let options = options.clone();
+        async move { sink::write_line_protocol(self, writer, &options).await }
+    }
+
+    /// Write all records to `writer` in Arrow IPC (Feather) format.
+    ///
+    /// See [`sink::write_arrow_ipc`] for details. Requires the `arrow` feature.
+    #[cfg(feature = "arrow")]
+    fn write_arrow_ipc<W>(
+        self,
+        writer: W,
+        options: &sink::ArrowIpcOptions,
+    ) -> impl Future<Output = Result<u64>> + Send
+    where
+        Self: Send,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let options = options.clone();
+        async move { sink::write_arrow_ipc(self, writer, &options).await }
+    }
+
+    /// Write all records to Parquet files.
+    ///
+    /// See [`sink::write_parquet`] for details. Requires the `parquet` feature.
+    #[cfg(feature = "parquet")]
+    fn write_parquet(
+        self,
+        options: &sink::ParquetOptions,
+    ) -> impl Future<Output = Result<sink::ParquetSummary>> + Send
+    where
+        Self: Send,
+    {
+        let options = options.clone();
+        async move { sink::write_parquet(self, &options).await }
+    }
+}
+
+impl<S> RecordStreamExt for S where S: Stream<Item = Result<FluxRecord>> {}
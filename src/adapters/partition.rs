@@ -0,0 +1,241 @@
+//! Per-series processing of a record stream.
+
+use std::collections::{BTreeMap, HashMap};
+
+use futures::{Stream, StreamExt};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Default for [`PartitionOptions::max_series`].
+pub const DEFAULT_MAX_SERIES: usize = 10_000;
+
+/// Values of the key columns identifying a series.
+///
+/// Missing and null columns are `None`; other values are compared by their
+/// [lossy string form](crate::Value::to_string_lossy).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SeriesKey {
+    values: Vec<Option<String>>,
+}
+
+impl SeriesKey {
+    fn of(record: &FluxRecord, columns: &[String]) -> Self {
+        let values = columns
+            .iter()
+            .map(|c| {
+                record
+                    .get(c)
+                    .filter(|v| !v.is_null())
+                    .map(|v| v.to_string_lossy().into_owned())
+            })
+            .collect();
+        Self { values }
+    }
+
+    /// Get the key values, in the order of the key columns.
+    pub fn values(&self) -> &[Option<String>] {
+        &self.values
+    }
+
+    /// Get the value of the `i`-th key column as a string.
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.values.get(i)?.as_deref()
+    }
+}
+
+/// Options for [`partition_by_series`].
+#[derive(Clone, Debug)]
+pub struct PartitionOptions {
+    columns: Vec<String>,
+    max_series: usize,
+}
+
+impl PartitionOptions {
+    /// Identify series by the values of `columns`, typically the tags.
+    pub fn new<I, T>(columns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            columns: columns.into_iter().map(Into::into).collect(),
+            max_series: DEFAULT_MAX_SERIES,
+        }
+    }
+
+    /// Keep the state of at most `n` series (default: [`DEFAULT_MAX_SERIES`]).
+    ///
+    /// When a new series would exceed the limit, the state of the series that
+    /// received a record least recently is dropped. If that series shows up
+    /// again, it starts over from a fresh state.
+    pub fn max_series(mut self, n: usize) -> Self {
+        self.max_series = n.max(1);
+        self
+    }
+}
+
+/// Drive one state per series from a single stream.
+///
+/// For each record, `f` is called with the key of the record's series and
+/// that series' state, created by `init` on its first record. This suits
+/// per-sensor state machines, such as threshold alarms or counters, without
+/// running one query per series. Records of different series may interleave
+/// freely.
+///
+/// Returns the states of the series still held when the stream ends. The
+/// first error from the stream or from `f` aborts the operation.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::adapters::{PartitionOptions, partition_by_series};
+///
+/// // Count how often each sensor crossed 30 degrees.
+/// let options = PartitionOptions::new(["sensor"]);
+/// let alarms = partition_by_series(stream, &options, |_| (false, 0u32), |_, (hot, n), record| {
+///     let now_hot = record.get_double("_value").unwrap_or_default() > 30.0;
+///     *n += u32::from(now_hot && !*hot);
+///     *hot = now_hot;
+///     Ok(())
+/// })
+/// .await?;
+/// ```
+pub async fn partition_by_series<S, T, I, F>(
+    stream: S,
+    options: &PartitionOptions,
+    mut init: I,
+    mut f: F,
+) -> Result<HashMap<SeriesKey, T>>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    I: FnMut(&SeriesKey) -> T,
+    F: FnMut(&SeriesKey, &mut T, FluxRecord) -> Result<()>,
+{
+    let mut stream = std::pin::pin!(stream);
+    // State of each series, with the sequence number of its latest record,
+    // and the series by that number, oldest first.
+    let mut series: HashMap<SeriesKey, (u64, T)> = HashMap::new();
+    let mut recency: BTreeMap<u64, SeriesKey> = BTreeMap::new();
+    let mut seq = 0u64;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        let key = SeriesKey::of(&record, &options.columns);
+        seq += 1;
+
+        if !series.contains_key(&key) && series.len() >= options.max_series {
+            if let Some((_, oldest)) = recency.pop_first() {
+                series.remove(&oldest);
+            }
+        }
+
+        let entry = series
+            .entry(key.clone())
+            .or_insert_with(|| (seq, init(&key)));
+        recency.remove(&entry.0);
+        entry.0 = seq;
+        recency.insert(seq, key.clone());
+        f(&key, &mut entry.1, record)?;
+    }
+
+    Ok(series
+        .into_iter()
+        .map(|(key, (_, state))| (key, state))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::value::Value;
+    use futures::stream;
+
+    fn record(sensor: &str, value: i64) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("sensor".to_string(), Value::String(sensor.to_string()));
+        record.insert("_value".to_string(), Value::Long(value));
+        record
+    }
+
+    fn input(records: Vec<FluxRecord>) -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter(records.into_iter().map(Ok))
+    }
+
+    #[tokio::test]
+    async fn test_partition_by_series() {
+        let records = input(vec![
+            record("a", 1),
+            record("b", 10),
+            record("a", 2),
+            record("b", 20),
+            record("a", 3),
+        ]);
+        let options = PartitionOptions::new(["sensor"]);
+
+        let sums = partition_by_series(
+            records,
+            &options,
+            |_| 0,
+            |_, sum, record| {
+                *sum += record.get_long("_value").unwrap();
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(sums.len(), 2);
+        let a = SeriesKey::of(&record("a", 0), &["sensor".to_string()]);
+        assert_eq!(a.get(0), Some("a"));
+        assert_eq!(sums[&a], 6);
+    }
+
+    #[tokio::test]
+    async fn test_partition_evicts_least_recent() {
+        let records = input(vec![
+            record("a", 1),
+            record("b", 1),
+            record("a", 1),
+            record("c", 1),
+            record("b", 1),
+        ]);
+        let options = PartitionOptions::new(["sensor"]).max_series(2);
+
+        let counts = partition_by_series(
+            records,
+            &options,
+            |_| 0,
+            |_, n, _| {
+                *n += 1;
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+        // "c" evicted "b", and "b" evicted "a" when it came back.
+        let mut counts: Vec<_> = counts
+            .into_iter()
+            .map(|(k, n)| (k.get(0).unwrap().to_string(), n))
+            .collect();
+        counts.sort();
+        assert_eq!(counts, [("b".to_string(), 1), ("c".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_partition_callback_error() {
+        let records = input(vec![record("a", 1), record("a", 2)]);
+        let options = PartitionOptions::new(["sensor"]);
+
+        let result = partition_by_series(
+            records,
+            &options,
+            |_| (),
+            |_, _, _| Err(Error::Encode("stop".to_string())),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Encode(_))));
+    }
+}
@@ -0,0 +1,140 @@
+//! Read-ahead buffering of record streams.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{self, Either};
+use futures::{Stream, StreamExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Stream returned by [`RecordStreamExt::prefetch`](super::RecordStreamExt::prefetch)
+/// and [`RecordStreamExt::prefetch_by`](super::RecordStreamExt::prefetch_by).
+///
+/// Yields the records of the source stream, which is read ahead on a spawned
+/// task.
+#[derive(Debug)]
+pub struct Prefetch {
+    rx: mpsc::UnboundedReceiver<(Result<FluxRecord>, OwnedSemaphorePermit)>,
+}
+
+impl Prefetch {
+    /// Start reading `stream` ahead, buffering up to `budget` units where each
+    /// record costs `cost(record)` units.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new<S, F>(stream: S, budget: u64, cost: F) -> Self
+    where
+        S: Stream<Item = Result<FluxRecord>> + Send + 'static,
+        F: FnMut(&FluxRecord) -> u64 + Send + 'static,
+    {
+        let budget = budget.clamp(1, u32::MAX as u64) as u32;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(drive(stream, budget, cost, tx));
+        Self { rx }
+    }
+}
+
+impl Stream for Prefetch {
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Dropping the permit returns the record's share of the budget.
+        self.rx
+            .poll_recv(cx)
+            .map(|item| item.map(|(item, _permit)| item))
+    }
+}
+
+async fn drive<S, F>(
+    stream: S,
+    budget: u32,
+    mut cost: F,
+    tx: mpsc::UnboundedSender<(Result<FluxRecord>, OwnedSemaphorePermit)>,
+) where
+    S: Stream<Item = Result<FluxRecord>>,
+    F: FnMut(&FluxRecord) -> u64,
+{
+    let semaphore = Arc::new(Semaphore::new(budget as usize));
+    let mut stream = std::pin::pin!(stream);
+
+    loop {
+        let read = async {
+            let item = stream.next().await?;
+            // A record larger than the whole budget waits for an empty buffer.
+            let units = match &item {
+                Ok(record) => cost(record).clamp(1, budget as u64) as u32,
+                Err(_) => 1,
+            };
+            let permit = semaphore.clone().acquire_many_owned(units).await.ok()?;
+            Some((item, permit))
+        };
+        let closed = std::pin::pin!(tx.closed());
+
+        match future::select(closed, std::pin::pin!(read)).await {
+            Either::Right((Some(entry), _)) => {
+                if tx.send(entry).is_err() {
+                    break;
+                }
+            }
+            Either::Right((None, _)) | Either::Left(_) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn counted(reads: Arc<AtomicUsize>) -> impl Stream<Item = Result<FluxRecord>> + Send + 'static {
+        futures::stream::iter(0..100).map(move |i| {
+            reads.fetch_add(1, Ordering::SeqCst);
+            Ok(FluxRecord::new(i))
+        })
+    }
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_yields_all_records_in_order() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let tables: Vec<_> = Prefetch::new(counted(reads), 8, |_| 1)
+            .map(|r| r.unwrap().table)
+            .collect()
+            .await;
+        assert_eq!(tables, (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_bounded_by_budget() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let mut stream = Prefetch::new(counted(reads.clone()), 4, |_| 1);
+        settle().await;
+        // Four buffered records plus one waiting for room.
+        assert_eq!(reads.load(Ordering::SeqCst), 5);
+
+        stream.next().await.unwrap().unwrap();
+        settle().await;
+        assert_eq!(reads.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_by_cost() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let _stream = Prefetch::new(counted(reads.clone()), 10, |_| 5);
+        settle().await;
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,381 @@
+//! Alignment of irregular series to a fixed time grid.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::Stream;
+use ordered_float::OrderedFloat;
+use pin_project_lite::pin_project;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// How values are produced at grid points between two records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fill {
+    /// Repeat the previous record, if it is at most the tolerance old.
+    #[default]
+    Previous,
+    /// Interpolate linearly between the surrounding records, if they are at
+    /// most the tolerance apart. Non-numeric values fall back to the previous
+    /// record.
+    Linear,
+}
+
+/// Options for [`RecordStreamExt::resample`](super::RecordStreamExt::resample).
+#[derive(Clone, Debug)]
+pub struct ResampleOptions {
+    every: TimeDelta,
+    tolerance: TimeDelta,
+    fill: Fill,
+    column: String,
+}
+
+impl ResampleOptions {
+    /// Align records to a grid of `every`, starting at the Unix epoch.
+    ///
+    /// Defaults to forward-filling `_value` with a tolerance of one interval.
+    pub fn new(every: TimeDelta) -> Self {
+        let every = every.max(TimeDelta::nanoseconds(1));
+        Self {
+            every,
+            tolerance: every,
+            fill: Fill::Previous,
+            column: "_value".to_string(),
+        }
+    }
+
+    /// Set how grid points between records are filled.
+    pub fn fill(mut self, fill: Fill) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Set the maximum gap that is filled.
+    ///
+    /// Grid points that cannot be filled within the tolerance are omitted.
+    pub fn tolerance(mut self, tolerance: TimeDelta) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the column that is interpolated (default: `_value`).
+    pub fn column(mut self, column: impl Into<String>) -> Self {
+        self.column = column.into();
+        self
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::resample`](super::RecordStreamExt::resample).
+    pub struct Resample<S> {
+        #[pin]
+        stream: S,
+        options: ResampleOptions,
+        prev: Option<FluxRecord>,
+        next_grid: i128,
+        pending: VecDeque<Result<FluxRecord>>,
+        done: bool,
+    }
+}
+
+impl<S> Resample<S> {
+    pub(crate) fn new(stream: S, options: ResampleOptions) -> Self {
+        Self {
+            stream,
+            options,
+            prev: None,
+            next_grid: 0,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<S> Stream for Resample<S>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let mut aligner = Aligner {
+            options: this.options,
+            prev: this.prev,
+            next_grid: this.next_grid,
+            out: this.pending,
+        };
+
+        loop {
+            if let Some(item) = aligner.out.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(record)) => aligner.push(record),
+                Some(Err(e)) => aligner.out.push_back(Err(e)),
+                None => {
+                    aligner.finish_series();
+                    *this.done = true;
+                }
+            }
+        }
+    }
+}
+
+/// Per-series alignment state, borrowed from [`Resample`].
+struct Aligner<'a> {
+    options: &'a ResampleOptions,
+    prev: &'a mut Option<FluxRecord>,
+    next_grid: &'a mut i128,
+    out: &'a mut VecDeque<Result<FluxRecord>>,
+}
+
+impl Aligner<'_> {
+    fn push(&mut self, record: FluxRecord) {
+        let Some(time) = record.time().copied() else {
+            // Records without a timestamp cannot be aligned.
+            self.out.push_back(Ok(record));
+            return;
+        };
+        if self.prev.as_ref().is_some_and(|p| p.table != record.table) {
+            self.finish_series();
+        }
+
+        let t = nanos(&time);
+        let every = nanos_delta(self.options.every);
+        match self.prev.take() {
+            // First grid point at or after the first record
+            None => *self.next_grid = (t + every - 1).div_euclid(every) * every,
+            Some(prev) => {
+                let prev_t = nanos(prev.time().expect("previous record has _time"));
+                let tolerance = nanos_delta(self.options.tolerance);
+                while *self.next_grid < t {
+                    let gap = match self.options.fill {
+                        Fill::Previous => *self.next_grid - prev_t > tolerance,
+                        Fill::Linear => t - prev_t > tolerance,
+                    };
+                    if gap {
+                        // Nothing is filled before `t`: skip to the first grid
+                        // point at or after it.
+                        let behind = t - *self.next_grid;
+                        *self.next_grid += (behind + every - 1).div_euclid(every) * every;
+                        break;
+                    }
+                    if let Some(filled) = self.fill_between(&prev, prev_t, &record, t) {
+                        self.out.push_back(Ok(filled));
+                    }
+                    *self.next_grid += every;
+                }
+            }
+        }
+
+        if *self.next_grid == t {
+            self.out.push_back(Ok(record.clone()));
+            *self.next_grid += every;
+        }
+        *self.prev = Some(record);
+    }
+
+    /// Emit forward-filled grid points after the last record of a series.
+    fn finish_series(&mut self) {
+        let Some(prev) = self.prev.take() else {
+            return;
+        };
+        if self.options.fill != Fill::Previous {
+            return;
+        }
+        let prev_t = nanos(prev.time().expect("previous record has _time"));
+        let limit = prev_t + nanos_delta(self.options.tolerance);
+        while *self.next_grid <= limit {
+            self.out.push_back(Ok(at(&prev, *self.next_grid)));
+            *self.next_grid += nanos_delta(self.options.every);
+        }
+    }
+
+    fn fill_between(
+        &self,
+        prev: &FluxRecord,
+        prev_t: i128,
+        next: &FluxRecord,
+        next_t: i128,
+    ) -> Option<FluxRecord> {
+        let grid = *self.next_grid;
+        let tolerance = nanos_delta(self.options.tolerance);
+        match self.options.fill {
+            Fill::Previous => (grid - prev_t <= tolerance).then(|| at(prev, grid)),
+            Fill::Linear => {
+                if next_t - prev_t > tolerance {
+                    return None;
+                }
+                let mut filled = at(prev, grid);
+                let column = &self.options.column;
+                let (Some(v0), Some(v1)) = (
+                    prev.get(column).and_then(Value::as_f64),
+                    next.get(column).and_then(Value::as_f64),
+                ) else {
+                    return Some(filled);
+                };
+                let ratio = (grid - prev_t) as f64 / (next_t - prev_t) as f64;
+                let value = v0 + (v1 - v0) * ratio;
+                filled.insert(column.clone(), Value::Double(OrderedFloat(value)));
+                Some(filled)
+            }
+        }
+    }
+}
+
+/// Copy `record` with `_time` moved to the grid point.
+fn at(record: &FluxRecord, grid: i128) -> FluxRecord {
+    let offset = *record.time().expect("record has _time").offset();
+    let secs = grid.div_euclid(1_000_000_000) as i64;
+    let nsecs = grid.rem_euclid(1_000_000_000) as u32;
+    let time = DateTime::from_timestamp(secs, nsecs)
+        .expect("grid point is between two valid timestamps")
+        .with_timezone(&offset);
+
+    let mut record = record.clone();
+    record.insert("_time".to_string(), Value::TimeRFC(time));
+    record
+}
+
+fn nanos(time: &DateTime<FixedOffset>) -> i128 {
+    time.timestamp() as i128 * 1_000_000_000 + time.timestamp_subsec_nanos() as i128
+}
+
+fn nanos_delta(delta: TimeDelta) -> i128 {
+    delta.num_seconds() as i128 * 1_000_000_000 + delta.subsec_nanos() as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use futures::{TryStreamExt, stream};
+
+    fn record(table: i32, ts: &str, value: f64) -> FluxRecord {
+        let mut record = FluxRecord::new(table);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record.insert("_value".to_string(), Value::Double(OrderedFloat(value)));
+        record
+    }
+
+    async fn resample(records: Vec<FluxRecord>, options: ResampleOptions) -> Vec<(String, f64)> {
+        let out: Vec<_> = stream::iter(records.into_iter().map(Ok))
+            .resample(options)
+            .try_collect()
+            .await
+            .unwrap();
+        out.iter()
+            .map(|r| {
+                (
+                    r.time().unwrap().format("%H:%M:%S").to_string(),
+                    r.get_double("_value").unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn point(t: &str, v: f64) -> (String, f64) {
+        (t.to_string(), v)
+    }
+
+    #[tokio::test]
+    async fn test_resample_forward_fill() {
+        let records = vec![
+            record(0, "2023-11-14T12:00:05Z", 1.0),
+            record(0, "2023-11-14T12:00:20Z", 2.0),
+        ];
+        let options = ResampleOptions::new(TimeDelta::seconds(10));
+
+        assert_eq!(
+            resample(records, options).await,
+            vec![
+                point("12:00:10", 1.0),
+                point("12:00:20", 2.0),
+                point("12:00:30", 2.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resample_tolerance_leaves_gaps() {
+        let records = vec![
+            record(0, "2023-11-14T12:00:00Z", 1.0),
+            record(0, "2023-11-14T12:01:00Z", 2.0),
+        ];
+        let options =
+            ResampleOptions::new(TimeDelta::seconds(20)).tolerance(TimeDelta::seconds(20));
+
+        assert_eq!(
+            resample(records, options).await,
+            vec![
+                point("12:00:00", 1.0),
+                point("12:00:20", 1.0),
+                point("12:01:00", 2.0),
+                point("12:01:20", 2.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resample_skips_long_gaps() {
+        let records = vec![
+            record(0, "2023-11-14T12:00:00Z", 1.0),
+            record(0, "2023-11-24T12:00:00.001Z", 2.0),
+        ];
+        for fill in [Fill::Previous, Fill::Linear] {
+            let options = ResampleOptions::new(TimeDelta::milliseconds(1))
+                .fill(fill)
+                .tolerance(TimeDelta::zero());
+            let out = resample(records.clone(), options).await;
+            assert_eq!(out, vec![point("12:00:00", 1.0), point("12:00:00", 2.0)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resample_linear() {
+        let records = vec![
+            record(0, "2023-11-14T12:00:00Z", 0.0),
+            record(0, "2023-11-14T12:00:40Z", 4.0),
+        ];
+        let options = ResampleOptions::new(TimeDelta::seconds(10))
+            .fill(Fill::Linear)
+            .tolerance(TimeDelta::minutes(1));
+
+        assert_eq!(
+            resample(records, options).await,
+            vec![
+                point("12:00:00", 0.0),
+                point("12:00:10", 1.0),
+                point("12:00:20", 2.0),
+                point("12:00:30", 3.0),
+                point("12:00:40", 4.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resample_restarts_per_table() {
+        let records = vec![
+            record(0, "2023-11-14T12:00:00Z", 1.0),
+            record(1, "2023-11-14T12:00:00Z", 5.0),
+        ];
+        let options = ResampleOptions::new(TimeDelta::seconds(10)).tolerance(TimeDelta::zero());
+
+        assert_eq!(
+            resample(records, options).await,
+            vec![point("12:00:00", 1.0), point("12:00:00", 5.0)]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Approximate quantiles of a numeric column in bounded memory.
+
+use std::collections::BTreeMap;
+
+use futures::{Stream, StreamExt};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Maximum number of buckets kept per sign before the lowest are collapsed.
+const MAX_BUCKETS: usize = 1024;
+
+/// Quantile sketch with relative error guarantees (DDSketch).
+///
+/// Values are counted in logarithmically sized buckets, so any quantile is
+/// estimated within `relative_accuracy` of the true value regardless of how
+/// many values were added. Memory is bounded: at most 1024 buckets are kept
+/// per sign, each spanning a factor of `(1 + a) / (1 - a)` for accuracy `a`,
+/// so at 1% accuracy they cover about 8.9 orders of magnitude per sign; beyond
+/// that the buckets closest to zero are merged and lose accuracy first.
+///
+/// Sketches built over separate streams (for example time shards) can be
+/// combined with [`merge`](Self::merge).
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    relative_accuracy: f64,
+    gamma_ln: f64,
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl QuantileSketch {
+    /// Create a sketch whose quantiles are within `relative_accuracy` (e.g. `0.01`) of the true value.
+    ///
+    /// The accuracy is clamped to `[0.0001, 0.5]`.
+    pub fn new(relative_accuracy: f64) -> Self {
+        let relative_accuracy = relative_accuracy.clamp(0.0001, 0.5);
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zero: 0,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a value. NaN and infinite values are ignored.
+    pub fn push(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        if value.abs() < f64::MIN_POSITIVE {
+            self.zero += 1;
+            return;
+        }
+        let index = self.index(value.abs());
+        let store = if value > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *store.entry(index).or_default() += 1;
+        collapse(store);
+    }
+
+    /// Combine with a sketch built over other values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches were created with different accuracies.
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        assert_eq!(
+            self.relative_accuracy, other.relative_accuracy,
+            "cannot merge sketches with different accuracies"
+        );
+        for (store, theirs) in [
+            (&mut self.positive, &other.positive),
+            (&mut self.negative, &other.negative),
+        ] {
+            for (index, n) in theirs {
+                *store.entry(*index).or_default() += n;
+            }
+            collapse(store);
+        }
+        self.zero += other.zero;
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Get the relative accuracy of quantile estimates.
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    /// Get the number of values added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Get the smallest value added.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Get the largest value added.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Estimate the `q`-quantile, for `q` in `[0, 1]` (e.g. `0.99` for p99).
+    ///
+    /// Returns `None` if the sketch is empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let q = q.clamp(0.0, 1.0);
+        if q == 0.0 {
+            return Some(self.min);
+        }
+        if q == 1.0 {
+            return Some(self.max);
+        }
+
+        let rank = (q * (self.count - 1) as f64).floor() as u64;
+        let mut seen = 0u64;
+
+        // Negative values in ascending order are the largest magnitudes first.
+        for (index, n) in self.negative.iter().rev() {
+            seen += n;
+            if seen > rank {
+                return Some(self.clamp(-self.value(*index)));
+            }
+        }
+        seen += self.zero;
+        if seen > rank {
+            return Some(0.0);
+        }
+        for (index, n) in &self.positive {
+            seen += n;
+            if seen > rank {
+                return Some(self.clamp(self.value(*index)));
+            }
+        }
+        Some(self.max)
+    }
+
+    fn index(&self, magnitude: f64) -> i32 {
+        (magnitude.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    /// Representative value of a bucket, within the relative accuracy of every value in it.
+    fn value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (index as f64 * self.gamma_ln).exp() / (gamma + 1.0)
+    }
+
+    fn clamp(&self, value: f64) -> f64 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+impl Default for QuantileSketch {
+    /// Create a sketch with 1% relative accuracy.
+    fn default() -> Self {
+        Self::new(0.01)
+    }
+}
+
+/// Merge the buckets closest to zero until at most [`MAX_BUCKETS`] remain.
+fn collapse(store: &mut BTreeMap<i32, u64>) {
+    while store.len() > MAX_BUCKETS {
+        let (_, n) = store.pop_first().expect("store is not empty");
+        *store
+            .first_entry()
+            .expect("store has buckets left")
+            .get_mut() += n;
+    }
+}
+
+/// Build a [`QuantileSketch`] of `column` over all records of `stream`.
+///
+/// Numeric values (`Double`, `Long`, `UnsignedLong`) are added; other rows
+/// are ignored. The first error from the stream is returned.
+pub async fn sketch<S>(stream: S, column: &str, relative_accuracy: f64) -> Result<QuantileSketch>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut sketch = QuantileSketch::new(relative_accuracy);
+    while let Some(record) = stream.next().await {
+        if let Some(value) = record?.get(column).and_then(|v| v.as_f64()) {
+            sketch.push(value);
+        }
+    }
+    Ok(sketch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use futures::stream;
+
+    fn assert_close(actual: f64, expected: f64, accuracy: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * accuracy + 1e-9,
+            "{actual} not within {accuracy} of {expected}"
+        );
+    }
+
+    #[test]
+    fn test_quantiles_within_accuracy() {
+        let mut sketch = QuantileSketch::new(0.01);
+        for i in 1..=10_000 {
+            sketch.push(i as f64);
+        }
+
+        assert_eq!(sketch.count(), 10_000);
+        assert_eq!(sketch.quantile(0.0), Some(1.0));
+        assert_eq!(sketch.quantile(1.0), Some(10_000.0));
+        assert_close(sketch.quantile(0.5).unwrap(), 5_000.0, 0.01);
+        assert_close(sketch.quantile(0.95).unwrap(), 9_500.0, 0.01);
+        assert_close(sketch.quantile(0.99).unwrap(), 9_900.0, 0.01);
+    }
+
+    #[test]
+    fn test_quantiles_mixed_signs() {
+        let mut sketch = QuantileSketch::new(0.02);
+        for v in [-100.0, -10.0, 0.0, 10.0, 100.0] {
+            sketch.push(v);
+        }
+        assert_close(sketch.quantile(0.25).unwrap(), -10.0, 0.02);
+        assert_eq!(sketch.quantile(0.5), Some(0.0));
+        assert_close(sketch.quantile(0.75).unwrap(), 10.0, 0.02);
+    }
+
+    #[test]
+    fn test_bucket_count_bounded() {
+        let mut sketch = QuantileSketch::new(0.0001);
+        for i in 0..200_000 {
+            sketch.push(1.0 + i as f64);
+        }
+        assert!(sketch.positive.len() <= MAX_BUCKETS);
+        assert_close(sketch.quantile(0.99).unwrap(), 198_000.0, 0.0001);
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut a, mut b, mut all) = (
+            QuantileSketch::default(),
+            QuantileSketch::default(),
+            QuantileSketch::default(),
+        );
+        for i in 0..1000 {
+            let v = (i * 7 % 1000) as f64;
+            if i % 2 == 0 {
+                a.push(v)
+            } else {
+                b.push(v)
+            }
+            all.push(v);
+        }
+        a.merge(&b);
+        assert_eq!(a.count(), all.count());
+        assert_eq!(a.quantile(0.9), all.quantile(0.9));
+    }
+
+    #[tokio::test]
+    async fn test_sketch_stream() {
+        let input = stream::iter((1..=100).map(|i| {
+            let mut record = FluxRecord::new(0);
+            record.insert("_value".to_string(), Value::Long(i));
+            Ok(record)
+        }));
+        let sketch = sketch(input, "_value", 0.01).await.unwrap();
+        assert_eq!(sketch.count(), 100);
+        assert_close(sketch.quantile(0.5).unwrap(), 50.0, 0.01);
+        assert_eq!(QuantileSketch::default().quantile(0.5), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Single-pass summary statistics over a numeric column.
+
+use std::collections::BTreeMap;
+
+use futures::{Stream, StreamExt};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Count, extrema, mean and standard deviation of a series of values.
+///
+/// Values are accumulated with Welford's algorithm, which uses constant
+/// memory and stays numerically stable over long series.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    count: u64,
+    skipped: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Summary {
+    /// Create an empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value. NaN values are counted as skipped.
+    pub fn push(&mut self, value: f64) {
+        if value.is_nan() {
+            self.skipped += 1;
+            return;
+        }
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Record a row whose value was missing or not numeric.
+    pub fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Combine with a summary computed over other values.
+    pub fn merge(&mut self, other: &Summary) {
+        if other.count == 0 {
+            self.skipped += other.skipped;
+            return;
+        }
+        if self.count == 0 {
+            let skipped = self.skipped;
+            *self = *other;
+            self.skipped += skipped;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+        self.skipped += other.skipped;
+    }
+
+    /// Get the number of values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Get the number of rows skipped because the value was missing, not numeric or NaN.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Get the smallest value.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Get the largest value.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Get the arithmetic mean.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Get the sample variance (with Bessel's correction).
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    /// Get the sample standard deviation.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+/// Compute a [`Summary`] of `column` over all records of `stream`.
+///
+/// `Double`, `Long` and `UnsignedLong` values are included; rows where the
+/// column is missing or has another type are counted as skipped. The first
+/// error from the stream is returned.
+pub async fn stats<S>(stream: S, column: &str) -> Result<Summary>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut summary = Summary::new();
+    while let Some(record) = stream.next().await {
+        push_column(&mut summary, &record?, column);
+    }
+    Ok(summary)
+}
+
+/// Compute a [`Summary`] of `column` for each distinct combination of `group_by` columns.
+///
+/// Keys list the values of the `group_by` columns in order, with an empty
+/// string for a missing column. Pass the tags of the group key to get one
+/// summary per series.
+pub async fn stats_by<S>(
+    stream: S,
+    column: &str,
+    group_by: &[&str],
+) -> Result<BTreeMap<Vec<String>, Summary>>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut summaries: BTreeMap<Vec<String>, Summary> = BTreeMap::new();
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        let key: Vec<String> = group_by
+            .iter()
+            .map(|name| record.get(name).map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+        push_column(summaries.entry(key).or_default(), &record, column);
+    }
+    Ok(summaries)
+}
+
+fn push_column(summary: &mut Summary, record: &FluxRecord, column: &str) {
+    match record.get(column).and_then(|v| v.as_f64()) {
+        Some(value) => summary.push(value),
+        None => summary.skip(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use futures::stream;
+    use ordered_float::OrderedFloat;
+
+    fn record(host: &str, value: Value) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("host".to_string(), Value::String(host.to_string()));
+        record.insert("_value".to_string(), value);
+        record
+    }
+
+    fn double(v: f64) -> Value {
+        Value::Double(OrderedFloat::from(v))
+    }
+
+    #[test]
+    fn test_summary_values() {
+        let mut summary = Summary::new();
+        for v in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            summary.push(v);
+        }
+        assert_eq!(summary.count(), 8);
+        assert_eq!(summary.min(), Some(2.0));
+        assert_eq!(summary.max(), Some(9.0));
+        assert_eq!(summary.mean(), Some(5.0));
+        assert!((summary.variance().unwrap() - 32.0 / 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_summary_empty() {
+        let summary = Summary::new();
+        assert_eq!(summary.count(), 0);
+        assert_eq!(summary.min(), None);
+        assert_eq!(summary.mean(), None);
+        assert_eq!(summary.stddev(), None);
+    }
+
+    #[test]
+    fn test_summary_merge_matches_single_pass() {
+        let values = [1.0, 3.5, -2.0, 8.0, 0.25, 6.0];
+        let mut all = Summary::new();
+        let (mut left, mut right) = (Summary::new(), Summary::new());
+        for (i, v) in values.iter().enumerate() {
+            all.push(*v);
+            if i < 2 { left.push(*v) } else { right.push(*v) }
+        }
+        left.merge(&right);
+
+        assert_eq!(left.count(), all.count());
+        assert_eq!(left.min(), all.min());
+        assert_eq!(left.max(), all.max());
+        assert!((left.mean().unwrap() - all.mean().unwrap()).abs() < 1e-12);
+        assert!((left.variance().unwrap() - all.variance().unwrap()).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_stats_skips_non_numeric() {
+        let input = stream::iter(vec![
+            Ok(record("a", double(1.0))),
+            Ok(record("a", Value::Long(3))),
+            Ok(record("a", Value::String("n/a".to_string()))),
+            Ok(FluxRecord::new(0)),
+        ]);
+        let summary = stats(input, "_value").await.unwrap();
+        assert_eq!(summary.count(), 2);
+        assert_eq!(summary.skipped(), 2);
+        assert_eq!(summary.mean(), Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_stats_by_group() {
+        let input = stream::iter(vec![
+            Ok(record("a", double(1.0))),
+            Ok(record("b", double(10.0))),
+            Ok(record("a", double(3.0))),
+        ]);
+        let summaries = stats_by(input, "_value", &["host"]).await.unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[&vec!["a".to_string()]].mean(), Some(2.0));
+        assert_eq!(summaries[&vec!["b".to_string()]].count(), 1);
+    }
+}
@@ -0,0 +1,186 @@
+//! Early termination at a timestamp boundary.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use chrono::{DateTime, FixedOffset};
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::take_until_time`](super::RecordStreamExt::take_until_time).
+    pub struct TakeUntilTime<S> {
+        #[pin]
+        stream: Option<S>,
+        until: DateTime<FixedOffset>,
+    }
+}
+
+impl<S> TakeUntilTime<S> {
+    pub(crate) fn new(stream: S, until: DateTime<FixedOffset>) -> Self {
+        Self {
+            stream: Some(stream),
+            until,
+        }
+    }
+}
+
+impl<S> Stream for TakeUntilTime<S>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let until = *this.until;
+        poll_while(this.stream, cx, |t| *t < until)
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::take_while_time`](super::RecordStreamExt::take_while_time).
+    pub struct TakeWhileTime<S, P> {
+        #[pin]
+        stream: Option<S>,
+        predicate: P,
+    }
+}
+
+impl<S, P> TakeWhileTime<S, P> {
+    pub(crate) fn new(stream: S, predicate: P) -> Self {
+        Self {
+            stream: Some(stream),
+            predicate,
+        }
+    }
+}
+
+impl<S, P> Stream for TakeWhileTime<S, P>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    P: FnMut(&DateTime<FixedOffset>) -> bool,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        poll_while(this.stream, cx, this.predicate)
+    }
+}
+
+/// Poll `stream` until a record's `_time` fails `keep`, then drop the stream.
+///
+/// Dropping the inner stream closes the HTTP response, so nothing past the
+/// boundary is downloaded. Records without `_time` and errors pass through.
+fn poll_while<S>(
+    mut stream: Pin<&mut Option<S>>,
+    cx: &mut Context<'_>,
+    mut keep: impl FnMut(&DateTime<FixedOffset>) -> bool,
+) -> Poll<Option<Result<FluxRecord>>>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let Some(inner) = stream.as_mut().as_pin_mut() else {
+        return Poll::Ready(None);
+    };
+
+    let item = ready!(inner.poll_next(cx));
+    if let Some(Ok(record)) = &item {
+        if record.time().is_some_and(|t| !keep(t)) {
+            stream.set(None);
+            return Poll::Ready(None);
+        }
+    }
+    if item.is_none() {
+        stream.set(None);
+    }
+
+    Poll::Ready(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::RecordStreamExt;
+    use crate::error::Result;
+    use crate::types::FluxRecord;
+    use crate::value::Value;
+    use chrono::DateTime;
+    use futures::{Stream, StreamExt, TryStreamExt, stream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn record(ts: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record
+    }
+
+    fn minutes(n: u32, pulled: Arc<AtomicUsize>) -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter((0..n).map(move |i| {
+            pulled.fetch_add(1, Ordering::SeqCst);
+            Ok(record(&format!("2023-11-14T12:{:02}:00Z", i)))
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_take_until_time_stops_at_boundary() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let until = DateTime::parse_from_rfc3339("2023-11-14T12:03:00Z").unwrap();
+
+        let records: Vec<_> = minutes(10, pulled.clone())
+            .take_until_time(until)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        // Only the first record past the boundary is read.
+        assert_eq!(pulled.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_take_while_time() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let limit = DateTime::parse_from_rfc3339("2023-11-14T12:05:00Z").unwrap();
+
+        let records: Vec<_> = minutes(10, pulled)
+            .take_while_time(|t| *t <= limit)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_take_until_time_passes_untimed_records() {
+        let input = stream::iter(vec![
+            Ok(FluxRecord::new(0)),
+            Ok(record("2023-11-14T12:00:00Z")),
+            Ok(record("2023-11-14T13:00:00Z")),
+            Ok(FluxRecord::new(1)),
+        ]);
+        let until = DateTime::parse_from_rfc3339("2023-11-14T12:30:00Z").unwrap();
+
+        let records: Vec<_> = input.take_until_time(until).try_collect().await.unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_take_until_time_fused_after_end() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let until = DateTime::parse_from_rfc3339("2023-11-14T12:01:00Z").unwrap();
+        let mut s = Box::pin(minutes(10, pulled).take_until_time(until));
+
+        assert!(s.next().await.is_some());
+        assert!(s.next().await.is_none());
+        assert!(s.next().await.is_none());
+    }
+}
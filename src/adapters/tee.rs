@@ -0,0 +1,142 @@
+//! Fan-out of one record stream to several consumers.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// One of the output streams returned by [`tee`].
+///
+/// Each branch receives a copy of every record of the source stream.
+#[derive(Debug)]
+pub struct TeeBranch {
+    rx: mpsc::Receiver<Result<FluxRecord>>,
+}
+
+impl Stream for TeeBranch {
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Duplicate `stream` into `branches` streams, each buffering up to `capacity` records.
+///
+/// The source is read on a spawned task that only pulls the next record once
+/// every branch has room for it, so the slowest consumer governs the read
+/// rate and memory stays bounded. A branch that is dropped no longer holds
+/// the others back; once all branches are dropped the source is dropped too.
+///
+/// Errors cannot be cloned, so an error from the source is delivered to every
+/// branch as [`Error::Shared`].
+///
+/// # Panics
+///
+/// Panics if called outside of a Tokio runtime.
+pub fn tee<S>(stream: S, branches: usize, capacity: usize) -> Vec<TeeBranch>
+where
+    S: Stream<Item = Result<FluxRecord>> + Send + 'static,
+{
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..branches.max(1))
+        .map(|_| mpsc::channel(capacity.max(1)))
+        .unzip();
+    tokio::spawn(drive(stream, senders));
+    receivers.into_iter().map(|rx| TeeBranch { rx }).collect()
+}
+
+async fn drive<S>(stream: S, mut senders: Vec<mpsc::Sender<Result<FluxRecord>>>)
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    loop {
+        // Reserve a slot in every live branch before reading the next record.
+        let mut permits = Vec::with_capacity(senders.len());
+        let mut closed = Vec::new();
+        for (i, tx) in senders.iter().enumerate() {
+            match tx.reserve().await {
+                Ok(permit) => permits.push(permit),
+                Err(_) => closed.push(i),
+            }
+        }
+        if permits.is_empty() {
+            break;
+        }
+
+        let Some(item) = stream.next().await else {
+            break;
+        };
+        let item = item.map_err(Arc::new);
+        for permit in permits {
+            permit.send(match &item {
+                Ok(record) => Ok(record.clone()),
+                Err(e) => Err(Error::Shared(e.clone())),
+            });
+        }
+
+        for i in closed.into_iter().rev() {
+            senders.swap_remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    fn records(n: i32) -> impl Stream<Item = Result<FluxRecord>> + Send + 'static {
+        stream::iter((0..n).map(|i| Ok(FluxRecord::new(i))))
+    }
+
+    #[tokio::test]
+    async fn test_tee_duplicates_records() {
+        let branches = tee(records(5), 3, 2);
+        assert_eq!(branches.len(), 3);
+
+        let tables = futures::future::join_all(
+            branches
+                .into_iter()
+                .map(|b| async { b.map(|r| r.unwrap().table).collect::<Vec<_>>().await }),
+        )
+        .await;
+        for t in tables {
+            assert_eq!(t, vec![0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tee_dropped_branch_does_not_block() {
+        let mut branches = tee(records(10), 2, 1);
+        drop(branches.pop());
+
+        let tables: Vec<_> = branches
+            .pop()
+            .unwrap()
+            .map(|r| r.unwrap().table)
+            .collect()
+            .await;
+        assert_eq!(tables.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_tee_shares_errors() {
+        let input = stream::iter(vec![Ok(FluxRecord::new(0)), Err(Error::Csv("boom".into()))]);
+        let branches = tee(input, 2, 4);
+
+        for branch in branches {
+            let items: Vec<_> = branch.collect().await;
+            assert_eq!(items.len(), 2);
+            match &items[1] {
+                Err(Error::Shared(e)) => assert!(matches!(**e, Error::Csv(_))),
+                other => panic!("unexpected item: {:?}", other),
+            }
+        }
+    }
+}
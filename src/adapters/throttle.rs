@@ -0,0 +1,149 @@
+//! Rate limiting of record consumption.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+use tokio::time::{Instant, Sleep, sleep_until};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Schedules work so that at most `per_sec` units are consumed per second.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_sec: u64,
+    next: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a limiter. A rate of zero disables limiting.
+    pub(crate) fn new(per_sec: u64) -> Self {
+        Self {
+            per_sec,
+            next: None,
+        }
+    }
+
+    /// Account for `cost` units consumed now.
+    ///
+    /// Returns the instant before which no further units should be consumed,
+    /// or `None` if the caller may continue immediately.
+    pub(crate) fn consume(&mut self, cost: u64) -> Option<Instant> {
+        if self.per_sec == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let start = self.next.map_or(now, |next| next.max(now));
+        let next = start + Duration::from_secs_f64(cost as f64 / self.per_sec as f64);
+        self.next = Some(next);
+
+        (next > now).then_some(next)
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::throttle`](super::RecordStreamExt::throttle)
+    /// and [`RecordStreamExt::throttle_by`](super::RecordStreamExt::throttle_by).
+    pub struct Throttle<S, F> {
+        #[pin]
+        stream: S,
+        cost: F,
+        limiter: RateLimiter,
+        delay: Option<Pin<Box<Sleep>>>,
+    }
+}
+
+impl<S, F> Throttle<S, F> {
+    pub(crate) fn new(stream: S, per_sec: u64, cost: F) -> Self {
+        Self {
+            stream,
+            cost,
+            limiter: RateLimiter::new(per_sec),
+            delay: None,
+        }
+    }
+}
+
+impl<S, F> Stream for Throttle<S, F>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    F: FnMut(&FluxRecord) -> u64,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        // Wait out the budget used by the previous record before reading on.
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            *this.delay = None;
+        }
+
+        let item = ready!(this.stream.poll_next(cx));
+        if let Some(Ok(record)) = &item {
+            let cost = (this.cost)(record);
+            *this.delay = this.limiter.consume(cost).map(|t| Box::pin(sleep_until(t)));
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use futures::{StreamExt, stream};
+
+    fn records(n: usize) -> impl Stream<Item = Result<FluxRecord>> {
+        stream::iter((0..n).map(|i| Ok(FluxRecord::new(i as i32))))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_records_per_sec() {
+        let start = Instant::now();
+        let items: Vec<_> = records(5).throttle(10).collect().await;
+
+        assert_eq!(items.len(), 5);
+        // One 100ms budget per record; the last one is waited out before EOF is read.
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_by_cost() {
+        let start = Instant::now();
+        let items: Vec<_> = records(3).throttle_by(1000, |_| 500).collect().await;
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_zero_disables() {
+        let start = Instant::now();
+        let items: Vec<_> = records(100).throttle(0).collect().await;
+
+        assert_eq!(items.len(), 100);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rate_limiter_spacing() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut limiter = RateLimiter::new(4);
+            let now = Instant::now();
+            assert_eq!(limiter.consume(1), Some(now + Duration::from_millis(250)));
+            assert_eq!(limiter.consume(2), Some(now + Duration::from_millis(750)));
+        });
+    }
+}
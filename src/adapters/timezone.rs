@@ -0,0 +1,100 @@
+//! Conversion of record times to a time zone.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use chrono::TimeZone;
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::in_timezone`](super::RecordStreamExt::in_timezone).
+    pub struct InTimezone<S, Tz> {
+        #[pin]
+        stream: S,
+        tz: Tz,
+    }
+}
+
+impl<S, Tz> InTimezone<S, Tz> {
+    pub(crate) fn new(stream: S, tz: Tz) -> Self {
+        Self { stream, tz }
+    }
+}
+
+impl<S, Tz> Stream for InTimezone<S, Tz>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    Tz: TimeZone,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut record = match ready!(this.stream.poll_next(cx)) {
+            Some(Ok(record)) => record,
+            other => return Poll::Ready(other),
+        };
+        for value in record.values_mut() {
+            if let Value::TimeRFC(time) = value {
+                *time = time.with_timezone(this.tz).fixed_offset();
+            }
+        }
+        Poll::Ready(Some(Ok(record)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::RecordStreamExt;
+    use crate::types::FluxRecord;
+    use crate::value::Value;
+    use chrono::{DateTime, FixedOffset};
+    use futures::{StreamExt, stream};
+
+    #[tokio::test]
+    async fn test_in_timezone_converts_time_columns() {
+        let utc = DateTime::parse_from_rfc3339("2023-11-14T22:30:00Z").unwrap();
+        let mut record = FluxRecord::new(0);
+        record.insert("_time", Value::TimeRFC(utc));
+        record.insert("_stop", Value::TimeRFC(utc));
+        record.insert("host", Value::String("a".to_string()));
+
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let records: Vec<_> = stream::iter(vec![Ok(record)])
+            .in_timezone(tokyo)
+            .collect()
+            .await;
+        let record = records[0].as_ref().unwrap();
+
+        let time = record.time().unwrap();
+        assert_eq!(time.to_rfc3339(), "2023-11-15T07:30:00+09:00");
+        assert_eq!(*time, utc);
+        assert_eq!(record.get("_stop"), Some(&Value::TimeRFC(*time)));
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[tokio::test]
+    async fn test_in_timezone_follows_dst() {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time",
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-07-01T12:00:00Z").unwrap()),
+        );
+
+        let records: Vec<_> = stream::iter(vec![Ok(record)])
+            .in_timezone(chrono_tz::Europe::Paris)
+            .collect()
+            .await;
+        let time = records[0].as_ref().unwrap().time().unwrap();
+        assert_eq!(time.to_rfc3339(), "2023-07-01T14:00:00+02:00");
+    }
+}
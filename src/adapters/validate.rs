@@ -0,0 +1,283 @@
+//! Validation of records against an expected schema.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::{Error, Result};
+use crate::types::{DataType, FluxRecord};
+
+/// Columns and types records are expected to have.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::DataType;
+/// use influxdb_stream::adapters::{ExpectedSchema, RecordStreamExt};
+///
+/// let schema = ExpectedSchema::new()
+///     .column("_time", DataType::TimeRFC)
+///     .column("host", DataType::String)
+///     .column("_value", DataType::Double);
+/// let stream = client.query_stream(query).await?.validate(schema);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExpectedSchema {
+    columns: Vec<(String, DataType)>,
+    allow_extra: bool,
+    allow_null: bool,
+}
+
+impl ExpectedSchema {
+    /// Create a schema with no columns that allows extra columns and nulls.
+    pub fn new() -> Self {
+        Self {
+            columns: Vec::new(),
+            allow_extra: true,
+            allow_null: true,
+        }
+    }
+
+    /// Expect column `name` with values of type `data_type`.
+    pub fn column(mut self, name: impl Into<String>, data_type: DataType) -> Self {
+        self.columns.push((name.into(), data_type));
+        self
+    }
+
+    /// Accept columns that are not part of the schema (default: `true`).
+    pub fn allow_extra(mut self, allow: bool) -> Self {
+        self.allow_extra = allow;
+        self
+    }
+
+    /// Accept null values in expected columns (default: `true`).
+    pub fn allow_null(mut self, allow: bool) -> Self {
+        self.allow_null = allow;
+        self
+    }
+
+    /// Check `record`, returning the first mismatch found.
+    pub fn check(&self, record: &FluxRecord) -> Option<SchemaMismatch> {
+        for (name, expected) in &self.columns {
+            let Some(value) = record.get(name) else {
+                return Some(SchemaMismatch::MissingColumn(name.clone()));
+            };
+            match value.data_type() {
+                Some(found) if found != *expected => {
+                    return Some(SchemaMismatch::WrongType {
+                        column: name.clone(),
+                        expected: *expected,
+                        found,
+                    });
+                }
+                None if !self.allow_null => {
+                    return Some(SchemaMismatch::NullValue(name.clone()));
+                }
+                _ => {}
+            }
+        }
+        if !self.allow_extra {
+            let extra = record
+                .columns()
+                .find(|c| !self.columns.iter().any(|(name, _)| name == c));
+            if let Some(extra) = extra {
+                return Some(SchemaMismatch::ExtraColumn(extra.to_string()));
+            }
+        }
+        None
+    }
+}
+
+impl Default for ExpectedSchema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Difference between a record and an [`ExpectedSchema`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaMismatch {
+    /// An expected column is missing.
+    MissingColumn(String),
+    /// A column holds values of another type.
+    WrongType {
+        /// Column name.
+        column: String,
+        /// Type required by the schema.
+        expected: DataType,
+        /// Type found in the record.
+        found: DataType,
+    },
+    /// An expected column is null, and nulls are not allowed.
+    NullValue(String),
+    /// A column is not part of the schema, and extra columns are not allowed.
+    ExtraColumn(String),
+}
+
+impl std::fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingColumn(c) => write!(f, "missing column '{}'", c),
+            Self::WrongType {
+                column,
+                expected,
+                found,
+            } => write!(f, "column '{}' is {}, expected {}", column, found, expected),
+            Self::NullValue(c) => write!(f, "column '{}' is null", c),
+            Self::ExtraColumn(c) => write!(f, "unexpected column '{}'", c),
+        }
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::validate`](super::RecordStreamExt::validate)
+    /// and [`RecordStreamExt::validate_with`](super::RecordStreamExt::validate_with).
+    pub struct Validate<S, F> {
+        #[pin]
+        stream: S,
+        schema: ExpectedSchema,
+        report: Option<F>,
+        failed: bool,
+    }
+}
+
+impl<S, F> Validate<S, F> {
+    pub(crate) fn new(stream: S, schema: ExpectedSchema, report: Option<F>) -> Self {
+        Self {
+            stream,
+            schema,
+            report,
+            failed: false,
+        }
+    }
+}
+
+impl<S, F> Stream for Validate<S, F>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    F: FnMut(&FluxRecord, &SchemaMismatch),
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.failed {
+            return Poll::Ready(None);
+        }
+
+        let record = match ready!(this.stream.poll_next(cx)) {
+            Some(Ok(record)) => record,
+            other => return Poll::Ready(other),
+        };
+        let Some(mismatch) = this.schema.check(&record) else {
+            return Poll::Ready(Some(Ok(record)));
+        };
+        match this.report {
+            Some(report) => {
+                report(&record, &mismatch);
+                Poll::Ready(Some(Ok(record)))
+            }
+            None => {
+                *this.failed = true;
+                Poll::Ready(Some(Err(Error::SchemaMismatch(format!(
+                    "table {}: {}",
+                    record.table, mismatch
+                )))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use crate::value::Value;
+    use futures::{StreamExt, stream};
+    use ordered_float::OrderedFloat;
+
+    fn record(value: Value) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("host".to_string(), Value::String("a".to_string()));
+        record.insert("_value".to_string(), value);
+        record
+    }
+
+    fn schema() -> ExpectedSchema {
+        ExpectedSchema::new()
+            .column("host", DataType::String)
+            .column("_value", DataType::Double)
+    }
+
+    #[test]
+    fn test_check() {
+        let schema = schema();
+        assert_eq!(
+            schema.check(&record(Value::Double(OrderedFloat(1.0)))),
+            None
+        );
+        assert_eq!(schema.check(&record(Value::Null)), None);
+        assert_eq!(
+            schema.check(&record(Value::Long(1))),
+            Some(SchemaMismatch::WrongType {
+                column: "_value".to_string(),
+                expected: DataType::Double,
+                found: DataType::Long,
+            })
+        );
+        assert_eq!(
+            schema.clone().allow_null(false).check(&record(Value::Null)),
+            Some(SchemaMismatch::NullValue("_value".to_string()))
+        );
+        assert_eq!(
+            schema.check(&FluxRecord::new(0)),
+            Some(SchemaMismatch::MissingColumn("host".to_string()))
+        );
+
+        let mut extra = record(Value::Null);
+        extra.insert("region".to_string(), Value::String("eu".to_string()));
+        assert_eq!(
+            schema.allow_extra(false).check(&extra),
+            Some(SchemaMismatch::ExtraColumn("region".to_string()))
+        );
+        assert_eq!(ExpectedSchema::default().check(&extra), None);
+    }
+
+    #[tokio::test]
+    async fn test_validate_fails_fast() {
+        let input = stream::iter(vec![
+            Ok(record(Value::Double(OrderedFloat(1.0)))),
+            Ok(record(Value::Long(2))),
+            Ok(record(Value::Double(OrderedFloat(3.0)))),
+        ]);
+
+        let items: Vec<_> = input.validate(schema()).collect().await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        match &items[1] {
+            Err(e @ Error::SchemaMismatch(_)) => assert_eq!(
+                e.to_string(),
+                "Schema mismatch: table 0: column '_value' is long, expected double"
+            ),
+            other => panic!("unexpected item: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_with_reports() {
+        let input = stream::iter(vec![
+            Ok(record(Value::Long(1))),
+            Ok(record(Value::Double(OrderedFloat(2.0)))),
+        ]);
+        let mut mismatches = Vec::new();
+
+        let count = input
+            .validate_with(schema(), |_, mismatch| mismatches.push(mismatch.clone()))
+            .count()
+            .await;
+        assert_eq!(count, 2);
+        assert_eq!(mismatches.len(), 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Replay of annotated CSV stored in object storage.
+//!
+//! Requires the `object-store` feature. Query results exported to S3, GCS,
+//! Azure Blob Storage or any other [`ObjectStore`] are parsed while they
+//! download, exactly like a live response, so archived results can be
+//! replayed without fetching whole files first.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::sync::Arc;
+//! use futures::StreamExt;
+//! use influxdb_stream::archive;
+//! use object_store::aws::AmazonS3Builder;
+//! use object_store::path::Path;
+//!
+//! let store = Arc::new(AmazonS3Builder::from_env().with_bucket_name("exports").build()?);
+//! let mut records = archive::stream_object(store, Path::from("2023/11/14/cpu.csv"));
+//! while let Some(record) = records.next().await {
+//!     println!("{:?}", record?);
+//! }
+//! ```
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_stream::stream;
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use object_store::path::Path;
+use object_store::{GetOptions, ObjectStore};
+use tokio_util::io::StreamReader;
+
+use crate::client::RecordStream;
+use crate::error::Result;
+use crate::parser::AnnotatedCsvParser;
+
+/// Reader over the bytes of a stored object.
+pub type ObjectReader =
+    StreamReader<Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>, Bytes>;
+
+/// Open the object at `location` and return a parser over its contents.
+///
+/// Fails with [`Error::Io`](crate::Error::Io) if the object cannot be
+/// fetched; a missing object has [`std::io::ErrorKind::NotFound`].
+pub async fn open_object(
+    store: &dyn ObjectStore,
+    location: &Path,
+) -> Result<AnnotatedCsvParser<ObjectReader>> {
+    let object = store
+        .get_opts(location, GetOptions::default())
+        .await
+        .map_err(io_error)?;
+    let body: Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>> =
+        Box::pin(object.into_stream().map_err(io_error));
+    Ok(AnnotatedCsvParser::new(StreamReader::new(body)))
+}
+
+/// Stream the records of the annotated CSV object at `location`.
+///
+/// Errors, including failing to open the object, are yielded by the stream.
+pub fn stream_object(store: Arc<dyn ObjectStore>, location: Path) -> RecordStream {
+    Box::pin(stream! {
+        let mut parser = match open_object(store.as_ref(), &location).await {
+            Ok(parser) => parser,
+            Err(e) => {
+                yield Err(e);
+                return;
+            }
+        };
+        loop {
+            match parser.next().await {
+                Ok(Some(record)) => yield Ok(record),
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+fn io_error(e: object_store::Error) -> std::io::Error {
+    let kind = match e {
+        object_store::Error::NotFound { .. } => std::io::ErrorKind::NotFound,
+        _ => std::io::ErrorKind::Other,
+    };
+    std::io::Error::new(kind, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use futures::StreamExt;
+    use object_store::memory::InMemory;
+    use object_store::{ObjectStoreExt, PutPayload};
+
+    const CSV: &str = "#datatype,string,long,string\n\
+                       #group,false,false,true\n\
+                       #default,_result,,\n\
+                       ,result,table,host\n\
+                       ,,0,server1\n\
+                       ,,0,server2\n";
+
+    async fn store_with(path: &str, body: &'static str) -> Arc<dyn ObjectStore> {
+        let store = InMemory::new();
+        store
+            .put(&Path::from(path), PutPayload::from_static(body.as_bytes()))
+            .await
+            .unwrap();
+        Arc::new(store)
+    }
+
+    #[tokio::test]
+    async fn test_stream_object() {
+        let store = store_with("exports/cpu.csv", CSV).await;
+
+        let records: Vec<_> = stream_object(store, Path::from("exports/cpu.csv"))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get_string("host"), Some("server2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_open_object_parser() {
+        let store = store_with("cpu.csv", CSV).await;
+
+        let mut parser = open_object(store.as_ref(), &Path::from("cpu.csv"))
+            .await
+            .unwrap();
+        assert!(parser.next().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_missing_object() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+
+        let items: Vec<_> = stream_object(store, Path::from("missing.csv"))
+            .collect()
+            .await;
+        assert_eq!(items.len(), 1);
+        match &items[0] {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("unexpected item: {:?}", other),
+        }
+    }
+}
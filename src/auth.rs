@@ -0,0 +1,327 @@
+//! Tokens obtained at request time.
+//!
+//! A [`TokenProvider`] set with
+//! [`ClientBuilder::token_provider`](crate::ClientBuilder::token_provider) is
+//! asked for the token before every request, so short-lived tokens issued by
+//! Vault, mounted from a Kubernetes secret or refreshed by a sidecar are
+//! picked up without rebuilding the client. Wrap slow providers in a
+//! [`CachedTokenProvider`] to fetch a token only when the previous one is
+//! about to expire. A request rejected with `401 Unauthorized` is sent once
+//! more with a fresh token, in case the previous one was revoked.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//! use influxdb_stream::Client;
+//! use influxdb_stream::auth::{CachedTokenProvider, Token, from_fn};
+//!
+//! let provider = CachedTokenProvider::new(from_fn(|| async {
+//!     let lease = vault.read("secret/influxdb").await?;
+//!     Ok(Token::new(lease.token).expires_in(lease.ttl))
+//! }))
+//! .refresh_before(Duration::from_secs(60));
+//!
+//! let client = Client::builder("http://localhost:8086", "my-org", "")
+//!     .token_provider(provider)
+//!     .build()?;
+//! ```
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+
+use crate::error::Result;
+
+/// Default for [`CachedTokenProvider::refresh_before`].
+pub const DEFAULT_REFRESH_BEFORE: Duration = Duration::from_secs(30);
+
+/// Default for [`CachedTokenProvider::max_age`].
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// Supplies the token of each request of a [`Client`](crate::Client).
+///
+/// Errors are returned from the request that asked for the token.
+pub trait TokenProvider: Send + Sync + 'static {
+    /// Get the token to send with the next request.
+    fn token(&self) -> BoxFuture<'_, Result<Token>>;
+
+    /// Forget any cached token, after the server rejected it.
+    ///
+    /// Called before the token of a request answered with `401
+    /// Unauthorized` is asked for again. The default does nothing.
+    fn invalidate(&self) {}
+}
+
+impl TokenProvider for Box<dyn TokenProvider> {
+    fn token(&self) -> BoxFuture<'_, Result<Token>> {
+        (**self).token()
+    }
+
+    fn invalidate(&self) {
+        (**self).invalidate()
+    }
+}
+
+/// A token returned by a [`TokenProvider`], with its expiry if known.
+#[derive(Clone)]
+pub struct Token {
+    secret: String,
+    expires_at: Option<Instant>,
+}
+
+impl Token {
+    /// Create a token that does not expire.
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            expires_at: None,
+        }
+    }
+
+    /// Mark the token as expiring `ttl` from now.
+    pub fn expires_in(mut self, ttl: Duration) -> Self {
+        self.expires_at = Some(Instant::now() + ttl);
+        self
+    }
+
+    /// Get the token itself.
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Create a provider calling `f` for every token.
+pub fn from_fn<F, Fut>(f: F) -> impl TokenProvider
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Token>> + Send + 'static,
+{
+    FnProvider(f)
+}
+
+struct FnProvider<F>(F);
+
+impl<F, Fut> TokenProvider for FnProvider<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Token>> + Send + 'static,
+{
+    fn token(&self) -> BoxFuture<'_, Result<Token>> {
+        Box::pin((self.0)())
+    }
+}
+
+/// [`TokenProvider`] that reuses the token of another provider until it is
+/// about to expire.
+///
+/// Tokens without an expiry are fetched again after
+/// [`max_age`](Self::max_age), so that rotated secrets are picked up. A
+/// single request refreshes the token; the others keep using the cached
+/// one while it is valid, and only wait for the refresh once it has
+/// expired. A failed refresh is retried by the next request.
+pub struct CachedTokenProvider {
+    inner: Box<dyn TokenProvider>,
+    refresh_before: Duration,
+    max_age: Duration,
+    cached: std::sync::Mutex<Option<Cached>>,
+    refresh: tokio::sync::Mutex<()>,
+}
+
+/// A cached token and when it is due for a refresh.
+struct Cached {
+    token: Token,
+    refresh_at: Instant,
+}
+
+impl Cached {
+    /// Returns true if the token can still be sent.
+    fn valid(&self) -> bool {
+        self.token.expires_at.is_none_or(|at| at > Instant::now())
+    }
+}
+
+impl CachedTokenProvider {
+    /// Cache the tokens of `inner`.
+    pub fn new(inner: impl TokenProvider) -> Self {
+        Self {
+            inner: Box::new(inner),
+            refresh_before: DEFAULT_REFRESH_BEFORE,
+            max_age: DEFAULT_MAX_AGE,
+            cached: std::sync::Mutex::new(None),
+            refresh: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Fetch a new token once the cached one expires within `margin`
+    /// (default: [`DEFAULT_REFRESH_BEFORE`]).
+    ///
+    /// The margin should cover the longest request, so that a token does not
+    /// expire while a query is being sent.
+    pub fn refresh_before(mut self, margin: Duration) -> Self {
+        self.refresh_before = margin;
+        self
+    }
+
+    /// Fetch a new token once a token without an expiry is `age` old
+    /// (default: [`DEFAULT_MAX_AGE`]).
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = age;
+        self
+    }
+
+    /// Get the cached token if it can be sent, and whether it is due for a
+    /// refresh.
+    fn cached(&self) -> Option<(Token, bool)> {
+        let cached = self.cached.lock().expect("token cache lock poisoned");
+        let cached = cached.as_ref().filter(|c| c.valid())?;
+        Some((cached.token.clone(), cached.refresh_at <= Instant::now()))
+    }
+
+    /// Ask the inner provider for a token and cache it.
+    async fn fetch(&self) -> Result<Token> {
+        let token = self.inner.token().await?;
+        let refresh_at = match token.expires_at {
+            Some(at) => at.checked_sub(self.refresh_before).unwrap_or(at),
+            None => Instant::now() + self.max_age,
+        };
+        *self.cached.lock().expect("token cache lock poisoned") = Some(Cached {
+            token: token.clone(),
+            refresh_at,
+        });
+        Ok(token)
+    }
+}
+
+impl TokenProvider for CachedTokenProvider {
+    fn token(&self) -> BoxFuture<'_, Result<Token>> {
+        Box::pin(async move {
+            match self.cached() {
+                Some((token, false)) => return Ok(token),
+                // Refresh in this request, unless another one already is;
+                // the token is still valid meanwhile.
+                Some((token, true)) => {
+                    let Ok(_refresh) = self.refresh.try_lock() else {
+                        return Ok(token);
+                    };
+                    return Ok(self.fetch().await.unwrap_or(token));
+                }
+                None => {}
+            }
+            let _refresh = self.refresh.lock().await;
+            // Another request may have fetched a token while this one waited.
+            match self.cached() {
+                Some((token, false)) => Ok(token),
+                _ => self.fetch().await,
+            }
+        })
+    }
+
+    fn invalidate(&self) {
+        *self.cached.lock().expect("token cache lock poisoned") = None;
+    }
+}
+
+impl std::fmt::Debug for CachedTokenProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedTokenProvider")
+            .field("refresh_before", &self.refresh_before)
+            .field("max_age", &self.max_age)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting(ttl: Option<Duration>) -> (impl TokenProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let provider = from_fn(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                let token = Token::new(format!("t{n}"));
+                Ok(match ttl {
+                    Some(ttl) => token.expires_in(ttl),
+                    None => token,
+                })
+            }
+        });
+        (provider, calls)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cached_until_close_to_expiry() {
+        let (inner, calls) = counting(Some(Duration::from_secs(300)));
+        let provider = CachedTokenProvider::new(inner).refresh_before(Duration::from_secs(60));
+
+        assert_eq!(provider.token().await.unwrap().secret(), "t1");
+        tokio::time::advance(Duration::from_secs(200)).await;
+        assert_eq!(provider.token().await.unwrap().secret(), "t1");
+        tokio::time::advance(Duration::from_secs(50)).await;
+        assert_eq!(provider.token().await.unwrap().secret(), "t2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_without_expiry_kept_for_max_age() {
+        let (inner, calls) = counting(None);
+        let provider = CachedTokenProvider::new(inner).max_age(Duration::from_secs(60));
+        for _ in 0..3 {
+            assert_eq!(provider.token().await.unwrap().secret(), "t1");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(provider.token().await.unwrap().secret(), "t2");
+        provider.invalidate();
+        assert_eq!(provider.token().await.unwrap().secret(), "t3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_valid_token_served_during_refresh() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let inner = from_fn(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if n > 1 {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                Ok(Token::new(format!("t{n}")).expires_in(Duration::from_secs(300)))
+            }
+        });
+        let provider =
+            Arc::new(CachedTokenProvider::new(inner).refresh_before(Duration::from_secs(60)));
+        assert_eq!(provider.token().await.unwrap().secret(), "t1");
+
+        tokio::time::advance(Duration::from_secs(250)).await;
+        let refresh = tokio::spawn({
+            let provider = provider.clone();
+            async move { provider.token().await.unwrap().secret().to_string() }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(provider.token().await.unwrap().secret(), "t1");
+        assert_eq!(refresh.await.unwrap(), "t2");
+        assert_eq!(provider.token().await.unwrap().secret(), "t2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_debug_hides_secret() {
+        assert!(!format!("{:?}", Token::new("s3cret")).contains("s3cret"));
+    }
+}
@@ -0,0 +1,584 @@
+//! Native backups through the `/api/v2/backup` endpoints.
+//!
+//! Unlike exporting query results, a backup captures the server's own
+//! storage: the KV store with buckets, users and tasks, the SQL metadata
+//! store, and a snapshot of every TSM shard. The files are what `influx
+//! backup` produces and can be restored into an empty server.
+//!
+//! [`Client::backup`](crate::Client::backup) writes, with a common timestamp
+//! prefix:
+//!
+//! - `<prefix>.bolt`: the KV snapshot
+//! - `<prefix>.sqlite`: the SQL snapshot
+//! - `<prefix>.s<id>.tar`: one archive per shard
+//! - `<prefix>.manifest`: a JSON [`BackupManifest`] listing the files
+//!
+//! Every artifact is streamed straight to disk, so backups need no more
+//! memory than a query.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::backup::BackupOptions;
+//!
+//! let options = BackupOptions::new()
+//!     .bucket("sensors")
+//!     .on_progress(|p| eprintln!("{}: {} bytes", p.file, p.bytes));
+//! let manifest = client.backup_opts("/var/backups/influx", &options).await?;
+//! println!("backed up {} shards", manifest.shards.len());
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes, BytesMut};
+use chrono::Utc;
+use futures::TryStreamExt;
+use http::HeaderMap;
+use http::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{Error, Result};
+use crate::transport::ByteStream;
+
+/// Progress of a backup, reported to [`BackupOptions::on_progress`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BackupProgress {
+    /// Name of the file being written, relative to the backup directory.
+    pub file: String,
+    /// Bytes written to `file` so far.
+    pub bytes: u64,
+    /// Bytes written to all files so far.
+    pub total_bytes: u64,
+    /// Number of files completed.
+    pub files: usize,
+}
+
+type ProgressCallback = Arc<dyn Fn(&BackupProgress) + Send + Sync>;
+
+/// Options for [`Client::backup_opts`](crate::Client::backup_opts).
+#[derive(Clone, Default)]
+pub struct BackupOptions {
+    buckets: Vec<String>,
+    progress: Option<ProgressCallback>,
+}
+
+impl BackupOptions {
+    /// Back up all buckets, without progress reporting.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only back up the shards of bucket `name`; may be repeated.
+    ///
+    /// The KV and SQL snapshots are always complete, as the server provides
+    /// them as a whole.
+    pub fn bucket(mut self, name: impl Into<String>) -> Self {
+        self.buckets.push(name.into());
+        self
+    }
+
+    /// Call `callback` after each chunk written to disk.
+    ///
+    /// The callback runs on the backup task, so it should return quickly.
+    pub fn on_progress(
+        mut self,
+        callback: impl Fn(&BackupProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+}
+
+impl fmt::Debug for BackupOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackupOptions")
+            .field("buckets", &self.buckets)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// A file of a backup.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupFile {
+    /// File name, relative to the backup directory.
+    pub file_name: String,
+    /// File size in bytes.
+    pub size: u64,
+}
+
+/// The snapshot of one shard.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShardBackup {
+    /// Shard ID on the backed-up server.
+    pub id: u64,
+    /// ID of the bucket the shard belongs to.
+    #[serde(rename = "bucketID")]
+    pub bucket_id: String,
+    /// The shard archive.
+    pub file: BackupFile,
+}
+
+/// Description of a backup, saved as `<prefix>.manifest`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupManifest {
+    /// The KV store snapshot.
+    pub kv: Option<BackupFile>,
+    /// The SQL store snapshot.
+    pub sql: Option<BackupFile>,
+    /// Metadata of the backed-up buckets, as reported by the server.
+    pub buckets: Vec<serde_json::Value>,
+    /// The shard snapshots.
+    pub shards: Vec<ShardBackup>,
+}
+
+/// Writer of the files of one backup.
+pub(crate) struct Backup<'a> {
+    dir: PathBuf,
+    prefix: String,
+    options: &'a BackupOptions,
+    manifest: BackupManifest,
+    total_bytes: u64,
+    files: usize,
+}
+
+impl<'a> Backup<'a> {
+    pub(crate) fn new(dir: &Path, options: &'a BackupOptions) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            prefix: Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+            options,
+            manifest: BackupManifest::default(),
+            total_bytes: 0,
+            files: 0,
+        }
+    }
+
+    /// Save the KV and SQL snapshots from the multipart metadata response,
+    /// and keep the metadata of the selected buckets.
+    pub(crate) async fn write_metadata(
+        &mut self,
+        headers: &HeaderMap,
+        body: ByteStream,
+    ) -> Result<()> {
+        let mut parts = Multipart::new(body, boundary(headers));
+        while let Some(name) = parts.next_part().await? {
+            match name.as_deref() {
+                Some("kv") => {
+                    let file = format!("{}.bolt", self.prefix);
+                    self.manifest.kv = Some(self.write_part(&mut parts, file).await?);
+                }
+                Some("sql") => {
+                    let file = format!("{}.sqlite", self.prefix);
+                    self.manifest.sql = Some(self.write_part(&mut parts, file).await?);
+                }
+                Some("buckets") => {
+                    let mut json = Vec::new();
+                    while let Some(chunk) = parts.next_chunk().await? {
+                        json.extend_from_slice(&chunk);
+                    }
+                    let buckets: Vec<serde_json::Value> = serde_json::from_slice(&json)?;
+                    self.manifest.buckets =
+                        buckets.into_iter().filter(|b| self.selected(b)).collect();
+                }
+                _ => while parts.next_chunk().await?.is_some() {},
+            }
+        }
+        Ok(())
+    }
+
+    /// List the shards of the selected buckets, with their bucket IDs.
+    pub(crate) fn shards(&self) -> Vec<(u64, String)> {
+        let mut shards = Vec::new();
+        for bucket in &self.manifest.buckets {
+            let bucket_id = bucket["bucketID"].as_str().unwrap_or_default();
+            let groups = json_array(&bucket["retentionPolicies"])
+                .flat_map(|policy| json_array(&policy["shardGroups"]));
+            for shard in groups.flat_map(|group| json_array(&group["shards"])) {
+                if let Some(id) = shard["id"].as_u64() {
+                    shards.push((id, bucket_id.to_string()));
+                }
+            }
+        }
+        shards
+    }
+
+    /// Save the snapshot of shard `id`.
+    pub(crate) async fn write_shard(
+        &mut self,
+        id: u64,
+        bucket_id: String,
+        body: ByteStream,
+    ) -> Result<()> {
+        let file = format!("{}.s{}.tar", self.prefix, id);
+        let file = self.write_stream(file, body).await?;
+        self.manifest.shards.push(ShardBackup {
+            id,
+            bucket_id,
+            file,
+        });
+        Ok(())
+    }
+
+    /// Write the manifest and return it.
+    pub(crate) async fn finish(self) -> Result<BackupManifest> {
+        let path = self.dir.join(format!("{}.manifest", self.prefix));
+        tokio::fs::write(path, serde_json::to_vec_pretty(&self.manifest)?).await?;
+        Ok(self.manifest)
+    }
+
+    fn selected(&self, bucket: &serde_json::Value) -> bool {
+        self.options.buckets.is_empty()
+            || bucket["bucketName"]
+                .as_str()
+                .is_some_and(|name| self.options.buckets.iter().any(|b| b == name))
+    }
+
+    async fn write_part(&mut self, parts: &mut Multipart, name: String) -> Result<BackupFile> {
+        let mut out = tokio::fs::File::create(self.dir.join(&name)).await?;
+        let mut size = 0;
+        while let Some(chunk) = parts.next_chunk().await? {
+            self.write_chunk(&mut out, &name, &mut size, &chunk).await?;
+        }
+        self.close(out, name, size).await
+    }
+
+    async fn write_stream(&mut self, name: String, mut body: ByteStream) -> Result<BackupFile> {
+        let mut out = tokio::fs::File::create(self.dir.join(&name)).await?;
+        let mut size = 0;
+        while let Some(chunk) = body.try_next().await? {
+            self.write_chunk(&mut out, &name, &mut size, &chunk).await?;
+        }
+        self.close(out, name, size).await
+    }
+
+    async fn write_chunk(
+        &mut self,
+        out: &mut tokio::fs::File,
+        name: &str,
+        size: &mut u64,
+        chunk: &[u8],
+    ) -> Result<()> {
+        out.write_all(chunk).await?;
+        *size += chunk.len() as u64;
+        self.total_bytes += chunk.len() as u64;
+        if let Some(progress) = &self.options.progress {
+            progress(&BackupProgress {
+                file: name.to_string(),
+                bytes: *size,
+                total_bytes: self.total_bytes,
+                files: self.files,
+            });
+        }
+        Ok(())
+    }
+
+    async fn close(
+        &mut self,
+        mut out: tokio::fs::File,
+        name: String,
+        size: u64,
+    ) -> Result<BackupFile> {
+        out.flush().await?;
+        out.sync_all().await?;
+        self.files += 1;
+        Ok(BackupFile {
+            file_name: name,
+            size,
+        })
+    }
+}
+
+fn json_array(value: &serde_json::Value) -> impl Iterator<Item = &serde_json::Value> {
+    value.as_array().into_iter().flatten()
+}
+
+/// Get the multipart boundary from the `Content-Type` header.
+fn boundary(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    content_type.split(';').find_map(|param| {
+        let (key, value) = param.trim().split_once('=')?;
+        key.eq_ignore_ascii_case("boundary")
+            .then(|| value.trim_matches('"').to_string())
+    })
+}
+
+/// Incremental reader of a `multipart/mixed` body.
+///
+/// Part bodies are returned in chunks as they arrive, so large parts are
+/// never held in memory.
+struct Multipart {
+    body: ByteStream,
+    buf: BytesMut,
+    /// `\r\n--<boundary>`, or `None` until read from the first line.
+    delimiter: Option<Vec<u8>>,
+    eof: bool,
+    done: bool,
+}
+
+impl Multipart {
+    fn new(body: ByteStream, boundary: Option<String>) -> Self {
+        // The leading CRLF lets the first boundary match like the others.
+        let mut buf = BytesMut::from(&b"\r\n"[..]);
+        buf.reserve(8 * 1024);
+        Self {
+            body,
+            buf,
+            delimiter: boundary.map(|b| format!("\r\n--{}", b).into_bytes()),
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Read more of the body; returns false at its end.
+    async fn fill(&mut self) -> Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        match self.body.try_next().await? {
+            Some(chunk) => self.buf.extend_from_slice(&chunk),
+            None => self.eof = true,
+        }
+        Ok(!self.eof)
+    }
+
+    fn truncated() -> Error {
+        Error::Parse {
+            message: "Truncated multipart response".to_string(),
+        }
+    }
+
+    /// Skip to the next part and return the `name` of its
+    /// `Content-Disposition`, or `None` after the last part.
+    async fn next_part(&mut self) -> Result<Option<Option<String>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.delimiter.is_none() {
+            // Without a Content-Type boundary, take it from the first line.
+            let line = loop {
+                if let Some(end) = find(&self.buf[2..], b"\r\n") {
+                    break self.buf[2..2 + end].to_vec();
+                }
+                if !self.fill().await? {
+                    return Err(Self::truncated());
+                }
+            };
+            let boundary = line.strip_prefix(b"--").ok_or_else(Self::truncated)?;
+            self.delimiter = Some([b"\r\n--", boundary].concat());
+        }
+        let delimiter = self.delimiter.clone().unwrap_or_default();
+
+        // Skip the rest of the current part, then the delimiter line.
+        loop {
+            if let Some(at) = find(&self.buf, &delimiter) {
+                self.buf.advance(at + delimiter.len());
+                break;
+            }
+            let keep = delimiter.len().min(self.buf.len());
+            self.buf.advance(self.buf.len() - keep);
+            if !self.fill().await? {
+                return Err(Self::truncated());
+            }
+        }
+        while self.buf.len() < 2 {
+            if !self.fill().await? {
+                return Err(Self::truncated());
+            }
+        }
+        if self.buf.starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+
+        let end = loop {
+            if let Some(end) = find(&self.buf, b"\r\n\r\n") {
+                break end;
+            }
+            if !self.fill().await? {
+                return Err(Self::truncated());
+            }
+        };
+        let headers = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        self.buf.advance(end + 4);
+        Ok(Some(part_name(&headers)))
+    }
+
+    /// Read the next chunk of the current part's body, or `None` at its end.
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>> {
+        let Some(delimiter) = self.delimiter.clone() else {
+            return Ok(None);
+        };
+        loop {
+            match find(&self.buf, &delimiter) {
+                Some(0) => return Ok(None),
+                Some(at) => return Ok(Some(self.buf.split_to(at).freeze())),
+                None if self.buf.len() >= delimiter.len() => {
+                    // The last bytes may be the start of the delimiter.
+                    let safe = self.buf.len() - (delimiter.len() - 1);
+                    return Ok(Some(self.buf.split_to(safe).freeze()));
+                }
+                None => {
+                    if !self.fill().await? {
+                        return Err(Self::truncated());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Get the `name` parameter of a part's `Content-Disposition` header.
+fn part_name(headers: &str) -> Option<String> {
+    let disposition = headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("content-disposition")
+            .then_some(value)
+    })?;
+    disposition.split(';').find_map(|param| {
+        let (key, value) = param.trim().split_once('=')?;
+        (key == "name").then(|| value.trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use http::HeaderValue;
+
+    const BODY: &str = "--b0\r\n\
+        Content-Disposition: attachment; name=\"kv\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n\
+        kv-data\r\n--b\r\n\
+        --b0\r\n\
+        Content-Disposition: attachment; name=\"sql\"\r\n\r\n\
+        \r\n\
+        --b0\r\n\
+        Content-Disposition: attachment; name=\"buckets\"\r\n\r\n\
+        [{\"bucketID\":\"a1\",\"bucketName\":\"sensors\",\"retentionPolicies\":[\
+        {\"shardGroups\":[{\"shards\":[{\"id\":3},{\"id\":4}]}]}]},\
+        {\"bucketID\":\"a2\",\"bucketName\":\"other\",\"retentionPolicies\":[\
+        {\"shardGroups\":[{\"shards\":[{\"id\":5}]}]}]}]\r\n\
+        --b0--\r\n";
+
+    fn body(data: &'static str, chunk: usize) -> ByteStream {
+        let chunks: Vec<_> = data
+            .as_bytes()
+            .chunks(chunk)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        Box::pin(stream::iter(chunks))
+    }
+
+    async fn parts(body: ByteStream, boundary: Option<&str>) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut parts = Multipart::new(body, boundary.map(str::to_string));
+        let mut out = Vec::new();
+        while let Some(name) = parts.next_part().await? {
+            let mut data = Vec::new();
+            while let Some(chunk) = parts.next_chunk().await? {
+                data.extend_from_slice(&chunk);
+            }
+            out.push((name.unwrap_or_default(), data));
+        }
+        Ok(out)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "influxdb-stream-backup-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_multipart_parts_across_chunks() {
+        for chunk in [1, 3, 7, 64, 4096] {
+            let parts = parts(body(BODY, chunk), Some("b0")).await.unwrap();
+            let names: Vec<_> = parts.iter().map(|(n, _)| n.as_str()).collect();
+            assert_eq!(names, ["kv", "sql", "buckets"], "chunk size {}", chunk);
+            assert_eq!(parts[0].1, b"kv-data\r\n--b");
+            assert!(parts[1].1.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multipart_boundary_from_body() {
+        let parts = parts(body(BODY, 5), None).await.unwrap();
+        assert_eq!(parts.len(), 3);
+
+        let truncated = &BODY[..40];
+        let result = super::tests::parts(body(truncated, 5), None).await;
+        assert!(matches!(result, Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn test_boundary_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/mixed; boundary=\"abc\""),
+        );
+        assert_eq!(boundary(&headers).as_deref(), Some("abc"));
+        assert_eq!(boundary(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_backup_writes_files_and_manifest() {
+        let dir = temp_dir("files");
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = BackupOptions::new().bucket("sensors").on_progress({
+            let seen = seen.clone();
+            move |p| seen.lock().unwrap().push(p.file.clone())
+        });
+
+        let mut backup = Backup::new(&dir, &options);
+        backup
+            .write_metadata(&HeaderMap::new(), body(BODY, 8))
+            .await
+            .unwrap();
+        assert_eq!(
+            backup.shards(),
+            [(3, "a1".to_string()), (4, "a1".to_string())]
+        );
+        backup
+            .write_shard(3, "a1".to_string(), body("tar", 2))
+            .await
+            .unwrap();
+        let manifest = backup.finish().await.unwrap();
+
+        let kv = manifest.kv.as_ref().unwrap();
+        assert_eq!(kv.size, 12);
+        assert_eq!(std::fs::read(dir.join(&kv.file_name)).unwrap().len(), 12);
+        assert_eq!(manifest.sql.as_ref().unwrap().size, 0);
+        assert_eq!(manifest.buckets.len(), 1);
+        assert_eq!(manifest.shards[0].file.size, 3);
+
+        let saved = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .find(|e| e.path().extension().is_some_and(|x| x == "manifest"))
+            .unwrap();
+        let saved: BackupManifest =
+            serde_json::from_slice(&std::fs::read(saved.path()).unwrap()).unwrap();
+        assert_eq!(saved, manifest);
+        assert!(seen.lock().unwrap().last().unwrap().ends_with(".s3.tar"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
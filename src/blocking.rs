@@ -0,0 +1,134 @@
+//! Synchronous wrapper around the async client.
+//!
+//! Requires the `blocking` feature. The [`Client`] in this module owns a
+//! single-threaded Tokio runtime and drives queries on the calling thread, so
+//! it can be used from CLI tools and other code that is not async.
+//!
+//! Do not use it from within an async context: blocking on a runtime inside
+//! another runtime panics.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::blocking::Client;
+//!
+//! let client = Client::new("http://localhost:8086", "my-org", "my-token")?;
+//! for record in client.query_iter(r#"from(bucket: "sensors") |> range(start: -1h)"#)? {
+//!     println!("{:?}", record?);
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::client::QueryStream;
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Blocking InfluxDB 2.x client.
+///
+/// Cloning is cheap: clones share the HTTP connection pool and the runtime.
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Create a new blocking client.
+    ///
+    /// Fails if the runtime cannot be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided URL is invalid.
+    pub fn new(
+        url: impl Into<String>,
+        org: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Result<Self> {
+        Self::from_async(crate::Client::new(url, org, token))
+    }
+
+    /// Wrap an existing async client.
+    pub fn from_async(client: crate::Client) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner: client,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Get the underlying async client.
+    pub fn as_async(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Get the base URL.
+    pub fn url(&self) -> &Url {
+        self.inner.url()
+    }
+
+    /// Get the organization name.
+    pub fn org(&self) -> &str {
+        self.inner.org()
+    }
+
+    /// Execute a Flux query and return an iterator over its records.
+    ///
+    /// The response is read as the iterator advances, so memory use stays
+    /// constant like [`crate::Client::query_stream`].
+    pub fn query_iter(&self, query: impl Into<String>) -> Result<QueryIter> {
+        let stream = self.runtime.block_on(self.inner.query_stream(query))?;
+        Ok(QueryIter {
+            stream,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Execute a Flux query and collect all results into a Vec.
+    ///
+    /// **Warning**: This loads all results into memory. For large result sets,
+    /// use `query_iter()` instead.
+    pub fn query(&self, query: impl Into<String>) -> Result<Vec<FluxRecord>> {
+        self.runtime.block_on(self.inner.query(query))
+    }
+}
+
+/// Iterator over the records of a query, returned by [`Client::query_iter`].
+pub struct QueryIter {
+    stream: QueryStream,
+    runtime: Arc<Runtime>,
+}
+
+impl Iterator for QueryIter {
+    type Item = Result<FluxRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn test_query_iter_reports_connection_error() {
+        // Nothing listens on port 1, so the request fails immediately.
+        let client = Client::new("http://127.0.0.1:1", "org", "token").unwrap();
+        assert_eq!(client.org(), "org");
+
+        let result = client.query_iter("buckets()");
+        #[cfg(feature = "reqwest")]
+        assert!(matches!(result, Err(Error::Http(e)) if e.is_connect()));
+        #[cfg(not(feature = "reqwest"))]
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
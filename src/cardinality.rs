@@ -0,0 +1,142 @@
+//! Result size estimation before running a large query.
+//!
+//! An [`Estimate`] counts the series and rows a query over a bucket would
+//! touch, using `influxdb.cardinality()` and a server-side `count()`. Both run
+//! on the server and return a single row, so they are cheap compared to the
+//! export they guard, and let jobs warn or pick a shard count up front.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::cardinality::Estimate;
+//! use influxdb_stream::schema::TimeRange;
+//!
+//! let estimate = Estimate::new("telegraf", TimeRange::last(chrono::Duration::days(30)))
+//!     .filter(r#"r._measurement == "cpu""#);
+//! let size = client.estimate_cardinality(&estimate).await?;
+//! if size.rows > 100_000_000 {
+//!     let shards = TimeShards::new(start, stop, (size.rows / 10_000_000) as usize);
+//!     // ...
+//! }
+//! ```
+
+use crate::flux::Literal;
+use crate::schema::TimeRange;
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Estimated size of a query's result.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cardinality {
+    /// Number of series (distinct group keys) in the range.
+    pub series: u64,
+    /// Number of rows in the range.
+    pub rows: u64,
+}
+
+/// Description of the data whose size is estimated.
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    bucket: String,
+    range: TimeRange,
+    predicate: Option<String>,
+}
+
+impl Estimate {
+    /// Estimate everything in `bucket` within `range`.
+    pub fn new(bucket: impl Into<String>, range: TimeRange) -> Self {
+        Self {
+            bucket: bucket.into(),
+            range,
+            predicate: None,
+        }
+    }
+
+    /// Only count data matching `predicate`, the body of a Flux
+    /// `(r) => ...` function such as `r._measurement == "cpu"`.
+    ///
+    /// `influxdb.cardinality()` only evaluates tag columns, so the predicate
+    /// should not refer to `_value`. The text is inserted as-is; build it with
+    /// [`flux!`](crate::flux!) when it contains user input.
+    pub fn filter(mut self, predicate: impl Into<String>) -> Self {
+        self.predicate = Some(predicate.into());
+        self
+    }
+
+    /// Get the Flux counting series.
+    pub fn series_query(&self) -> String {
+        let mut args = format!(
+            "bucket: {}, start: {}",
+            Literal(self.bucket.as_str()),
+            self.range.start()
+        );
+        if let Some(stop) = self.range.stop() {
+            args.push_str(&format!(", stop: {}", stop));
+        }
+        if let Some(predicate) = &self.predicate {
+            args.push_str(&format!(", predicate: (r) => {}", predicate));
+        }
+        format!(
+            "import \"influxdata/influxdb\"\ninfluxdb.cardinality({})",
+            args
+        )
+    }
+
+    /// Get the Flux counting rows.
+    pub fn rows_query(&self) -> String {
+        let mut query = format!(
+            "from(bucket: {}) |> {}",
+            Literal(self.bucket.as_str()),
+            self.range.to_flux()
+        );
+        if let Some(predicate) = &self.predicate {
+            query.push_str(&format!(" |> filter(fn: (r) => {})", predicate));
+        }
+        query.push_str(" |> count() |> group() |> sum()");
+        query
+    }
+}
+
+/// Read the count from the single-row result of a counting query.
+pub(crate) fn count(records: &[FluxRecord]) -> u64 {
+    records
+        .iter()
+        .filter_map(|r| match r.get("_value")? {
+            Value::Long(n) => u64::try_from(*n).ok(),
+            Value::UnsignedLong(n) => Some(*n),
+            _ => None,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries() {
+        let estimate = Estimate::new("tele\"graf", TimeRange::last(chrono::Duration::hours(2)))
+            .filter(r#"r._measurement == "cpu""#);
+
+        assert_eq!(
+            estimate.series_query(),
+            "import \"influxdata/influxdb\"\n\
+             influxdb.cardinality(bucket: \"tele\\\"graf\", start: -2h, \
+             predicate: (r) => r._measurement == \"cpu\")"
+        );
+        assert_eq!(
+            estimate.rows_query(),
+            "from(bucket: \"tele\\\"graf\") |> range(start: -2h) \
+             |> filter(fn: (r) => r._measurement == \"cpu\") \
+             |> count() |> group() |> sum()"
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let mut record = FluxRecord::new(0);
+        record.insert("_value".to_string(), Value::Long(42));
+        assert_eq!(count(&[record]), 42);
+        assert_eq!(count(&[]), 0);
+    }
+}
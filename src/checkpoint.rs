@@ -0,0 +1,323 @@
+//! Persisting stream progress so restarted jobs continue where they left off.
+//!
+//! [`Checkpointed`] tracks the last record the consumer has acknowledged and
+//! saves it through a [`Checkpointer`] when asked to commit. On restart, load
+//! the checkpoint and start the query from [`Checkpoint::resume_from`].
+//!
+//! Unlike [`resume`](crate::resume), which recovers from failures within one
+//! process using the last *yielded* record, checkpoints only advance when the
+//! consumer confirms a record has been fully processed.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::adapters::RecordStreamExt;
+//! use influxdb_stream::checkpoint::{Checkpointer, FileCheckpointer};
+//!
+//! let mut checkpointer = FileCheckpointer::new("backfill.checkpoint");
+//! let start = match checkpointer.load().await? {
+//!     Some(checkpoint) => checkpoint.resume_from().to_rfc3339(),
+//!     None => "-30d".to_string(),
+//! };
+//!
+//! let query = format!(r#"from(bucket: "sensors") |> range(start: {start})"#);
+//! let mut stream = client.query_stream(query).await?.checkpointed(checkpointer);
+//! let mut n = 0;
+//! while let Some(record) = stream.next().await {
+//!     let record = record?;
+//!     process(&record).await?;
+//!     stream.ack(&record);
+//!     n += 1;
+//!     if n % 10_000 == 0 {
+//!         stream.commit().await?;
+//!     }
+//! }
+//! stream.commit().await?;
+//! ```
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::Stream;
+use pin_project_lite::pin_project;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Progress marker of a record stream.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// `_time` of the last acknowledged record.
+    pub time: DateTime<FixedOffset>,
+    /// String columns of the last acknowledged record, other than `_value`
+    /// and `result`.
+    ///
+    /// With the default grouping these are the group key (`_measurement`,
+    /// `_field` and tags), identifying the series the watermark belongs to.
+    pub group_key: BTreeMap<String, String>,
+}
+
+impl Checkpoint {
+    /// Create a checkpoint from a record, or `None` if it has no `_time`.
+    pub fn from_record(record: &FluxRecord) -> Option<Self> {
+        let time = *record.time()?;
+        let group_key = record
+            .iter()
+            .filter(|(name, _)| !matches!(*name, "_value" | "result"))
+            .filter_map(|(name, value)| Some((name.to_string(), value.string()?)))
+            .collect();
+        Some(Self { time, group_key })
+    }
+
+    /// Get the time to restart the query from: one nanosecond after [`time`](Self::time).
+    pub fn resume_from(&self) -> DateTime<FixedOffset> {
+        self.time + TimeDelta::nanoseconds(1)
+    }
+}
+
+/// Storage for checkpoints.
+///
+/// Implementations are provided for files ([`FileCheckpointer`]) and
+/// closures ([`FnCheckpointer`]); implement this trait to keep checkpoints in
+/// a database or key-value store.
+pub trait Checkpointer {
+    /// Load the last saved checkpoint, if any.
+    fn load(&mut self) -> impl Future<Output = Result<Option<Checkpoint>>> + Send;
+
+    /// Save `checkpoint`, replacing the previous one.
+    fn save(&mut self, checkpoint: &Checkpoint) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Stores checkpoints as JSON in a file.
+///
+/// Saving writes to a temporary file next to the target and renames it into
+/// place, so a crash never leaves a partially written checkpoint.
+#[derive(Clone, Debug)]
+pub struct FileCheckpointer {
+    path: PathBuf,
+}
+
+impl FileCheckpointer {
+    /// Create a checkpointer for the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the checkpoint file path.
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+impl Checkpointer for FileCheckpointer {
+    async fn load(&mut self) -> Result<Option<Checkpoint>> {
+        match tokio::fs::read(&self.path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> impl Future<Output = Result<()>> + Send {
+        let json = serde_json::to_vec(checkpoint);
+        async move {
+            let mut tmp = self.path.clone().into_os_string();
+            tmp.push(".tmp");
+            tokio::fs::write(&tmp, json?).await?;
+            tokio::fs::rename(&tmp, &self.path).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Stores checkpoints through a pair of closures.
+#[derive(Clone, Debug)]
+pub struct FnCheckpointer<L, S> {
+    load: L,
+    save: S,
+}
+
+impl<L, S> FnCheckpointer<L, S>
+where
+    L: FnMut() -> Result<Option<Checkpoint>> + Send,
+    S: FnMut(&Checkpoint) -> Result<()> + Send,
+{
+    /// Create a checkpointer that calls `load` and `save`.
+    pub fn new(load: L, save: S) -> Self {
+        Self { load, save }
+    }
+}
+
+impl<L, S> Checkpointer for FnCheckpointer<L, S>
+where
+    L: FnMut() -> Result<Option<Checkpoint>> + Send,
+    S: FnMut(&Checkpoint) -> Result<()> + Send,
+{
+    fn load(&mut self) -> impl Future<Output = Result<Option<Checkpoint>>> + Send {
+        std::future::ready((self.load)())
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> impl Future<Output = Result<()>> + Send {
+        std::future::ready((self.save)(checkpoint))
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::checkpointed`](crate::adapters::RecordStreamExt::checkpointed).
+    ///
+    /// Passes records through unchanged. Call [`ack`](Self::ack) once a
+    /// record has been processed and [`commit`](Self::commit) to persist
+    /// the latest acknowledged position.
+    pub struct Checkpointed<S, C> {
+        #[pin]
+        stream: S,
+        checkpointer: C,
+        acked: Option<Checkpoint>,
+        dirty: bool,
+    }
+}
+
+impl<S, C> Checkpointed<S, C> {
+    pub(crate) fn new(stream: S, checkpointer: C) -> Self {
+        Self {
+            stream,
+            checkpointer,
+            acked: None,
+            dirty: false,
+        }
+    }
+
+    /// Mark `record` as processed.
+    ///
+    /// Records without `_time` are ignored. This only updates the in-memory
+    /// position; nothing is saved until [`commit`](Self::commit).
+    pub fn ack(&mut self, record: &FluxRecord) {
+        if let Some(checkpoint) = Checkpoint::from_record(record) {
+            self.acked = Some(checkpoint);
+            self.dirty = true;
+        }
+    }
+
+    /// Get the last acknowledged position.
+    pub fn acked(&self) -> Option<&Checkpoint> {
+        self.acked.as_ref()
+    }
+
+    /// Get a mutable reference to the checkpointer.
+    pub fn checkpointer_mut(&mut self) -> &mut C {
+        &mut self.checkpointer
+    }
+
+    /// Consume the adapter, returning the underlying stream and checkpointer.
+    pub fn into_inner(self) -> (S, C) {
+        (self.stream, self.checkpointer)
+    }
+}
+
+impl<S, C: Checkpointer> Checkpointed<S, C> {
+    /// Save the last acknowledged position if it changed since the last commit.
+    pub async fn commit(&mut self) -> Result<()> {
+        if let (true, Some(checkpoint)) = (self.dirty, &self.acked) {
+            self.checkpointer.save(checkpoint).await?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl<S, C> Stream for Checkpointed<S, C>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use crate::value::Value;
+    use futures::{StreamExt, stream};
+    use std::sync::{Arc, Mutex};
+
+    fn record_at(ts: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record.insert("host".to_string(), Value::String("server1".to_string()));
+        record.insert("_value".to_string(), Value::String("on".to_string()));
+        record
+    }
+
+    #[test]
+    fn test_checkpoint_from_record() {
+        let checkpoint = Checkpoint::from_record(&record_at("2023-11-14T12:00:00Z")).unwrap();
+        assert_eq!(checkpoint.group_key.len(), 1);
+        assert_eq!(checkpoint.group_key["host"], "server1");
+        assert_eq!(
+            checkpoint.resume_from(),
+            DateTime::parse_from_rfc3339("2023-11-14T12:00:00.000000001Z").unwrap()
+        );
+        assert!(Checkpoint::from_record(&FluxRecord::new(0)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_checkpointed_saves_only_acked() {
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let sink = saved.clone();
+        let checkpointer = FnCheckpointer::new(
+            || Ok(None),
+            move |c: &Checkpoint| {
+                sink.lock().unwrap().push(c.time);
+                Ok(())
+            },
+        );
+
+        let input = stream::iter(vec![
+            Ok(record_at("2023-11-14T12:00:00Z")),
+            Ok(record_at("2023-11-14T12:00:01Z")),
+        ]);
+        let mut stream = input.checkpointed(checkpointer);
+
+        let first = stream.next().await.unwrap().unwrap();
+        stream.ack(&first);
+        let _second = stream.next().await.unwrap().unwrap();
+        stream.commit().await.unwrap();
+        // Nothing new acknowledged, so this is a no-op.
+        stream.commit().await.unwrap();
+
+        assert_eq!(*saved.lock().unwrap(), vec![*first.time().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_file_checkpointer_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "influxdb-stream-checkpoint-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let mut checkpointer = FileCheckpointer::new(&path);
+        assert!(checkpointer.load().await.unwrap().is_none());
+
+        let checkpoint = Checkpoint::from_record(&record_at("2023-11-14T12:00:00.5Z")).unwrap();
+        checkpointer.save(&checkpoint).await.unwrap();
+        assert_eq!(checkpointer.load().await.unwrap(), Some(checkpoint));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
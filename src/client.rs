@@ -3,17 +3,106 @@
 //! This module provides the main `Client` type for executing streaming queries
 //! against an InfluxDB 2.x server.
 
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use async_stream::stream;
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeDelta};
+use futures::task::AtomicWaker;
 use futures::{Stream, StreamExt, TryStreamExt};
-use reqwest::{Method, Url};
+use http::Method;
+use http::header::{
+    ACCEPT, AUTHORIZATION, CONTENT_TYPE, COOKIE, HeaderMap, HeaderName, HeaderValue, SET_COOKIE,
+    USER_AGENT,
+};
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio_util::io::StreamReader;
+use url::Url;
 
-use crate::error::Result;
-use crate::parser::AnnotatedCsvParser;
-use crate::types::FluxRecord;
+use crate::adapters::{CollectLimit, collect_limited};
+use crate::auth::TokenProvider;
+use crate::backup::{Backup, BackupManifest, BackupOptions};
+use crate::cardinality::{self, Cardinality, Estimate};
+use crate::error::{Error, Result};
+use crate::flux;
+use crate::hooks::SlowQueryHook;
+use crate::instrument::{self, QueryTimer};
+use crate::parser::{AnnotatedCsvParser, DEFAULT_BUFFER_CAPACITY, NullPolicy};
+use crate::restore::{self, RestoreOptions, RestoreState, RestoreSummary};
+use crate::resume::{ResumeFrom, resume_from};
+use crate::retry::RetryPolicy;
+use crate::schema::{SchemaRegistry, TimeRange};
+use crate::server::{ApiVersion, Capability, Health, ServerFlavor, ServerInfo};
+use crate::shard::TimeShards;
+use crate::sql;
+use crate::stacks::{ExportRequest, NewStack, Stack, StackList, TemplateApply, TemplateSummary};
+#[cfg(feature = "reqwest")]
+use crate::transport::ReqwestTransport;
+use crate::transport::{
+    ByteStream, ConnectionOptions, FailoverTransport, ThrottledTransport, TlsConfig, Transport,
+    TransportRequest, TransportResponse, default_transport, transport_with,
+};
+use crate::typed::{Measurement, Typed, TypedStream};
+use crate::types::{FluxRecord, RecordSchema};
+use crate::value::Value;
+use crate::write::{Precision, WriteApi, WriteOptions};
+
+/// Boxed stream of records returned by query methods.
+pub type RecordStream = Pin<Box<dyn Stream<Item = Result<FluxRecord>> + Send>>;
+
+/// Boxed stream of records tagged with the label of their query, returned by
+/// [`Client::query_many`].
+pub type LabeledRecordStream<L> = Pin<Box<dyn Stream<Item = (L, Result<FluxRecord>)> + Send>>;
+
+/// Number of queries [`Client::query_many`] runs at once.
+pub const DEFAULT_QUERY_MANY_CONCURRENCY: usize = 4;
+
+/// Query operations of a client, for code that should also run against a fake.
+///
+/// [`Client`] implements this trait by forwarding to its inherent methods. In
+/// tests, use `testing::FakeClient` (with the `testing` feature) or any other
+/// implementation serving preset records.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::QueryClient;
+///
+/// async fn count_rows(client: &impl QueryClient) -> influxdb_stream::Result<usize> {
+///     let mut stream = client.query_stream("from(bucket: \"sensors\") |> range(start: -1h)").await?;
+///     let mut rows = 0;
+///     while let Some(record) = stream.next().await {
+///         record?;
+///         rows += 1;
+///     }
+///     Ok(rows)
+/// }
+/// ```
+pub trait QueryClient: Send + Sync {
+    /// Execute a Flux query and return results as a stream.
+    fn query_stream(
+        &self,
+        query: impl Into<String> + Send,
+    ) -> impl Future<Output = Result<RecordStream>> + Send;
+
+    /// Execute a Flux query and collect all results into a Vec.
+    fn query(
+        &self,
+        query: impl Into<String> + Send,
+    ) -> impl Future<Output = Result<Vec<FluxRecord>>> + Send {
+        async move { self.query_stream(query).await?.try_collect().await }
+    }
+}
 
 /// InfluxDB 2.x streaming client.
 ///
@@ -46,10 +135,577 @@ use crate::types::FluxRecord;
 /// ```
 #[derive(Clone)]
 pub struct Client {
-    http: reqwest::Client,
+    transport: Arc<dyn Transport>,
     base_url: Url,
     org: String,
     token: String,
+    api: ApiVersion,
+    slow_query: Option<SlowQueryHook>,
+    request_format: RequestFormat,
+    auth_scheme: Option<AuthScheme>,
+    flavor: Option<ServerFlavor>,
+    retry: Option<RetryPolicy>,
+    headers: HeaderMap,
+    password: Option<Arc<Password>>,
+    tokens: Option<Arc<dyn TokenProvider>>,
+}
+
+/// Username and password a [`Client`] authenticates with instead of a token.
+struct Password {
+    username: String,
+    password: String,
+    /// Send the credentials as `Authorization: Token username:password`
+    /// with each request, instead of signing in for a session.
+    v1: bool,
+    /// `Cookie` header of the current session.
+    session: tokio::sync::Mutex<Option<HeaderValue>>,
+}
+
+/// Per-query settings, for [`Client::query_stream_opts`] and
+/// [`Client::query_reader_opts`].
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::client::QueryOptions;
+///
+/// // Large reads for a bulk export over a fast link.
+/// let options = QueryOptions::new().buffer_size(256 * 1024);
+/// let stream = client.query_stream_opts(query, &options).await?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    buffer_size: Option<usize>,
+    limit: CollectLimit,
+    null_policy: NullPolicy,
+    location: Option<String>,
+    idle_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    raw_times: bool,
+    skip_bad_tables: bool,
+    worker: Option<usize>,
+    params: BTreeMap<String, Value>,
+    now: Option<DateTime<FixedOffset>>,
+    org: Option<String>,
+    headers: HeaderMap,
+    dialect: Option<QueryDialect>,
+    token: Option<Secret>,
+    request_format: Option<RequestFormat>,
+}
+
+/// A token, kept out of `Debug` output.
+#[derive(Clone)]
+struct Secret(String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl QueryOptions {
+    /// Create options with the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size in bytes of the buffer the response is read through
+    /// (default: [`DEFAULT_BUFFER_CAPACITY`]).
+    ///
+    /// Raise it for large exports over fast links; lower it when running many
+    /// small queries concurrently.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set how empty cells without a default are read (default: [`NullPolicy::Null`]).
+    pub fn null_policy(mut self, policy: NullPolicy) -> Self {
+        self.null_policy = policy;
+        self
+    }
+
+    /// Read times and durations as `i64` nanoseconds instead of `chrono`
+    /// values; see [`AnnotatedCsvParser::raw_times`].
+    pub fn raw_times(mut self, raw: bool) -> Self {
+        self.raw_times = raw;
+        self
+    }
+
+    /// Continue with the next table when a row fails to parse; see
+    /// [`AnnotatedCsvParser::skip_bad_tables`].
+    ///
+    /// Streams then yield [`Error::TableSkipped`] for each abandoned table and
+    /// keep going.
+    pub fn skip_bad_tables(mut self, skip: bool) -> Self {
+        self.skip_bad_tables = skip;
+        self
+    }
+
+    /// Fail with [`Error::Stalled`] if no record arrives for `timeout`.
+    ///
+    /// The clock restarts with every record, and also covers the wait for the
+    /// first record once the response has started, so set it above the time
+    /// the query needs to produce its first row. Unlike a total timeout, a
+    /// long but steadily progressing export is never cut off. This catches
+    /// connections that hang without being closed, as happens when a NAT
+    /// gateway drops its mapping.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Read and parse the response on a spawned task, buffering up to
+    /// `records` parsed records ahead of the consumer.
+    ///
+    /// Parsing then runs in parallel with the code consuming the stream
+    /// instead of between its polls, which pays off on multi-threaded
+    /// runtimes when both sides are CPU-bound. Once the buffer is full the
+    /// task stops reading, so the response is still throttled by the
+    /// consumer. Only [`Client::query_stream_opts`] uses this setting; it
+    /// must be called within a Tokio runtime.
+    pub fn parse_in_worker(mut self, records: usize) -> Self {
+        self.worker = Some(records);
+        self
+    }
+
+    /// Set the query parameter `name`, which the query reads as
+    /// `params.<name>`.
+    ///
+    /// Parameters are sent next to the query rather than spliced into it, so
+    /// values taken from user input cannot change the query. Strings,
+    /// numbers and booleans keep their type; other values are sent as their
+    /// [text form](Value::to_string_lossy), to be converted in Flux with
+    /// `time(v:)` or `duration(v:)`. Requires [`RequestFormat::Json`].
+    ///
+    /// Only InfluxDB Cloud accepts parameters. Against other servers detected
+    /// by [`Client::with_detected_api`], they are declared in the script as
+    /// an escaped `params` record instead, with any request format; null
+    /// values then fail with [`Error::Config`].
+    pub fn param(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+
+    /// Evaluate the query as if it ran at `now`.
+    ///
+    /// Relative times such as `range(start: -1h)` and calls to `now()` then
+    /// refer to this instant instead of the server's clock, which makes
+    /// backfills and tests reproducible. Sent as the `now` field of the
+    /// request, or as a Flux `now` option with [`RequestFormat::Flux`].
+    pub fn now(mut self, now: DateTime<FixedOffset>) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Request the response in `dialect` (default: [`QueryDialect::new`]).
+    ///
+    /// Requires [`RequestFormat::Json`].
+    pub fn dialect(mut self, dialect: QueryDialect) -> Self {
+        self.dialect = Some(dialect);
+        self
+    }
+
+    /// Run the query in organization `org` instead of the client's.
+    pub fn org(mut self, org: impl Into<String>) -> Self {
+        self.org = Some(org.into());
+        self
+    }
+
+    /// Add header `name` to the query request, replacing any value the
+    /// client would send.
+    ///
+    /// Useful for routing or tracing headers expected by a proxy, such as
+    /// `X-Request-Id`. `Authorization` is always set from the client's token,
+    /// or the one given to [`token`](Self::token).
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Send the query in `format` instead of the client's
+    /// [request format](ClientBuilder::request_format).
+    ///
+    /// Use [`RequestFormat::Flux`] for very large generated scripts, which
+    /// are then posted as they are instead of escaped into JSON.
+    pub fn request_format(mut self, format: RequestFormat) -> Self {
+        self.request_format = Some(format);
+        self
+    }
+
+    /// Authorize the query with `token` instead of the client's.
+    ///
+    /// Lets a multi-tenant service share one client, and its connection
+    /// pool, between tenants with their own tokens. Combine with
+    /// [`org`](Self::org) when tenants live in separate organizations.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(Secret(token.into()));
+        self
+    }
+
+    /// Fail with [`Error::Timeout`] if the query has not completed within
+    /// `timeout`.
+    ///
+    /// The deadline covers sending the request, waiting for the response and
+    /// reading it to the end; when it passes, the response is dropped, which
+    /// closes its connection. See [`idle_timeout`](Self::idle_timeout) for a
+    /// limit that never cuts off a progressing query. Raw response streams
+    /// fail with an I/O error of kind [`TimedOut`](std::io::ErrorKind::TimedOut)
+    /// instead.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run the query in the IANA time zone `zone`, such as `"Europe/Paris"`.
+    ///
+    /// Sets the Flux `location` option, which `aggregateWindow`, `window`,
+    /// `truncateTimeColumn` and the `date` functions use to place day and
+    /// month boundaries. Returned times are still in UTC; see
+    /// [`RecordStreamExt::in_timezone`](crate::adapters::RecordStreamExt::in_timezone)
+    /// to convert them.
+    pub fn location(mut self, zone: impl Into<String>) -> Self {
+        self.location = Some(zone.into());
+        self
+    }
+
+    /// Run the query in time zone `tz`; see [`location`](Self::location).
+    #[cfg(feature = "chrono-tz")]
+    pub fn timezone(self, tz: chrono_tz::Tz) -> Self {
+        self.location(tz.name())
+    }
+
+    /// Fail [`Client::query_opts`] with
+    /// [`Error::LimitExceeded`] if the result has more than `n` records.
+    pub fn max_records(mut self, n: usize) -> Self {
+        self.limit = self.limit.records(n);
+        self
+    }
+
+    /// Fail [`Client::query_opts`] with [`Error::LimitExceeded`] if the
+    /// result takes more than `bytes` of memory, as estimated by
+    /// [`FluxRecord::estimated_size`].
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.limit = self.limit.bytes(bytes);
+        self
+    }
+}
+
+/// Builder for a [`Client`] with custom connection settings.
+///
+/// Created with [`Client::builder`]. Unlike [`Client::new`], [`build`](Self::build)
+/// reports an invalid URL as an error instead of panicking.
+///
+/// # Example
+///
+/// ```ignore
+/// use std::time::Duration;
+/// use influxdb_stream::Client;
+///
+/// // Keep long streams alive behind a load balancer with a 60s idle timeout.
+/// let client = Client::builder("http://localhost:8086", "my-org", "my-token")
+///     .tcp_keepalive(Duration::from_secs(30))
+///     .pool_idle_timeout(Duration::from_secs(50))
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct ClientBuilder {
+    url: String,
+    org: String,
+    token: String,
+    connection: ConnectionOptions,
+    transport: Option<Arc<dyn Transport>>,
+    api: ApiVersion,
+    slow_query: Option<SlowQueryHook>,
+    max_download_rate: Option<u64>,
+    request_format: RequestFormat,
+    auth_scheme: Option<AuthScheme>,
+    retry: Option<RetryPolicy>,
+    failover: Vec<String>,
+    user_agent: Option<String>,
+    headers: HeaderMap,
+    password: Option<(String, String, bool)>,
+    tokens: Option<Arc<dyn TokenProvider>>,
+}
+
+impl ClientBuilder {
+    /// Close pooled connections that have been idle for `timeout`.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.connection = self.connection.pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host in the pool.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.connection = self.connection.pool_max_idle_per_host(max);
+        self
+    }
+
+    /// Send TCP keepalive probes after `interval` of inactivity.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.connection = self.connection.tcp_keepalive(interval);
+        self
+    }
+
+    /// Give up connecting to the server after `timeout`.
+    ///
+    /// Limits only the TCP and TLS handshakes; see
+    /// [`QueryOptions::timeout`] for a limit on whole queries.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connection = self.connection.connect_timeout(timeout);
+        self
+    }
+
+    /// Recreate connections once they are `lifetime` old, so DNS changes of
+    /// the endpoint are picked up without restarting the process.
+    pub fn max_connection_lifetime(mut self, lifetime: Duration) -> Self {
+        self.connection = self.connection.max_lifetime(lifetime);
+        self
+    }
+
+    /// Set the TLS settings of `https` connections, such as a private root
+    /// CA or a client certificate for mutual TLS. See [`TlsConfig`].
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.connection = self.connection.tls(tls);
+        self
+    }
+
+    /// Replace all connection settings at once.
+    pub fn connection_options(mut self, options: ConnectionOptions) -> Self {
+        self.connection = options;
+        self
+    }
+
+    /// Send `agent` as the `User-Agent` of every request, to tell services
+    /// apart in server and proxy logs.
+    pub fn user_agent(mut self, agent: impl Into<String>) -> Self {
+        self.user_agent = Some(agent.into());
+        self
+    }
+
+    /// Add header `name` to every request of the client; may be repeated.
+    ///
+    /// Useful for headers required by a proxy in front of InfluxDB, such as
+    /// `Proxy-Authorization` or a tenant ID. Headers set by the client for a
+    /// request, including `Authorization`, `Content-Type` and those of
+    /// [`QueryOptions::header`], take precedence.
+    pub fn default_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Send requests through `transport`.
+    ///
+    /// Connection settings only apply to the built-in transports and are
+    /// ignored when a custom transport is set.
+    pub fn transport(mut self, transport: impl Transport) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Talk to the server through `api` (default: [`ApiVersion::V2`]).
+    ///
+    /// Use [`Client::with_detected_api`] to pick it from the server's
+    /// [`ping`](Client::ping) response instead.
+    pub fn api_version(mut self, api: ApiVersion) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// Send the token with `scheme` instead of the one implied by the
+    /// [API version](Self::api_version).
+    pub fn auth_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.auth_scheme = Some(scheme);
+        self
+    }
+
+    /// Ask `provider` for the token of each request, instead of sending the
+    /// one the builder was created with. See [`TokenProvider`].
+    ///
+    /// A request answered with `401 Unauthorized` is sent once more, after
+    /// [`TokenProvider::invalidate`], with a new token from `provider`.
+    pub fn token_provider(mut self, provider: impl TokenProvider) -> Self {
+        self.tokens = Some(Arc::new(provider));
+        self
+    }
+
+    /// Sign in with `username` and `password` instead of the token.
+    ///
+    /// For InfluxDB 2.x installations that do not hand out tokens. The
+    /// client posts the credentials to `/api/v2/signin` before its first
+    /// request, sends the session cookie it receives with the following
+    /// ones, and signs in again when the server no longer accepts it.
+    pub fn username_password(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.password = Some((username.into(), password.into(), false));
+        self
+    }
+
+    /// Authenticate with InfluxDB 1.x `username` and `password`, sent as
+    /// `Authorization: Token username:password` with every request, instead
+    /// of the token.
+    ///
+    /// InfluxDB 1.8 and the 1.x compatibility API of 2.x accept this header.
+    /// The credentials are not put in the query string, where they would end
+    /// up in proxy logs and error messages.
+    pub fn v1_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.password = Some((username.into(), password.into(), true));
+        self
+    }
+
+    /// Send Flux queries in `format` (default: [`RequestFormat::Json`]).
+    pub fn request_format(mut self, format: RequestFormat) -> Self {
+        self.request_format = format;
+        self
+    }
+
+    /// Read responses at no more than `bytes_per_sec`, summed over all
+    /// queries of the client. See [`ThrottledTransport`].
+    ///
+    /// This keeps large exports from saturating the network of a shared
+    /// host; it also applies to custom transports.
+    pub fn max_download_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.max_download_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Fail over to the replica at `url` when a request cannot be started on
+    /// the endpoints before it; may be repeated. See [`FailoverTransport`].
+    ///
+    /// Replicas are tried in the order they were added, after the URL the
+    /// builder was created with. Like the download rate, this also applies
+    /// to custom transports.
+    pub fn failover(mut self, url: impl Into<String>) -> Self {
+        self.failover.push(url.into());
+        self
+    }
+
+    /// Re-send queries and, depending on its [scope](RetryPolicy::scope),
+    /// other requests that fail with a retryable error according to
+    /// `policy`. See [`RetryPolicy`].
+    ///
+    /// Without a policy, every request is sent once.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Report slow queries to `hook`. See [`SlowQueryHook`].
+    pub fn slow_query_hook(mut self, hook: SlowQueryHook) -> Self {
+        self.slow_query = Some(hook);
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// Fails with [`Error::Config`] if the URL, a failover URL or the user
+    /// agent is invalid.
+    pub fn build(self) -> Result<Client> {
+        let mut transport = match self.transport {
+            Some(transport) => transport,
+            None if self.connection == ConnectionOptions::default() => default_transport(),
+            None => transport_with(&self.connection)?,
+        };
+        if let Some(rate) = self.max_download_rate {
+            transport = Arc::new(ThrottledTransport::from_arc(transport, rate));
+        }
+        if !self.failover.is_empty() {
+            let endpoints = std::iter::once(&self.url)
+                .chain(&self.failover)
+                .map(|url| parse_url(url))
+                .collect::<Result<Vec<_>>>()?;
+            transport = Arc::new(FailoverTransport::from_arc(transport, endpoints));
+        }
+        let mut client = Client::from_parts(transport, &self.url, self.org, self.token)?;
+        client.api = self.api;
+        client.slow_query = self.slow_query;
+        client.request_format = self.request_format;
+        client.auth_scheme = self.auth_scheme;
+        client.retry = self.retry;
+        client.headers = self.headers;
+        client.tokens = self.tokens;
+        client.password = self.password.map(|(username, password, v1)| {
+            Arc::new(Password {
+                username,
+                password,
+                v1,
+                session: tokio::sync::Mutex::new(None),
+            })
+        });
+        if let Some(agent) = self.user_agent {
+            let agent = HeaderValue::try_from(agent)
+                .map_err(|e| Error::Config(format!("Invalid user agent: {}", e)))?;
+            client.headers.insert(USER_AGENT, agent);
+        }
+        Ok(client)
+    }
+}
+
+impl std::fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientBuilder")
+            .field("url", &self.url)
+            .field("org", &self.org)
+            .field("connection", &self.connection)
+            .field("api", &self.api)
+            .field("slow_query", &self.slow_query)
+            .field("max_download_rate", &self.max_download_rate)
+            .field("request_format", &self.request_format)
+            .field("auth_scheme", &self.auth_scheme)
+            .field("retry", &self.retry)
+            .field("failover", &self.failover)
+            .field("user_agent", &self.user_agent)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("username", &self.password.as_ref().map(|p| &p.0))
+            .field("token_provider", &self.tokens.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Format of the `Authorization` header sent by a [`Client`].
+///
+/// Defaults to `Token` for [`ApiVersion::V2`] and `Bearer` for
+/// [`ApiVersion::V3`]; set it with [`ClientBuilder::auth_scheme`] for
+/// deployments that expect the other one, such as OAuth proxies or Cloud
+/// Dedicated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AuthScheme {
+    /// `Authorization: Token <token>`.
+    Token,
+    /// `Authorization: Bearer <token>`.
+    Bearer,
+}
+
+impl AuthScheme {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthScheme::Token => "Token",
+            AuthScheme::Bearer => "Bearer",
+        }
+    }
+}
+
+/// How a [`Client`] encodes the body of a Flux query request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RequestFormat {
+    /// A JSON object holding the script and the CSV dialect, sent as
+    /// `application/json`.
+    #[default]
+    Json,
+    /// The bare script, sent as `application/vnd.flux`.
+    ///
+    /// Some proxies and older gateways only accept this form, and large
+    /// scripts are sent without JSON escaping. The server answers without
+    /// annotations, so column types are limited; see
+    /// [`AnnotatedCsvParser::annotations`].
+    Flux,
 }
 
 /// Query payload for the InfluxDB API.
@@ -59,34 +715,112 @@ struct QueryPayload {
     #[serde(rename = "type")]
     query_type: String,
     dialect: QueryDialect,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    params: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    now: Option<String>,
 }
 
-/// CSV dialect settings for query responses.
-#[derive(Debug, Serialize)]
-struct QueryDialect {
-    annotations: Vec<String>,
+/// An annotation row InfluxDB can add to each table of a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Annotation {
+    /// `#datatype`: the type of each column. Required to read values with
+    /// their types.
+    Datatype,
+    /// `#group`: whether each column is part of the group key.
+    Group,
+    /// `#default`: the value of empty cells.
+    Default,
+}
+
+/// CSV dialect of query responses, for [`QueryOptions::dialect`].
+///
+/// The response parser is configured to match the requested dialect.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::client::{Annotation, QueryDialect, QueryOptions};
+///
+/// // Only column types, for smaller exports.
+/// let dialect = QueryDialect::new().annotations([Annotation::Datatype]);
+/// let options = QueryOptions::new().dialect(dialect);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryDialect {
+    annotations: Vec<Annotation>,
     #[serde(rename = "commentPrefix")]
-    comment_prefix: String,
+    comment_prefix: &'static str,
     #[serde(rename = "dateTimeFormat")]
-    date_time_format: String,
-    delimiter: String,
+    date_time_format: &'static str,
+    #[serde(serialize_with = "serialize_delimiter")]
+    delimiter: u8,
     header: bool,
 }
 
-impl Default for QueryDialect {
-    fn default() -> Self {
+impl QueryDialect {
+    /// Create the default dialect: all annotations, `,` delimiter and a
+    /// header row.
+    pub fn new() -> Self {
         Self {
-            annotations: vec![
-                "datatype".to_string(),
-                "group".to_string(),
-                "default".to_string(),
-            ],
-            comment_prefix: "#".to_string(),
-            date_time_format: "RFC3339".to_string(),
-            delimiter: ",".to_string(),
+            annotations: vec![Annotation::Datatype, Annotation::Group, Annotation::Default],
+            comment_prefix: "#",
+            date_time_format: "RFC3339",
+            delimiter: b',',
             header: true,
         }
     }
+
+    /// Request only `annotations`.
+    ///
+    /// Without [`Annotation::Datatype`], every value except `table` and the
+    /// `_start`, `_stop` and `_time` columns is read as a string, as for
+    /// [`RequestFormat::Flux`] requests. Without [`Annotation::Group`],
+    /// the `group` flag of every column is `false`.
+    /// Without [`Annotation::Default`], empty cells are read only according to
+    /// the [`NullPolicy`].
+    pub fn annotations(mut self, annotations: impl IntoIterator<Item = Annotation>) -> Self {
+        self.annotations = annotations.into_iter().collect();
+        self
+    }
+
+    /// Set the field delimiter (default: `,`).
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Request a header row naming the columns of each table (default:
+    /// `true`).
+    ///
+    /// Queries with `false` fail with [`Error::Config`]: without the header,
+    /// records could not be told apart by table or time, and error tables
+    /// would be read as data. Parse such responses with
+    /// [`AnnotatedCsvParser::header`] instead.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+impl Default for QueryDialect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueryDialect {
+    fn typed(&self) -> bool {
+        self.annotations.contains(&Annotation::Datatype)
+    }
+}
+
+fn serialize_delimiter<S: serde::Serializer>(
+    delimiter: &u8,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_char(char::from(*delimiter))
 }
 
 impl QueryPayload {
@@ -95,7 +829,106 @@ impl QueryPayload {
             query: query.into(),
             query_type: "flux".to_string(),
             dialect: QueryDialect::default(),
+            params: serde_json::Map::new(),
+            now: None,
+        }
+    }
+}
+
+/// Get the `Accept-Encoding` of a query, adding zstd to the encodings in
+/// `accepted`, if any.
+///
+/// Proxies and gateways in front of InfluxDB may compress responses when
+/// asked to; zstd decodes several times faster than gzip, which matters for
+/// multi-gigabyte exports.
+#[cfg(feature = "zstd")]
+fn accept_zstd(accepted: Option<&HeaderValue>) -> HeaderValue {
+    let Some(accepted) = accepted.and_then(|v| v.to_str().ok()) else {
+        return HeaderValue::from_static("zstd, gzip;q=0.9");
+    };
+    let listed = accepted.split(',').any(|e| {
+        e.split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .eq_ignore_ascii_case("zstd")
+    });
+    if listed {
+        return HeaderValue::from_str(accepted).expect("valid header value");
+    }
+    HeaderValue::from_str(&format!("{accepted}, zstd")).expect("valid header value")
+}
+
+/// Decode a response body compressed with zstd or gzip.
+#[cfg(feature = "zstd")]
+fn decode_response(mut response: TransportResponse) -> TransportResponse {
+    use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+    use tokio_util::io::ReaderStream;
+
+    let encoding = (response.headers.get(http::header::CONTENT_ENCODING))
+        .map(|e| e.as_bytes().to_ascii_lowercase());
+    let zstd = match encoding.as_deref() {
+        Some(b"zstd") => true,
+        Some(b"gzip") => false,
+        _ => return response,
+    };
+    response.headers.remove(http::header::CONTENT_ENCODING);
+    let body = StreamReader::new(response.body);
+    response.body = if zstd {
+        Box::pin(ReaderStream::new(ZstdDecoder::new(body)))
+    } else {
+        Box::pin(ReaderStream::new(GzipDecoder::new(body)))
+    };
+    response
+}
+
+/// Returns true if `request` only reads: a `GET` or a query.
+fn is_read(request: &TransportRequest) -> bool {
+    let path = request.url.path();
+    matches!(request.method, Method::GET | Method::HEAD)
+        || path.ends_with("/api/v2/query")
+        || path.ends_with("/api/v3/query_sql")
+}
+
+/// Declare `params` as a Flux record in `query`, for servers that do not
+/// accept query parameters.
+fn with_params(query: &str, params: &BTreeMap<String, Value>) -> Result<String> {
+    let mut record = String::from("params = {");
+    for (i, (name, value)) in params.iter().enumerate() {
+        if i > 0 {
+            record.push_str(", ");
         }
+        flux::validate_identifier(name)?;
+        let literal = match value {
+            Value::Bool(b) => flux::Literal(b).to_string(),
+            Value::Long(l) => flux::Literal(l).to_string(),
+            Value::UnsignedLong(u) => flux::Literal(u).to_string(),
+            Value::Double(d) => flux::Literal(&d.0).to_string(),
+            Value::Null => {
+                return Err(Error::Config(format!(
+                    "query parameter '{}' is null, which Flux cannot declare",
+                    name
+                )));
+            }
+            // Sent as text, like the JSON parameters.
+            other => flux::Literal(other.to_string_lossy().as_ref()).to_string(),
+        };
+        record.push_str(&format!("{}: {}", name, literal));
+    }
+    record.push('}');
+    Ok(flux::with_option(query, &record))
+}
+
+/// Convert a query parameter to JSON.
+fn param_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Bool(b) => (*b).into(),
+        Value::Long(i) => (*i).into(),
+        Value::UnsignedLong(u) => (*u).into(),
+        Value::Double(d) => serde_json::Number::from_f64(d.0)
+            .map_or_else(|| value.to_string_lossy().into(), serde_json::Value::Number),
+        Value::Null => serde_json::Value::Null,
+        other => other.to_string_lossy().into(),
     }
 }
 
@@ -110,39 +943,133 @@ impl Client {
     ///
     /// # Panics
     ///
-    /// Panics if the provided URL is invalid.
+    /// Panics if the provided URL is invalid. Use [`Client::builder`] for URLs
+    /// taken from configuration, to get an error instead.
     pub fn new(url: impl Into<String>, org: impl Into<String>, token: impl Into<String>) -> Self {
-        let url_str = url.into();
-        let base_url = Url::parse(&url_str)
-            .unwrap_or_else(|e| panic!("Invalid InfluxDB URL '{}': {}", url_str, e));
+        Self::from_parts(default_transport(), &url.into(), org.into(), token.into())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        Self {
-            http: reqwest::Client::new(),
-            base_url,
+    /// Create a client from the environment variables used by the `influx`
+    /// CLI and the official client libraries.
+    ///
+    /// The server URL is read from `INFLUX_HOST`, or `INFLUX_URL` if it is
+    /// unset, the organization from `INFLUX_ORG`, and the token from
+    /// `INFLUX_TOKEN`. Fails with [`Error::Config`] naming every missing
+    /// variable, or if the URL is invalid. Use
+    /// [`builder_from_env`](Self::builder_from_env) to change other settings.
+    pub fn from_env() -> Result<Self> {
+        Self::builder_from_env()?.build()
+    }
+
+    /// Start building a client configured from the environment; see
+    /// [`from_env`](Self::from_env).
+    pub fn builder_from_env() -> Result<ClientBuilder> {
+        builder_from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Create a client from a configuration profile of the `influx` CLI.
+    ///
+    /// Profiles are read from `~/.influxdbv2/configs`, or the file named by
+    /// `INFLUX_CONFIGS_PATH`, as written by `influx config create`. `profile`
+    /// selects one by name; `None` selects the active one. Fails with
+    /// [`Error::Config`] if the file cannot be read or has no such profile.
+    #[cfg(feature = "influx-config")]
+    pub fn from_influx_config(profile: Option<&str>) -> Result<Self> {
+        Self::builder_from_influx_config(profile)?.build()
+    }
+
+    /// Start building a client from a profile of the `influx` CLI; see
+    /// [`from_influx_config`](Self::from_influx_config).
+    #[cfg(feature = "influx-config")]
+    pub fn builder_from_influx_config(profile: Option<&str>) -> Result<ClientBuilder> {
+        let settings = crate::config::load(profile)?;
+        Ok(Self::builder(settings.url, settings.org, settings.token))
+    }
+
+    /// Start building a client with custom connection settings.
+    ///
+    /// See [`ClientBuilder`].
+    pub fn builder(
+        url: impl Into<String>,
+        org: impl Into<String>,
+        token: impl Into<String>,
+    ) -> ClientBuilder {
+        ClientBuilder {
+            url: url.into(),
             org: org.into(),
             token: token.into(),
+            connection: ConnectionOptions::default(),
+            transport: None,
+            api: ApiVersion::default(),
+            slow_query: None,
+            max_download_rate: None,
+            request_format: RequestFormat::default(),
+            auth_scheme: None,
+            retry: None,
+            failover: Vec::new(),
+            user_agent: None,
+            headers: HeaderMap::new(),
+            password: None,
+            tokens: None,
         }
     }
 
     /// Create a new client with a custom reqwest client.
     ///
     /// This allows you to configure timeouts, proxies, TLS settings, etc.
+    #[cfg(feature = "reqwest")]
     pub fn with_http_client(
         http: reqwest::Client,
         url: impl Into<String>,
         org: impl Into<String>,
         token: impl Into<String>,
     ) -> Self {
-        let url_str = url.into();
-        let base_url = Url::parse(&url_str)
-            .unwrap_or_else(|e| panic!("Invalid InfluxDB URL '{}': {}", url_str, e));
+        Self::with_transport(ReqwestTransport::new(http), url, org, token)
+    }
+
+    /// Create a new client that sends requests through `transport`.
+    ///
+    /// Use this to plug in another HTTP stack, or to serve byte streams to the
+    /// client directly in tests. See [`Transport`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided URL is invalid.
+    pub fn with_transport(
+        transport: impl Transport,
+        url: impl Into<String>,
+        org: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Self::from_parts(Arc::new(transport), &url.into(), org.into(), token.into())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        Self {
-            http,
+    /// Create a client sharing `transport`, failing on an invalid URL.
+    pub(crate) fn from_parts(
+        transport: Arc<dyn Transport>,
+        url: &str,
+        org: String,
+        token: String,
+    ) -> Result<Self> {
+        let base_url = parse_url(url)?;
+
+        Ok(Self {
+            transport,
             base_url,
-            org: org.into(),
-            token: token.into(),
-        }
+            org,
+            token,
+            api: ApiVersion::default(),
+            slow_query: None,
+            request_format: RequestFormat::default(),
+            auth_scheme: None,
+            flavor: None,
+            retry: None,
+            headers: HeaderMap::new(),
+            password: None,
+            tokens: None,
+        })
     }
 
     /// Get the base URL.
@@ -155,11 +1082,697 @@ impl Client {
         &self.org
     }
 
+    /// Get the API generation this client talks to.
+    pub fn api_version(&self) -> ApiVersion {
+        self.api
+    }
+
+    /// Get the scheme of the `Authorization` header this client sends.
+    pub fn auth_scheme(&self) -> AuthScheme {
+        self.auth_scheme.unwrap_or(match self.api {
+            ApiVersion::V2 => AuthScheme::Token,
+            ApiVersion::V3 => AuthScheme::Bearer,
+        })
+    }
+
+    /// Get the kind of server detected by
+    /// [`with_detected_api`](Self::with_detected_api), if any.
+    pub fn server_flavor(&self) -> Option<ServerFlavor> {
+        self.flavor
+    }
+
+    /// Fail early if the detected server lacks `capability`.
+    ///
+    /// Without detection, requests are sent and left to the server to reject.
+    fn require(&self, capability: Capability) -> Result<()> {
+        match self.flavor {
+            Some(flavor) if !flavor.supports(capability) => Err(Error::Config(format!(
+                "{} is not supported by {}",
+                capability, flavor
+            ))),
+            _ => Ok(()),
+        }
+    }
+
     /// Build the full URL for an API endpoint.
-    fn endpoint(&self, path: &str) -> String {
+    ///
+    /// `path` is appended to the path of the base URL, so a client created
+    /// for `https://gateway.example/influx/` talks to
+    /// `https://gateway.example/influx/api/v2/...`.
+    fn endpoint(&self, path: &str) -> Url {
         let mut url = self.base_url.clone();
-        url.set_path(path);
-        url.to_string()
+        let prefix = url.path().trim_end_matches('/');
+        url.set_path(&format!("{}{}", prefix, path));
+        url
+    }
+
+    /// Send a request with the authorization header and check the response status.
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        body: impl Into<bytes::Bytes>,
+    ) -> Result<TransportResponse> {
+        let request = TransportRequest {
+            method,
+            url,
+            headers,
+            body: body.into(),
+            body_stream: None,
+        };
+        self.send_request(request).await
+    }
+
+    /// Like [`send`](Self::send), streaming the request body from `body`.
+    async fn send_stream(
+        &self,
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        body: ByteStream,
+    ) -> Result<TransportResponse> {
+        let request = TransportRequest {
+            method,
+            url,
+            headers,
+            body: Bytes::new(),
+            body_stream: Some(body),
+        };
+        self.send_request(request).await
+    }
+
+    /// Build the `Authorization` header for `token`.
+    fn authorization(&self, token: &str) -> Result<HeaderValue> {
+        let scheme = self.auth_scheme().as_str();
+        let mut value = HeaderValue::try_from(format!("{} {}", scheme, token))
+            .map_err(|e| Error::Config(format!("Invalid token: {}", e)))?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    /// Send `request`, authorized with the client's credentials unless it
+    /// already carries an `Authorization` header.
+    async fn send_request(&self, mut request: TransportRequest) -> Result<TransportResponse> {
+        if request.headers.contains_key(AUTHORIZATION) {
+            return self.send_authorized(request).await;
+        }
+        let Some(password) = &self.password else {
+            let Some(provider) = &self.tokens else {
+                request
+                    .headers
+                    .insert(AUTHORIZATION, self.authorization(&self.token)?);
+                return self.send_authorized(request).await;
+            };
+            let auth = self.authorization(provider.token().await?.secret())?;
+            request.headers.insert(AUTHORIZATION, auth);
+            let Some(mut again) = request.try_clone() else {
+                return self.send_authorized(request).await;
+            };
+            // A token can be revoked or rotated before it expires.
+            return match self.send_authorized(request).await {
+                Err(e) if e.status() == Some(401) => {
+                    provider.invalidate();
+                    let auth = self.authorization(provider.token().await?.secret())?;
+                    again.headers.insert(AUTHORIZATION, auth);
+                    self.send_authorized(again).await
+                }
+                result => result,
+            };
+        };
+        if password.v1 {
+            let credentials = format!("Token {}:{}", password.username, password.password);
+            let mut auth = HeaderValue::try_from(credentials)
+                .map_err(|e| Error::Config(format!("Invalid username or password: {}", e)))?;
+            auth.set_sensitive(true);
+            request.headers.insert(AUTHORIZATION, auth);
+            return self.send_authorized(request).await;
+        }
+
+        let cookie = self.session(password, None).await?;
+        request.headers.insert(COOKIE, cookie.clone());
+        let Some(mut again) = request.try_clone() else {
+            return self.send_authorized(request).await;
+        };
+        match self.send_authorized(request).await {
+            Err(e) if e.status() == Some(401) => {
+                let cookie = self.session(password, Some(&cookie)).await?;
+                again.headers.insert(COOKIE, cookie);
+                self.send_authorized(again).await
+            }
+            result => result,
+        }
+    }
+
+    /// Get the `Cookie` header of the current session, signing in first if
+    /// there is none or the server rejected `stale`.
+    async fn session(
+        &self,
+        password: &Password,
+        stale: Option<&HeaderValue>,
+    ) -> Result<HeaderValue> {
+        let mut session = password.session.lock().await;
+        if let Some(cookie) = session.as_ref().filter(|c| Some(*c) != stale) {
+            return Ok(cookie.clone());
+        }
+
+        let credentials = base64::engine::general_purpose::STANDARD
+            .encode(format!("{}:{}", password.username, password.password));
+        let mut headers = HeaderMap::new();
+        let mut basic = HeaderValue::try_from(format!("Basic {}", credentials))
+            .map_err(|e| Error::Config(format!("Invalid username or password: {}", e)))?;
+        basic.set_sensitive(true);
+        headers.insert(AUTHORIZATION, basic);
+        let request = TransportRequest {
+            method: Method::POST,
+            url: self.endpoint("/api/v2/signin"),
+            headers,
+            body: Bytes::new(),
+            body_stream: None,
+        };
+        let response = self.send_authorized(request).await?;
+        let mut cookie = response
+            .headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok()?.split(';').next())
+            .next()
+            .and_then(|cookie| HeaderValue::try_from(cookie.trim()).ok())
+            .ok_or_else(|| Error::Parse {
+                message: "Sign-in response has no session cookie".to_string(),
+            })?;
+        cookie.set_sensitive(true);
+        *session = Some(cookie.clone());
+        Ok(cookie)
+    }
+
+    /// Send an authorized `request` with the default headers, retrying it
+    /// according to the retry policy.
+    async fn send_authorized(&self, mut request: TransportRequest) -> Result<TransportResponse> {
+        for name in self.headers.keys() {
+            if !request.headers.contains_key(name) {
+                for value in self.headers.get_all(name) {
+                    request.headers.append(name, value.clone());
+                }
+            }
+        }
+
+        let Some(policy) = self
+            .retry
+            .as_ref()
+            .filter(|policy| request.body_stream.is_none() && policy.covers(is_read(&request)))
+        else {
+            return self.send_once(request).await;
+        };
+        let mut retry = 0;
+        loop {
+            let attempt = request.try_clone().expect("buffered body");
+            match self.send_once(attempt).await {
+                Err(e) if e.is_retryable() && retry + 1 < policy.attempts() => {
+                    tokio::time::sleep(policy.delay_after(&e, retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_once(&self, request: TransportRequest) -> Result<TransportResponse> {
+        let response = self.transport.send(request).await?;
+        if response.status.is_success() {
+            Ok(response)
+        } else {
+            Err(status_error(response).await)
+        }
+    }
+
+    /// Get a batched writer for `bucket`; see [`WriteApi`].
+    pub fn write_api(&self, bucket: impl Into<String>) -> WriteApi {
+        self.write_api_opts(bucket, WriteOptions::default())
+    }
+
+    /// Get a batched writer for `bucket` with custom `options`.
+    pub fn write_api_opts(&self, bucket: impl Into<String>, options: WriteOptions) -> WriteApi {
+        WriteApi::new(self.clone(), bucket.into(), options)
+    }
+
+    /// Write line protocol to `bucket` in one request.
+    ///
+    /// `lines` are newline-separated, with timestamps in `precision`.
+    pub async fn write_lines(
+        &self,
+        bucket: &str,
+        precision: Precision,
+        lines: impl Into<Bytes>,
+    ) -> Result<()> {
+        let mut endpoint = self.endpoint("/api/v2/write");
+        endpoint
+            .query_pairs_mut()
+            .append_pair("org", &self.org)
+            .append_pair("bucket", bucket)
+            .append_pair("precision", precision.as_str());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        self.send(Method::POST, endpoint, headers, lines).await?;
+        Ok(())
+    }
+
+    /// Write line protocol to an InfluxDB 1.x `database` in one request.
+    ///
+    /// Posts to `/write?db=...&rp=...`, leaving out `rp` when
+    /// `retention_policy` is `None` so the default policy is used. InfluxDB
+    /// 1.8 accepts `Authorization: Token username:password`, so with
+    /// authentication enabled the client's token must be
+    /// `username:password`, or the client built with
+    /// [`ClientBuilder::v1_credentials`].
+    pub async fn write_lines_v1(
+        &self,
+        database: &str,
+        retention_policy: Option<&str>,
+        precision: Precision,
+        lines: impl Into<Bytes>,
+    ) -> Result<()> {
+        let mut endpoint = self.endpoint("/write");
+        {
+            let mut query = endpoint.query_pairs_mut();
+            query.append_pair("db", database);
+            if let Some(rp) = retention_policy {
+                query.append_pair("rp", rp);
+            }
+            let precision = match precision {
+                Precision::Nanoseconds => "n",
+                Precision::Microseconds => "u",
+                Precision::Milliseconds => "ms",
+                Precision::Seconds => "s",
+            };
+            query.append_pair("precision", precision);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        self.send(Method::POST, endpoint, headers, lines).await?;
+        Ok(())
+    }
+
+    /// Check that the server is reachable and report its version.
+    pub async fn ping(&self) -> Result<ServerInfo> {
+        let response = self
+            .send(Method::GET, self.endpoint("/ping"), HeaderMap::new(), "")
+            .await?;
+        Ok(ServerInfo::from_headers(&response.headers))
+    }
+
+    /// Get the health status of the server from `/health`.
+    pub async fn health(&self) -> Result<Health> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        let response = self
+            .send(Method::GET, self.endpoint("/health"), headers, "")
+            .await?;
+        let body = read_body(response.body, MAX_ERROR_BODY).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Ping the server and adapt the client to the kind of server it is.
+    ///
+    /// The API generation and, unless set explicitly, the authorization
+    /// scheme follow the detected [`ServerFlavor`]; see
+    /// [`ServerInfo::flavor`] for how it is chosen. When `/ping` reports no
+    /// version, the version from [`health`](Self::health) is used instead.
+    ///
+    /// Afterwards, calls the server cannot handle, such as Flux queries
+    /// against InfluxDB 3 or backups of InfluxDB Cloud, fail with
+    /// [`Error::Config`] before any request is sent, and query parameters
+    /// are declared in the script for servers other than InfluxDB Cloud;
+    /// see [`QueryOptions::param`].
+    pub async fn with_detected_api(mut self) -> Result<Self> {
+        let mut info = self.ping().await?;
+        if info.version.is_none() {
+            info.version = self.health().await.ok().and_then(|h| h.version);
+        }
+        let flavor = info.flavor();
+        self.api = flavor.api_version();
+        self.flavor = Some(flavor);
+        Ok(self)
+    }
+
+    /// Execute a SQL query against an InfluxDB 3 `database` and stream the
+    /// resulting rows.
+    ///
+    /// Rows are requested as JSON Lines from `/api/v3/query_sql`. Values keep
+    /// their JSON type, except the `time` column, which becomes a UTC
+    /// timestamp. All records have table `0`.
+    ///
+    /// This uses the HTTP SQL API rather than FlightSQL, so it needs no gRPC
+    /// stack.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use influxdb_stream::server::ApiVersion;
+    ///
+    /// let client = Client::builder("https://cluster.influxdb.io", "", token)
+    ///     .api_version(ApiVersion::V3)
+    ///     .build()?;
+    /// let mut rows = client
+    ///     .query_sql("sensors", "SELECT host, usage FROM cpu WHERE time > now() - INTERVAL '1 hour'")
+    ///     .await?;
+    /// ```
+    pub async fn query_sql(
+        &self,
+        database: impl Into<String>,
+        sql: impl Into<String>,
+    ) -> Result<RecordStream> {
+        self.require(Capability::Sql)?;
+        let body = serde_json::to_string(&serde_json::json!({
+            "db": database.into(),
+            "q": sql.into(),
+            "format": "jsonl",
+        }))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let response = self
+            .send(
+                Method::POST,
+                self.endpoint("/api/v3/query_sql"),
+                headers,
+                body,
+            )
+            .await
+            .inspect_err(instrument::error)?;
+        Ok(sql::jsonl_records(response.body))
+    }
+
+    /// Estimate the number of series and rows described by `estimate`.
+    ///
+    /// Runs [`Estimate::series_query`] and [`Estimate::rows_query`]
+    /// concurrently.
+    pub async fn estimate_cardinality(&self, estimate: &Estimate) -> Result<Cardinality> {
+        let (series, rows) = futures::try_join!(
+            self.query(estimate.series_query()),
+            self.query(estimate.rows_query()),
+        )?;
+        Ok(Cardinality {
+            series: cardinality::count(&series),
+            rows: cardinality::count(&rows),
+        })
+    }
+
+    /// Look up the ID of the client's organization.
+    ///
+    /// Some API endpoints require the organization ID rather than its name.
+    pub async fn org_id(&self) -> Result<String> {
+        self.require(Capability::Management)?;
+        let mut endpoint = self.endpoint("/api/v2/orgs");
+        endpoint.query_pairs_mut().append_pair("org", &self.org);
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        let response = self.send(Method::GET, endpoint, headers, "").await?;
+        let body = read_body(response.body, usize::MAX).await?;
+        let orgs: serde_json::Value = serde_json::from_slice(&body)?;
+
+        orgs.get("orgs")
+            .and_then(|orgs| orgs.as_array()?.first()?.get("id")?.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| Error::QueryError {
+                message: format!("Organization '{}' not found", self.org),
+                reference: None,
+            })
+    }
+
+    /// Back up the server's metadata and all shards into `dir`; see the
+    /// [`backup`](crate::backup) module for the files written.
+    ///
+    /// Requires an operator token.
+    pub async fn backup(&self, dir: impl AsRef<Path>) -> Result<BackupManifest> {
+        self.backup_opts(dir, &BackupOptions::default()).await
+    }
+
+    /// Back up the server into `dir` with custom `options`.
+    ///
+    /// Shards deleted by retention while the backup runs are left out.
+    pub async fn backup_opts(
+        &self,
+        dir: impl AsRef<Path>,
+        options: &BackupOptions,
+    ) -> Result<BackupManifest> {
+        self.require(Capability::Backup)?;
+        let dir = dir.as_ref();
+        tokio::fs::create_dir_all(dir).await?;
+        let mut backup = Backup::new(dir, options);
+
+        let endpoint = self.endpoint("/api/v2/backup/metadata");
+        let response = self
+            .send(Method::GET, endpoint, HeaderMap::new(), "")
+            .await?;
+        backup
+            .write_metadata(&response.headers, response.body)
+            .await?;
+
+        for (id, bucket_id) in backup.shards() {
+            let endpoint = self.endpoint(&format!("/api/v2/backup/shards/{}", id));
+            match self.send(Method::GET, endpoint, HeaderMap::new(), "").await {
+                Ok(response) => backup.write_shard(id, bucket_id, response.body).await?,
+                Err(e) if e.status() == Some(404) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        backup.finish().await
+    }
+
+    /// Restore the newest backup in `dir` into the server; see the
+    /// [`restore`] module.
+    ///
+    /// This is a full restore that replaces all data on the server. Running
+    /// it again after an interruption resumes where it stopped.
+    pub async fn restore(&self, dir: impl AsRef<Path>) -> Result<RestoreSummary> {
+        self.restore_opts(dir, &RestoreOptions::default()).await
+    }
+
+    /// Restore the newest backup in `dir` with custom `options`.
+    pub async fn restore_opts(
+        &self,
+        dir: impl AsRef<Path>,
+        options: &RestoreOptions,
+    ) -> Result<RestoreSummary> {
+        self.require(Capability::Backup)?;
+        let dir = dir.as_ref();
+        let (manifest_path, manifest) = restore::latest_manifest(dir).await?;
+        let mut state = RestoreState::load(&manifest_path).await?;
+        let mut client = self.clone();
+        if let Some(token) = &state.token {
+            client = client.with_token(token);
+        }
+        let mut summary = RestoreSummary::default();
+        let mut octets = HeaderMap::new();
+        octets.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+
+        if options.is_full() {
+            if let (false, Some(kv)) = (state.kv, &manifest.kv) {
+                let body = restore::file_body(&dir.join(&kv.file_name)).await?;
+                let endpoint = client.endpoint("/api/v2/restore/kv");
+                let response = client
+                    .send_stream(Method::POST, endpoint, octets.clone(), body)
+                    .await?;
+                let body = read_body(response.body, MAX_ERROR_BODY).await?;
+                // The restored KV store may not know the client's token.
+                let token = serde_json::from_slice::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|r| Some(r.get("token")?.as_str()?.to_string()));
+                if let Some(token) = token {
+                    client = client.with_token(&token);
+                    state.token = Some(token);
+                    state.save_token().await?;
+                }
+            }
+            // Saved before the next upload, so that a failure does not
+            // restore the KV store twice.
+            state.kv = true;
+            state.save().await?;
+            if let (false, Some(sql)) = (state.sql, &manifest.sql) {
+                let body = restore::file_body(&dir.join(&sql.file_name)).await?;
+                let endpoint = client.endpoint("/api/v2/restore/sql");
+                client
+                    .send_stream(Method::POST, endpoint, octets.clone(), body)
+                    .await?;
+            }
+            state.sql = true;
+            state.save().await?;
+        } else {
+            for bucket in manifest.buckets.iter().filter(|b| options.selected(b)) {
+                let id = bucket["bucketID"].as_str().unwrap_or_default();
+                if state.buckets.contains_key(id) {
+                    continue;
+                }
+                let response: serde_json::Value = client
+                    .send_json(
+                        Method::POST,
+                        client.endpoint("/api/v2/restore/bucketMetadata"),
+                        Some(bucket),
+                    )
+                    .await?;
+                state
+                    .buckets
+                    .insert(id.to_string(), restore::shard_mappings(&response));
+                state.save().await?;
+            }
+        }
+        summary.token = state.token.clone();
+        let policy = self.retry.clone().unwrap_or_default();
+
+        for shard in &manifest.shards {
+            let target = if options.is_full() {
+                Some(shard.id)
+            } else {
+                let mappings = state.buckets.get(&shard.bucket_id);
+                mappings.and_then(|m| m.get(&shard.id).copied())
+            };
+            // Shards of other buckets, or dropped by the server as expired.
+            let Some(target) = target else { continue };
+            if state.shards.contains(&shard.id) {
+                summary.resumed += 1;
+                continue;
+            }
+
+            let path = dir.join(&shard.file.file_name);
+            let endpoint = client.endpoint(&format!("/api/v2/restore/shards/{}", target));
+            let mut attempt = 0;
+            loop {
+                let body = restore::file_body(&path).await?;
+                let result = client
+                    .send_stream(Method::POST, endpoint.clone(), octets.clone(), body)
+                    .await;
+                match result {
+                    Ok(_) => break,
+                    Err(e) if e.is_retryable() && attempt < options.retries() => {
+                        tokio::time::sleep(policy.delay_after(&e, attempt)).await;
+                        attempt += 1;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            state.shards.insert(shard.id);
+            state.save().await?;
+            summary.shards += 1;
+        }
+        state.finish().await?;
+        Ok(summary)
+    }
+
+    /// Get a copy of the client authenticated with `token` alone, ignoring
+    /// its token provider and username and password.
+    fn with_token(&self, token: &str) -> Client {
+        let mut client = self.clone();
+        client.token = token.to_string();
+        client.tokens = None;
+        client.password = None;
+        client
+    }
+
+    /// List the stacks of the client's organization.
+    pub async fn list_stacks(&self) -> Result<Vec<Stack>> {
+        let mut endpoint = self.endpoint("/api/v2/stacks");
+        endpoint
+            .query_pairs_mut()
+            .append_pair("orgID", &self.org_id().await?);
+        let list: StackList = self.send_json(Method::GET, endpoint, None::<&()>).await?;
+        Ok(list.stacks)
+    }
+
+    /// Create an empty stack named `name`.
+    ///
+    /// Pass its ID to [`TemplateApply::stack`] to install a template into it.
+    pub async fn create_stack(&self, name: &str) -> Result<Stack> {
+        let org_id = self.org_id().await?;
+        let body = NewStack {
+            org_id: &org_id,
+            name,
+        };
+        self.send_json(Method::POST, self.endpoint("/api/v2/stacks"), Some(&body))
+            .await
+    }
+
+    /// Delete the stack `id`, leaving the resources it installed in place.
+    ///
+    /// Use [`uninstall_stack`](Self::uninstall_stack) first to remove them.
+    pub async fn delete_stack(&self, id: &str) -> Result<()> {
+        let mut endpoint = self.stack_endpoint(id, None);
+        endpoint
+            .query_pairs_mut()
+            .append_pair("orgID", &self.org_id().await?);
+        self.send(Method::DELETE, endpoint, HeaderMap::new(), "")
+            .await?;
+        Ok(())
+    }
+
+    /// Remove all resources installed by the stack `id`.
+    pub async fn uninstall_stack(&self, id: &str) -> Result<Stack> {
+        self.require(Capability::Management)?;
+        let endpoint = self.stack_endpoint(id, Some("uninstall"));
+        self.send_json(Method::POST, endpoint, None::<&()>).await
+    }
+
+    /// Apply templates to the client's organization; see [`TemplateApply`].
+    ///
+    /// With [`dry_run`](TemplateApply::dry_run), nothing is installed and the
+    /// summary only describes the changes.
+    pub async fn apply_template(&self, apply: &TemplateApply) -> Result<TemplateSummary> {
+        let mut apply = apply.clone();
+        apply.org_id = self.org_id().await?;
+        let endpoint = self.endpoint("/api/v2/templates/apply");
+        self.send_json(Method::POST, endpoint, Some(&apply)).await
+    }
+
+    /// Export the resources of the stack `id` as a template.
+    ///
+    /// The result is the list of template objects, which can be saved to a
+    /// file or applied elsewhere with [`TemplateApply::new`].
+    pub async fn export_stack(&self, id: &str) -> Result<serde_json::Value> {
+        self.require(Capability::Management)?;
+        let body = ExportRequest { stack_id: id };
+        let endpoint = self.endpoint("/api/v2/templates/export");
+        self.send_json(Method::POST, endpoint, Some(&body)).await
+    }
+
+    fn stack_endpoint(&self, id: &str, action: Option<&str>) -> Url {
+        let mut endpoint = self.endpoint("/api/v2/stacks");
+        if let Ok(mut segments) = endpoint.path_segments_mut() {
+            segments.push(id).extend(action);
+        }
+        endpoint
+    }
+
+    /// Send `body` as JSON, if any, and decode the JSON response.
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<&impl Serialize>,
+    ) -> Result<T> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        let body = match body {
+            Some(body) => {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                serde_json::to_vec(body)?
+            }
+            None => Vec::new(),
+        };
+
+        let response = self.send(method, url, headers, body).await?;
+        let body = read_body(response.body, usize::MAX).await?;
+        Ok(serde_json::from_slice(&body)?)
     }
 
     /// Execute a Flux query and return results as an async stream.
@@ -191,68 +1804,2321 @@ impl Client {
     /// }
     /// println!("Processed {} records", count);
     /// ```
-    pub async fn query_stream(
+    pub async fn query_stream(&self, query: impl Into<String>) -> Result<QueryStream> {
+        self.query_stream_opts(query, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a Flux query with per-query `options` and return a stream of records.
+    ///
+    /// See [`query_stream`](Self::query_stream).
+    pub async fn query_stream_opts(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+    ) -> Result<QueryStream> {
+        let started = Instant::now();
+        let reader = self.query_reader_opts(query, options).await?;
+        let bytes = reader.bytes.clone();
+        let source = match options.worker {
+            Some(records) => Source::Worker(crate::adapters::into_channel(
+                Source::Reader(Box::new(reader)),
+                records,
+            )),
+            None => Source::Reader(Box::new(reader)),
+        };
+
+        Ok(QueryStream {
+            source,
+            cancel: CancelHandle::default(),
+            bytes,
+            started,
+            records: 0,
+            tables: 0,
+            table: None,
+            schema: None,
+        })
+    }
+
+    /// Execute a Flux query with parameters and return a stream of records.
+    ///
+    /// The query reads each entry of `params` as `params.<name>`; see
+    /// [`QueryOptions::param`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let params = BTreeMap::from([("host".to_string(), Value::from(user_input))]);
+    /// let stream = client
+    ///     .query_stream_with_params(
+    ///         r#"from(bucket: "sensors") |> range(start: -1h) |> filter(fn: (r) => r.host == params.host)"#,
+    ///         params,
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn query_stream_with_params(
         &self,
         query: impl Into<String>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<FluxRecord>> + Send>>> {
-        let endpoint = self.endpoint("/api/v2/query");
-        let payload = QueryPayload::new(query);
-        let body = serde_json::to_string(&payload)?;
+        params: BTreeMap<String, Value>,
+    ) -> Result<QueryStream> {
+        let options = QueryOptions {
+            params,
+            ..QueryOptions::default()
+        };
+        self.query_stream_opts(query, &options).await
+    }
 
-        let response = self
-            .http
-            .request(Method::POST, &endpoint)
-            .header("Authorization", format!("Token {}", self.token))
-            .header("Accept", "application/csv")
-            .header("Content-Type", "application/json")
-            .query(&[("org", &self.org)])
-            .body(body)
-            .send()
-            .await?
-            .error_for_status()?;
+    /// Execute a Flux query and read its records one call at a time.
+    ///
+    /// Unlike [`query_stream`](Self::query_stream), the returned reader can
+    /// parse into a record owned by the caller with
+    /// [`RecordReader::next_into`], reusing its allocations from row to row.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut reader = client.query_reader("from(bucket: \"test\") |> range(start: -1h)").await?;
+    /// let mut record = FluxRecord::new(0);
+    /// while reader.next_into(&mut record).await? {
+    ///     total += record.get_double("_value").unwrap_or_default();
+    /// }
+    /// ```
+    pub async fn query_reader(&self, query: impl Into<String>) -> Result<RecordReader> {
+        self.query_reader_opts(query, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a Flux query with per-query `options` and read its records one
+    /// call at a time.
+    ///
+    /// See [`query_reader`](Self::query_reader).
+    pub async fn query_reader_opts(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+    ) -> Result<RecordReader> {
+        let SentQuery {
+            body,
+            timer,
+            dialect,
+            deadline,
+        } = self.send_query(query, options).await?;
 
         // Convert the response body to an async reader
-        let reader = StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+        let bytes = Arc::new(AtomicU64::new(0));
+        let counter = bytes.clone();
+        let body: ByteStream = Box::pin(body.inspect_ok(move |chunk| {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            instrument::bytes_downloaded(chunk.len());
+        }));
+
+        let capacity = options.buffer_size.unwrap_or(DEFAULT_BUFFER_CAPACITY);
+        let annotated = self.request_format_for(options) == RequestFormat::Json && dialect.typed();
+        Ok(RecordReader {
+            parser: AnnotatedCsvParser::with_capacity(StreamReader::new(body), capacity)
+                .annotations(annotated)
+                .terminated(annotated)
+                .delimiter(dialect.delimiter)
+                .null_policy(options.null_policy.clone())
+                .raw_times(options.raw_times)
+                .skip_bad_tables(options.skip_bad_tables),
+            timer: Some(timer),
+            idle_timeout: options.idle_timeout,
+            deadline,
+            idle: None,
+            idle_armed: false,
+            deadline_timer: None,
+            bytes,
+        })
+    }
 
-        let mut parser = AnnotatedCsvParser::new(reader);
+    /// Execute a Flux query and return the response body without parsing it.
+    ///
+    /// The annotated CSV arrives in chunks as the server sends it, ready to
+    /// be written to a file or forwarded to another service without the cost
+    /// of parsing and re-encoding each record. The body is not checked for
+    /// [truncation](Error::Truncated) or error tables.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use futures::TryStreamExt;
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// let mut body = client.query_raw_stream(query).await?;
+    /// let mut file = tokio::fs::File::create("export.csv").await?;
+    /// while let Some(chunk) = body.try_next().await? {
+    ///     file.write_all(&chunk).await?;
+    /// }
+    /// ```
+    pub async fn query_raw_stream(&self, query: impl Into<String>) -> Result<ByteStream> {
+        self.query_raw_stream_opts(query, &QueryOptions::default())
+            .await
+    }
 
-        // Create an async stream that yields records
-        let s = stream! {
+    /// Execute a Flux query with per-query `options` and return the response
+    /// body without parsing it.
+    ///
+    /// See [`query_raw_stream`](Self::query_raw_stream). Options of the
+    /// parser, such as [`QueryOptions::null_policy`], have no effect.
+    pub async fn query_raw_stream_opts(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+    ) -> Result<ByteStream> {
+        let SentQuery {
+            mut body,
+            timer,
+            deadline,
+            ..
+        } = self.send_query(query, options).await?;
+        Ok(Box::pin(stream! {
+            // The query ends with the stream, when its last chunk was received.
+            let _timer = timer;
             loop {
-                match parser.next().await {
-                    Ok(Some(record)) => yield Ok(record),
-                    Ok(None) => break,       // EOF
-                    Err(e) => {
-                        yield Err(e);
-                        break;
-                    }
+                let next = match deadline {
+                    Some((at, timeout)) => tokio::time::timeout_at(at, body.next())
+                        .await
+                        .unwrap_or_else(|_| {
+                            let error = Error::Timeout(timeout);
+                            Some(Err(std::io::Error::new(std::io::ErrorKind::TimedOut, error)))
+                        }),
+                    None => body.next().await,
+                };
+                let Some(chunk) = next else {
+                    break;
+                };
+                let failed = chunk.is_err();
+                if let Ok(chunk) = &chunk {
+                    instrument::bytes_downloaded(chunk.len());
+                }
+                yield chunk;
+                if failed {
+                    break;
                 }
             }
-        };
-
-        Ok(Box::pin(s))
+        }))
     }
 
-    /// Execute a Flux query and collect all results into a Vec.
-    ///
-    /// **Warning**: This loads all results into memory. For large result sets,
-    /// use `query_stream()` instead to process records one at a time.
+    /// Execute a Flux query and copy its response body into `writer`.
     ///
-    /// # Arguments
+    /// Chunks are written as they arrive, without parsing them into records,
+    /// and `writer` is flushed at the end. Returns the number of bytes
+    /// written. See [`query_raw_stream`](Self::query_raw_stream).
     ///
-    /// * `query` - Flux query string
+    /// # Example
     ///
-    /// # Returns
+    /// ```ignore
+    /// let file = tokio::fs::File::create("export.csv").await?;
+    /// let bytes = client.query_to_writer(query, file).await?;
+    /// ```
+    pub async fn query_to_writer<W>(&self, query: impl Into<String>, writer: W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.query_to_writer_opts(query, &QueryOptions::default(), writer)
+            .await
+    }
+
+    /// Execute a Flux query with per-query `options` and copy its response
+    /// body into `writer`.
     ///
-    /// A vector of all records from the query.
-    pub async fn query(&self, query: impl Into<String>) -> Result<Vec<FluxRecord>> {
-        let mut stream = self.query_stream(query).await?;
-        let mut results = Vec::new();
+    /// See [`query_to_writer`](Self::query_to_writer).
+    pub async fn query_to_writer_opts<W>(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+        mut writer: W,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut body = self.query_raw_stream_opts(query, options).await?;
+        let mut written = 0;
+        while let Some(chunk) = body.try_next().await? {
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Get the format the query request of `options` is sent in.
+    fn request_format_for(&self, options: &QueryOptions) -> RequestFormat {
+        options.request_format.unwrap_or(self.request_format)
+    }
+
+    /// Send a Flux query and return the response body with the dialect it
+    /// was requested in.
+    async fn send_query(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+    ) -> Result<SentQuery> {
+        let deadline = options
+            .timeout
+            .map(|timeout| (tokio::time::Instant::now() + timeout, timeout));
+        if self.api == ApiVersion::V3 {
+            return Err(Error::Config(
+                "Flux queries are not supported by InfluxDB 3; use query_sql".to_string(),
+            ));
+        }
+        self.require(Capability::Flux)?;
+        let mut endpoint = self.endpoint("/api/v2/query");
+        let org = options.org.as_deref().unwrap_or(&self.org);
+        endpoint.query_pairs_mut().append_pair("org", org);
+        let format = self.request_format_for(options);
+        let mut payload = QueryPayload::new(query);
+        if let Some(zone) = &options.location {
+            payload.query = flux::with_location(&payload.query, zone);
+        }
+        let inline_params = self
+            .flavor
+            .is_some_and(|flavor| !flavor.supports(Capability::QueryParams));
+        if inline_params && !options.params.is_empty() {
+            payload.query = with_params(&payload.query, &options.params)?;
+        } else if !options.params.is_empty() && format != RequestFormat::Json {
+            return Err(Error::Config(
+                "query parameters require RequestFormat::Json".to_string(),
+            ));
+        }
+        if let Some(dialect) = &options.dialect {
+            if format != RequestFormat::Json {
+                return Err(Error::Config(
+                    "a query dialect requires RequestFormat::Json".to_string(),
+                ));
+            }
+            if !dialect.header {
+                return Err(Error::Config(
+                    "a query dialect without the header row is not supported".to_string(),
+                ));
+            }
+            payload.dialect = dialect.clone();
+        }
+        if !inline_params {
+            payload.params = options
+                .params
+                .iter()
+                .map(|(name, value)| (name.clone(), param_json(value)))
+                .collect();
+        }
+        if let Some(now) = &options.now {
+            let now = now.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+            match format {
+                RequestFormat::Json => payload.now = Some(now),
+                RequestFormat::Flux => {
+                    payload.query =
+                        flux::with_option(&payload.query, &format!("now = () => {now}"));
+                }
+            }
+        }
 
-        while let Some(item) = stream.next().await {
-            results.push(item?);
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/csv"));
+        let body = match format {
+            RequestFormat::Json => {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                serde_json::to_string(&payload)?
+            }
+            RequestFormat::Flux => {
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/vnd.flux"),
+                );
+                payload.query.clone()
+            }
+        };
+        for (name, value) in &options.headers {
+            if name != AUTHORIZATION {
+                headers.insert(name, value.clone());
+            }
+        }
+        #[cfg(feature = "zstd")]
+        {
+            let accepted = headers
+                .get(http::header::ACCEPT_ENCODING)
+                .or_else(|| self.headers.get(http::header::ACCEPT_ENCODING));
+            let accept = accept_zstd(accepted);
+            headers.insert(http::header::ACCEPT_ENCODING, accept);
+        }
+        if let Some(token) = &options.token {
+            headers.insert(AUTHORIZATION, self.authorization(&token.0)?);
         }
+        let timer = QueryTimer::start_with(self.slow_query.as_ref(), &payload.query);
+        let send = self.send(Method::POST, endpoint, headers, body);
+        let response = match deadline {
+            Some((at, timeout)) => tokio::time::timeout_at(at, send)
+                .await
+                .unwrap_or(Err(Error::Timeout(timeout))),
+            None => send.await,
+        }
+        .inspect_err(instrument::error)?;
+        #[cfg(feature = "zstd")]
+        let response = decode_response(response);
+        Ok(SentQuery {
+            body: response.body,
+            timer,
+            dialect: payload.dialect,
+            deadline,
+        })
+    }
 
-        Ok(results)
+    /// Execute a Flux query that resumes automatically after transient failures.
+    ///
+    /// `query` builds the Flux script for a given start time: `None` for the
+    /// initial request, or the last seen `_time` plus one nanosecond when the
+    /// stream is resumed after a [retryable](Error::is_retryable) error.
+    /// Up to `max_retries` retries are attempted before the error is yielded.
+    ///
+    /// Errors, including those from the initial request, are reported through
+    /// the returned stream. Records must arrive in ascending `_time` order; see
+    /// [`resume`](crate::resume::resume) for details.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use chrono::SecondsFormat;
+    ///
+    /// let mut stream = client.query_stream_resumable(
+    ///     |start| {
+    ///         let start = start
+    ///             .map(|t| t.to_rfc3339_opts(SecondsFormat::Nanos, true))
+    ///             .unwrap_or_else(|| "-30d".to_string());
+    ///         format!(
+    ///             r#"from(bucket: "sensors") |> range(start: {start}) |> group() |> sort(columns: ["_time"])"#
+    ///         )
+    ///     },
+    ///     5,
+    /// );
+    /// ```
+    pub fn query_stream_resumable<Q>(&self, query: Q, max_retries: u32) -> RecordStream
+    where
+        Q: Fn(Option<DateTime<FixedOffset>>) -> String + Send + 'static,
+    {
+        self.query_stream_resumable_from(query, max_retries, ResumeFrom::AfterLast)
+    }
+
+    /// Like [`query_stream_resumable`](Self::query_stream_resumable), resuming
+    /// at the time chosen by `from`.
+    ///
+    /// With [`ResumeFrom::Last`], `query` is passed the last seen `_time`
+    /// itself, so no record is lost when several share it, at the cost of
+    /// yielding those already seen again.
+    pub fn query_stream_resumable_from<Q>(
+        &self,
+        query: Q,
+        max_retries: u32,
+        from: ResumeFrom,
+    ) -> RecordStream
+    where
+        Q: Fn(Option<DateTime<FixedOffset>>) -> String + Send + 'static,
+    {
+        let client = self.clone();
+        let s = resume_from(
+            move |start| {
+                let client = client.clone();
+                let query = query(start);
+                async move { client.query_stream(query).await }
+            },
+            max_retries,
+            from,
+        );
+
+        Box::pin(s)
+    }
+
+    /// Execute a query over several time windows concurrently.
+    ///
+    /// `query` builds the Flux script for one window from its `start` and
+    /// `stop` bounds. Windows, concurrency and output order are configured by
+    /// `shards`; see [`TimeShards`] for details.
+    pub fn query_stream_sharded<Q>(&self, query: Q, shards: &TimeShards) -> RecordStream
+    where
+        Q: Fn(DateTime<FixedOffset>, DateTime<FixedOffset>) -> String + Send + Sync + 'static,
+    {
+        let client = self.clone();
+        shards.execute(move |start, stop| {
+            let client = client.clone();
+            let query = query(start, stop);
+            async move { client.query_stream(query).await }
+        })
+    }
+
+    /// Execute several labeled queries concurrently and merge their records.
+    ///
+    /// Runs at most [`DEFAULT_QUERY_MANY_CONCURRENCY`] queries at once; see
+    /// [`query_many_with`](Self::query_many_with).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut stream = client.query_many([("cpu", cpu_query), ("mem", mem_query)]);
+    /// while let Some((label, record)) = stream.next().await {
+    ///     dashboard.panel(label).push(record?);
+    /// }
+    /// ```
+    pub fn query_many<I, L, Q>(&self, queries: I) -> LabeledRecordStream<L>
+    where
+        I: IntoIterator<Item = (L, Q)>,
+        I::IntoIter: Send + 'static,
+        L: Clone + Send + 'static,
+        Q: Into<String>,
+    {
+        self.query_many_with(queries, DEFAULT_QUERY_MANY_CONCURRENCY)
+    }
+
+    /// Execute several labeled queries, at most `max_concurrent` at a time,
+    /// and merge their records.
+    ///
+    /// Every item carries the label of its query. Records of one query keep
+    /// their order, but records of different queries interleave as they
+    /// arrive. A query that fails yields its error under its label; the other
+    /// queries are unaffected. Queries are started in the given order as
+    /// slots free up.
+    pub fn query_many_with<I, L, Q>(
+        &self,
+        queries: I,
+        max_concurrent: usize,
+    ) -> LabeledRecordStream<L>
+    where
+        I: IntoIterator<Item = (L, Q)>,
+        I::IntoIter: Send + 'static,
+        L: Clone + Send + 'static,
+        Q: Into<String>,
+    {
+        let client = self.clone();
+        let opens = futures::stream::iter(
+            queries
+                .into_iter()
+                .map(|(label, query)| (label, query.into())),
+        )
+        .map(move |(label, query): (L, String)| {
+            let client = client.clone();
+            let open = async move { client.query_stream(query).await };
+            futures::stream::once(open)
+                .try_flatten()
+                .map(move |record| (label.clone(), record))
+                .boxed()
+        });
+        Box::pin(opens.flatten_unordered(max_concurrent.max(1)))
+    }
+
+    /// Execute a query over a long time range one `window` at a time.
+    ///
+    /// `query` builds the Flux script for one window from its `start` and
+    /// `stop` bounds. Windows are requested sequentially and their records
+    /// yielded in time order, so no single request runs into server-side
+    /// query timeouts. A window that fails yields its error and the stream
+    /// continues with the next one; see [`TimeShards::every`] to run the
+    /// windows with other settings. A `window` that is not positive yields
+    /// [`Error::Config`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use chrono::TimeDelta;
+    ///
+    /// let stream = client.query_stream_chunked(
+    ///     |start, stop| format!(
+    ///         r#"from(bucket: "sensors") |> range(start: {}, stop: {})"#,
+    ///         start.to_rfc3339(),
+    ///         stop.to_rfc3339(),
+    ///     ),
+    ///     start,
+    ///     stop,
+    ///     TimeDelta::days(1),
+    /// );
+    /// ```
+    pub fn query_stream_chunked<Q>(
+        &self,
+        query: Q,
+        start: DateTime<FixedOffset>,
+        stop: DateTime<FixedOffset>,
+        window: TimeDelta,
+    ) -> RecordStream
+    where
+        Q: Fn(DateTime<FixedOffset>, DateTime<FixedOffset>) -> String + Send + Sync + 'static,
+    {
+        match TimeShards::every(start, stop, window) {
+            Ok(shards) => self.query_stream_sharded(query, &shards),
+            Err(e) => Box::pin(futures::stream::once(async { Err(e) })),
+        }
+    }
+
+    /// Execute a query one page of `page_size` rows at a time.
+    ///
+    /// Each page appends `|> limit(n: page_size, offset: ...)` to `query` and
+    /// runs as its own request, for gateways that cut off large responses.
+    /// Pages are requested until one returns fewer than `page_size` records
+    /// in every table. The first error ends the stream.
+    ///
+    /// Flux limits each table separately, so a page may hold up to
+    /// `page_size` records *per table*; end the query with `group()` to page
+    /// through one table. Rows must come back in a stable order for pages not
+    /// to overlap, which range queries over unchanged data guarantee; table
+    /// numbers restart with every page.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let stream = client.query_paged(
+    ///     r#"from(bucket: "sensors") |> range(start: -30d) |> group()"#,
+    ///     100_000,
+    /// );
+    /// ```
+    pub fn query_paged(&self, query: impl Into<String>, page_size: usize) -> RecordStream {
+        let client = self.clone();
+        let query = query.into();
+        let page_size = page_size.max(1);
+        Box::pin(stream! {
+            for page in 0usize.. {
+                let paged = format!(
+                    "{}\n  |> limit(n: {}, offset: {})",
+                    query.trim_end(),
+                    page_size,
+                    page * page_size,
+                );
+                let mut records = match client.query_stream(paged).await {
+                    Ok(records) => records,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                let mut counts: HashMap<i32, usize> = HashMap::new();
+                loop {
+                    match records.try_next().await {
+                        Ok(Some(record)) => {
+                            *counts.entry(record.table).or_default() += 1;
+                            yield Ok(record);
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    }
+                }
+                if counts.values().all(|&n| n < page_size) {
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Query measurement `T` over `range`, yielding typed values.
+    ///
+    /// The Flux is generated by [`SchemaRegistry::query_for`], which fails if
+    /// `T` is not registered.
+    pub async fn query_typed<T>(
+        &self,
+        registry: &SchemaRegistry,
+        range: &TimeRange,
+    ) -> Result<TypedStream<T>>
+    where
+        T: Measurement + Send + 'static,
+    {
+        let query = registry.query_for::<T>(range)?;
+        Ok(Box::pin(Typed::new(self.query_stream(query).await?)))
+    }
+
+    /// Execute a Flux query and collect all results into a Vec.
+    ///
+    /// **Warning**: This loads all results into memory. For large result sets,
+    /// use `query_stream()` instead to process records one at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Flux query string
+    ///
+    /// # Returns
+    ///
+    /// A vector of all records from the query.
+    pub async fn query(&self, query: impl Into<String>) -> Result<Vec<FluxRecord>> {
+        self.query_opts(query, &QueryOptions::default()).await
+    }
+
+    /// Execute a Flux query with per-query `options` and collect all results
+    /// into a Vec.
+    ///
+    /// Set [`QueryOptions::max_records`] or [`QueryOptions::max_bytes`] to
+    /// fail with [`Error::LimitExceeded`] rather than exhaust memory when a
+    /// query returns more than expected.
+    pub async fn query_opts(
+        &self,
+        query: impl Into<String>,
+        options: &QueryOptions,
+    ) -> Result<Vec<FluxRecord>> {
+        let stream = self.query_stream_opts(query, options).await?;
+        collect_limited(stream, &options.limit).await
+    }
+}
+
+/// Maximum number of bytes of an error response read for its message.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Read a response body into memory, keeping at most `limit` bytes.
+async fn read_body(mut chunks: ByteStream, limit: usize) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = chunks.try_next().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= limit {
+            body.truncate(limit);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+/// Build an [`Error::Status`] from an error response, using the `message`
+/// field of InfluxDB's JSON error body when present.
+async fn status_error(response: TransportResponse) -> Error {
+    let body = read_body(response.body, MAX_ERROR_BODY)
+        .await
+        .unwrap_or_default();
+
+    let message = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v.get("message")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).trim().to_string());
+
+    let status = response.status.as_u16();
+    let retry_after = retry_after(&response.headers);
+    if status == 429 || (status == 503 && retry_after.is_some()) {
+        return Error::RateLimited {
+            status,
+            retry_after,
+            message,
+        };
+    }
+    Error::Status { status, message }
+}
+
+/// Read the settings of [`Client::from_env`] through `var`.
+pub(crate) fn builder_from_vars(var: impl Fn(&str) -> Option<String>) -> Result<ClientBuilder> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    let url = var("INFLUX_HOST").or_else(|| var("INFLUX_URL"));
+    let org = var("INFLUX_ORG");
+    let token = var("INFLUX_TOKEN");
+
+    let missing: Vec<_> = [
+        (url.is_none(), "INFLUX_HOST (or INFLUX_URL)"),
+        (org.is_none(), "INFLUX_ORG"),
+        (token.is_none(), "INFLUX_TOKEN"),
+    ]
+    .into_iter()
+    .filter_map(|(missing, name)| missing.then_some(name))
+    .collect();
+    match (url, org, token) {
+        (Some(url), Some(org), Some(token)) => Ok(Client::builder(url, org, token)),
+        _ => Err(Error::Config(format!(
+            "Missing environment variables: {}",
+            missing.join(", ")
+        ))),
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| Error::Config(format!("Invalid InfluxDB URL '{}': {}", url, e)))
+}
+
+/// Read a `Retry-After` header, given in seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.to_utc() - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// A query whose response has started, from [`Client::send_query`].
+struct SentQuery {
+    body: ByteStream,
+    timer: QueryTimer,
+    dialect: QueryDialect,
+    /// Instant the query must complete by, and the timeout it derives from.
+    deadline: Option<(tokio::time::Instant, Duration)>,
+}
+
+/// Pull-based reader over the records of a query, returned by
+/// [`Client::query_reader`].
+///
+/// After the last record or the first error, every further call reports the
+/// end of the results.
+pub struct RecordReader {
+    parser: AnnotatedCsvParser<StreamReader<ByteStream, Bytes>>,
+    timer: Option<QueryTimer>,
+    idle_timeout: Option<Duration>,
+    deadline: Option<(tokio::time::Instant, Duration)>,
+    // Timers of the idle timeout and the deadline, created on first use. The
+    // idle timer is armed while a record is awaited.
+    idle: Option<Pin<Box<tokio::time::Sleep>>>,
+    idle_armed: bool,
+    deadline_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl RecordReader {
+    /// Parse the next record into `record`, reusing its allocations.
+    ///
+    /// Returns `Ok(false)` once all records have been read. See
+    /// [`AnnotatedCsvParser::next_into`] for details.
+    pub async fn next_into(&mut self, record: &mut FluxRecord) -> Result<bool> {
+        std::future::poll_fn(|cx| self.poll_next_into(cx, record)).await
+    }
+
+    fn poll_next_into(
+        &mut self,
+        cx: &mut Context<'_>,
+        record: &mut FluxRecord,
+    ) -> Poll<Result<bool>> {
+        if self.timer.is_none() {
+            return Poll::Ready(Ok(false));
+        }
+        let next = std::task::ready!(self.poll_parse(cx, record));
+        self.idle_armed = false;
+        Poll::Ready(match next {
+            Ok(true) => {
+                if let Some(timer) = &mut self.timer {
+                    timer.record_parsed();
+                }
+                Ok(true)
+            }
+            Ok(false) => {
+                self.timer = None;
+                Ok(false)
+            }
+            Err(e @ Error::TableSkipped { .. }) => {
+                instrument::error(&e);
+                Err(e)
+            }
+            Err(e) => {
+                instrument::error(&e);
+                self.timer = None;
+                Err(e)
+            }
+        })
+    }
+
+    /// Poll the parser, then the idle timeout and the deadline.
+    fn poll_parse(&mut self, cx: &mut Context<'_>, record: &mut FluxRecord) -> Poll<Result<bool>> {
+        if let Some(timeout) = self.idle_timeout
+            && !self.idle_armed
+        {
+            let at = tokio::time::Instant::now() + timeout;
+            match &mut self.idle {
+                Some(idle) => idle.as_mut().reset(at),
+                None => self.idle = Some(Box::pin(tokio::time::sleep_until(at))),
+            }
+            self.idle_armed = true;
+        }
+        if let Poll::Ready(next) = self.parser.poll_next_into(cx, record) {
+            return Poll::Ready(next);
+        }
+        if let (Some(timeout), Some(idle)) = (self.idle_timeout, &mut self.idle)
+            && idle.as_mut().poll(cx).is_ready()
+        {
+            return Poll::Ready(Err(Error::Stalled(timeout)));
+        }
+        if let Some((at, timeout)) = self.deadline {
+            let deadline = self
+                .deadline_timer
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(at)));
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(Error::Timeout(timeout)));
+            }
+        }
+        Poll::Pending
+    }
+
+    /// Parse and return the next record, or `None` once all have been read.
+    pub async fn next(&mut self) -> Result<Option<FluxRecord>> {
+        let mut record = FluxRecord::new(0);
+        Ok(self.next_into(&mut record).await?.then_some(record))
+    }
+}
+
+impl std::fmt::Debug for RecordReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordReader")
+            .field("finished", &self.timer.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Statistics of a [`QueryStream`], from [`QueryStream::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QueryStats {
+    /// Records yielded so far.
+    pub records: u64,
+    /// Tables the yielded records belong to.
+    pub tables: u64,
+    /// Response body bytes received so far.
+    pub bytes: u64,
+    /// Time since the query was sent.
+    pub elapsed: Duration,
+}
+
+/// Stream of the records of a query, returned by [`Client::query_stream`].
+///
+/// Besides yielding records, it reports the schema of the current table and
+/// statistics, and can be [cancelled](Self::cancel). Convert it into a
+/// [`RecordStream`] with [`StreamExt::boxed`] where a uniform type is needed.
+pub struct QueryStream {
+    source: Source,
+    cancel: CancelHandle,
+    bytes: Arc<AtomicU64>,
+    started: Instant,
+    records: u64,
+    tables: u64,
+    table: Option<i32>,
+    schema: Option<Arc<RecordSchema>>,
+}
+
+impl QueryStream {
+    /// Get the schema of the most recently yielded record.
+    pub fn schema(&self) -> Option<&Arc<RecordSchema>> {
+        self.schema.as_ref()
+    }
+
+    /// Get statistics of the query so far.
+    pub fn stats(&self) -> QueryStats {
+        QueryStats {
+            records: self.records,
+            tables: self.tables,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    /// Stop the query: the response is dropped, closing its connection, and
+    /// the stream ends.
+    pub fn cancel(&mut self) {
+        self.cancel.cancel();
+        self.source = Source::Done;
+    }
+
+    /// Get a handle that cancels the query from another task.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+}
+
+/// Cancels a [`QueryStream`] from outside the task consuming it, from
+/// [`QueryStream::cancel_handle`].
+///
+/// A consumer waiting for the next record is woken up and sees the end of
+/// the stream; the response is dropped, closing its connection, as soon as
+/// the stream is polled again or dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut stream = client.query_stream(query).await?;
+/// let cancel = stream.cancel_handle();
+/// tokio::spawn(async move {
+///     shutdown.await;
+///     cancel.cancel();
+/// });
+/// while let Some(record) = stream.next().await {
+///     process(record?);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    state: Arc<CancelState>,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl CancelHandle {
+    /// Cancel the query.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+        self.state.waker.wake();
+    }
+
+    /// Returns true if the query was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+}
+
+impl Stream for QueryStream {
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.cancel.state.waker.register(cx.waker());
+        if this.cancel.is_cancelled() {
+            this.source = Source::Done;
+            return Poll::Ready(None);
+        }
+        let item = std::task::ready!(Pin::new(&mut this.source).poll_next(cx));
+        if let Some(Ok(record)) = &item {
+            this.records += 1;
+            if this.table != Some(record.table) {
+                this.table = Some(record.table);
+                this.tables += 1;
+            }
+            let same = this
+                .schema
+                .as_ref()
+                .is_some_and(|s| Arc::ptr_eq(s, record.schema()));
+            if !same {
+                this.schema = Some(record.schema().clone());
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Where a [`QueryStream`] gets its records from.
+enum Source {
+    /// Records are parsed by the task polling the stream.
+    Reader(Box<RecordReader>),
+    /// Records are parsed by a [worker](QueryOptions::worker) task.
+    Worker(tokio::sync::mpsc::Receiver<Result<FluxRecord>>),
+    /// The stream was cancelled.
+    Done,
+}
+
+impl Stream for Source {
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Source::Reader(reader) => {
+                let mut record = FluxRecord::new(0);
+                let next = std::task::ready!(reader.poll_next_into(cx, &mut record));
+                Poll::Ready(next.map(|more| more.then_some(record)).transpose())
+            }
+            Source::Worker(rx) => rx.poll_recv(cx),
+            Source::Done => Poll::Ready(None),
+        }
+    }
+}
+
+impl std::fmt::Debug for QueryStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryStream")
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl QueryClient for Client {
+    async fn query_stream(&self, query: impl Into<String> + Send) -> Result<RecordStream> {
+        Ok(Box::pin(Client::query_stream(self, query).await?))
+    }
+
+    async fn query(&self, query: impl Into<String> + Send) -> Result<Vec<FluxRecord>> {
+        Client::query(self, query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::{Requests, StaticTransport};
+    use futures::future::BoxFuture;
+    use futures::stream;
+    use http::StatusCode;
+    use std::sync::Mutex;
+
+    /// Annotated CSV of one table with the `long` column `n` set to `values`.
+    fn longs(values: &[i64]) -> String {
+        let mut csv = String::from(
+            "#datatype,string,long\n#group,false,false\n#default,_result,\n,result,n\n",
+        );
+        for value in values {
+            csv.push_str(&format!(",,{value}\n"));
+        }
+        csv.push('\n');
+        csv
+    }
+
+    /// Get a builder whose requests are answered with `status` and `body`,
+    /// and the requests it sends.
+    fn builder(status: StatusCode, body: impl Into<Bytes>) -> (ClientBuilder, Requests) {
+        let (transport, requests) = StaticTransport::new(status, body);
+        let builder =
+            Client::builder("http://influx.invalid:8086", "org", "token").transport(transport);
+        (builder, requests)
+    }
+
+    fn client(status: StatusCode, body: impl Into<Bytes>) -> (Client, Requests) {
+        let (builder, requests) = builder(status, body);
+        (builder.build().unwrap(), requests)
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_stats_and_cancel() {
+        let csv = "#datatype,string,long,long\n#group,false,false,false\n#default,_result,,\n\
+            ,result,table,n\n,,0,1\n\n\
+            #datatype,string,long,long\n#group,false,false,false\n#default,_result,,\n\
+            ,result,table,n\n,,1,2\n,,1,3\n";
+        let (client, _) = client(StatusCode::OK, csv);
+
+        let mut stream = client.query_stream("buckets()").await.unwrap();
+        assert!(stream.schema().is_none());
+        stream.next().await.unwrap().unwrap();
+        stream.next().await.unwrap().unwrap();
+        let stats = stream.stats();
+        assert_eq!((stats.records, stats.tables), (2, 2));
+        assert!(stats.bytes > 0);
+        assert_eq!(stream.schema().unwrap().names(), ["result", "table", "n"]);
+
+        stream.cancel();
+        assert!(stream.next().await.is_none());
+        assert_eq!(stream.stats().records, 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_parse_in_worker() {
+        let csv = "#datatype,string,long,long\n#group,false,false,false\n#default,_result,,\n\
+            ,result,table,n\n,,0,1\n,,0,2\n,,0,3\n\n";
+        let (client, _) = client(StatusCode::OK, csv);
+
+        let options = QueryOptions::new().parse_in_worker(1);
+        let stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        let values: Vec<_> = stream
+            .map(|r| r.unwrap().get_long("n").unwrap())
+            .collect()
+            .await;
+        assert_eq!(values, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_auth_scheme_bearer() {
+        let (builder, requests) = builder(
+            StatusCode::OK,
+            "#datatype,long\n#group,false\n#default,\n,n\n,1\n\n",
+        );
+        let influx = builder
+            .auth_scheme(crate::AuthScheme::Bearer)
+            .build()
+            .unwrap();
+        assert_eq!(influx.auth_scheme(), crate::AuthScheme::Bearer);
+
+        influx.query("buckets()").await.unwrap();
+        assert_eq!(
+            requests.lock().unwrap()[0].headers["authorization"],
+            "Bearer token"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_format_flux() {
+        let csv = ",result,table,_time,host\n,_result,0,2023-11-14T12:00:00Z,a\n";
+        let (builder, requests) = builder(StatusCode::OK, csv);
+        let influx = builder.request_format(RequestFormat::Flux).build().unwrap();
+
+        let records = influx.query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].get_str("host"), Some("a"));
+        assert!(records[0].time().is_some());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers["content-type"], "application/vnd.flux");
+        assert_eq!(&requests[0].body[..], b"buckets()");
+    }
+
+    #[tokio::test]
+    async fn test_query_options_request_format() {
+        let csv = ",result,table,host\n,_result,0,a\n";
+        let (client, requests) = client(StatusCode::OK, csv);
+        let options = QueryOptions::new().request_format(RequestFormat::Flux);
+        let records: Vec<_> = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records[0].get_str("host"), Some("a"));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers["content-type"], "application/vnd.flux");
+        assert_eq!(&requests[0].body[..], b"buckets()");
+    }
+
+    #[tokio::test]
+    async fn test_query_many_labels_records() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1, 2]));
+
+        let items: Vec<_> = client
+            .query_many([("cpu", "q1"), ("mem", "q2")])
+            .collect()
+            .await;
+        assert_eq!(items.len(), 4);
+        for label in ["cpu", "mem"] {
+            let values: Vec<_> = items
+                .iter()
+                .filter(|(l, _)| *l == label)
+                .map(|(_, r)| r.as_ref().unwrap().get_long("n").unwrap())
+                .collect();
+            assert_eq!(values, [1, 2]);
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_many_errors_keep_label() {
+        let (client, _) = client(StatusCode::INTERNAL_SERVER_ERROR, "boom");
+
+        let items: Vec<_> = client
+            .query_many_with([(1, "q1"), (2, "q2")], 1)
+            .collect()
+            .await;
+        let mut labels: Vec<_> = items.iter().map(|(l, _)| *l).collect();
+        labels.sort();
+        assert_eq!(labels, [1, 2]);
+        assert!(items.iter().all(|(_, r)| r.is_err()));
+    }
+
+    #[tokio::test]
+    async fn test_skip_bad_tables_keeps_streaming() {
+        let csv = "#datatype,long\n#group,false\n#default,\n,n\n,x\n\n\
+                   #datatype,long\n#group,false\n#default,\n,n\n,2\n\n";
+        let (client, _) = client(StatusCode::OK, csv);
+
+        let options = QueryOptions::new().skip_bad_tables(true);
+        let items: Vec<_> = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(
+            items[0],
+            Err(Error::TableSkipped { table: 0, .. })
+        ));
+        assert_eq!(items[1].as_ref().unwrap().get_long("n"), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_query_options_location() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1]));
+
+        let options = QueryOptions::new().location("Europe/Paris");
+        let stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        let records: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(
+            body["query"],
+            "option location = {zone: \"Europe/Paris\", offset: 0h}\nbuckets()"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_with_params() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1]));
+
+        let params = std::collections::BTreeMap::from([
+            ("host".to_string(), crate::Value::from("a\" or true")),
+            ("limit".to_string(), crate::Value::Long(10)),
+        ]);
+        let stream = client
+            .query_stream_with_params("from(bucket: params.host)", params)
+            .await
+            .unwrap();
+        let records: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(
+            body["params"],
+            serde_json::json!({"host": "a\" or true", "limit": 10})
+        );
+        assert_eq!(body["query"], "from(bucket: params.host)");
+    }
+
+    #[tokio::test]
+    async fn test_params_declared_without_server_support() {
+        let (mut client, requests) = client(StatusCode::OK, longs(&[1]));
+        client.flavor = Some(ServerFlavor::Oss2);
+
+        let params = std::collections::BTreeMap::from([
+            ("host".to_string(), crate::Value::from("a\" or true")),
+            ("limit".to_string(), crate::Value::Long(10)),
+        ]);
+        let stream = client
+            .query_stream_with_params("import \"strings\"\nfrom(bucket: params.host)", params)
+            .await
+            .unwrap();
+        let records: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body.get("params"), None);
+        assert_eq!(
+            body["query"],
+            "import \"strings\"\noption params = {host: \"a\\\" or true\", limit: 10}\nfrom(bucket: params.host)"
+        );
+
+        let null = QueryOptions::new().param("host", crate::Value::Null);
+        assert!(matches!(
+            client.query_stream_opts("buckets()", &null).await,
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_a_config_error() {
+        let (transport, _) = StaticTransport::new(StatusCode::OK, longs(&[1]));
+        let client = Client::builder("http://influx.invalid:8086", "org", "bad\ntoken")
+            .transport(transport)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.query_stream("buckets()").await,
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_query_options_now() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1]));
+
+        let now = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        let options = QueryOptions::new().now(now);
+        let stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        let records: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["now"], "2024-01-01T00:00:00Z");
+        assert_eq!(body["query"], "buckets()");
+    }
+
+    #[tokio::test]
+    async fn test_query_options_org_and_headers() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1]));
+
+        let options = QueryOptions::new().org("other-org").header(
+            http::HeaderName::from_static("x-request-id"),
+            http::HeaderValue::from_static("42"),
+        );
+        let stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        let _: Vec<_> = stream.try_collect().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].url.query(), Some("org=other-org"));
+        assert_eq!(requests[0].headers["x-request-id"], "42");
+        assert_eq!(requests[0].headers["authorization"], "Token token");
+        let debug = format!("{:?}", requests[0]);
+        assert!(debug.contains("\"authorization\": <redacted>"));
+        assert!(debug.contains("\"x-request-id\": \"42\""));
+        assert!(!debug.contains("buckets()"));
+    }
+
+    #[tokio::test]
+    async fn test_query_options_dialect() {
+        let csv = "#datatype\tstring\tlong\tdouble\n\tresult\ttable\t_value\n\t_result\t0\t1.5\n\n";
+        let (client, requests) = client(StatusCode::OK, csv);
+
+        let dialect = QueryDialect::new()
+            .annotations([Annotation::Datatype])
+            .delimiter(b'\t');
+        let options = QueryOptions::new().dialect(dialect);
+        let stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        let records: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(records[0].get_double("_value"), Some(1.5));
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(
+            body["dialect"]["annotations"],
+            serde_json::json!(["datatype"])
+        );
+        assert_eq!(body["dialect"]["delimiter"], "\t");
+
+        for dialect in [QueryDialect::new(), QueryDialect::new().annotations([])] {
+            let headerless = QueryOptions::new().dialect(dialect.header(false));
+            let err = client
+                .query_stream_opts("buckets()", &headerless)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, Error::Config(_)));
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_raw_stream() {
+        let csv = longs(&[1]);
+        let (client, _) = client(StatusCode::OK, csv.clone());
+
+        let chunks: Vec<_> = client
+            .query_raw_stream("buckets()")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), csv.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_query_to_writer() {
+        let csv = longs(&[1]);
+        let (client, _) = client(StatusCode::OK, csv.clone());
+
+        let mut out = Vec::new();
+        let written = client.query_to_writer("buckets()", &mut out).await.unwrap();
+        assert_eq!(written, csv.len() as u64);
+        assert_eq!(out, csv.as_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn test_query_zstd_response() {
+        use tokio::io::AsyncWriteExt;
+
+        struct ZstdTransport(&'static str, Bytes, Arc<Mutex<Vec<TransportRequest>>>);
+
+        impl Transport for ZstdTransport {
+            fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+                self.2.lock().unwrap().push(request);
+                let mut headers = HeaderMap::new();
+                headers.insert("content-encoding", http::HeaderValue::from_static(self.0));
+                let body = self.1.clone();
+                Box::pin(futures::future::ready(Ok(TransportResponse {
+                    status: StatusCode::OK,
+                    headers,
+                    body: Box::pin(stream::iter([Ok(body)])),
+                })))
+            }
+        }
+
+        let mut encoder = async_compression::tokio::write::ZstdEncoder::new(Vec::new());
+        encoder.write_all(longs(&[1, 2]).as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let transport = ZstdTransport("zstd", encoder.into_inner().into(), requests.clone());
+        let client =
+            Client::with_transport(transport, "http://influx.invalid:8086", "org", "token");
+
+        let records = client.query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            requests.lock().unwrap()[0].headers["accept-encoding"],
+            "zstd, gzip;q=0.9"
+        );
+
+        let mut encoder = async_compression::tokio::write::GzipEncoder::new(Vec::new());
+        encoder.write_all(longs(&[1, 2]).as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let transport = ZstdTransport("gzip", encoder.into_inner().into(), requests.clone());
+        let client =
+            Client::with_transport(transport, "http://influx.invalid:8086", "org", "token");
+        let options = QueryOptions::new().header(
+            http::header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip"),
+        );
+        let records = client.query_opts("buckets()", &options).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            requests.lock().unwrap()[1].headers["accept-encoding"],
+            "gzip, zstd"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_api_sink_batches() {
+        use crate::write::{Point, WriteOptions};
+        use futures::SinkExt;
+
+        let (client, requests) = client(StatusCode::NO_CONTENT, "");
+        let options = WriteOptions::new().batch_size(2);
+        let mut writer = client.write_api_opts("bucket", options);
+
+        let points = (0..3).map(|i| Ok(Point::new("m").field("n", i as i64)));
+        writer.send_all(&mut stream::iter(points)).await.unwrap();
+        writer.close().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url.path(), "/api/v2/write");
+        assert_eq!(
+            requests[0].url.query(),
+            Some("org=org&bucket=bucket&precision=ns")
+        );
+        assert_eq!(&requests[0].body[..], b"m n=0i\nm n=1i\n");
+        assert_eq!(&requests[1].body[..], b"m n=2i\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_api_v1_endpoint() {
+        use crate::write::{Point, Precision, WriteOptions};
+        use futures::SinkExt;
+
+        let (client, requests) = client(StatusCode::NO_CONTENT, "");
+        let options = WriteOptions::new()
+            .v1_endpoint(true)
+            .precision(Precision::Milliseconds);
+        let mut writer = client.write_api_opts("telegraf/autogen", options);
+        writer.send(Point::new("m").field("n", 1i64)).await.unwrap();
+        writer.close().await.unwrap();
+
+        client
+            .write_lines_v1("telegraf", None, Precision::Nanoseconds, "m n=2i\n")
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].url.path(), "/write");
+        assert_eq!(
+            requests[0].url.query(),
+            Some("db=telegraf&rp=autogen&precision=ms")
+        );
+        assert_eq!(&requests[0].body[..], b"m n=1i\n");
+        assert_eq!(requests[1].url.query(), Some("db=telegraf&precision=n"));
+    }
+
+    #[tokio::test]
+    async fn test_backup_requests_metadata_and_shards() {
+        // Served for every request, so each shard archive is this body too.
+        let body = "--b\r\nContent-Disposition: attachment; name=\"buckets\"\r\n\r\n\
+            [{\"bucketID\":\"a1\",\"retentionPolicies\":[{\"shardGroups\":\
+            [{\"shards\":[{\"id\":7}]}]}]}]\r\n--b--\r\n";
+        let (client, requests) = client(StatusCode::OK, body);
+        let dir = std::env::temp_dir().join(format!(
+            "influxdb-stream-transport-backup-{}",
+            std::process::id()
+        ));
+
+        let manifest = client.backup(&dir).await.unwrap();
+        assert_eq!(manifest.shards.len(), 1);
+        assert_eq!(manifest.shards[0].bucket_id, "a1");
+        assert_eq!(manifest.shards[0].file.size, body.len() as u64);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].url.path(), "/api/v2/backup/metadata");
+        assert_eq!(requests[1].url.path(), "/api/v2/backup/shards/7");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Write a backup of a KV snapshot, an SQL snapshot if `sql`, and
+    /// shard 7 in a fresh directory named after `name`.
+    fn backup_dir(name: &str, sql: bool) -> std::path::PathBuf {
+        use crate::backup::{BackupFile, BackupManifest, ShardBackup};
+
+        let dir =
+            std::env::temp_dir().join(format!("influxdb-stream-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, data: &str| {
+            std::fs::write(dir.join(name), data).unwrap();
+            BackupFile {
+                file_name: name.to_string(),
+                size: data.len() as u64,
+            }
+        };
+        let manifest = BackupManifest {
+            kv: Some(file("t.bolt", "kv-snapshot")),
+            sql: sql.then(|| file("t.sqlite", "sql-snapshot")),
+            buckets: Vec::new(),
+            shards: vec![ShardBackup {
+                id: 7,
+                bucket_id: "a1".to_string(),
+                file: file("t.s7.tar", "shard"),
+            }],
+        };
+        std::fs::write(
+            dir.join("t.manifest"),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_restore_streams_files_and_resumes() {
+        let dir = backup_dir("restore", false);
+        let (client, requests) = client(StatusCode::OK, r#"{"token":"restored"}"#);
+        let summary = client.restore(&dir).await.unwrap();
+        assert_eq!(summary.token.as_deref(), Some("restored"));
+        assert_eq!(summary.shards, 1);
+
+        let again = client.restore(&dir).await.unwrap();
+        assert_eq!((again.shards, again.resumed), (0, 1));
+
+        let mut requests = std::mem::take(&mut *requests.lock().unwrap());
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url.path(), "/api/v2/restore/kv");
+        let body: Vec<_> = requests[0]
+            .body_stream
+            .take()
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(body.concat(), b"kv-snapshot");
+        assert_eq!(requests[1].url.path(), "/api/v2/restore/shards/7");
+        assert_eq!(requests[1].headers["authorization"], "Token restored");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Issues a token on `/api/v2/restore/kv`, fails SQL restores while
+    /// `fail_sql` is set and answers shard uploads with 503 and `Retry-After`
+    /// while `busy_shards` is set, recording the path and `Authorization` of
+    /// requests.
+    #[derive(Default)]
+    struct RestoreTransport {
+        fail_sql: std::sync::atomic::AtomicBool,
+        busy_shards: std::sync::atomic::AtomicBool,
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Transport for Arc<RestoreTransport> {
+        fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            let path = request.url.path().to_string();
+            let auth = request.headers[AUTHORIZATION].to_str().unwrap().to_string();
+            self.sent.lock().unwrap().push((path.clone(), auth));
+            let mut headers = HeaderMap::new();
+            let (status, body) = match path.as_str() {
+                "/api/v2/restore/kv" => (StatusCode::OK, r#"{"token":"restored"}"#),
+                "/api/v2/restore/sql" if self.fail_sql.load(Ordering::SeqCst) => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "disk full")
+                }
+                p if p.starts_with("/api/v2/restore/shards/")
+                    && self.busy_shards.swap(false, Ordering::SeqCst) =>
+                {
+                    headers.insert("retry-after", HeaderValue::from_static("30"));
+                    (StatusCode::SERVICE_UNAVAILABLE, "busy")
+                }
+                _ => (StatusCode::NO_CONTENT, ""),
+            };
+            Box::pin(async move {
+                Ok(TransportResponse {
+                    status,
+                    headers,
+                    body: Box::pin(stream::iter([Ok(Bytes::from_static(body.as_bytes()))])),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_resumes_with_restored_token() {
+        use crate::auth::{Token, from_fn};
+
+        let dir = backup_dir("restore-token", true);
+        let transport = Arc::new(RestoreTransport::default());
+        transport.fail_sql.store(true, Ordering::SeqCst);
+        let client = Client::builder("http://influx.invalid:8086", "org", "token")
+            .transport(transport.clone())
+            .token_provider(from_fn(|| async { Ok(Token::new("stale")) }))
+            .build()
+            .unwrap();
+
+        client.restore(&dir).await.unwrap_err();
+        let state = std::fs::read_to_string(dir.join("t.restore")).unwrap();
+        assert!(state.contains("\"kv\": true"));
+        assert!(!state.contains("restored"));
+        let token = std::fs::read_to_string(dir.join("t.restore-token")).unwrap();
+        assert_eq!(token, "restored");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("t.restore-token"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        transport.fail_sql.store(false, Ordering::SeqCst);
+        let summary = client.restore(&dir).await.unwrap();
+        assert_eq!(summary.token.as_deref(), Some("restored"));
+        assert_eq!(summary.shards, 1);
+        assert!(!dir.join("t.restore-token").exists());
+
+        let sent = transport.sent.lock().unwrap();
+        let sent: Vec<_> = sent.iter().map(|(p, a)| (p.as_str(), a.as_str())).collect();
+        assert_eq!(
+            sent,
+            [
+                ("/api/v2/restore/kv", "Token stale"),
+                ("/api/v2/restore/sql", "Token restored"),
+                ("/api/v2/restore/sql", "Token restored"),
+                ("/api/v2/restore/shards/7", "Token restored"),
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_restore_waits_before_uploading_a_shard_again() {
+        let dir = backup_dir("restore-busy", false);
+        let transport = Arc::new(RestoreTransport::default());
+        transport.busy_shards.store(true, Ordering::SeqCst);
+        let client =
+            Client::with_transport(transport.clone(), "http://influx.invalid:8086", "o", "t");
+
+        let started = tokio::time::Instant::now();
+        let summary = client.restore(&dir).await.unwrap();
+        assert_eq!(summary.shards, 1);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(30));
+        let shards = transport
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(p, _)| p.ends_with("/7"))
+            .count();
+        assert_eq!(shards, 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_apply_template_and_delete_stack() {
+        use crate::stacks::TemplateApply;
+
+        // The same body answers the organization lookup and the apply call.
+        let body = r#"{"orgs":[{"id":"o1"}],"stackID":"s1","sources":["inline"]}"#;
+        let (client, requests) = client(StatusCode::OK, body);
+
+        let apply = TemplateApply::new(serde_json::json!([{"kind": "Bucket"}]))
+            .stack("s1")
+            .dry_run(true);
+        let summary = client.apply_template(&apply).await.unwrap();
+        assert_eq!(summary.stack_id.as_deref(), Some("s1"));
+        client.delete_stack("s1").await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[1].url.path(), "/api/v2/templates/apply");
+        assert_eq!(requests[1].headers["content-type"], "application/json");
+        let sent: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(sent["orgID"], "o1");
+        assert_eq!(sent["dryRun"], true);
+        assert_eq!(requests[3].method, Method::DELETE);
+        assert_eq!(requests[3].url.path(), "/api/v2/stacks/s1");
+        assert_eq!(requests[3].url.query(), Some("orgID=o1"));
+    }
+
+    #[tokio::test]
+    async fn test_write_api_reports_errors() {
+        use futures::SinkExt;
+
+        let (client, _) = client(StatusCode::BAD_REQUEST, r#"{"message":"bad line"}"#);
+        let mut writer = client.write_api("bucket");
+
+        let mut record = FluxRecord::new(0);
+        record.insert("_measurement", crate::Value::from("m"));
+        record.insert("_field", crate::Value::from("f"));
+        record.insert("_value", crate::Value::from(1.5));
+        writer.send(record).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_query_paged_continues_on_full_pages() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1, 2]));
+
+        // Every page is full, so paging goes on as long as records are read.
+        let records: Vec<_> = client
+            .query_paged("buckets()", 2)
+            .take(5)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 5);
+        let queries: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body["query"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            queries,
+            [
+                "buckets()\n  |> limit(n: 2, offset: 0)",
+                "buckets()\n  |> limit(n: 2, offset: 2)",
+                "buckets()\n  |> limit(n: 2, offset: 4)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_paged_stops_on_short_page() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1, 2]));
+
+        let records: Vec<_> = client
+            .query_paged("buckets()", 3)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    /// Serve `body`, then keep the connection open without sending anything.
+    struct StallingTransport {
+        body: String,
+    }
+
+    impl Transport for StallingTransport {
+        fn send(&self, _: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            let body = stream::iter([Ok(Bytes::from(self.body.clone()))]).chain(stream::pending());
+            Box::pin(futures::future::ready(Ok(TransportResponse {
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Box::pin(body),
+            })))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_stall() {
+        let transport = StallingTransport { body: longs(&[1]) };
+        let client = Client::with_transport(transport, "http://influx.invalid:8086", "o", "t");
+        let timeout = std::time::Duration::from_secs(30);
+        let options = QueryOptions::new().idle_timeout(timeout);
+
+        let mut reader = client
+            .query_reader_opts("buckets()", &options)
+            .await
+            .unwrap();
+        assert_eq!(reader.next().await.unwrap().unwrap().get_long("n"), Some(1));
+        let err = reader.next().await.unwrap_err();
+        assert!(matches!(err, Error::Stalled(t) if t == timeout));
+        assert!(err.is_retryable());
+        assert!(reader.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_cancel_handle() {
+        let transport = StallingTransport { body: longs(&[1]) };
+        let client = Client::with_transport(transport, "http://influx.invalid:8086", "o", "t");
+
+        let mut stream = client.query_stream("buckets()").await.unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        let cancel = stream.cancel_handle();
+        let consumer = tokio::spawn(async move { stream.next().await.is_none() });
+        tokio::task::yield_now().await;
+        cancel.cancel();
+        assert!(consumer.await.unwrap());
+        assert!(cancel.is_cancelled());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_query_timeout() {
+        let transport = StallingTransport { body: longs(&[1]) };
+        let client = Client::with_transport(transport, "http://influx.invalid:8086", "o", "t");
+        let timeout = std::time::Duration::from_secs(30);
+        let options = QueryOptions::new().timeout(timeout);
+
+        let mut stream = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::Timeout(t) if t == timeout));
+        assert!(stream.next().await.is_none());
+
+        let mut raw = client
+            .query_raw_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        assert!(raw.next().await.unwrap().is_ok());
+        let err = raw.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_query_reader_next_into() {
+        let (client, _) = client(StatusCode::OK, longs(&[1, 2]));
+
+        let mut reader = client.query_reader("buckets()").await.unwrap();
+        let mut record = FluxRecord::new(0);
+        let mut seen = Vec::new();
+        while reader.next_into(&mut record).await.unwrap() {
+            seen.push(record.get_long("n").unwrap());
+        }
+        assert_eq!(seen, vec![1, 2]);
+        assert!(reader.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_opts_small_buffer() {
+        let (client, _) = client(StatusCode::OK, longs(&[1, 2]));
+
+        let options = QueryOptions::new().buffer_size(3);
+        let records: Vec<_> = client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_opts_max_records() {
+        let (client, _) = client(StatusCode::OK, longs(&[1, 2]));
+
+        let options = QueryOptions::new().max_records(2);
+        assert_eq!(
+            client
+                .query_opts("buckets()", &options)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let options = QueryOptions::new().max_records(1);
+        let err = client.query_opts("buckets()", &options).await.unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(_)));
+        assert!(!err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_builder_with_transport() {
+        let (transport, requests) = StaticTransport::new(StatusCode::OK, longs(&[1]));
+        let client = Client::builder("http://influx.invalid:8086", "org", "token")
+            .tcp_keepalive(std::time::Duration::from_secs(30))
+            .transport(transport)
+            .build()
+            .unwrap();
+        assert_eq!(client.query("buckets()").await.unwrap().len(), 1);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_query_hook_fires() {
+        let (builder, _) = builder(StatusCode::OK, longs(&[1, 2]));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let hook = crate::hooks::SlowQueryHook::new(move |slow| {
+            sink.lock()
+                .unwrap()
+                .push((slow.query.clone(), slow.records))
+        })
+        .total(std::time::Duration::ZERO);
+
+        let client = builder.slow_query_hook(hook).build().unwrap();
+        client.query(r#"from(bucket: "b")"#).await.unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(r#"from(bucket: "?")"#.to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_builder_with_connection_options() {
+        let client = Client::builder("http://localhost:8086", "org", "token")
+            .pool_idle_timeout(std::time::Duration::from_secs(50))
+            .pool_max_idle_per_host(4)
+            .tcp_keepalive(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap();
+        assert_eq!(client.url().as_str(), "http://localhost:8086/");
+
+        let result = Client::builder("not a url", "org", "token").build();
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_chunked_requests_each_window() {
+        let (client, requests) = client(StatusCode::OK, longs(&[1]));
+        let start = chrono::DateTime::parse_from_rfc3339("2023-11-14T00:00:00Z").unwrap();
+
+        let records: Vec<_> = client
+            .query_stream_chunked(
+                |start, stop| format!("range(start: {}, stop: {})", start, stop),
+                start,
+                start + chrono::TimeDelta::hours(3),
+                chrono::TimeDelta::hours(1),
+            )
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_v3_sql_query() {
+        let body = "{\"host\":\"a\",\"n\":1}\n{\"host\":\"b\",\"n\":2}\n";
+        let (builder, requests) = builder(StatusCode::OK, body);
+        let client = builder.api_version(ApiVersion::V3).build().unwrap();
+
+        let records: Vec<_> = client
+            .query_sql("sensors", "SELECT * FROM cpu")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get_string("host"), Some("b".to_string()));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].url.path(), "/api/v3/query_sql");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["db"], "sensors");
+        assert_eq!(body["format"], "jsonl");
+    }
+
+    #[tokio::test]
+    async fn test_v3_rejects_flux() {
+        let (builder, requests) = builder(StatusCode::OK, "");
+        let client = builder.api_version(ApiVersion::V3).build().unwrap();
+
+        assert!(matches!(
+            client.query("buckets()").await,
+            Err(Error::Config(_))
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detect_api_defaults_to_v2() {
+        let (client, requests) = client(StatusCode::NO_CONTENT, "");
+
+        let client = client.with_detected_api().await.unwrap();
+        assert_eq!(client.api_version(), ApiVersion::V2);
+        assert_eq!(requests.lock().unwrap()[0].url.path(), "/ping");
+    }
+
+    #[tokio::test]
+    async fn test_detect_flavor_from_health() {
+        let body = r#"{"name":"influxdb","status":"pass","version":"1.8.10"}"#;
+        let (client, requests) = client(StatusCode::OK, body);
+
+        let client = client.with_detected_api().await.unwrap();
+        assert_eq!(client.server_flavor(), Some(ServerFlavor::Oss1));
+        assert_eq!(client.auth_scheme(), AuthScheme::Token);
+        assert_eq!(requests.lock().unwrap()[1].url.path(), "/health");
+
+        // Backups are not offered by 1.x, so nothing is sent.
+        let dir = std::env::temp_dir().join("influxdb-stream-unsupported-backup");
+        assert!(matches!(client.backup(&dir).await, Err(Error::Config(_))));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_cardinality() {
+        let csv = "#datatype,string,long,long\n#group,false,false,false\n#default,_result,,\n,result,table,_value\n,,0,7\n\n";
+        let (client, requests) = client(StatusCode::OK, csv);
+        let estimate = crate::cardinality::Estimate::new(
+            "telegraf",
+            crate::schema::TimeRange::last(chrono::Duration::hours(1)),
+        );
+
+        let size = client.estimate_cardinality(&estimate).await.unwrap();
+        assert_eq!(size.series, 7);
+        assert_eq!(size.rows, 7);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_builder_user_agent() {
+        let (builder, requests) = builder(StatusCode::OK, "");
+        let client = builder.user_agent("nightly-export/2.1").build().unwrap();
+        client.query("buckets()").await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers["user-agent"], "nightly-export/2.1");
+
+        let err = Client::builder("http://influx.invalid:8086", "o", "t")
+            .user_agent("bad\nagent")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Config(_)));
+        let err = Client::builder("not a url", "o", "t")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Config(_)));
+    }
+
+    #[test]
+    fn test_builder_from_env_vars() {
+        let vars = |name: &str| match name {
+            "INFLUX_URL" => Some("http://influx.invalid:8086".to_string()),
+            "INFLUX_ORG" => Some("o".to_string()),
+            "INFLUX_TOKEN" => Some("t".to_string()),
+            _ => None,
+        };
+        let client = builder_from_vars(vars).unwrap().build().unwrap();
+        assert_eq!(client.url().as_str(), "http://influx.invalid:8086/");
+        assert_eq!(client.org(), "o");
+
+        let vars = |name: &str| (name == "INFLUX_ORG").then(|| "o".to_string());
+        match builder_from_vars(vars) {
+            Err(Error::Config(message)) => assert_eq!(
+                message,
+                "Missing environment variables: INFLUX_HOST (or INFLUX_URL), INFLUX_TOKEN"
+            ),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_default_headers() {
+        use http::header::{CONTENT_TYPE, HeaderName, HeaderValue};
+
+        let (builder, requests) = builder(StatusCode::OK, "");
+        let client = builder
+            .default_header(
+                HeaderName::from_static("x-tenant"),
+                HeaderValue::from_static("acme"),
+            )
+            .default_header(CONTENT_TYPE, HeaderValue::from_static("text/plain"))
+            .build()
+            .unwrap();
+        client.query("buckets()").await.unwrap();
+        client.ping().await.ok();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.headers["x-tenant"] == "acme"));
+        assert_eq!(requests[0].headers[CONTENT_TYPE], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_query_options_token() {
+        use http::header::{AUTHORIZATION, HeaderValue};
+
+        let (client, requests) = client(StatusCode::OK, "");
+        let options = QueryOptions::new()
+            .token("tenant-token")
+            .header(AUTHORIZATION, HeaderValue::from_static("Token forged"));
+        client
+            .query_stream_opts("buckets()", &options)
+            .await
+            .unwrap();
+        client.query("buckets()").await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers[AUTHORIZATION], "Token tenant-token");
+        assert_eq!(requests[1].headers[AUTHORIZATION], "Token token");
+        assert!(!format!("{options:?}").contains("tenant-token"));
+    }
+
+    /// Serves `/api/v2/signin` and accepts the latest session cookie.
+    #[derive(Default)]
+    struct SessionTransport {
+        signins: Mutex<usize>,
+        valid: Mutex<Option<String>>,
+    }
+
+    impl Transport for Arc<SessionTransport> {
+        fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            let mut headers = HeaderMap::new();
+            let status = if request.url.path() == "/api/v2/signin" {
+                assert_eq!(request.headers["authorization"], "Basic dXNlcjpwYXNz");
+                let mut signins = self.signins.lock().unwrap();
+                *signins += 1;
+                let cookie = format!("influxdb-oss-session=s{}", signins);
+                let set_cookie = format!("{cookie}; Path=/api/; HttpOnly");
+                headers.insert("set-cookie", set_cookie.parse().unwrap());
+                *self.valid.lock().unwrap() = Some(cookie);
+                StatusCode::NO_CONTENT
+            } else {
+                assert!(!request.headers.contains_key("authorization"));
+                let cookie = request.headers["cookie"].to_str().unwrap();
+                match self.valid.lock().unwrap().as_deref() {
+                    Some(valid) if valid == cookie => StatusCode::OK,
+                    _ => StatusCode::UNAUTHORIZED,
+                }
+            };
+            Box::pin(async move {
+                Ok(TransportResponse {
+                    status,
+                    headers,
+                    body: Box::pin(stream::empty()),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_username_password_session() {
+        let transport = Arc::new(SessionTransport::default());
+        let client = Client::builder("http://influx.invalid:8086", "o", "")
+            .transport(transport.clone())
+            .username_password("user", "pass")
+            .build()
+            .unwrap();
+
+        client.query("buckets()").await.unwrap();
+        client.query("buckets()").await.unwrap();
+        assert_eq!(*transport.signins.lock().unwrap(), 1);
+
+        // The session expires; the client signs in again.
+        *transport.valid.lock().unwrap() = None;
+        client.query("buckets()").await.unwrap();
+        assert_eq!(*transport.signins.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_v1_credentials() {
+        let (builder, requests) = builder(StatusCode::NO_CONTENT, "");
+        let client = builder.v1_credentials("user", "p&ss").build().unwrap();
+        client
+            .write_lines_v1("db", None, crate::write::Precision::Seconds, "m v=1 1")
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].url.query(), Some("db=db&precision=s"));
+        assert_eq!(requests[0].headers["authorization"], "Token user:p&ss");
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_v1_credentials_not_in_errors() {
+        // Nothing listens on the port once the listener is dropped.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = Client::builder(&url, "o", "")
+            .v1_credentials("user", "hunter2")
+            .build()
+            .unwrap();
+
+        let err = client
+            .write_lines_v1("db", None, crate::write::Precision::Seconds, "m v=1 1")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Http(_)), "unexpected error: {err:?}");
+        assert!(!err.to_string().contains("hunter2"));
+        assert!(!format!("{err:?}").contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn test_token_provider() {
+        use crate::auth::{Token, from_fn};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (builder, requests) = builder(StatusCode::OK, "");
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counter = issued.clone();
+        let client = builder
+            .token_provider(from_fn(move || {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                async move { Ok(Token::new(format!("lease-{n}"))) }
+            }))
+            .build()
+            .unwrap();
+        client.query("buckets()").await.unwrap();
+        client.query("buckets()").await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers["authorization"], "Token lease-1");
+        assert_eq!(requests[1].headers["authorization"], "Token lease-2");
+    }
+
+    #[tokio::test]
+    async fn test_token_provider_retries_unauthorized() {
+        use crate::auth::{CachedTokenProvider, Token, from_fn};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct RotatedTransport(Requests);
+
+        impl Transport for RotatedTransport {
+            fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+                let status = match request.headers["authorization"] == "Token new" {
+                    true => StatusCode::OK,
+                    false => StatusCode::UNAUTHORIZED,
+                };
+                self.0.lock().unwrap().push(request);
+                Box::pin(futures::future::ready(Ok(TransportResponse {
+                    status,
+                    headers: HeaderMap::new(),
+                    body: Box::pin(stream::empty()),
+                })))
+            }
+        }
+
+        let requests = Requests::default();
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counter = issued.clone();
+        let provider = CachedTokenProvider::new(from_fn(move || {
+            let secret = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => "old",
+                _ => "new",
+            };
+            async move { Ok(Token::new(secret)) }
+        }));
+        let client = Client::builder("http://influx.invalid:8086", "org", "")
+            .transport(RotatedTransport(requests.clone()))
+            .token_provider(provider)
+            .build()
+            .unwrap();
+        client.query("buckets()").await.unwrap();
+        client.query("buckets()").await.unwrap();
+
+        let auth: Vec<_> = (requests.lock().unwrap().iter())
+            .map(|r| r.headers["authorization"].clone())
+            .collect();
+        assert_eq!(auth, ["Token old", "Token new", "Token new"]);
+        assert_eq!(issued.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_base_path_prefix() {
+        for base in [
+            "http://gateway.invalid/influx",
+            "http://gateway.invalid/influx/",
+        ] {
+            let (transport, requests) = StaticTransport::new(StatusCode::OK, "");
+            let client = Client::with_transport(transport, base, "o", "t");
+            client.query("buckets()").await.unwrap();
+            assert_eq!(
+                requests.lock().unwrap()[0].url.path(),
+                "/influx/api/v2/query"
+            );
+        }
     }
 }
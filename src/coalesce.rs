@@ -0,0 +1,320 @@
+//! Coalescing of identical concurrent queries.
+//!
+//! Dashboards often issue the same query from many tasks at once. A
+//! [`Coalescer`] sends one request per distinct query text while it is in
+//! flight and hands every caller its own copy of the records.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::coalesce::Coalescer;
+//!
+//! let coalescer = Coalescer::new(client);
+//! let query = r#"from(bucket: "sensors") |> range(start: -5m)"#;
+//!
+//! // One HTTP request serves both streams.
+//! let (a, b) = futures::join!(
+//!     coalescer.query_stream(query).count(),
+//!     coalescer.query_stream(query).count(),
+//! );
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_stream::stream;
+use futures::StreamExt;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+use crate::client::{Client, QueryClient, RecordStream};
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+type Inflight = Arc<Mutex<HashMap<String, Arc<Shared>>>>;
+
+/// Runs identical concurrent queries as a single request.
+///
+/// Cloning the coalescer is cheap; clones share the same in-flight requests.
+#[derive(Clone)]
+pub struct Coalescer<C = Client> {
+    client: C,
+    inflight: Inflight,
+}
+
+impl<C> Coalescer<C>
+where
+    C: QueryClient + Clone + Send + Sync + 'static,
+{
+    /// Create a coalescer sending queries through `client`.
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            inflight: Arc::default(),
+        }
+    }
+
+    /// Execute `query`, sharing the request with identical queries in flight.
+    ///
+    /// Queries are matched by their exact text when the returned stream is
+    /// first polled. The request runs on a spawned task; every subscriber
+    /// receives all records from the start, so the records read so far are
+    /// kept in memory until the last subscriber is done. That suits the small,
+    /// frequently repeated queries coalescing is meant for. Dropping every
+    /// subscriber cancels the request.
+    ///
+    /// Errors cannot be cloned, so they are yielded as [`Error::Shared`].
+    ///
+    /// # Panics
+    ///
+    /// The stream panics if polled outside of a Tokio runtime.
+    pub fn query_stream(&self, query: impl Into<String>) -> RecordStream {
+        let client = self.client.clone();
+        let inflight = self.inflight.clone();
+        let query = query.into();
+
+        Box::pin(stream! {
+            let subscription = Subscription::join(&inflight, client, query);
+            let shared = subscription.shared.clone();
+            let mut next = 0;
+            loop {
+                let notified = shared.notify.notified();
+                let mut notified = std::pin::pin!(notified);
+                notified.as_mut().enable();
+
+                let item = {
+                    let buffer = shared.buffer.lock().expect("coalescer lock poisoned");
+                    match buffer.items.get(next) {
+                        Some(Ok(record)) => Some(Ok(record.clone())),
+                        Some(Err(e)) => Some(Err(Error::Shared(e.clone()))),
+                        None if buffer.done => break,
+                        None => None,
+                    }
+                };
+                match item {
+                    Some(item) => {
+                        next += 1;
+                        yield item;
+                    }
+                    None => notified.await,
+                }
+            }
+        })
+    }
+
+    /// Get the number of distinct queries currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.inflight.lock().expect("coalescer lock poisoned").len()
+    }
+
+    /// Get the underlying client.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+}
+
+impl<C> std::fmt::Debug for Coalescer<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coalescer").finish_non_exhaustive()
+    }
+}
+
+/// Records of one in-flight query, shared by its subscribers.
+#[derive(Default)]
+struct Shared {
+    buffer: Mutex<Buffer>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct Buffer {
+    items: Vec<std::result::Result<FluxRecord, Arc<Error>>>,
+    done: bool,
+    subscribers: usize,
+    task: Option<AbortHandle>,
+}
+
+impl Shared {
+    fn push(&self, item: Result<FluxRecord>) {
+        let mut buffer = self.buffer.lock().expect("coalescer lock poisoned");
+        buffer.items.push(item.map_err(Arc::new));
+        drop(buffer);
+        self.notify.notify_waiters();
+    }
+
+    fn finish(&self) {
+        self.buffer.lock().expect("coalescer lock poisoned").done = true;
+        self.notify.notify_waiters();
+    }
+}
+
+/// Membership of one stream in a shared query; leaving as the last member
+/// cancels the request.
+struct Subscription {
+    inflight: Inflight,
+    query: String,
+    shared: Arc<Shared>,
+}
+
+impl Subscription {
+    fn join<C>(inflight: &Inflight, client: C, query: String) -> Self
+    where
+        C: QueryClient + Send + Sync + 'static,
+    {
+        let mut map = inflight.lock().expect("coalescer lock poisoned");
+        let shared = match map.get(&query) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared = Arc::new(Shared::default());
+                let task = tokio::spawn(drive(
+                    client,
+                    query.clone(),
+                    shared.clone(),
+                    inflight.clone(),
+                ));
+                shared.buffer.lock().expect("coalescer lock poisoned").task =
+                    Some(task.abort_handle());
+                map.insert(query.clone(), shared.clone());
+                shared
+            }
+        };
+        shared
+            .buffer
+            .lock()
+            .expect("coalescer lock poisoned")
+            .subscribers += 1;
+
+        Self {
+            inflight: inflight.clone(),
+            query,
+            shared,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut map = self.inflight.lock().expect("coalescer lock poisoned");
+        let mut buffer = self.shared.buffer.lock().expect("coalescer lock poisoned");
+        buffer.subscribers -= 1;
+        if buffer.subscribers == 0 && !buffer.done {
+            if let Some(task) = buffer.task.take() {
+                task.abort();
+            }
+            remove(&mut map, &self.query, &self.shared);
+        }
+    }
+}
+
+/// Read the query into `shared`, then stop accepting new subscribers.
+async fn drive<C: QueryClient>(client: C, query: String, shared: Arc<Shared>, inflight: Inflight) {
+    match client.query_stream(query.clone()).await {
+        Ok(mut records) => {
+            while let Some(item) = records.next().await {
+                shared.push(item);
+            }
+        }
+        Err(e) => shared.push(Err(e)),
+    }
+
+    remove(
+        &mut inflight.lock().expect("coalescer lock poisoned"),
+        &query,
+        &shared,
+    );
+    shared.finish();
+}
+
+/// Remove `shared` from the in-flight map, unless a newer request replaced it.
+fn remove(map: &mut HashMap<String, Arc<Shared>>, query: &str, shared: &Arc<Shared>) {
+    if map.get(query).is_some_and(|s| Arc::ptr_eq(s, shared)) {
+        map.remove(query);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Serve three records per query, slowly, counting requests.
+    #[derive(Clone, Default)]
+    struct CountingClient {
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl QueryClient for CountingClient {
+        fn query_stream(
+            &self,
+            query: impl Into<String> + Send,
+        ) -> impl Future<Output = Result<RecordStream>> + Send {
+            let query = query.into();
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if query == "bad" {
+                    return Err(Error::Csv("bad query".to_string()));
+                }
+                let records = stream::iter(0..3).then(|i| async move {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    Ok(FluxRecord::new(i))
+                });
+                Ok(Box::pin(records) as RecordStream)
+            }
+        }
+    }
+
+    async fn tables(stream: RecordStream) -> Vec<i32> {
+        stream.map(|r| r.unwrap().table).collect().await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_queries_share_request() {
+        let client = CountingClient::default();
+        let coalescer = Coalescer::new(client.clone());
+
+        let mut first = coalescer.query_stream("q");
+        assert_eq!(first.next().await.unwrap().unwrap().table, 0);
+        assert_eq!(coalescer.in_flight(), 1);
+
+        // Joins the request in flight and still sees every record.
+        let (rest, second, other) = futures::join!(
+            tables(first),
+            tables(coalescer.query_stream("q")),
+            tables(coalescer.query_stream("other")),
+        );
+        assert_eq!(rest, vec![1, 2]);
+        assert_eq!(second, vec![0, 1, 2]);
+        assert_eq!(other, vec![0, 1, 2]);
+        assert_eq!(client.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(coalescer.in_flight(), 0);
+
+        // Finished queries are not reused.
+        assert_eq!(tables(coalescer.query_stream("q")).await, vec![0, 1, 2]);
+        assert_eq!(client.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropping_all_subscribers_cancels() {
+        let coalescer = Coalescer::new(CountingClient::default());
+
+        let mut stream = coalescer.query_stream("q");
+        assert!(stream.next().await.is_some());
+        drop(stream);
+        assert_eq!(coalescer.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_shared() {
+        let coalescer = Coalescer::new(CountingClient::default());
+
+        let items: Vec<_> = coalescer.query_stream("bad").collect().await;
+        assert_eq!(items.len(), 1);
+        match &items[0] {
+            Err(Error::Shared(e)) => assert!(matches!(**e, Error::Csv(_))),
+            other => panic!("unexpected item: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Configuration profiles of the `influx` CLI.
+//!
+//! `influx config create` saves named connection profiles in a TOML file,
+//! `~/.influxdbv2/configs` unless `INFLUX_CONFIGS_PATH` points elsewhere:
+//!
+//! ```toml
+//! [default]
+//!   url = "http://localhost:8086"
+//!   token = "my-token"
+//!   org = "my-org"
+//!   active = true
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Deserialize)]
+struct Profile {
+    url: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    org: String,
+    #[serde(default)]
+    active: bool,
+}
+
+/// Connection settings read from a profile.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Settings {
+    pub(crate) url: String,
+    pub(crate) org: String,
+    pub(crate) token: String,
+}
+
+/// Get the path of the CLI's configuration file.
+pub(crate) fn configs_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("INFLUX_CONFIGS_PATH") {
+        return Ok(path.into());
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .ok_or_else(|| Error::Config("Cannot locate the home directory".to_string()))?;
+    Ok(PathBuf::from(home).join(".influxdbv2").join("configs"))
+}
+
+/// Read profile `name`, or the active profile, from the CLI's configuration.
+pub(crate) fn load(name: Option<&str>) -> Result<Settings> {
+    let path = configs_path()?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| Error::Config(format!("Cannot read {}: {}", path.display(), e)))?;
+    select(&text, name).map_err(|e| match e {
+        Error::Config(message) => Error::Config(format!("{}: {}", path.display(), message)),
+        other => other,
+    })
+}
+
+fn select(text: &str, name: Option<&str>) -> Result<Settings> {
+    let mut profiles: BTreeMap<String, Profile> =
+        toml::from_str(text).map_err(|e| Error::Config(format!("Invalid influx config: {}", e)))?;
+    let profile = match name {
+        Some(name) => profiles
+            .remove(name)
+            .ok_or_else(|| Error::Config(format!("No influx profile named '{}'", name)))?,
+        None => profiles
+            .into_values()
+            .find(|p| p.active)
+            .ok_or_else(|| Error::Config("No active influx profile".to_string()))?,
+    };
+    Ok(Settings {
+        url: profile.url,
+        org: profile.org,
+        token: profile.token,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIGS: &str = r#"
+[default]
+  url = "http://localhost:8086"
+  token = "local-token"
+  org = "dev"
+  active = true
+
+[cloud]
+  url = "https://us-east-1-1.aws.cloud2.influxdata.com"
+  token = "cloud-token"
+  org = "acme"
+"#;
+
+    #[test]
+    fn test_select_profile() {
+        let cloud = select(CONFIGS, Some("cloud")).unwrap();
+        assert_eq!(cloud.url, "https://us-east-1-1.aws.cloud2.influxdata.com");
+        assert_eq!(cloud.org, "acme");
+        assert_eq!(cloud.token, "cloud-token");
+
+        let active = select(CONFIGS, None).unwrap();
+        assert_eq!(active.org, "dev");
+    }
+
+    #[test]
+    fn test_select_errors() {
+        let err = select(CONFIGS, Some("staging")).unwrap_err();
+        assert!(matches!(err, Error::Config(m) if m == "No influx profile named 'staging'"));
+        let inactive = CONFIGS.replace("active = true", "");
+        assert!(select(&inactive, None).is_err());
+        assert!(select("[default", None).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//! Deserializing records with serde.
+//!
+//! Any `Deserialize` type can be read from a record: structs take their
+//! fields from the columns of the same name (after `#[serde(rename)]`), and
+//! maps receive all columns. Values convert as follows:
+//!
+//! | Column type | Deserialized as |
+//! |---|---|
+//! | `string` | string, or a unit enum variant |
+//! | `double`, `long`, `unsignedLong`, `boolean` | the matching number or bool |
+//! | `dateTime:RFC3339` | RFC 3339 string, which `chrono` types parse |
+//! | `duration` | nanoseconds as `i64` |
+//! | `base64Binary` | bytes |
+//! | null | `None`, or unit |
+//!
+//! A missing column is treated like a null, so `Option` fields become `None`
+//! and other fields fail.
+//!
+//! A [`Decoder`] matches struct fields to columns once per table and reuses
+//! the mapping for every record sharing its [`RecordSchema`], so wide structs
+//! cost no name lookups per row.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::adapters::RecordStreamExt;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Reading {
+//!     #[serde(rename = "_time")]
+//!     time: chrono::DateTime<chrono::FixedOffset>,
+//!     sensor: String,
+//!     #[serde(rename = "_value")]
+//!     value: Option<f64>,
+//! }
+//!
+//! let mut readings = client.query_stream(query).await?.deserialize::<Reading>();
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use chrono::SecondsFormat;
+use futures::Stream;
+use pin_project_lite::pin_project;
+use serde::de::value::StrDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::error::{Error, Result};
+use crate::types::{FluxRecord, RecordSchema};
+use crate::value::Value;
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Parse {
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Deserialize a single record into `T`.
+///
+/// Use a [`Decoder`] to convert many records.
+pub fn from_record<T: DeserializeOwned>(record: &FluxRecord) -> Result<T> {
+    Decoder::new().decode(record)
+}
+
+/// Converter of records into `T` that caches the column of each field.
+pub struct Decoder<T> {
+    cache: FieldCache,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Decoder<T> {
+    /// Create a decoder with an empty cache.
+    pub fn new() -> Self {
+        Self {
+            cache: FieldCache::default(),
+            _type: PhantomData,
+        }
+    }
+
+    /// Deserialize `record`.
+    pub fn decode(&mut self, record: &FluxRecord) -> Result<T> {
+        T::deserialize(RecordDeserializer {
+            record,
+            cache: &mut self.cache,
+        })
+    }
+}
+
+impl<T: DeserializeOwned> Default for Decoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Decoder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("fields", &self.cache.fields)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Column positions of the fields of a struct in the current schema.
+#[derive(Default)]
+struct FieldCache {
+    schema: Option<Arc<RecordSchema>>,
+    fields: &'static [&'static str],
+    positions: Vec<Option<usize>>,
+}
+
+impl FieldCache {
+    fn positions(
+        &mut self,
+        schema: &Arc<RecordSchema>,
+        fields: &'static [&'static str],
+    ) -> &[Option<usize>] {
+        let current = std::ptr::eq(self.fields, fields)
+            && self.schema.as_ref().is_some_and(|s| Arc::ptr_eq(s, schema));
+        if !current {
+            self.positions = fields.iter().map(|f| schema.index_of(f)).collect();
+            self.fields = fields;
+            self.schema = Some(schema.clone());
+        }
+        &self.positions
+    }
+}
+
+struct RecordDeserializer<'a> {
+    record: &'a FluxRecord,
+    cache: &'a mut FieldCache,
+}
+
+impl<'de> de::Deserializer<'de> for RecordDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(ColumnAccess {
+            columns: self.record.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let positions = self.cache.positions(self.record.schema(), fields);
+        visitor.visit_map(FieldAccess {
+            fields,
+            positions,
+            values: self.record.values(),
+            next: 0,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// All columns of a record, as a map.
+struct ColumnAccess<'a, I> {
+    columns: I,
+    value: Option<&'a Value>,
+}
+
+impl<'de, 'a, I> MapAccess<'de> for ColumnAccess<'a, I>
+where
+    I: Iterator<Item = (&'a str, &'a Value)>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some((name, value)) = self.columns.next() else {
+            return Ok(None);
+        };
+        self.value = Some(value);
+        let name: StrDeserializer<'_, Error> = name.into_deserializer();
+        seed.deserialize(name).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(ValueDeserializer(self.value.take().unwrap_or(&Value::Null)))
+    }
+}
+
+/// The columns of a record holding the fields of a struct.
+///
+/// Fields are identified by their index, which serde matches without
+/// comparing names; fields without a column are left out.
+struct FieldAccess<'a> {
+    fields: &'static [&'static str],
+    positions: &'a [Option<usize>],
+    values: &'a [Value],
+    next: usize,
+}
+
+impl<'de> MapAccess<'de> for FieldAccess<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        while self.next < self.positions.len() {
+            let i = self.next;
+            if self.positions[i].is_some() {
+                return seed.deserialize(FieldIndex(i as u64)).map(Some);
+            }
+            self.next += 1;
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let i = self.next;
+        self.next += 1;
+        let value = self.positions[i]
+            .and_then(|p| self.values.get(p))
+            .unwrap_or(&Value::Null);
+        seed.deserialize(ValueDeserializer(value)).map_err(|e| {
+            let message = match e {
+                Error::Parse { message } => message,
+                e => e.to_string(),
+            };
+            Error::Parse {
+                message: format!("column '{}': {}", self.fields[i], message),
+            }
+        })
+    }
+}
+
+struct FieldIndex(u64);
+
+impl<'de> de::Deserializer<'de> for FieldIndex {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct ValueDeserializer<'a>(&'a Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::String(s) => visitor.visit_str(s),
+            Value::SharedString(s) => visitor.visit_str(s),
+            Value::Double(d) => visitor.visit_f64(d.into_inner()),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Long(n) => visitor.visit_i64(*n),
+            Value::UnsignedLong(n) => visitor.visit_u64(*n),
+            Value::Duration(d) => match d.num_nanoseconds() {
+                Some(ns) => visitor.visit_i64(ns),
+                None => Err(de::Error::custom("duration out of range")),
+            },
+            Value::Base64Binary(b) => visitor.visit_bytes(b),
+            Value::TimeRFC(t) => {
+                visitor.visit_string(t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            Value::Null => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0.as_string() {
+            Some(s) => visitor.visit_enum(s.into_deserializer()),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::deserialize`](crate::adapters::RecordStreamExt::deserialize).
+    pub struct Deserialized<S, T> {
+        #[pin]
+        stream: S,
+        decoder: Decoder<T>,
+    }
+}
+
+impl<S, T: DeserializeOwned> Deserialized<S, T> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            decoder: Decoder::new(),
+        }
+    }
+}
+
+impl<S, T> Stream for Deserialized<S, T>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.stream.poll_next(cx));
+        Poll::Ready(item.map(|r| r.and_then(|record| this.decoder.decode(&record))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use chrono::{DateTime, FixedOffset};
+    use futures::{StreamExt, stream};
+    use ordered_float::OrderedFloat;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Level {
+        Ok,
+        Crit,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Reading {
+        #[serde(rename = "_time")]
+        time: DateTime<FixedOffset>,
+        sensor: String,
+        #[serde(rename = "_value")]
+        value: Option<f64>,
+        level: Level,
+        count: Option<u32>,
+    }
+
+    fn record(columns: &[(&str, Value)]) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        for (name, value) in columns {
+            record.insert(name.to_string(), value.clone());
+        }
+        record
+    }
+
+    fn time() -> Value {
+        Value::TimeRFC(DateTime::parse_from_rfc3339("2024-01-01T10:00:00.5+02:00").unwrap())
+    }
+
+    #[test]
+    fn test_from_record_struct() {
+        let record = record(&[
+            ("_time", time()),
+            ("_value", Value::Double(OrderedFloat(1.5))),
+            ("sensor", Value::String("a".to_string())),
+            ("level", Value::String("crit".to_string())),
+            ("unused", Value::Long(1)),
+        ]);
+        let reading: Reading = from_record(&record).unwrap();
+        assert_eq!(
+            reading.time,
+            *record.get("_time").and_then(Value::as_time).unwrap()
+        );
+        assert_eq!(reading.sensor, "a");
+        assert_eq!(reading.value, Some(1.5));
+        assert_eq!(reading.level, Level::Crit);
+        assert_eq!(reading.count, None);
+    }
+
+    #[test]
+    fn test_from_record_errors_name_column() {
+        let record = record(&[
+            ("_time", time()),
+            ("sensor", Value::Long(1)),
+            ("level", Value::String("ok".to_string())),
+        ]);
+        let err = from_record::<Reading>(&record).unwrap_err();
+        assert!(err.to_string().contains("column 'sensor'"), "{}", err);
+
+        let missing = self::record(&[("_time", time())]);
+        assert!(from_record::<Reading>(&missing).is_err());
+    }
+
+    #[test]
+    fn test_from_record_map() {
+        let record = record(&[("a", Value::Long(1)), ("b", Value::Null)]);
+        let map: BTreeMap<String, Option<i64>> = from_record(&record).unwrap();
+        assert_eq!(map["a"], Some(1));
+        assert_eq!(map["b"], None);
+    }
+
+    #[tokio::test]
+    async fn test_deserialize_stream_across_schemas() {
+        let first = record(&[
+            ("sensor", Value::String("a".to_string())),
+            ("level", Value::String("ok".to_string())),
+            ("_time", time()),
+        ]);
+        let mut same_schema = first.clone();
+        same_schema.values_mut()[0] = Value::String("b".to_string());
+        let reordered = record(&[
+            ("_time", time()),
+            ("level", Value::String("crit".to_string())),
+            ("count", Value::Long(3)),
+            ("sensor", Value::String("c".to_string())),
+        ]);
+
+        let input = stream::iter(vec![Ok(first), Ok(same_schema), Ok(reordered)]);
+        let readings: Vec<Reading> = input
+            .deserialize::<Reading>()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        let sensors: Vec<_> = readings.iter().map(|r| r.sensor.as_str()).collect();
+        assert_eq!(sensors, ["a", "b", "c"]);
+        assert_eq!(readings[2].count, Some(3));
+        assert_eq!(readings[2].level, Level::Crit);
+    }
+}
@@ -0,0 +1,457 @@
+//! Synthetic data for load tests and benchmarks.
+//!
+//! Requires the `devtools` feature. A [`Corpus`] describes a set of series,
+//! given by a measurement, tags of a chosen cardinality and typed fields,
+//! and renders it either as line protocol, to load into a server, or as the
+//! annotated CSV that querying those series returns, to feed the parser or a
+//! [`MockServer`](crate::testing::MockServer) without a server at all.
+//!
+//! Values are derived from the seed and the position of each value, so a
+//! corpus renders the same bytes every time and both formats carry the same
+//! data.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::devtools::{Corpus, FieldType};
+//! use influxdb_stream::write::Precision;
+//!
+//! // 400 series with 1000 points each.
+//! let corpus = Corpus::new("cpu")
+//!     .tag("host", 100)
+//!     .tag("region", 4)
+//!     .field("usage", FieldType::Double)
+//!     .field("healthy", FieldType::Boolean)
+//!     .rows(1_000);
+//!
+//! client
+//!     .write_lines("load-test", Precision::Nanoseconds, corpus.line_protocol())
+//!     .await?;
+//! let parser = AnnotatedCsvParser::new(&corpus.annotated_csv()[..]);
+//! ```
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeDelta, Utc};
+
+use crate::types::DataType;
+use crate::value::Value;
+use crate::write::{Point, Precision};
+
+/// Type of a generated field.
+///
+/// String fields take one of 100 values, `value0` to `value99`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    /// Doubles between 0 and 100.
+    Double,
+    /// Signed integers between -1 000 000 and 1 000 000.
+    Long,
+    /// Unsigned integers below 1 000 000.
+    UnsignedLong,
+    /// Booleans.
+    Boolean,
+    /// Strings.
+    String,
+}
+
+impl FieldType {
+    fn data_type(self) -> DataType {
+        match self {
+            FieldType::Double => DataType::Double,
+            FieldType::Long => DataType::Long,
+            FieldType::UnsignedLong => DataType::UnsignedLong,
+            FieldType::Boolean => DataType::Bool,
+            FieldType::String => DataType::String,
+        }
+    }
+}
+
+/// A generated data set: every series has a point at each of
+/// [`rows`](Self::rows) timestamps.
+///
+/// Series are the combinations of the tag values, `<key>0` to
+/// `<key><cardinality - 1>` for each tag, optionally capped with
+/// [`series`](Self::series). Without any [`field`](Self::field), points have
+/// a single double field named `value`.
+#[derive(Clone, Debug)]
+pub struct Corpus {
+    measurement: String,
+    tags: Vec<(String, usize)>,
+    fields: Vec<(String, FieldType)>,
+    series: Option<usize>,
+    rows: usize,
+    start: DateTime<FixedOffset>,
+    interval: Duration,
+    seed: u64,
+    pivot: bool,
+}
+
+impl Corpus {
+    /// Create a corpus in `measurement` with 100 rows, one second apart,
+    /// starting at 2023-11-14T22:13:20Z.
+    pub fn new(measurement: impl Into<String>) -> Self {
+        Self {
+            measurement: measurement.into(),
+            tags: Vec::new(),
+            fields: Vec::new(),
+            series: None,
+            rows: 100,
+            start: DateTime::from_timestamp(1_700_000_000, 0)
+                .unwrap_or_default()
+                .fixed_offset(),
+            interval: Duration::from_secs(1),
+            seed: 0,
+            pivot: false,
+        }
+    }
+
+    /// Add tag `key` with `cardinality` distinct values.
+    pub fn tag(mut self, key: impl Into<String>, cardinality: usize) -> Self {
+        self.tags.push((key.into(), cardinality.max(1)));
+        self
+    }
+
+    /// Add field `key` of type `field_type`.
+    pub fn field(mut self, key: impl Into<String>, field_type: FieldType) -> Self {
+        self.fields.push((key.into(), field_type));
+        self
+    }
+
+    /// Generate at most `n` series (default: every combination of tag values).
+    pub fn series(mut self, n: usize) -> Self {
+        self.series = Some(n);
+        self
+    }
+
+    /// Set the number of points per series (default: 100).
+    pub fn rows(mut self, n: usize) -> Self {
+        self.rows = n;
+        self
+    }
+
+    /// Set the time of the first point.
+    pub fn start(mut self, start: DateTime<FixedOffset>) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Set the time between the points of a series (default: one second).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the seed values are derived from (default: `0`).
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Render annotated CSV as returned after `pivot(rowKey: ["_time"],
+    /// columnKey: ["_field"], ...)`, with one table per series and one
+    /// column per field (default: `false`, one table per series and field).
+    pub fn pivot(mut self, pivot: bool) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    /// Get the number of series.
+    pub fn series_count(&self) -> usize {
+        let combinations = self
+            .tags
+            .iter()
+            .fold(1usize, |n, (_, cardinality)| n.saturating_mul(*cardinality));
+        self.series.map_or(combinations, |n| n.min(combinations))
+    }
+
+    /// Get the points, ordered by time and then by series.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        let series = self.series_count();
+        let fields = self.field_types();
+        (0..self.rows).flat_map(move |row| {
+            let fields = fields.clone();
+            (0..series).map(move |s| {
+                let mut point = Point::new(&self.measurement).timestamp(self.time(row));
+                for (key, value) in self.tag_values(s) {
+                    point = point.tag(key, value);
+                }
+                for (f, (key, field_type)) in fields.iter().enumerate() {
+                    point = point.field(key.as_ref(), self.value(*field_type, s, f, row));
+                }
+                point
+            })
+        })
+    }
+
+    /// Write the points as line protocol with nanosecond timestamps.
+    pub fn write_line_protocol(&self, mut writer: impl Write) -> io::Result<()> {
+        for point in self.points() {
+            let line = point
+                .to_line_protocol(Precision::Nanoseconds)
+                .map_err(io::Error::other)?;
+            writeln!(writer, "{line}")?;
+        }
+        Ok(())
+    }
+
+    /// Render the points as line protocol with nanosecond timestamps.
+    pub fn line_protocol(&self) -> String {
+        let mut out = Vec::new();
+        // Writing to a Vec cannot fail, and generated points always have a field.
+        let _ = self.write_line_protocol(&mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+
+    /// Write the annotated CSV of a query returning the whole corpus.
+    pub fn write_annotated_csv(&self, writer: impl Write) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        let fields = self.field_types();
+        let start = rfc3339(self.time(0));
+        let stop = rfc3339(self.time(self.rows));
+
+        let mut table = 0;
+        for s in 0..self.series_count() {
+            let mut tags = self.tag_values(s);
+            tags.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            // A table holds one field of the series, or all of them when pivoted.
+            let groups: Vec<Vec<usize>> = if self.pivot {
+                vec![(0..fields.len()).collect()]
+            } else {
+                (0..fields.len()).map(|f| vec![f]).collect()
+            };
+
+            for group in groups {
+                let mut columns = vec![
+                    ("_start", DataType::TimeRFC, true),
+                    ("_stop", DataType::TimeRFC, true),
+                    ("_time", DataType::TimeRFC, false),
+                ];
+                if !self.pivot {
+                    columns.push(("_value", fields[group[0]].1.data_type(), false));
+                    columns.push(("_field", DataType::String, true));
+                }
+                columns.push(("_measurement", DataType::String, true));
+                columns.extend(tags.iter().map(|(key, _)| (*key, DataType::String, true)));
+                if self.pivot {
+                    columns.extend(
+                        fields
+                            .iter()
+                            .map(|(key, t)| (key.as_ref(), t.data_type(), false)),
+                    );
+                }
+                write_header(&mut writer, table == 0, &columns)?;
+
+                for row in 0..self.rows {
+                    let mut cells = vec![start.clone(), stop.clone(), rfc3339(self.time(row))];
+                    let values = group
+                        .iter()
+                        .map(|&f| csv_value(&self.value(fields[f].1, s, f, row)));
+                    if self.pivot {
+                        cells.push(cell(&self.measurement).into_owned());
+                        cells.extend(tags.iter().map(|(_, v)| cell(v).into_owned()));
+                        cells.extend(values);
+                    } else {
+                        cells.extend(values);
+                        cells.push(cell(&fields[group[0]].0).into_owned());
+                        cells.push(cell(&self.measurement).into_owned());
+                        cells.extend(tags.iter().map(|(_, v)| cell(v).into_owned()));
+                    }
+                    writeln!(writer, ",,{table},{}", cells.join(","))?;
+                }
+                table += 1;
+            }
+        }
+        // InfluxDB ends its responses with a blank line.
+        if table > 0 {
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Render the annotated CSV of a query returning the whole corpus.
+    pub fn annotated_csv(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        // Writing to a Vec cannot fail.
+        let _ = self.write_annotated_csv(&mut out);
+        out
+    }
+
+    fn field_types(&self) -> Vec<(Cow<'_, str>, FieldType)> {
+        if self.fields.is_empty() {
+            return vec![(Cow::Borrowed("value"), FieldType::Double)];
+        }
+        self.fields
+            .iter()
+            .map(|(key, t)| (Cow::Borrowed(key.as_str()), *t))
+            .collect()
+    }
+
+    /// Tag values of series `s`; the first tag varies fastest.
+    fn tag_values(&self, mut s: usize) -> Vec<(&str, String)> {
+        self.tags
+            .iter()
+            .map(|(key, cardinality)| {
+                let value = format!("{key}{}", s % cardinality);
+                s /= cardinality;
+                (key.as_str(), value)
+            })
+            .collect()
+    }
+
+    fn time(&self, row: usize) -> DateTime<FixedOffset> {
+        let nanos = self.interval.as_nanos().saturating_mul(row as u128);
+        let offset = TimeDelta::nanoseconds(i64::try_from(nanos).unwrap_or(i64::MAX));
+        self.start
+            .checked_add_signed(offset)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC.fixed_offset())
+    }
+
+    fn value(&self, field_type: FieldType, series: usize, field: usize, row: usize) -> Value {
+        let h = [series, field, row]
+            .into_iter()
+            .fold(self.seed, |h, n| splitmix64(h ^ n as u64));
+        match field_type {
+            FieldType::Double => Value::from((h % 100_000) as f64 / 1000.0),
+            FieldType::Long => Value::Long((h % 2_000_001) as i64 - 1_000_000),
+            FieldType::UnsignedLong => Value::UnsignedLong(h % 1_000_000),
+            FieldType::Boolean => Value::Bool(h & 1 == 1),
+            FieldType::String => Value::String(format!("value{}", h % 100)),
+        }
+    }
+}
+
+/// Write the annotations and header row of a table.
+fn write_header(
+    writer: &mut impl Write,
+    first: bool,
+    columns: &[(&str, DataType, bool)],
+) -> io::Result<()> {
+    if !first {
+        writeln!(writer)?;
+    }
+    let join = |f: &dyn Fn(&(&str, DataType, bool)) -> String| {
+        columns.iter().map(f).collect::<Vec<_>>().join(",")
+    };
+    writeln!(
+        writer,
+        "#datatype,string,long,{}",
+        join(&|c| c.1.to_string())
+    )?;
+    writeln!(writer, "#group,false,false,{}", join(&|c| c.2.to_string()))?;
+    writeln!(writer, "#default,_result,,{}", join(&|_| String::new()))?;
+    writeln!(
+        writer,
+        ",result,table,{}",
+        join(&|c| cell(c.0).into_owned())
+    )
+}
+
+fn rfc3339(time: DateTime<FixedOffset>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Quote a CSV cell if needed.
+fn cell(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Double(v) => v.to_string(),
+        Value::Long(v) => v.to_string(),
+        Value::UnsignedLong(v) => v.to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::String(v) => cell(v).into_owned(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AnnotatedCsvParser;
+
+    fn corpus() -> Corpus {
+        Corpus::new("cpu")
+            .tag("host", 3)
+            .tag("region", 2)
+            .field("usage", FieldType::Double)
+            .field("state", FieldType::String)
+            .rows(4)
+            .seed(7)
+    }
+
+    async fn parse(csv: &[u8]) -> Vec<crate::FluxRecord> {
+        let mut parser = AnnotatedCsvParser::new(csv);
+        let mut records = Vec::new();
+        while let Some(record) = parser.next().await.unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    #[test]
+    fn test_line_protocol() {
+        let corpus = corpus();
+        assert_eq!(corpus.series_count(), 6);
+        let lp = corpus.line_protocol();
+        assert_eq!(lp.lines().count(), 24);
+        let first = lp.lines().next().unwrap();
+        assert!(
+            first.starts_with("cpu,host=host0,region=region0 "),
+            "{first}"
+        );
+        assert!(first.ends_with(" 1700000000000000000"), "{first}");
+        assert_eq!(lp, corpus.line_protocol());
+        assert_ne!(lp, corpus.seed(8).line_protocol());
+    }
+
+    #[test]
+    fn test_series_limit() {
+        let corpus = corpus().series(4);
+        assert_eq!(corpus.series_count(), 4);
+        assert_eq!(corpus.points().count(), 16);
+        assert_eq!(Corpus::new("m").series(10).series_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_annotated_csv_matches_points() {
+        let corpus = corpus();
+        let records = parse(&corpus.annotated_csv()).await;
+        // One table per series and field.
+        assert_eq!(records.len(), 6 * 2 * 4);
+        assert_eq!(records.last().unwrap().table, 11);
+
+        let first = &records[0];
+        assert_eq!(first.get_string("_field").as_deref(), Some("usage"));
+        assert_eq!(first.get_string("host").as_deref(), Some("host0"));
+        let point = corpus.points().next().unwrap();
+        let usage = point.fields().find(|(k, _)| *k == "usage").unwrap().1;
+        assert_eq!(first.get("_value"), Some(usage));
+    }
+
+    #[tokio::test]
+    async fn test_annotated_csv_pivoted() {
+        let records = parse(&corpus().pivot(true).annotated_csv()).await;
+        assert_eq!(records.len(), 6 * 4);
+        let record = &records[5];
+        assert_eq!(record.table, 1);
+        assert!(record.get_double("usage").is_some());
+        assert!(record.get_string("state").unwrap().starts_with("value"));
+        assert_eq!(record.get_string("region").as_deref(), Some("region0"));
+    }
+}
@@ -3,12 +3,44 @@
 use thiserror::Error;
 
 /// Error type for influxdb-stream operations.
+///
+/// New variants may be added in minor releases, so matches need a wildcard
+/// arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// HTTP request failed.
+    #[cfg(feature = "reqwest")]
     #[error("HTTP request failed: {0}")]
     Http(#[from] reqwest::Error),
 
+    /// Server responded with an error status.
+    ///
+    /// Returned for responses from transports other than `ReqwestTransport`,
+    /// which reports error statuses other than `429` and `503` as
+    /// `Error::Http`.
+    #[error("HTTP status {status}: {message}")]
+    Status {
+        /// HTTP status code.
+        status: u16,
+        /// Error message from the response body.
+        message: String,
+    },
+
+    /// Server rejected the request with `429 Too Many Requests`, or with
+    /// `503 Service Unavailable` and a `Retry-After` header.
+    ///
+    /// Reported by every transport, including `ReqwestTransport`.
+    #[error("Rate limited with HTTP status {status}: {message}")]
+    RateLimited {
+        /// HTTP status code.
+        status: u16,
+        /// Wait requested by the `Retry-After` header, if any.
+        retry_after: Option<std::time::Duration>,
+        /// Error message from the response body.
+        message: String,
+    },
+
     /// Failed to serialize query to JSON.
     #[error("Failed to serialize query: {0}")]
     Serialization(#[from] serde_json::Error),
@@ -50,10 +82,233 @@ pub enum Error {
         reference: Option<String>,
     },
 
+    /// A table was abandoned after one of its rows failed to parse.
+    ///
+    /// Returned with
+    /// [`AnnotatedCsvParser::skip_bad_tables`](crate::parser::AnnotatedCsvParser::skip_bad_tables);
+    /// the stream continues with the next table.
+    #[error("Skipped table {table}: {source}")]
+    TableSkipped {
+        /// Value of the `table` column of the abandoned table, or its
+        /// position in the response if the failure came before its rows.
+        table: i32,
+        /// The error that ended the table.
+        source: Box<Error>,
+    },
+
+    /// A string cannot be used as a Flux identifier.
+    #[error("Invalid Flux identifier: {0:?}")]
+    InvalidIdentifier(String),
+
+    /// Invalid client configuration, such as a malformed URL.
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    /// A result collected into memory exceeded its configured limit.
+    ///
+    /// See [`CollectLimit`](crate::adapters::CollectLimit).
+    #[error("Result limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    /// A record did not match the expected schema.
+    ///
+    /// See [`ExpectedSchema`](crate::adapters::ExpectedSchema).
+    #[error("Schema mismatch: {0}")]
+    SchemaMismatch(String),
+
+    /// No record arrived within the idle timeout of a query.
+    ///
+    /// See [`QueryOptions::idle_timeout`](crate::client::QueryOptions::idle_timeout).
+    #[error("Query stalled: no record received for {0:?}")]
+    Stalled(std::time::Duration),
+
+    /// The response ended before the blank line InfluxDB writes after its
+    /// last table, or in the middle of a table's annotations.
+    ///
+    /// The connection was closed before the server finished sending the
+    /// result, for example by a load balancer failing over. The records read
+    /// before the error are valid but the result is incomplete.
+    #[error("Response truncated after {bytes} bytes and {records} records")]
+    Truncated {
+        /// Response bytes read.
+        bytes: u64,
+        /// Records parsed before the truncation.
+        records: u64,
+    },
+
+    /// A query did not complete within its timeout.
+    ///
+    /// See [`QueryOptions::timeout`](crate::client::QueryOptions::timeout).
+    #[error("Query timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    /// Failed to encode records into an output format.
+    #[error("Encoding error: {0}")]
+    Encode(String),
+
     /// I/O error during streaming.
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    /// An error delivered to several consumers of the same stream.
+    ///
+    /// Produced by fan-out adapters such as
+    /// [`RecordStreamExt::tee`](crate::adapters::RecordStreamExt::tee).
+    #[error("{0}")]
+    Shared(std::sync::Arc<Error>),
+}
+
+impl Error {
+    /// Returns true if the error is transient and re-issuing the query may succeed.
+    ///
+    /// Connection failures, timeouts, stalls, interrupted response bodies and
+    /// `429`/`5xx` responses are retryable. Parse errors and errors reported by InfluxDB for
+    /// the query itself are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Http(e) => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_body()
+                    || e.is_request()
+                    || e.status().is_some_and(|s| {
+                        s.is_server_error() || s == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    })
+            }
+            Error::Status { status, .. } => *status == 429 || (500..600).contains(status),
+            Error::RateLimited { .. } => true,
+            Error::Io(_) | Error::Stalled(_) | Error::Timeout(_) | Error::Truncated { .. } => true,
+            Error::Shared(e) => e.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl Error {
+    /// Get the HTTP status of a failed response, if the error is one.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Http(e) => e.status().map(|s| s.as_u16()),
+            Error::Status { status, .. } | Error::RateLimited { status, .. } => Some(*status),
+            Error::Shared(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Get the wait requested by the server's `Retry-After` header, if any.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } => *retry_after,
+            Error::Shared(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Get a short, stable name for the kind of error (e.g. `"http"`, `"csv"`).
+    ///
+    /// Useful as a label in logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Http(_) => "http",
+            Error::Status { .. } => "status",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::Serialization(_) => "serialization",
+            Error::Csv(_) => "csv",
+            Error::Parse { .. } => "parse",
+            Error::UnknownDataType(_) => "unknown_data_type",
+            Error::MissingAnnotation(_) => "missing_annotation",
+            Error::ColumnMismatch { .. } => "column_mismatch",
+            Error::QueryError { .. } => "query",
+            Error::TableSkipped { .. } => "table_skipped",
+            Error::InvalidIdentifier(_) => "invalid_identifier",
+            Error::Config(_) => "config",
+            Error::LimitExceeded(_) => "limit_exceeded",
+            Error::SchemaMismatch(_) => "schema_mismatch",
+            Error::Stalled(_) => "stalled",
+            Error::Truncated { .. } => "truncated",
+            Error::Timeout(_) => "timeout",
+            Error::Encode(_) => "encode",
+            Error::Io(_) => "io",
+            Error::Shared(e) => e.kind(),
+        }
+    }
+}
+
+/// Convert a CSV reader or writer error, preserving I/O failures.
+///
+/// Connection drops and write failures surface as I/O errors inside the CSV
+/// reader and writer; keeping them as `Error::Io` lets callers tell transport
+/// failures apart from malformed CSV.
+pub(crate) fn csv_error(context: &str, e: csv_async::Error) -> Error {
+    let message = format!("{}: {}", context, e);
+    match e.into_kind() {
+        csv_async::ErrorKind::Io(io) => Error::Io(io),
+        _ => Error::Csv(message),
+    }
 }
 
 /// Result type alias for influxdb-stream operations.
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(Error::Csv("bad row".to_string()).kind(), "csv");
+        let shared = Error::Shared(std::sync::Arc::new(Error::Encode("x".to_string())));
+        assert_eq!(shared.kind(), "encode");
+    }
+
+    #[test]
+    fn test_status() {
+        let err = Error::Status {
+            status: 503,
+            message: "unavailable".to_string(),
+        };
+        assert_eq!(err.status(), Some(503));
+        assert_eq!(Error::Csv("bad row".to_string()).status(), None);
+    }
+
+    #[test]
+    fn test_is_retryable_io() {
+        let err = Error::Io(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset",
+        ));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_is_retryable_truncated() {
+        let err = Error::Truncated {
+            bytes: 1024,
+            records: 10,
+        };
+        assert!(err.is_retryable());
+        assert_eq!(err.kind(), "truncated");
+    }
+
+    #[test]
+    fn test_is_retryable_non_transient() {
+        assert!(!Error::Csv("bad row".to_string()).is_retryable());
+        assert!(
+            !Error::QueryError {
+                message: "bucket not found".to_string(),
+                reference: None,
+            }
+            .is_retryable()
+        );
+        assert!(
+            !Error::ColumnMismatch {
+                expected: 2,
+                actual: 3
+            }
+            .is_retryable()
+        );
+    }
+}
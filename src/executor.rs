@@ -0,0 +1,402 @@
+//! Bounded-concurrency query execution.
+//!
+//! A [`QueryExecutor`] runs many queries against one client while keeping at
+//! most N in flight, so that a burst of export jobs does not overload a
+//! shared InfluxDB instance. Each submitted query returns a [`QueryHandle`]
+//! right away; the query starts once a slot is free and the handle is polled.
+//!
+//! Queries waiting for a slot are served by [`Priority`]: interactive ones
+//! first, so background exports submitted with [`Priority::Batch`] cannot
+//! hold up dashboard queries sharing the same executor.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::executor::QueryExecutor;
+//!
+//! let executor = QueryExecutor::new(client, 4);
+//! let handles: Vec<_> = buckets
+//!     .iter()
+//!     .map(|b| executor.submit(format!(r#"from(bucket: "{}") |> range(start: -1d)"#, b)))
+//!     .collect();
+//!
+//! // At most four of these streams download at the same time.
+//! let counts = futures::future::join_all(handles.into_iter().map(|h| h.count())).await;
+//! ```
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use tokio::sync::oneshot;
+
+use crate::client::{Client, QueryClient, RecordStream};
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Runs queries with a limit on how many stream at once.
+///
+/// Cloning the executor is cheap; clones share the same limit.
+#[derive(Clone)]
+pub struct QueryExecutor<C = Client> {
+    client: C,
+    slots: Arc<Slots>,
+}
+
+/// Scheduling class of a query submitted to a [`QueryExecutor`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// Latency-sensitive queries, such as dashboards. Served first.
+    #[default]
+    Interactive,
+    /// Background work, such as exports. Only started while no interactive
+    /// query is waiting.
+    Batch,
+}
+
+impl Priority {
+    fn index(self) -> usize {
+        match self {
+            Priority::Interactive => 0,
+            Priority::Batch => 1,
+        }
+    }
+}
+
+impl<C> QueryExecutor<C>
+where
+    C: QueryClient + Clone + 'static,
+{
+    /// Create an executor running at most `max_concurrent` queries at once.
+    ///
+    /// A limit of zero is treated as one.
+    pub fn new(client: C, max_concurrent: usize) -> Self {
+        Self {
+            client,
+            slots: Arc::new(Slots::new(max_concurrent.max(1))),
+        }
+    }
+
+    /// Queue `query` as [`Priority::Interactive`] and return a handle to its
+    /// result stream.
+    ///
+    /// See [`submit_with_priority`](Self::submit_with_priority).
+    pub fn submit(&self, query: impl Into<String>) -> QueryHandle {
+        self.submit_with_priority(query, Priority::Interactive)
+    }
+
+    /// Queue `query` with `priority` and return a handle to its result stream.
+    ///
+    /// The query is sent once the handle is first polled and a slot is free.
+    /// Waiting interactive queries get slots before waiting batch queries;
+    /// within a priority, slots are granted in the order handles start
+    /// waiting for them. The slot is held until the stream ends, fails or
+    /// the handle is dropped.
+    pub fn submit_with_priority(
+        &self,
+        query: impl Into<String>,
+        priority: Priority,
+    ) -> QueryHandle {
+        let client = self.client.clone();
+        let slots = self.slots.clone();
+        let query = query.into();
+
+        let inner = stream! {
+            let Some(_slot) = slots.acquire(priority).await else {
+                return;
+            };
+            let mut records = match client.query_stream(query).await {
+                Ok(records) => records,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            while let Some(item) = records.next().await {
+                yield item;
+            }
+        };
+
+        QueryHandle {
+            inner: Box::pin(inner),
+        }
+    }
+
+    /// Get the maximum number of concurrent queries.
+    pub fn limit(&self) -> usize {
+        self.slots.limit
+    }
+
+    /// Get the number of queries currently running.
+    pub fn running(&self) -> usize {
+        self.slots.state().running
+    }
+
+    /// Get the number of queries with `priority` waiting for a slot.
+    pub fn queued(&self, priority: Priority) -> usize {
+        self.slots.state().queues[priority.index()]
+            .iter()
+            .filter(|waiter| !waiter.is_closed())
+            .count()
+    }
+
+    /// Get the underlying client.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+}
+
+impl<C> std::fmt::Debug for QueryExecutor<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryExecutor")
+            .field("limit", &self.slots.limit)
+            .field("running", &self.slots.state().running)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Concurrency slots, handed out by priority.
+struct Slots {
+    limit: usize,
+    state: Mutex<SlotState>,
+}
+
+struct SlotState {
+    running: usize,
+    /// Waiters per priority, in [`Priority::index`] order.
+    queues: [VecDeque<oneshot::Sender<Slot>>; 2],
+}
+
+impl Slots {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(SlotState {
+                running: 0,
+                queues: Default::default(),
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, SlotState> {
+        self.state.lock().expect("executor lock poisoned")
+    }
+
+    /// Wait for a free slot. Returns `None` only if the executor is gone.
+    async fn acquire(self: &Arc<Self>, priority: Priority) -> Option<Slot> {
+        let rx = {
+            let mut state = self.state();
+            // Slots are handed to waiters directly when released, so a free
+            // slot means nobody (alive) is waiting.
+            if state.running < self.limit {
+                state.running += 1;
+                return Some(Slot {
+                    slots: Some(self.clone()),
+                });
+            }
+            let (tx, rx) = oneshot::channel();
+            state.queues[priority.index()].push_back(tx);
+            rx
+        };
+        rx.await.ok()
+    }
+
+    /// Pass a released slot to the next waiter, or free it.
+    fn release(self: &Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut state = self.state();
+                match state.queues.iter_mut().find_map(VecDeque::pop_front) {
+                    Some(waiter) => waiter,
+                    None => {
+                        state.running -= 1;
+                        return;
+                    }
+                }
+            };
+            let slot = Slot {
+                slots: Some(self.clone()),
+            };
+            match waiter.send(slot) {
+                Ok(()) => return,
+                // The waiter gave up; disarm the slot and try the next one.
+                Err(mut slot) => drop(slot.slots.take()),
+            }
+        }
+    }
+}
+
+/// A running query's claim on a slot, released when dropped.
+struct Slot {
+    slots: Option<Arc<Slots>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(slots) = self.slots.take() {
+            slots.release();
+        }
+    }
+}
+
+/// Result stream of a query submitted to a [`QueryExecutor`].
+pub struct QueryHandle {
+    inner: RecordStream,
+}
+
+impl Stream for QueryHandle {
+    type Item = Result<FluxRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl std::fmt::Debug for QueryHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryHandle").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use futures::stream;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Serve three records per query, slowly, tracking peak concurrency.
+    #[derive(Clone, Default)]
+    struct SlowClient {
+        active: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    struct Active(Arc<AtomicUsize>);
+
+    impl Drop for Active {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    impl QueryClient for SlowClient {
+        fn query_stream(
+            &self,
+            query: impl Into<String> + Send,
+        ) -> impl Future<Output = Result<RecordStream>> + Send {
+            let query = query.into();
+            let this = self.clone();
+            async move {
+                if query == "bad" {
+                    return Err(Error::Csv("bad query".to_string()));
+                }
+                let now = this.active.fetch_add(1, Ordering::SeqCst) + 1;
+                this.peak.fetch_max(now, Ordering::SeqCst);
+                let active = Active(this.active.clone());
+
+                let records = stream::iter(0..3).then(move |i| {
+                    let _keep = &active;
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Ok(FluxRecord::new(i))
+                    }
+                });
+                Ok(Box::pin(records) as RecordStream)
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limits_concurrency() {
+        let client = SlowClient::default();
+        let executor = QueryExecutor::new(client.clone(), 2);
+
+        let handles: Vec<_> = (0..5).map(|i| executor.submit(format!("q{}", i))).collect();
+        let counts = futures::future::join_all(handles.into_iter().map(|h| h.count())).await;
+
+        assert_eq!(counts, vec![3; 5]);
+        assert_eq!(client.peak.load(Ordering::SeqCst), 2);
+        assert_eq!(executor.running(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropped_handle_frees_slot() {
+        let executor = QueryExecutor::new(SlowClient::default(), 1);
+
+        let mut first = executor.submit("q1");
+        assert!(first.next().await.is_some());
+        assert_eq!(executor.running(), 1);
+        drop(first);
+        assert_eq!(executor.running(), 0);
+
+        let records: Vec<_> = executor.submit("q2").collect().await;
+        assert_eq!(records.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_before_batch() {
+        let executor = QueryExecutor::new(SlowClient::default(), 1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut running = executor.submit("q0");
+        assert!(running.next().await.is_some());
+
+        let run = |handle: QueryHandle, name: &'static str| {
+            let order = order.clone();
+            tokio::spawn(async move {
+                let mut handle = handle;
+                if handle.next().await.is_some() {
+                    order.lock().unwrap().push(name);
+                }
+                handle.count().await
+            })
+        };
+        let batch = run(executor.submit_with_priority("b", Priority::Batch), "batch");
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        let interactive = run(executor.submit("i"), "interactive");
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(executor.queued(Priority::Batch), 1);
+        assert_eq!(executor.queued(Priority::Interactive), 1);
+
+        drop(running);
+        batch.await.unwrap();
+        interactive.await.unwrap();
+        assert_eq!(*order.lock().unwrap(), vec!["interactive", "batch"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_abandoned_waiter_skipped() {
+        let executor = QueryExecutor::new(SlowClient::default(), 1);
+
+        let mut running = executor.submit("q0");
+        assert!(running.next().await.is_some());
+        let mut waiting = executor.submit("q1");
+        assert!(
+            tokio::time::timeout(Duration::from_millis(1), waiting.next())
+                .await
+                .is_err()
+        );
+        drop(waiting);
+        drop(running);
+
+        assert_eq!(executor.running(), 0);
+        assert_eq!(executor.submit("q2").count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_error_is_yielded() {
+        let executor = QueryExecutor::new(SlowClient::default(), 0);
+        assert_eq!(executor.limit(), 1);
+
+        let items: Vec<_> = executor.submit("bad").collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(matches!(items[0], Err(Error::Csv(_))));
+        assert_eq!(executor.running(), 0);
+    }
+}
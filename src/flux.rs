@@ -0,0 +1,422 @@
+//! Helpers for embedding values in Flux queries safely.
+//!
+//! Flux queries are usually built with `format!`, which makes it easy to
+//! splice user input into the query unescaped. The functions here escape
+//! string and regex literals and validate identifiers, and the [`flux!`](crate::flux!)
+//! macro formats a query with every argument, including variables captured
+//! inline, converted through [`ToFlux`].
+//!
+//! # Example
+//!
+//! ```
+//! use influxdb_stream::flux;
+//!
+//! let host = r#"web"01"#;
+//! let query = flux!(
+//!     "from(bucket: {}) |> range(start: {}) |> filter(fn: (r) => r.host == {})",
+//!     "sensors",
+//!     chrono::Duration::hours(-1),
+//!     host,
+//! );
+//! assert_eq!(
+//!     query,
+//!     r#"from(bucket: "sensors") |> range(start: -1h) |> filter(fn: (r) => r.host == "web\"01")"#
+//! );
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, SecondsFormat, TimeZone};
+
+use crate::error::{Error, Result};
+
+/// Format a Flux query, converting every argument with [`ToFlux`].
+///
+/// Takes a format string like [`format!`], with positional `{}`
+/// placeholders or variables captured inline (`{bucket}`). Strings become
+/// quoted, escaped string literals; numbers, booleans, times and durations
+/// become the matching Flux literals. Wrap a value in [`Regex`] for a regex
+/// literal or in [`Raw`] to insert it as-is.
+///
+/// ```
+/// use influxdb_stream::flux;
+/// use influxdb_stream::flux::Regex;
+///
+/// let bucket = "sensors";
+/// let host = Regex("10.0.0.1");
+/// assert_eq!(
+///     flux!("from(bucket: {bucket}) |> filter(fn: (r) => r.host =~ {host})"),
+///     r#"from(bucket: "sensors") |> filter(fn: (r) => r.host =~ /10\.0\.0\.1/)"#
+/// );
+/// ```
+#[macro_export]
+macro_rules! flux {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {
+        $crate::__flux_inline!($crate, $fmt $(, $crate::flux::Literal(&$arg))*)
+    };
+}
+
+/// Conversion of a Rust value to Flux source code.
+pub trait ToFlux {
+    /// Write the Flux literal for this value.
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+/// Displays a value as Flux source code. Used by [`flux!`](crate::flux!).
+pub struct Literal<'a, T: ?Sized>(pub &'a T);
+
+impl<T: ToFlux + ?Sized> fmt::Display for Literal<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_flux(f)
+    }
+}
+
+/// A regular expression literal matching the wrapped text literally.
+///
+/// `Regex("10.0.0.1")` becomes `/10\.0\.0\.1/`.
+#[derive(Clone, Copy, Debug)]
+pub struct Regex<'a>(pub &'a str);
+
+/// Flux source inserted without escaping.
+///
+/// Only use this with trusted input.
+#[derive(Clone, Copy, Debug)]
+pub struct Raw<'a>(pub &'a str);
+
+/// Escape `s` for use inside a Flux string literal (between double quotes).
+pub fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // `${` starts string interpolation
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Set the `location` option of `query` to the IANA time zone `zone`.
+pub(crate) fn with_location(query: &str, zone: &str) -> String {
+    with_option(
+        query,
+        &format!(
+            "location = {{zone: \"{}\", offset: 0h}}",
+            escape_string(zone)
+        ),
+    )
+}
+
+/// Declare `option <assignment>` in `query`.
+///
+/// The option is inserted after the leading `import` statements, where Flux
+/// requires options to be declared.
+pub(crate) fn with_option(query: &str, assignment: &str) -> String {
+    let mut split = 0;
+    for line in query.split_inclusive('\n') {
+        let line_start = line.trim();
+        if line_start.is_empty()
+            || line_start.starts_with("//")
+            || line_start.starts_with("import ")
+        {
+            split += line.len();
+        } else {
+            break;
+        }
+    }
+    let (imports, body) = query.split_at(split);
+    let newline = if imports.is_empty() || imports.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{}{}option {}\n{}", imports, newline, assignment, body)
+}
+
+/// Escape `s` so a Flux regex literal (between slashes) matches it literally.
+pub fn escape_regex(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '/'
+                | '.'
+                | '+'
+                | '*'
+                | '?'
+                | '('
+                | ')'
+                | '|'
+                | '['
+                | ']'
+                | '{'
+                | '}'
+                | '^'
+                | '$'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+const KEYWORDS: &[&str] = &[
+    "and", "builtin", "else", "empty", "exists", "if", "import", "in", "not", "option", "or",
+    "package", "return", "test", "then",
+];
+
+/// Check that `s` can be used as a Flux identifier, e.g. in `r.<identifier>`.
+///
+/// Identifiers start with a letter or underscore, continue with letters,
+/// digits or underscores, and must not be a keyword. Column names that fail
+/// this check can still be accessed as `r["name"]` with an escaped string.
+pub fn validate_identifier(s: &str) -> Result<&str> {
+    let mut chars = s.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+    let valid =
+        valid_start && chars.all(|c| c.is_alphanumeric() || c == '_') && !KEYWORDS.contains(&s);
+
+    if valid {
+        Ok(s)
+    } else {
+        Err(Error::InvalidIdentifier(s.to_string()))
+    }
+}
+
+impl ToFlux for str {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", escape_string(self))
+    }
+}
+
+impl ToFlux for String {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt_flux(f)
+    }
+}
+
+impl<T: ToFlux + ?Sized> ToFlux for &T {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt_flux(f)
+    }
+}
+
+impl ToFlux for bool {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+macro_rules! int_to_flux {
+    ($($t:ty),*) => {$(
+        impl ToFlux for $t {
+            fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self)
+            }
+        }
+    )*};
+}
+
+int_to_flux!(i8, i16, i32, i64, u8, u16, u32);
+
+impl ToFlux for u64 {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uint(v: {})", self)
+    }
+}
+
+impl ToFlux for f64 {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_nan() {
+            f.write_str("float(v: \"NaN\")")
+        } else if self.is_infinite() {
+            let sign = if *self > 0.0 { '+' } else { '-' };
+            write!(f, "float(v: \"{}Inf\")", sign)
+        } else {
+            // Flux float literals have no exponent, and need a decimal point
+            // to not be read as integers; Display never uses an exponent.
+            let digits = self.to_string();
+            if digits.contains('.') {
+                f.write_str(&digits)
+            } else {
+                write!(f, "{}.0", digits)
+            }
+        }
+    }
+}
+
+impl<Tz: TimeZone> ToFlux for DateTime<Tz> {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_utc().to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+}
+
+impl ToFlux for chrono::Duration {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(mut nanos) = self.num_nanoseconds().map(i128::from) else {
+            // Beyond ±292 years; whole seconds are precise enough
+            return write!(f, "{}s", self.num_seconds());
+        };
+        if nanos == 0 {
+            return f.write_str("0s");
+        }
+        if nanos < 0 {
+            f.write_str("-")?;
+            nanos = -nanos;
+        }
+        const UNITS: [(&str, i128); 6] = [
+            ("h", 3_600_000_000_000),
+            ("m", 60_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+            ("ns", 1),
+        ];
+        for (unit, size) in UNITS {
+            if nanos >= size {
+                write!(f, "{}{}", nanos / size, unit)?;
+                nanos %= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ToFlux for Regex<'_> {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/{}/", escape_regex(self.0))
+    }
+}
+
+impl ToFlux for Raw<'_> {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl<T: ToFlux> ToFlux for [T] {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, item) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            item.fmt_flux(f)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl<T: ToFlux> ToFlux for Vec<T> {
+    fn fmt_flux(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt_flux(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render<T: ToFlux + ?Sized>(value: &T) -> String {
+        Literal(value).to_string()
+    }
+
+    #[test]
+    fn test_flux_inline_captures() {
+        let bucket = "b\"1";
+        let start = chrono::Duration::minutes(-5);
+        let limit = 10i64;
+        assert_eq!(
+            crate::flux!(
+                "from(bucket: {bucket}) |> range(start: {start}) |> limit(n: {}) // {{x}}",
+                limit
+            ),
+            r#"from(bucket: "b\"1") |> range(start: -5m) |> limit(n: 10) // {x}"#
+        );
+        assert_eq!(crate::flux!("{bucket} {bucket}"), r#""b\"1" "b\"1""#);
+        assert_eq!(crate::flux!("\u{41}={bucket}"), r#"A="b\"1""#);
+        assert_eq!(crate::flux!(r"{bucket}\n"), r#""b\"1"\n"#);
+        assert_eq!(crate::flux!(r#"{bucket}"#), r#""b\"1""#);
+    }
+
+    #[test]
+    fn test_with_location() {
+        assert_eq!(
+            with_location("from(bucket: \"b\")", "Europe/Paris"),
+            "option location = {zone: \"Europe/Paris\", offset: 0h}\nfrom(bucket: \"b\")"
+        );
+        assert_eq!(
+            with_location("import \"strings\"\n\nfrom(bucket: \"b\")", "UTC"),
+            "import \"strings\"\n\noption location = {zone: \"UTC\", offset: 0h}\nfrom(bucket: \"b\")"
+        );
+    }
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_string("line\nnext"), r"line\nnext");
+        assert_eq!(escape_string("${x} costs $5"), r"\${x} costs $5");
+    }
+
+    #[test]
+    fn test_escape_regex() {
+        assert_eq!(escape_regex("10.0.0.1"), r"10\.0\.0\.1");
+        assert_eq!(escape_regex("a/b(c)"), r"a\/b\(c\)");
+        assert_eq!(render(&Regex("x+")), r"/x\+/");
+    }
+
+    #[test]
+    fn test_validate_identifier() {
+        assert!(validate_identifier("host").is_ok());
+        assert!(validate_identifier("_value2").is_ok());
+        assert!(validate_identifier("2fast").is_err());
+        assert!(validate_identifier("cpu-usage").is_err());
+        assert!(validate_identifier("").is_err());
+        assert!(matches!(
+            validate_identifier("import"),
+            Err(Error::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(render("x"), "\"x\"");
+        assert_eq!(render(&42i64), "42");
+        assert_eq!(render(&42u64), "uint(v: 42)");
+        assert_eq!(render(&1.0f64), "1.0");
+        assert_eq!(render(&-2.5f64), "-2.5");
+        assert_eq!(render(&1e20f64), "100000000000000000000.0");
+        assert_eq!(render(&1.5e-7f64), "0.00000015");
+        assert!(!render(&f64::MAX).contains('e'));
+        assert!(!render(&f64::MIN_POSITIVE).contains('e'));
+        assert_eq!(render(&f64::NAN), "float(v: \"NaN\")");
+        assert_eq!(render(&true), "true");
+        assert_eq!(render(&vec!["a", "b"]), r#"["a", "b"]"#);
+        assert_eq!(render(&Raw("now()")), "now()");
+
+        let t = DateTime::parse_from_rfc3339("2023-11-14T13:00:00.5+01:00").unwrap();
+        assert_eq!(render(&t), "2023-11-14T12:00:00.500Z");
+    }
+
+    #[test]
+    fn test_duration_literals() {
+        assert_eq!(render(&chrono::Duration::minutes(90)), "1h30m");
+        assert_eq!(render(&chrono::Duration::seconds(-5)), "-5s");
+        assert_eq!(render(&chrono::Duration::milliseconds(1500)), "1s500ms");
+        assert_eq!(render(&chrono::Duration::zero()), "0s");
+    }
+
+    #[test]
+    fn test_flux_macro() {
+        let bucket = String::from("my\"bucket");
+        let query = crate::flux!("from(bucket: {}) |> limit(n: {})", bucket, 10);
+        assert_eq!(query, r#"from(bucket: "my\"bucket") |> limit(n: 10)"#);
+    }
+}
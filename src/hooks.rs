@@ -0,0 +1,273 @@
+//! Client-level callbacks.
+//!
+//! A [`SlowQueryHook`] reports queries that take longer than expected, so
+//! they can be logged in one place instead of timing every call site.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//! use influxdb_stream::Client;
+//! use influxdb_stream::hooks::SlowQueryHook;
+//!
+//! let hook = SlowQueryHook::new(|slow| {
+//!     eprintln!("slow query ({:?}, {} records): {}", slow.total, slow.records, slow.query);
+//! })
+//! .total(Duration::from_secs(10))
+//! .first_record(Duration::from_secs(2));
+//!
+//! let client = Client::builder("http://localhost:8086", "my-org", "my-token")
+//!     .slow_query_hook(hook)
+//!     .build()?;
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Timings of a query reported to a [`SlowQueryHook`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SlowQuery {
+    /// The Flux query, with string literals redacted by [`sanitize_query`].
+    pub query: String,
+    /// Time from sending the request until the stream ended or was dropped.
+    pub total: Duration,
+    /// Time from sending the request until the first record was parsed, or
+    /// `None` if the query produced no records.
+    pub time_to_first_record: Option<Duration>,
+    /// Number of records parsed.
+    pub records: u64,
+}
+
+/// Callback fired when a query exceeds configured thresholds.
+///
+/// The callback runs when the query's stream ends or is dropped, on the task
+/// that dropped it, so it should return quickly. With no threshold set every
+/// query is reported.
+#[derive(Clone)]
+pub struct SlowQueryHook {
+    total: Option<Duration>,
+    first_record: Option<Duration>,
+    callback: Arc<dyn Fn(&SlowQuery) + Send + Sync>,
+}
+
+impl SlowQueryHook {
+    /// Create a hook calling `callback` for slow queries.
+    pub fn new(callback: impl Fn(&SlowQuery) + Send + Sync + 'static) -> Self {
+        Self {
+            total: None,
+            first_record: None,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Report queries whose total duration exceeds `threshold`.
+    pub fn total(mut self, threshold: Duration) -> Self {
+        self.total = Some(threshold);
+        self
+    }
+
+    /// Report queries whose first record takes longer than `threshold`.
+    ///
+    /// Queries without records count as slow once their total duration
+    /// exceeds the threshold.
+    pub fn first_record(mut self, threshold: Duration) -> Self {
+        self.first_record = Some(threshold);
+        self
+    }
+
+    /// Returns true if a query with these timings should be reported.
+    fn is_slow(&self, total: Duration, first_record: Option<Duration>) -> bool {
+        if self.total.is_none() && self.first_record.is_none() {
+            return true;
+        }
+        self.total.is_some_and(|t| total > t)
+            || self
+                .first_record
+                .is_some_and(|t| first_record.unwrap_or(total) > t)
+    }
+}
+
+impl std::fmt::Debug for SlowQueryHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlowQueryHook")
+            .field("total", &self.total)
+            .field("first_record", &self.first_record)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Timings of one query, reported to the hook when dropped.
+pub(crate) struct SlowQueryTracker {
+    hook: SlowQueryHook,
+    query: String,
+    started: Instant,
+    first_record: Option<Duration>,
+    records: u64,
+}
+
+impl SlowQueryTracker {
+    /// Start timing `query`.
+    pub(crate) fn start(hook: SlowQueryHook, query: &str) -> Self {
+        Self {
+            hook,
+            query: query.to_string(),
+            started: Instant::now(),
+            first_record: None,
+            records: 0,
+        }
+    }
+
+    /// Count a parsed record.
+    pub(crate) fn record(&mut self) {
+        if self.records == 0 {
+            self.first_record = Some(self.started.elapsed());
+        }
+        self.records += 1;
+    }
+}
+
+impl Drop for SlowQueryTracker {
+    fn drop(&mut self) {
+        let total = self.started.elapsed();
+        if self.hook.is_slow(total, self.first_record) {
+            (self.hook.callback)(&SlowQuery {
+                query: sanitize_query(&self.query),
+                total,
+                time_to_first_record: self.first_record,
+                records: self.records,
+            });
+        }
+    }
+}
+
+/// Prepare a Flux query for logging.
+///
+/// The contents of string and regular expression literals are replaced with
+/// `?`, since they may hold tag values or secrets, comments are removed and
+/// runs of whitespace are collapsed to one space.
+pub fn sanitize_query(query: &str) -> String {
+    let mut out = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        if c == '/' && chars.peek() == Some(&'/') {
+            chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            space = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        // A `/` after an operand divides; anywhere else it starts a regex.
+        let operand = out
+            .chars()
+            .last()
+            .is_some_and(|p| p.is_alphanumeric() || matches!(p, '_' | ')' | ']' | '"'));
+        if space && !out.is_empty() {
+            out.push(' ');
+        }
+        space = false;
+
+        match c {
+            '"' => {
+                // Skip to the closing quote, honouring escapes.
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                out.push_str("\"?\"");
+            }
+            '/' if !operand => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '/' => break,
+                        _ => {}
+                    }
+                }
+                out.push_str("/?/");
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn recording(
+        hook: impl FnOnce(SlowQueryHook) -> SlowQueryHook,
+    ) -> (SlowQueryHook, Arc<Mutex<Vec<SlowQuery>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let hook = hook(SlowQueryHook::new(move |slow| {
+            sink.lock().unwrap().push(slow.clone())
+        }));
+        (hook, seen)
+    }
+
+    #[test]
+    fn test_sanitize_query() {
+        let query = "from(bucket: \"prod\")\n    |> filter(fn: (r) => r.token == \"a\\\"b\")";
+        assert_eq!(
+            sanitize_query(query),
+            "from(bucket: \"?\") |> filter(fn: (r) => r.token == \"?\")"
+        );
+        assert_eq!(sanitize_query("  buckets()  "), "buckets()");
+        assert_eq!(
+            sanitize_query(
+                "filter(fn: (r) => r.host =~ /^db-\\/9$/) // token\n|> map(fn: (r) => r._value / 2.0)"
+            ),
+            "filter(fn: (r) => r.host =~ /?/) |> map(fn: (r) => r._value / 2.0)"
+        );
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let hook = SlowQueryHook::new(|_| {})
+            .total(Duration::from_secs(10))
+            .first_record(Duration::from_secs(1));
+        let secs = Duration::from_secs;
+
+        assert!(!hook.is_slow(secs(5), Some(secs(0))));
+        assert!(hook.is_slow(secs(11), Some(secs(0))));
+        assert!(hook.is_slow(secs(5), Some(secs(2))));
+        assert!(hook.is_slow(secs(5), None));
+        assert!(SlowQueryHook::new(|_| {}).is_slow(Duration::ZERO, None));
+    }
+
+    #[test]
+    fn test_tracker_reports_on_drop() {
+        let (hook, seen) = recording(|h| h.first_record(Duration::ZERO));
+        let mut tracker = SlowQueryTracker::start(hook, "from(bucket: \"b\")");
+        tracker.record();
+        tracker.record();
+        drop(tracker);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].query, "from(bucket: \"?\")");
+        assert_eq!(seen[0].records, 2);
+        assert!(seen[0].time_to_first_record.unwrap() <= seen[0].total);
+    }
+
+    #[test]
+    fn test_fast_query_not_reported() {
+        let (hook, seen) = recording(|h| h.total(Duration::from_secs(3600)));
+        drop(SlowQueryTracker::start(hook, "buckets()"));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! Metrics emitted through the `metrics` facade.
+//!
+//! Without the `metrics` feature every function here is a no-op, so call
+//! sites need no feature gates. See the crate documentation for the list of
+//! metric names.
+
+use std::time::Instant;
+
+use crate::error::Error;
+use crate::hooks::{SlowQueryHook, SlowQueryTracker};
+
+#[cfg(feature = "metrics")]
+mod names {
+    pub(crate) const QUERIES_STARTED: &str = "influxdb_stream_queries_started_total";
+    pub(crate) const RECORDS_PARSED: &str = "influxdb_stream_records_parsed_total";
+    pub(crate) const BYTES_DOWNLOADED: &str = "influxdb_stream_bytes_downloaded_total";
+    pub(crate) const QUERY_DURATION: &str = "influxdb_stream_query_duration_seconds";
+    pub(crate) const ERRORS: &str = "influxdb_stream_errors_total";
+}
+#[cfg(feature = "metrics")]
+use names::*;
+
+/// Tracks one query from the request until its stream ends or is dropped.
+///
+/// The duration is recorded, and reported to the slow-query hook if any, when
+/// the guard is dropped.
+pub(crate) struct QueryTimer {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    started: Instant,
+    slow: Option<SlowQueryTracker>,
+}
+
+impl QueryTimer {
+    /// Count a started query and start timing it.
+    pub(crate) fn start() -> Self {
+        #[cfg(feature = "metrics")]
+        metrics::counter!(QUERIES_STARTED).increment(1);
+        Self {
+            started: Instant::now(),
+            slow: None,
+        }
+    }
+
+    /// Like [`start`](Self::start), also reporting `query` to `hook` if slow.
+    pub(crate) fn start_with(hook: Option<&SlowQueryHook>, query: &str) -> Self {
+        let mut timer = Self::start();
+        timer.slow = hook.map(|hook| SlowQueryTracker::start(hook.clone(), query));
+        timer
+    }
+
+    /// Count a parsed record of this query.
+    pub(crate) fn record_parsed(&mut self) {
+        record_parsed();
+        if let Some(slow) = &mut self.slow {
+            slow.record();
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!(QUERY_DURATION).record(self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// Count a parsed record.
+#[inline]
+pub(crate) fn record_parsed() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(RECORDS_PARSED).increment(1);
+}
+
+/// Count bytes received from the server.
+#[inline]
+pub(crate) fn bytes_downloaded(_bytes: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(BYTES_DOWNLOADED).increment(_bytes as u64);
+}
+
+/// Count an error, labelled by its kind.
+#[inline]
+pub(crate) fn error(_error: &Error) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ERRORS, "kind" => _error.kind()).increment(1);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Recorder keeping the sum of every counter and the count of histogram samples.
+    #[derive(Default)]
+    struct TestRecorder {
+        values: Arc<Mutex<HashMap<String, u64>>>,
+    }
+
+    struct Slot {
+        key: String,
+        values: Arc<Mutex<HashMap<String, u64>>>,
+    }
+
+    impl CounterFn for Slot {
+        fn increment(&self, value: u64) {
+            *self
+                .values
+                .lock()
+                .expect("recorder lock poisoned")
+                .entry(self.key.clone())
+                .or_default() += value;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.values
+                .lock()
+                .expect("recorder lock poisoned")
+                .insert(self.key.clone(), value);
+        }
+    }
+
+    impl HistogramFn for Slot {
+        fn record(&self, _value: f64) {
+            self.increment(1);
+        }
+    }
+
+    impl TestRecorder {
+        fn slot(&self, key: &Key) -> Arc<Slot> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            let name = if labels.is_empty() {
+                key.name().to_string()
+            } else {
+                format!("{}{{{}}}", key.name(), labels.join(","))
+            };
+            Arc::new(Slot {
+                key: name,
+                values: self.values.clone(),
+            })
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.slot(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.slot(key))
+        }
+    }
+
+    #[test]
+    fn test_metrics_recorded() {
+        let recorder = TestRecorder::default();
+        let values = recorder.values.clone();
+
+        metrics::with_local_recorder(&recorder, || {
+            let timer = QueryTimer::start();
+            record_parsed();
+            record_parsed();
+            bytes_downloaded(128);
+            error(&Error::Csv("bad".to_string()));
+            drop(timer);
+        });
+
+        let values = values.lock().expect("recorder lock poisoned");
+        assert_eq!(values[QUERIES_STARTED], 1);
+        assert_eq!(values[RECORDS_PARSED], 2);
+        assert_eq!(values[BYTES_DOWNLOADED], 128);
+        assert_eq!(values[QUERY_DURATION], 1);
+        assert_eq!(values[&format!("{}{{kind=csv}}", ERRORS)], 1);
+    }
+}
@@ -60,18 +60,97 @@
 //!   long, unsignedLong, duration, base64Binary, dateTime:RFC3339)
 //! - **Error handling**: All errors are returned as Results, no panics
 //! - **Zero copy parsing**: Parses InfluxDB's annotated CSV format on the fly
+//!
+//! ## Cargo features
+//!
+//! - `reqwest` (default): HTTP transport backed by reqwest
+//! - `hyper`: slimmer HTTP transport built directly on hyper and rustls; use
+//!   with `default-features = false` to drop reqwest
+//! - `rustls` (default): TLS through rustls for the reqwest transport
+//! - `native-tls`: TLS through the platform library (OpenSSL on Linux) for the
+//!   reqwest transport; use with `default-features = false, features =
+//!   ["reqwest", "native-tls"]` to avoid rustls, or drop both TLS features for
+//!   plain HTTP only
+//! - `arrow`: Arrow IPC (Feather) output via [`sink`]
+//! - `parquet`: Parquet file output via [`sink`]
+//! - `prometheus`: Prometheus remote-write request bodies via [`sink`]
+//! - `serde-arrow`: Arrow record batches from typed streams via [`serde_arrow`](https://docs.rs/serde_arrow)
+//! - `object-store`: replay annotated CSV stored in S3, GCS or Azure via the
+//!   `archive` module
+//! - `chrono-tz`: set the Flux `location` of a query from a
+//!   [`chrono_tz::Tz`](https://docs.rs/chrono-tz)
+//! - `zstd`: request zstd-compressed query responses, as some proxies and
+//!   gateways provide, with gzip as a fallback, and decode them
+//! - `influx-config`: create clients from the configuration profiles of the
+//!   `influx` CLI
+//! - `time`: read and build time and duration values as
+//!   [`time`](https://docs.rs/time) types
+//! - `blocking`: synchronous client in the `blocking` module
+//! - `testing`: in-process mock server in the `testing` module
+//! - `devtools`: synthetic line protocol and annotated CSV for load tests in
+//!   the `devtools` module
+//! - `metrics`: query metrics through the [`metrics`](https://docs.rs/metrics) facade:
+//!   - `influxdb_stream_queries_started_total` (counter)
+//!   - `influxdb_stream_records_parsed_total` (counter)
+//!   - `influxdb_stream_bytes_downloaded_total` (counter)
+//!   - `influxdb_stream_query_duration_seconds` (histogram, from request to end of stream)
+//!   - `influxdb_stream_errors_total` (counter, labelled by `kind`)
 
+pub mod adapters;
+#[cfg(feature = "object-store")]
+pub mod archive;
+pub mod auth;
+pub mod backup;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cardinality;
+pub mod checkpoint;
 pub mod client;
+pub mod coalesce;
+#[cfg(feature = "influx-config")]
+mod config;
+pub mod de;
+#[cfg(feature = "devtools")]
+pub mod devtools;
 pub mod error;
+pub mod executor;
+pub mod flux;
+pub mod hooks;
+mod instrument;
+pub mod line_protocol;
 pub mod parser;
+pub mod pool;
+pub mod restore;
+pub mod resume;
+pub mod retry;
+pub mod scheduler;
+pub mod schema;
+pub mod server;
+pub mod shard;
+pub mod sink;
+mod sql;
+pub mod stacks;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+pub mod typed;
 pub mod types;
 pub mod value;
+pub mod view;
+pub mod write;
 
 // Re-export main types at crate root
-pub use client::Client;
+pub use client::{
+    AuthScheme, CancelHandle, Client, ClientBuilder, LabeledRecordStream, QueryClient, QueryStats,
+    QueryStream, RecordReader, RecordStream,
+};
 pub use error::{Error, Result};
-pub use types::{DataType, FluxColumn, FluxRecord, FluxTableMetadata};
+#[doc(hidden)]
+pub use influxdb_stream_macros::flux_inline as __flux_inline;
+pub use types::{DataType, FluxColumn, FluxRecord, FluxTableMetadata, RecordSchema};
 pub use value::Value;
+pub use view::RecordView;
+pub use write::{Point, WriteApi};
 
 // Re-export parser for advanced use cases
 pub use parser::AnnotatedCsvParser;
@@ -0,0 +1,457 @@
+//! Parsing line protocol.
+//!
+//! [`LineProtocolParser`] reads line protocol, as written by `influx write`,
+//! Telegraf or [`sink::write_line_protocol`](crate::sink::write_line_protocol),
+//! from any async reader. Lines come out as [`Point`]s, or as records shaped
+//! like Flux results so that files can be validated, transformed and
+//! converted with the same adapters and sinks as query results:
+//!
+//! ```ignore
+//! use influxdb_stream::line_protocol::LineProtocolParser;
+//! use influxdb_stream::sink::{CsvOptions, write_csv};
+//!
+//! let file = tokio::fs::File::open("export.lp").await?;
+//! let records = LineProtocolParser::new(file).into_records();
+//! write_csv(records, tokio::io::stdout(), &CsvOptions::new()).await?;
+//! ```
+
+use async_stream::stream;
+use chrono::DateTime;
+use futures::{Stream, StreamExt};
+use ordered_float::OrderedFloat;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+use crate::value::Value;
+use crate::write::{Point, Precision};
+
+/// Measurement, tags and field of a series.
+type Series = (String, Vec<(String, String)>, String);
+
+/// Streaming parser for line protocol.
+///
+/// Blank lines and `#` comments are skipped. A malformed line fails with
+/// [`Error::Parse`], naming its line number; the parser can keep going with
+/// the next line afterwards.
+pub struct LineProtocolParser<R> {
+    reader: BufReader<R>,
+    buf: String,
+    line: u64,
+    precision: Precision,
+}
+
+impl<R: AsyncRead + Unpin> LineProtocolParser<R> {
+    /// Create a parser reading from `reader`, with nanosecond timestamps.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buf: String::new(),
+            line: 0,
+            precision: Precision::Nanoseconds,
+        }
+    }
+
+    /// Set the precision of timestamps (default: nanoseconds).
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Get the number of the line read last, starting at 1.
+    pub fn line_number(&self) -> u64 {
+        self.line
+    }
+
+    /// Parse and return the next point, or `None` at the end of the input.
+    pub async fn next_point(&mut self) -> Result<Option<Point>> {
+        loop {
+            self.buf.clear();
+            if self.reader.read_line(&mut self.buf).await? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            let line = self.buf.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return parse(line, self.precision)
+                .map(Some)
+                .map_err(|message| Error::Parse {
+                    message: format!("Invalid line protocol on line {}: {}", self.line, message),
+                });
+        }
+    }
+
+    /// Turn the parser into a stream of points.
+    ///
+    /// Malformed lines are yielded as errors and parsing continues; I/O
+    /// errors end the stream.
+    pub fn into_points(mut self) -> impl Stream<Item = Result<Point>>
+    where
+        R: Send,
+    {
+        stream! {
+            loop {
+                match self.next_point().await {
+                    Ok(Some(point)) => yield Ok(point),
+                    Ok(None) => break,
+                    Err(e @ Error::Io(_)) => {
+                        yield Err(e);
+                        break;
+                    }
+                    Err(e) => yield Err(e),
+                }
+            }
+        }
+    }
+
+    /// Turn the parser into a stream of records, one per field.
+    ///
+    /// Each record has the `_measurement`, tag, `_field`, `_value` and, if
+    /// the line has a timestamp, `_time` columns of the matching Flux result.
+    /// Consecutive records of the same series share a table number, so
+    /// per-table adapters such as
+    /// [`filter_tags`](crate::adapters::RecordStreamExt::filter_tags) work as
+    /// on query results. Errors are reported as by
+    /// [`into_points`](Self::into_points).
+    pub fn into_records(self) -> impl Stream<Item = Result<FluxRecord>>
+    where
+        R: Send,
+    {
+        let points = self.into_points();
+        stream! {
+            let mut points = std::pin::pin!(points);
+            let mut table = -1;
+            let mut last: Option<Series> = None;
+
+            while let Some(point) = points.next().await {
+                let point = match point {
+                    Ok(point) => point,
+                    Err(e) => {
+                        yield Err(e);
+                        continue;
+                    }
+                };
+                let tags: Vec<(String, String)> = point
+                    .tags()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+
+                for (field, value) in point.fields() {
+                    let same_series = last.as_ref().is_some_and(|(m, t, f)| {
+                        m == point.measurement() && *t == tags && f == field
+                    });
+                    if !same_series {
+                        table += 1;
+                        let series = (point.measurement().to_string(), tags.clone(), field.to_string());
+                        last = Some(series);
+                    }
+
+                    let mut record = FluxRecord::new(table);
+                    let measurement = Value::String(point.measurement().to_string());
+                    record.insert("_measurement", measurement);
+                    for (key, value) in &tags {
+                        record.insert(key.clone(), Value::String(value.clone()));
+                    }
+                    record.insert("_field", Value::String(field.to_string()));
+                    record.insert("_value", value.clone());
+                    if let Some(time) = point.time() {
+                        record.insert("_time", Value::TimeRFC(*time));
+                    }
+                    yield Ok(record);
+                }
+            }
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for LineProtocolParser<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LineProtocolParser")
+            .field("line", &self.line)
+            .field("precision", &self.precision)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parse one line of line protocol, without trailing newline.
+///
+/// Fails with [`Error::Parse`] on malformed input.
+///
+/// # Example
+///
+/// ```
+/// use influxdb_stream::line_protocol::parse_line;
+/// use influxdb_stream::write::{Point, Precision};
+///
+/// let point = parse_line("cpu,host=a usage=0.5", Precision::Nanoseconds).unwrap();
+/// assert_eq!(point, Point::new("cpu").tag("host", "a").field("usage", 0.5));
+/// ```
+pub fn parse_line(line: &str, precision: Precision) -> Result<Point> {
+    parse(line, precision).map_err(|message| Error::Parse {
+        message: format!("Invalid line protocol: {}", message),
+    })
+}
+
+fn parse(line: &str, precision: Precision) -> std::result::Result<Point, String> {
+    let mut cursor = Cursor { rest: line };
+
+    let measurement = cursor.token(&[',', ' ']);
+    if measurement.is_empty() {
+        return Err("missing measurement".to_string());
+    }
+    let mut point = Point::new(measurement);
+
+    while cursor.eat(',') {
+        let key = cursor.token(&['=', ',', ' ']);
+        if key.is_empty() || !cursor.eat('=') {
+            return Err(format!("malformed tag after '{}'", point.measurement()));
+        }
+        let value = cursor.token(&[',', ' ']);
+        point = point.tag(key, value);
+    }
+
+    if !cursor.eat(' ') {
+        return Err("missing fields".to_string());
+    }
+    loop {
+        let key = cursor.token(&['=', ',', ' ']);
+        if key.is_empty() || !cursor.eat('=') {
+            return Err("malformed field".to_string());
+        }
+        let value = cursor.field_value(&key)?;
+        point = point.field(key, value);
+        if !cursor.eat(',') {
+            break;
+        }
+    }
+
+    if cursor.eat(' ') {
+        let raw = cursor.rest.trim();
+        let timestamp: i64 = raw
+            .parse()
+            .map_err(|_| format!("invalid timestamp '{}'", raw))?;
+        let nanos = timestamp
+            .checked_mul(precision.divisor())
+            .ok_or_else(|| format!("timestamp '{}' out of range", raw))?;
+        point = point.timestamp(DateTime::from_timestamp_nanos(nanos).fixed_offset());
+    } else if !cursor.rest.is_empty() {
+        return Err(format!("unexpected '{}'", cursor.rest));
+    }
+    Ok(point)
+}
+
+/// Position in a line being parsed.
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl Cursor<'_> {
+    /// Consume `c` if the rest starts with it.
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Read up to the first unescaped `stop` character.
+    ///
+    /// A backslash before a comma, equals sign or space is dropped.
+    fn token(&mut self, stop: &[char]) -> String {
+        let mut out = String::new();
+        let mut chars = self.rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if stop.contains(&c) {
+                self.rest = &self.rest[i..];
+                return out;
+            }
+            match (c, chars.peek()) {
+                ('\\', Some(&(_, next @ (',' | '=' | ' ')))) => {
+                    out.push(next);
+                    chars.next();
+                }
+                _ => out.push(c),
+            }
+        }
+        self.rest = "";
+        out
+    }
+
+    /// Read the value of field `key`.
+    fn field_value(&mut self, key: &str) -> std::result::Result<Value, String> {
+        if self.eat('"') {
+            let mut out = String::new();
+            let mut chars = self.rest.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        self.rest = &self.rest[i + 1..];
+                        return Ok(Value::String(out));
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => out.push(escaped),
+                        Some((_, other)) => {
+                            out.push('\\');
+                            out.push(other);
+                        }
+                        None => out.push('\\'),
+                    },
+                    _ => out.push(c),
+                }
+            }
+            return Err(format!("unterminated string in field '{}'", key));
+        }
+
+        let raw = self.token(&[',', ' ']);
+        let invalid = || format!("invalid value '{}' for field '{}'", raw, key);
+        let value = match raw.as_str() {
+            "t" | "T" | "true" | "True" | "TRUE" => Value::Bool(true),
+            "f" | "F" | "false" | "False" | "FALSE" => Value::Bool(false),
+            _ => {
+                if let Some(n) = raw.strip_suffix('i') {
+                    Value::Long(n.parse().map_err(|_| invalid())?)
+                } else if let Some(n) = raw.strip_suffix('u') {
+                    Value::UnsignedLong(n.parse().map_err(|_| invalid())?)
+                } else {
+                    let n: f64 = raw.parse().map_err(|_| invalid())?;
+                    Value::Double(OrderedFloat(n))
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::{LineProtocolOptions, to_line_protocol};
+    use futures::TryStreamExt;
+    use std::io::Cursor as IoCursor;
+
+    fn parser(input: &str) -> LineProtocolParser<IoCursor<Vec<u8>>> {
+        LineProtocolParser::new(IoCursor::new(input.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_parse_line_types() {
+        let point = parse_line(
+            r#"weather,city=New\ York,zone=a\,b temp=21.5,count=3i,big=7u,ok=t,note="say \"hi\"" 1700000000000000000"#,
+            Precision::Nanoseconds,
+        )
+        .unwrap();
+
+        let expected = Point::new("weather")
+            .tag("city", "New York")
+            .tag("zone", "a,b")
+            .field("temp", 21.5)
+            .field("count", 3i64)
+            .field("big", 7u64)
+            .field("ok", true)
+            .field("note", "say \"hi\"")
+            .timestamp(
+                DateTime::from_timestamp(1_700_000_000, 0)
+                    .unwrap()
+                    .fixed_offset(),
+            );
+        assert_eq!(point, expected);
+    }
+
+    #[test]
+    fn test_parse_line_precision() {
+        let point = parse_line("cpu usage=1 1700000000", Precision::Seconds).unwrap();
+        assert_eq!(point.time().unwrap().timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_parse_line_errors() {
+        for line in [
+            "cpu",
+            "cpu,host usage=1",
+            "cpu usage=",
+            "cpu usage=abc",
+            "cpu usage=\"open",
+            "cpu usage=1 soon",
+        ] {
+            assert!(
+                matches!(
+                    parse_line(line, Precision::Nanoseconds),
+                    Err(Error::Parse { .. })
+                ),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_through_sink() {
+        let lines = [
+            "cpu,host=server1 usage=0.5 1700000000000000000",
+            "mem,host=server1 used=42i 1700000000000000000",
+        ];
+        for line in lines {
+            let point = parse_line(line, Precision::Nanoseconds).unwrap();
+            assert_eq!(
+                point.to_line_protocol(Precision::Nanoseconds).unwrap(),
+                line
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parser_skips_comments_and_reports_line() {
+        let mut parser = parser("# header\n\ncpu usage=1\ncpu usage=\ncpu usage=2\n");
+
+        assert!(parser.next_point().await.unwrap().is_some());
+        match parser.next_point().await {
+            Err(Error::Parse { message }) => assert!(message.contains("line 4"), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(parser.line_number(), 4);
+        assert!(parser.next_point().await.unwrap().is_some());
+        assert!(parser.next_point().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_into_records() {
+        let input = "cpu,host=a user=1,system=2 1700000000000000000\n\
+                     cpu,host=a user=3 1700000001000000000\n\
+                     bad line\n\
+                     cpu,host=b user=4\n";
+        let items: Vec<_> = parser(input).into_records().collect().await;
+        assert_eq!(items.len(), 5);
+        assert!(items[3].is_err());
+
+        let records: Vec<_> = items.into_iter().filter_map(|r| r.ok()).collect();
+        let tables: Vec<_> = records.iter().map(|r| r.table).collect();
+        assert_eq!(tables, [0, 1, 1, 2]);
+        assert_eq!(records[0].field_str(), Some("system"));
+        assert_eq!(records[0].get_str("host"), Some("a"));
+        assert_eq!(records[0].get_double("_value"), Some(2.0));
+        assert!(records[3].time().is_none());
+
+        let options = LineProtocolOptions::new();
+        assert_eq!(
+            to_line_protocol(&records[1], &options).unwrap().unwrap(),
+            "cpu,host=a user=1 1700000000000000000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_into_points() {
+        let points: Vec<_> = parser("a x=1\nb y=2\n")
+            .into_points()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].measurement(), "b");
+    }
+}
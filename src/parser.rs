@@ -3,21 +3,243 @@
 //! This module provides a streaming parser for InfluxDB's annotated CSV format,
 //! which is the format returned by the `/api/v2/query` endpoint.
 
-use std::collections::BTreeMap;
+use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
 
 use base64::Engine;
 use chrono::DateTime;
-use csv_async::{AsyncReaderBuilder, StringRecord, Trim};
-use futures::StreamExt;
+use csv_core::ReadRecordResult;
 use go_parse_duration::parse_duration;
 use ordered_float::OrderedFloat;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, ReadBuf};
 
 use crate::error::{Error, Result};
-use crate::types::{DataType, FluxRecord, FluxTableMetadata};
+use crate::types::{DataType, FluxColumn, FluxRecord, FluxTableMetadata, RecordSchema};
 use crate::value::Value;
 
+/// Reader of CSV rows that remembers how much was read and how the input ended.
+struct RowReader<R> {
+    inner: R,
+    csv: csv_core::Reader,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+    // Unescaped fields of the row being read, kept across polls that
+    // return `Pending` in the middle of a row.
+    fields: Vec<u8>,
+    ends: Vec<usize>,
+    fields_len: usize,
+    ends_len: usize,
+    bytes: u64,
+    // Last bytes of the input, to tell how it ended.
+    tail: [u8; 3],
+    eof: bool,
+}
+
+impl<R> RowReader<R> {
+    fn new(inner: R, capacity: usize) -> Self {
+        Self {
+            inner,
+            csv: csv_core::Reader::new(),
+            buf: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            fields: vec![0; 1024],
+            ends: vec![0; 32],
+            fields_len: 0,
+            ends_len: 0,
+            bytes: 0,
+            tail: [0; 3],
+            eof: false,
+        }
+    }
+
+    fn set_delimiter(&mut self, delimiter: u8) {
+        self.csv = csv_core::ReaderBuilder::new().delimiter(delimiter).build();
+    }
+
+    /// Whether the input ended without a final line break.
+    fn partial_row(&self) -> bool {
+        self.eof && self.tail[2] != b'\n'
+    }
+
+    /// Whether the input ended with a blank line, which InfluxDB writes
+    /// after the last table of a response.
+    fn terminated(&self) -> bool {
+        self.eof && (self.tail.ends_with(b"\n\n") || self.tail.ends_with(b"\n\r\n"))
+    }
+}
+
+impl<R: AsyncRead + Unpin> RowReader<R> {
+    /// Read the next row into `row`, returning `false` at the end of the input.
+    fn poll_read_row(&mut self, cx: &mut Context<'_>, row: &mut Row) -> Poll<Result<bool>> {
+        loop {
+            if self.pos == self.filled && !self.eof {
+                let mut buf = ReadBuf::new(&mut self.buf);
+                ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+                let read = buf.filled();
+                if read.is_empty() {
+                    self.eof = true;
+                }
+                self.bytes += read.len() as u64;
+                let keep = read.len().min(self.tail.len());
+                self.tail.rotate_left(keep);
+                self.tail[3 - keep..].copy_from_slice(&read[read.len() - keep..]);
+                self.pos = 0;
+                self.filled = read.len();
+            }
+            // Empty input tells the reader the end was reached.
+            let (result, read, written, ends) = self.csv.read_record(
+                &self.buf[self.pos..self.filled],
+                &mut self.fields[self.fields_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            self.pos += read;
+            self.fields_len += written;
+            self.ends_len += ends;
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => self.fields.resize(self.fields.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let set = row.set(&self.fields[..self.fields_len], &self.ends[..self.ends_len]);
+                    self.fields_len = 0;
+                    self.ends_len = 0;
+                    return Poll::Ready(set.map(|()| true));
+                }
+                ReadRecordResult::End => return Poll::Ready(Ok(false)),
+            }
+        }
+    }
+}
+
+/// Fields of a CSV row, trimmed of surrounding whitespace.
+#[derive(Debug, Default)]
+struct Row {
+    text: String,
+    bounds: Vec<(usize, usize)>,
+}
+
+impl Row {
+    /// Replace the fields with `fields`, where field `i` ends at `ends[i]`.
+    fn set(&mut self, fields: &[u8], ends: &[usize]) -> Result<()> {
+        self.text.clear();
+        self.bounds.clear();
+        let invalid =
+            |field: usize| Error::Csv(format!("CSV read error: invalid UTF-8 in field {field}"));
+        let text = std::str::from_utf8(fields).map_err(|_| invalid(ends.len()))?;
+        let mut start = 0;
+        for (i, &end) in ends.iter().enumerate() {
+            let field = text.get(start..end).ok_or_else(|| invalid(i))?;
+            let trimmed = field.trim_start();
+            let from = end - trimmed.len();
+            self.bounds.push((from, from + trimmed.trim_end().len()));
+            start = end;
+        }
+        self.text.push_str(text);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    fn get(&self, i: usize) -> Option<&str> {
+        self.bounds.get(i).map(|&(from, to)| &self.text[from..to])
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        self.bounds.iter().map(|&(from, to)| &self.text[from..to])
+    }
+}
+
+/// Read buffer size used by [`AnnotatedCsvParser::new`].
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// What to do with empty cells of columns that have no `#default` value.
+///
+/// Only non-string columns are affected: an empty string cell is read as an
+/// empty [`Value::String`] under every policy.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::parser::{AnnotatedCsvParser, NullPolicy};
+///
+/// let parser = AnnotatedCsvParser::new(reader).null_policy(NullPolicy::Skip);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum NullPolicy {
+    /// Yield [`Value::Null`].
+    #[default]
+    Null,
+    /// Leave the column out of the record.
+    ///
+    /// Records with skipped columns no longer share their table's schema, so
+    /// each of them allocates its own.
+    Skip,
+    /// Yield the given value instead.
+    ///
+    /// A null in a column of another type than the value fails with
+    /// [`Error::Parse`], so substituted records keep their table's types.
+    Substitute(Value),
+    /// Fail with [`Error::Parse`].
+    Error,
+}
+
+impl NullPolicy {
+    /// Apply the policy to the null values of `record`, a row of a table
+    /// with `columns`.
+    fn apply(&self, record: &mut FluxRecord, columns: &[FluxColumn]) -> Result<()> {
+        let is_null = |v: &Value| matches!(v, Value::Null);
+        match self {
+            NullPolicy::Null => {}
+            NullPolicy::Skip => {
+                if record.values().iter().any(is_null) {
+                    let mut schema = RecordSchema::default();
+                    let mut values = Vec::new();
+                    for (name, value) in record.iter().filter(|(_, value)| !is_null(value)) {
+                        let group = record.schema().is_group_key(name);
+                        schema.push_column(name.to_string(), group);
+                        values.push(value.clone());
+                    }
+                    *record = FluxRecord::from_parts(record.table, Arc::new(schema), values);
+                }
+            }
+            NullPolicy::Substitute(substitute) => {
+                let data_type = substitute.data_type();
+                let mismatch =
+                    record.values().iter().zip(columns).find(|(value, column)| {
+                        is_null(value) && data_type != Some(column.data_type)
+                    });
+                if let Some((_, column)) = mismatch {
+                    let message = format!(
+                        "Substitute for null in {} column '{}' is not a {} value",
+                        column.data_type, column.name, column.data_type
+                    );
+                    record.clear();
+                    return Err(Error::Parse { message });
+                }
+                for value in record.values_mut().iter_mut().filter(|v| is_null(v)) {
+                    *value = substitute.clone();
+                }
+            }
+            NullPolicy::Error => {
+                let null = record.iter().find(|(_, value)| is_null(value));
+                if let Some(message) =
+                    null.map(|(name, _)| format!("Empty value for column '{}'", name))
+                {
+                    record.clear();
+                    return Err(Error::Parse { message });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Internal state of the CSV parser.
 ///
 /// State transitions:
@@ -41,8 +263,8 @@ enum ParsingState {
 enum RowAction {
     /// Continue to next row (annotation or header processed).
     Continue,
-    /// Return a parsed record.
-    Record(FluxRecord),
+    /// A record was parsed into the caller's buffer.
+    Record,
     /// Return an error.
     Error(Error),
 }
@@ -69,105 +291,401 @@ enum RowAction {
 /// }
 /// ```
 pub struct AnnotatedCsvParser<R: AsyncRead + Unpin> {
-    csv: csv_async::AsyncReader<R>,
+    csv: RowReader<R>,
+    records: u64,
+    row: Row,
     table_position: i32,
     table: Option<FluxTableMetadata>,
+    schema: Option<Arc<RecordSchema>>,
     parsing_state: ParsingState,
     data_type_annotation_found: bool,
+    null_policy: NullPolicy,
+    annotated: bool,
+    header: bool,
+    raw_times: bool,
+    // Types of the current table as written by the server, while the
+    // metadata has times and durations as longs for `raw_times`.
+    wire_types: Vec<DataType>,
+    skip_bad_tables: bool,
+    // Set while the rest of a failed table is being skipped, with the value
+    // of its `table` column when the failure was in a data row.
+    skipping: bool,
+    skipped_table: Option<String>,
+    terminated: bool,
 }
 
 impl<R: AsyncRead + Unpin + Send> AnnotatedCsvParser<R> {
     /// Create a new parser from an async reader.
     pub fn new(reader: R) -> Self {
-        let csv = AsyncReaderBuilder::new()
-            .has_headers(false) // We handle headers/annotations ourselves
-            .trim(Trim::Fields)
-            .flexible(true)
-            .create_reader(reader);
+        Self::with_capacity(reader, DEFAULT_BUFFER_CAPACITY)
+    }
 
+    /// Create a new parser that reads `reader` through a buffer of `capacity` bytes.
+    ///
+    /// Larger buffers mean fewer, bigger reads from `reader`; smaller ones
+    /// reduce memory when many parsers run at once.
+    pub fn with_capacity(reader: R, capacity: usize) -> Self {
         Self {
-            csv,
+            csv: RowReader::new(reader, capacity),
+            records: 0,
+            row: Row::default(),
             table_position: 0,
             table: None,
+            schema: None,
             parsing_state: ParsingState::Normal,
             data_type_annotation_found: false,
+            null_policy: NullPolicy::default(),
+            annotated: true,
+            header: true,
+            raw_times: false,
+            wire_types: Vec::new(),
+            skip_bad_tables: false,
+            skipping: false,
+            skipped_table: None,
+            terminated: false,
         }
     }
 
+    /// Expect CSV without annotations (default: `true`, annotations required).
+    ///
+    /// InfluxDB leaves annotations out when the query is sent without a
+    /// dialect, as with `application/vnd.flux` requests. Tables are then
+    /// recognized by their header row, which starts with the `result` and
+    /// `table` columns. Without `#datatype` rows, `table` is read as a long,
+    /// `_start`, `_stop` and `_time` as times, and every other column as a
+    /// string. Annotation rows in the input, such as `#group`, are skipped.
+    pub fn annotations(mut self, annotated: bool) -> Self {
+        self.annotated = annotated;
+        self
+    }
+
+    /// Expect a header row naming the columns of each table (default: `true`).
+    ///
+    /// Without one, the first row after a table's annotations is data and
+    /// columns are named by their position, starting with `"0"` for the
+    /// `result` column. Requires annotations, and error tables are no longer
+    /// recognized.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the field delimiter (default: `,`).
+    ///
+    /// Call it before reading.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.csv.set_delimiter(delimiter);
+        self
+    }
+
+    /// Set how empty cells without a default are read (default: [`NullPolicy::Null`]).
+    pub fn null_policy(mut self, policy: NullPolicy) -> Self {
+        self.null_policy = policy;
+        self
+    }
+
+    /// Read `dateTime:RFC3339` and `duration` cells as [`Value::Long`]
+    /// nanoseconds (default: `false`).
+    ///
+    /// Times are converted to nanoseconds since the Unix epoch without going
+    /// through `chrono`, which makes parsing cheaper for consumers that only
+    /// forward the data. This is a parsing mode: `chrono` remains a dependency
+    /// of the crate. The table metadata reports these columns as
+    /// [`DataType::Long`], and accessors such as [`FluxRecord::time`] return
+    /// `None`; use [`FluxRecord::time_nanos`] instead.
+    pub fn raw_times(mut self, raw: bool) -> Self {
+        self.raw_times = raw;
+        self
+    }
+
+    /// Abandon a table when one of its rows fails to parse (default: `false`).
+    ///
+    /// The failure is returned as [`Error::TableSkipped`], the remaining rows
+    /// of the table are skipped, and parsing resumes at the next row with
+    /// another value in the `table` column, as tables with the same columns
+    /// share one annotation block, or at the next annotation block (or header
+    /// row, without annotations). Other errors, such as I/O failures and
+    /// errors reported by InfluxDB, still end the stream.
+    pub fn skip_bad_tables(mut self, skip: bool) -> Self {
+        self.skip_bad_tables = skip;
+        self
+    }
+
+    /// Expect the input to end with a blank line, as InfluxDB ends its
+    /// query responses (default: `false`).
+    ///
+    /// A response cut off by a proxy or a load balancer failing over most
+    /// often ends between two rows, which looks like a complete result. With
+    /// this set, input that has tables but does not end with the blank line,
+    /// including input cut in the middle of a row, fails with
+    /// [`Error::Truncated`]. Leave it unset for files and other CSV that
+    /// does not come straight from InfluxDB.
+    pub fn terminated(mut self, terminated: bool) -> Self {
+        self.terminated = terminated;
+        self
+    }
+
     /// Parse and return the next record.
     ///
     /// Returns:
     /// - `Ok(Some(record))` - Successfully parsed a record
     /// - `Ok(None)` - End of stream (EOF)
     /// - `Err(e)` - Parse error
+    ///
+    /// Input that ends in the middle of a table's annotations, or without
+    /// the blank line that ends a response when [`terminated`](Self::terminated)
+    /// is set, fails with [`Error::Truncated`] instead of ending the stream.
     pub async fn next(&mut self) -> Result<Option<FluxRecord>> {
-        let mut records = self.csv.records();
+        let mut record = FluxRecord::new(0);
+        Ok(self.next_into(&mut record).await?.then_some(record))
+    }
+
+    /// Parse the next record into `record`, reusing its allocations.
+    ///
+    /// Handing the same record back on every call avoids allocating a value
+    /// buffer and string values for each row, which matters in tight loops.
+    /// Whatever `record` held before is overwritten.
+    ///
+    /// Returns `Ok(true)` if a record was parsed and `Ok(false)` at the end of
+    /// the stream. On error, `record` is left empty.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut record = FluxRecord::new(0);
+    /// while parser.next_into(&mut record).await? {
+    ///     process(&record);
+    /// }
+    /// ```
+    pub async fn next_into(&mut self, record: &mut FluxRecord) -> Result<bool> {
+        std::future::poll_fn(|cx| self.poll_next_into(cx, record)).await
+    }
 
+    /// Poll for the next record, parsing it into `record`.
+    ///
+    /// Like [`next_into`](Self::next_into), for use in [`Stream`](futures::Stream)
+    /// implementations. `record` is only written to once a row is complete.
+    pub(crate) fn poll_next_into(
+        &mut self,
+        cx: &mut Context<'_>,
+        record: &mut FluxRecord,
+    ) -> Poll<Result<bool>> {
         loop {
-            let row = match records.next().await {
-                Some(Ok(r)) => r,
-                Some(Err(e)) => return Err(Error::Csv(format!("CSV read error: {}", e))),
-                None => return Ok(None), // EOF
-            };
+            match ready!(self.csv.poll_read_row(cx, &mut self.row)) {
+                // A response cut in the middle of a row ends without a line
+                // break; its last row may be missing cells or digits.
+                Ok(true) if self.terminated && self.csv.partial_row() => {
+                    return Poll::Ready(Err(self.truncated(record)));
+                }
+                Ok(true) => {}
+                Ok(false)
+                    if self.parsing_state == ParsingState::Annotation
+                        || (self.terminated && self.table.is_some() && !self.csv.terminated()) =>
+                {
+                    return Poll::Ready(Err(self.truncated(record)));
+                }
+                Ok(false) => return Poll::Ready(Ok(false)), // EOF
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+            let row = &self.row;
 
             // Skip empty rows or rows with only 1 column
             if row.len() <= 1 {
                 continue;
             }
 
-            // Detect start of new annotation block
-            if detect_annotation_start(
-                &row,
-                self.parsing_state,
-                &mut self.table,
-                &mut self.table_position,
-                &mut self.parsing_state,
-                &mut self.data_type_annotation_found,
-            ) {
-                // New table started, parsing_state is now Annotation
+            if self.skipping {
+                // A table that failed in its annotations is past them once a
+                // row without `#` shows up; the next `#` row starts a new one.
+                let annotation = row.get(0).is_some_and(|c| c.starts_with('#'));
+                let next_table = (annotation && self.parsing_state == ParsingState::Normal)
+                    || (!self.annotated && is_unannotated_header(row));
+                let next_table_value = !annotation
+                    && self.skipped_table.is_some()
+                    && self.table_value() != self.skipped_table;
+                if !next_table && !next_table_value {
+                    if !annotation {
+                        self.parsing_state = ParsingState::Normal;
+                    }
+                    continue;
+                }
+                self.skipping = false;
+                self.skipped_table = None;
             }
 
-            // Get table reference or return error if missing
-            let table = match &mut self.table {
-                Some(t) => t,
-                None => {
-                    return Err(Error::MissingAnnotation(
-                        "No annotations found before data".to_string(),
-                    ));
+            if !self.annotated {
+                // Annotations other than `#datatype` carry nothing to go on.
+                if row.get(0).is_some_and(|c| c.starts_with('#')) {
+                    continue;
+                }
+                if let Some(mut table) = unannotated_header(row, self.table_position) {
+                    if self.raw_times {
+                        raw_columns(&mut table, &mut self.wire_types);
+                    }
+                    self.parsing_state = match table.columns[0].name.as_str() {
+                        "error" => ParsingState::Error,
+                        _ => ParsingState::Normal,
+                    };
+                    self.table = Some(table);
+                    self.table_position += 1;
+                    self.schema = None;
+                    continue;
                 }
-            };
-
-            // Validate column count
-            if row.len() - 1 != table.columns.len() {
-                return Err(Error::ColumnMismatch {
-                    expected: table.columns.len(),
-                    actual: row.len() - 1,
-                });
             }
 
-            // Process the row based on its first cell
-            let action = process_row(
-                &row,
-                table,
-                self.parsing_state,
-                self.data_type_annotation_found,
-                &mut self.parsing_state,
-                &mut self.data_type_annotation_found,
-            )?;
+            let action = match self.process_current_row(record) {
+                Ok(action) => action,
+                Err(e) if self.skip_bad_tables => {
+                    record.clear();
+                    self.skipping = true;
+                    self.skipped_table = match self.parsing_state {
+                        ParsingState::Normal => self.table_value(),
+                        _ => None,
+                    };
+                    let table = self.skipped_table.as_deref().and_then(|t| t.parse().ok());
+                    return Poll::Ready(Err(Error::TableSkipped {
+                        table: table
+                            .unwrap_or_else(|| self.table.as_ref().map_or(0, |t| t.position)),
+                        source: Box::new(e),
+                    }));
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            };
 
             match action {
                 RowAction::Continue => continue,
-                RowAction::Record(record) => return Ok(Some(record)),
-                RowAction::Error(e) => return Err(e),
+                RowAction::Record => {
+                    self.records += 1;
+                    return Poll::Ready(Ok(true));
+                }
+                RowAction::Error(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    /// Value of the `table` column in the row just read, with its default.
+    fn table_value(&self) -> Option<String> {
+        let table = self.table.as_ref()?;
+        let i = table.columns.iter().position(|c| c.name == "table")?;
+        match self.row.get(i + 1)? {
+            "" => Some(table.columns[i].default_value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    fn truncated(&self, record: &mut FluxRecord) -> Error {
+        record.clear();
+        Error::Truncated {
+            bytes: self.csv.bytes,
+            records: self.records,
+        }
+    }
+
+    /// Process the row just read, applying the null policy to records.
+    fn process_current_row(&mut self, record: &mut FluxRecord) -> Result<RowAction> {
+        let row = &self.row;
+
+        // Detect start of new annotation block
+        if detect_annotation_start(
+            row,
+            self.parsing_state,
+            &mut self.table,
+            &mut self.table_position,
+            &mut self.parsing_state,
+            &mut self.data_type_annotation_found,
+        ) {
+            // New table started, parsing_state is now Annotation
+        }
+
+        // Get table reference or return error if missing
+        let table = match &mut self.table {
+            Some(t) => t,
+            None => {
+                return Err(Error::MissingAnnotation(
+                    "No annotations found before data".to_string(),
+                ));
+            }
+        };
+
+        // Validate column count
+        if row.len() - 1 != table.columns.len() {
+            return Err(Error::ColumnMismatch {
+                expected: table.columns.len(),
+                actual: row.len() - 1,
+            });
+        }
+
+        // Without a header row, the first row after the annotations is data.
+        if !self.header && self.parsing_state == ParsingState::Annotation && row.get(0) == Some("")
+        {
+            if !self.data_type_annotation_found {
+                return Err(Error::MissingAnnotation(
+                    "#datatype annotation not found".to_string(),
+                ));
+            }
+            for (i, column) in table.columns.iter_mut().enumerate() {
+                column.name = i.to_string();
             }
+            if self.raw_times {
+                raw_columns(table, &mut self.wire_types);
+            }
+            self.schema = None;
+            self.parsing_state = ParsingState::Normal;
+        }
+
+        // Process the row based on its first cell
+        let annotation = self.parsing_state == ParsingState::Annotation;
+        let action = process_row(
+            row,
+            table,
+            &mut self.schema,
+            record,
+            &mut self.parsing_state,
+            &mut self.data_type_annotation_found,
+            self.raw_times.then_some(&self.wire_types[..]),
+        )?;
+        // The header row was read, so the table's types are complete.
+        if self.raw_times && annotation && self.parsing_state == ParsingState::Normal {
+            raw_columns(table, &mut self.wire_types);
+        }
+
+        if let RowAction::Record = action {
+            self.null_policy.apply(record, &table.columns)?;
         }
+        Ok(action)
     }
 }
 
+/// Returns true if `row` is the header row of a table without annotations.
+fn is_unannotated_header(row: &Row) -> bool {
+    matches!(
+        (row.get(1), row.get(2)),
+        (Some("result"), Some("table")) | (Some("error"), Some("reference"))
+    )
+}
+
+/// Read the header row of a table without annotations, if `row` is one.
+fn unannotated_header(row: &Row, position: i32) -> Option<FluxTableMetadata> {
+    if !is_unannotated_header(row) {
+        return None;
+    }
+
+    let mut table = FluxTableMetadata::new(position, row.len() - 1);
+    for (column, name) in table.columns.iter_mut().zip(row.iter().skip(1)) {
+        column.name = name.to_string();
+        column.data_type = match name {
+            "table" => DataType::Long,
+            "_start" | "_stop" | "_time" => DataType::TimeRFC,
+            _ => DataType::String,
+        };
+    }
+    Some(table)
+}
+
 /// Detect if a row starts a new annotation block.
 /// Returns true if a new annotation block was started.
 fn detect_annotation_start(
-    row: &StringRecord,
+    row: &Row,
     current_state: ParsingState,
     table: &mut Option<FluxTableMetadata>,
     table_position: &mut i32,
@@ -189,22 +707,26 @@ fn detect_annotation_start(
 
 /// Process a single row and return the appropriate action.
 fn process_row(
-    row: &StringRecord,
+    row: &Row,
     table: &mut FluxTableMetadata,
-    current_state: ParsingState,
-    current_datatype_found: bool,
+    schema: &mut Option<Arc<RecordSchema>>,
+    record: &mut FluxRecord,
     parsing_state: &mut ParsingState,
     data_type_annotation_found: &mut bool,
+    raw: Option<&[DataType]>,
 ) -> Result<RowAction> {
+    let current_datatype_found = *data_type_annotation_found;
     let first_cell = row.get(0).unwrap_or_default();
 
     match first_cell {
         "" => process_empty_first_cell(
             row,
             table,
-            current_state,
+            schema,
+            record,
             current_datatype_found,
             parsing_state,
+            raw,
         ),
         "#datatype" => {
             process_datatype_annotation(row, table, data_type_annotation_found)?;
@@ -226,24 +748,27 @@ fn process_row(
 
 /// Process a row with empty first cell (header, data, or error row).
 fn process_empty_first_cell(
-    row: &StringRecord,
+    row: &Row,
     table: &mut FluxTableMetadata,
-    current_state: ParsingState,
+    schema: &mut Option<Arc<RecordSchema>>,
+    record: &mut FluxRecord,
     data_type_annotation_found: bool,
     parsing_state: &mut ParsingState,
+    raw: Option<&[DataType]>,
 ) -> Result<RowAction> {
-    match current_state {
+    match *parsing_state {
         ParsingState::Annotation => {
+            *schema = None;
             process_header_row(row, table, data_type_annotation_found, parsing_state)
         }
         ParsingState::Error => Ok(RowAction::Error(parse_error_response(row))),
-        ParsingState::Normal => parse_data_row(row, table),
+        ParsingState::Normal => parse_data_row(row, table, schema, record, raw),
     }
 }
 
 /// Process the header row (first row after annotations with empty first cell).
 fn process_header_row(
-    row: &StringRecord,
+    row: &Row,
     table: &mut FluxTableMetadata,
     data_type_annotation_found: bool,
     parsing_state: &mut ParsingState,
@@ -272,7 +797,7 @@ fn process_header_row(
 }
 
 /// Parse an error response from InfluxDB.
-fn parse_error_response(row: &StringRecord) -> Error {
+fn parse_error_response(row: &Row) -> Error {
     let message = row
         .get(1)
         .filter(|s| !s.is_empty())
@@ -284,9 +809,37 @@ fn parse_error_response(row: &StringRecord) -> Error {
     Error::QueryError { message, reference }
 }
 
-/// Parse a data row into a FluxRecord.
-fn parse_data_row(row: &StringRecord, table: &FluxTableMetadata) -> Result<RowAction> {
-    let mut values = BTreeMap::new();
+/// Parse a data row into `record`.
+///
+/// `schema` caches the column names of the current table so that all of its
+/// records share one allocation; it is reset whenever a new table starts.
+fn parse_data_row(
+    row: &Row,
+    table: &FluxTableMetadata,
+    schema: &mut Option<Arc<RecordSchema>>,
+    record: &mut FluxRecord,
+    raw: Option<&[DataType]>,
+) -> Result<RowAction> {
+    let schema = schema.get_or_insert_with(|| Arc::new(RecordSchema::from_columns(&table.columns)));
+    let values = record.refill(table.position, schema);
+    if let Err(e) = parse_values(row, table, values, raw) {
+        record.clear();
+        return Err(e);
+    }
+    Ok(RowAction::Record)
+}
+
+/// Parse the cells of a data row into `values`, reusing existing values.
+///
+/// With `raw`, the wire types of the columns, times and durations are read
+/// as nanoseconds.
+fn parse_values(
+    row: &Row,
+    table: &FluxTableMetadata,
+    values: &mut Vec<Value>,
+    raw: Option<&[DataType]>,
+) -> Result<()> {
+    values.truncate(table.columns.len());
 
     for i in 1..row.len() {
         let col = &table.columns[i - 1];
@@ -297,19 +850,139 @@ fn parse_data_row(row: &StringRecord, table: &FluxTableMetadata) -> Result<RowAc
             raw_value
         };
 
-        let parsed = parse_value(value, col.data_type, &col.name)?;
-        values.insert(col.name.clone(), parsed);
+        match values.get_mut(i - 1) {
+            // Reuse the string buffer left by the previous row.
+            Some(Value::String(buf)) if col.data_type == DataType::String => {
+                buf.clear();
+                buf.push_str(value);
+            }
+            Some(slot) => *slot = parse_cell(value, col, raw.map(|types| types[i - 1]))?,
+            None => values.push(parse_cell(value, col, raw.map(|types| types[i - 1]))?),
+        }
+    }
+    Ok(())
+}
+
+/// Parse a cell of `col`, as nanoseconds if its wire type is a raw time or
+/// duration.
+fn parse_cell(value: &str, col: &FluxColumn, wire: Option<DataType>) -> Result<Value> {
+    match wire {
+        Some(data_type) => parse_raw_value(value, data_type, &col.name),
+        None => parse_value(value, col.data_type, &col.name),
+    }
+}
+
+/// Record the wire types of `table` in `wire_types`, and report the columns
+/// read as nanoseconds as longs.
+fn raw_columns(table: &mut FluxTableMetadata, wire_types: &mut Vec<DataType>) {
+    wire_types.clear();
+    for column in &mut table.columns {
+        wire_types.push(column.data_type);
+        if matches!(column.data_type, DataType::TimeRFC | DataType::Duration) {
+            column.data_type = DataType::Long;
+        }
+    }
+}
+
+/// Parse a value like [`parse_value`], reading times and durations as
+/// nanoseconds.
+fn parse_raw_value(s: &str, data_type: DataType, column_name: &str) -> Result<Value> {
+    match data_type {
+        _ if s.is_empty() => parse_value(s, data_type, column_name),
+        DataType::TimeRFC => rfc3339_nanos(s)
+            .map(Value::Long)
+            .ok_or_else(|| Error::Parse {
+                message: format!(
+                    "Invalid RFC3339 timestamp '{}' for column '{}'",
+                    s, column_name
+                ),
+            }),
+        DataType::Duration => parse_duration(s)
+            .map(Value::Long)
+            .map_err(|_| Error::Parse {
+                message: format!("Invalid duration '{}' for column '{}'", s, column_name),
+            }),
+        _ => parse_value(s, data_type, column_name),
+    }
+}
+
+/// Convert an RFC 3339 timestamp to nanoseconds since the Unix epoch.
+///
+/// Accepts the `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)` form that
+/// InfluxDB writes; returns `None` for anything else or on overflow.
+fn rfc3339_nanos(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    let digits = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = b.get(range)?;
+        part.iter().try_fold(0i64, |n, &c| {
+            c.is_ascii_digit().then(|| n * 10 + i64::from(c - b'0'))
+        })
+    };
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    if b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let (year, month, day) = (digits(0..4)?, digits(5..7)?, digits(8..10)?);
+    let (hour, minute, second) = (digits(11..13)?, digits(14..16)?, digits(17..19)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    if second > 60 {
+        return None;
+    }
+
+    let mut pos = 19;
+    let mut fraction = 0i64;
+    if b[pos] == b'.' {
+        let start = pos + 1;
+        pos = start;
+        while pos < b.len() && b[pos].is_ascii_digit() {
+            if pos - start < 9 {
+                fraction = fraction * 10 + i64::from(b[pos] - b'0');
+            }
+            pos += 1;
+        }
+        if pos == start {
+            return None;
+        }
+        fraction *= 10i64.pow(9u32.saturating_sub((pos - start) as u32));
     }
 
-    Ok(RowAction::Record(FluxRecord {
-        table: table.position,
-        values,
-    }))
+    let offset = match b.get(pos..)? {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let minutes = digits(pos + 1..pos + 3)? * 60 + digits(pos + 4..pos + 6)?;
+            if *sign == b'-' { -minutes } else { minutes }
+        }
+        _ => return None,
+    };
+
+    // Days since the epoch of a proleptic Gregorian date (Howard Hinnant's
+    // `days_from_civil`).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset * 60;
+    seconds.checked_mul(1_000_000_000)?.checked_add(fraction)
 }
 
 /// Process #datatype annotation row.
 fn process_datatype_annotation(
-    row: &StringRecord,
+    row: &Row,
     table: &mut FluxTableMetadata,
     data_type_annotation_found: &mut bool,
 ) -> Result<()> {
@@ -326,7 +999,7 @@ fn process_datatype_annotation(
 }
 
 /// Process #group annotation row.
-fn process_group_annotation(row: &StringRecord, table: &mut FluxTableMetadata) {
+fn process_group_annotation(row: &Row, table: &mut FluxTableMetadata) {
     for i in 1..row.len() {
         if let Some(value) = row.get(i) {
             table.columns[i - 1].group = value == "true";
@@ -335,7 +1008,7 @@ fn process_group_annotation(row: &StringRecord, table: &mut FluxTableMetadata) {
 }
 
 /// Process #default annotation row.
-fn process_default_annotation(row: &StringRecord, table: &mut FluxTableMetadata) {
+fn process_default_annotation(row: &Row, table: &mut FluxTableMetadata) {
     for i in 1..row.len() {
         if let Some(value) = row.get(i) {
             table.columns[i - 1].default_value = value.to_string();
@@ -699,6 +1372,233 @@ mod tests {
         assert!(parser.next().await.unwrap().is_none());
     }
 
+    #[tokio::test]
+    async fn test_parser_rows_across_reads() {
+        let csv = "#datatype,string,string\n,name,note\n, zoë ,\"a, \"\"b\"\"\"\n";
+        let mut parser = AnnotatedCsvParser::with_capacity(Cursor::new(csv.as_bytes().to_vec()), 1);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_string("name"), Some("zoë".to_string()));
+        assert_eq!(record.get_string("note"), Some("a, \"b\"".to_string()));
+        assert!(parser.next().await.unwrap().is_none());
+
+        let invalid = b"#datatype,string\n,name\n,\xff\n".to_vec();
+        let mut parser = AnnotatedCsvParser::new(Cursor::new(invalid));
+        assert!(matches!(parser.next().await, Err(Error::Csv(_))));
+    }
+
+    const CSV_WITH_NULLS: &str = r#"#datatype,string,long,double
+#group,false,false,false
+#default,,,
+,name,count,value
+,alice,,1.5
+,bob,20,
+"#;
+
+    #[tokio::test]
+    async fn test_parser_dialect() {
+        let csv = "#datatype\tstring\tlong\tdouble\n\t_result\t0\t1.5\n\t_result\t0\t2\n";
+        let mut parser = parser_from_str(csv).delimiter(b'\t').header(false);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_str("0"), Some("_result"));
+        assert_eq!(record.get_double("2"), Some(1.5));
+        assert_eq!(
+            parser.next().await.unwrap().unwrap().get_double("2"),
+            Some(2.0)
+        );
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parser_unannotated() {
+        let csv = ",result,table,_time,host\n\
+                   ,_result,0,2023-11-14T12:00:00Z,a\n\
+                   ,_result,1,2023-11-14T12:00:01Z,b\n\
+                   \n\
+                   ,result,table,_value\n\
+                   ,_result,2,1.5\n";
+        let mut parser = parser_from_str(csv).annotations(false);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.table, 0);
+        assert_eq!(record.get_long("table"), Some(0));
+        assert_eq!(record.time().unwrap().timestamp(), 1_699_963_200);
+        assert_eq!(record.get_str("host"), Some("a"));
+        parser.next().await.unwrap().unwrap();
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.table, 1);
+        assert_eq!(record.get_str("_value"), Some("1.5"));
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parser_unannotated_error() {
+        let csv = ",error,reference\n,bad query,897\n";
+        let mut parser = parser_from_str(csv).annotations(false);
+
+        match parser.next().await {
+            Err(Error::QueryError { message, reference }) => {
+                assert_eq!(message, "bad query");
+                assert_eq!(reference.as_deref(), Some("897"));
+            }
+            other => panic!("unexpected result: {:?}", other.map(|r| r.is_some())),
+        }
+    }
+
+    #[test]
+    fn test_rfc3339_nanos() {
+        for s in [
+            "2023-11-14T12:30:45Z",
+            "2023-11-14T12:30:45.123456789Z",
+            "2023-11-14T12:30:45.5+09:00",
+            "1969-12-31T23:59:59.999-01:30",
+            "2024-02-29T00:00:00Z",
+        ] {
+            let expected = DateTime::parse_from_rfc3339(s)
+                .unwrap()
+                .timestamp_nanos_opt()
+                .unwrap();
+            assert_eq!(rfc3339_nanos(s), Some(expected), "{}", s);
+        }
+        assert_eq!(rfc3339_nanos("2023-11-14T12:30:45"), None);
+        assert_eq!(rfc3339_nanos("2023-13-14T12:30:45Z"), None);
+        assert_eq!(rfc3339_nanos("2023-02-29T00:00:00Z"), None);
+        assert_eq!(rfc3339_nanos("1900-02-29T00:00:00Z"), None);
+        assert_eq!(rfc3339_nanos("2023-04-31T00:00:00Z"), None);
+        assert!(rfc3339_nanos("2000-02-29T00:00:00Z").is_some());
+        assert_eq!(rfc3339_nanos("2023/11/14 12:30:45Z"), None);
+    }
+
+    #[tokio::test]
+    async fn test_parser_raw_times() {
+        let csv = "#datatype,string,long,dateTime:RFC3339,duration\n\
+                   #group,false,false,false,false\n\
+                   #default,_result,,,\n\
+                   ,result,table,_time,elapsed\n\
+                   ,,0,2023-11-14T12:00:00.5Z,1m30s\n\
+                   ,,0,,\n";
+        let mut parser = parser_from_str(csv).raw_times(true);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(
+            record.get("_time"),
+            Some(&Value::Long(1_699_963_200_500_000_000))
+        );
+        assert_eq!(record.time_nanos(), Some(1_699_963_200_500_000_000));
+        assert!(record.time().is_none());
+        assert_eq!(record.get_long("elapsed"), Some(90_000_000_000));
+        let table = parser.table.as_ref().unwrap();
+        assert_eq!(table.column("_time").unwrap().data_type, DataType::Long);
+        assert_eq!(table.column("elapsed").unwrap().data_type, DataType::Long);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get("_time"), Some(&Value::Null));
+    }
+
+    #[tokio::test]
+    async fn test_skip_bad_tables() {
+        let csv = "#datatype,string,long,double\n\
+                   #group,false,false,false\n\
+                   #default,_result,,\n\
+                   ,result,table,_value\n\
+                   ,,0,1.5\n\
+                   ,,0,oops\n\
+                   ,,0,2.5\n\
+                   \n\
+                   #datatype,string,long,bogus\n\
+                   #group,false,false,false\n\
+                   #default,_result,,\n\
+                   ,result,table,_value\n\
+                   ,,1,1\n\
+                   \n\
+                   #datatype,string,long,long\n\
+                   #group,false,false,false\n\
+                   #default,_result,,\n\
+                   ,result,table,_value\n\
+                   ,,2,7\n";
+        let mut parser = parser_from_str(csv).skip_bad_tables(true);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_double("_value"), Some(1.5));
+        match parser.next().await {
+            Err(Error::TableSkipped { table, source }) => {
+                assert_eq!(table, 0);
+                assert!(matches!(*source, Error::Parse { .. }));
+            }
+            other => panic!("unexpected result: {:?}", other.map(|r| r.is_some())),
+        }
+        assert!(matches!(
+            parser.next().await,
+            Err(Error::TableSkipped { table: 1, .. })
+        ));
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_long("_value"), Some(7));
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_skip_bad_tables_in_one_block() {
+        let csv = "#datatype,string,long,double\n\
+                   #group,false,true,false\n\
+                   #default,_result,,\n\
+                   ,result,table,_value\n\
+                   ,,3,oops\n\
+                   ,,3,1.5\n\
+                   ,,4,2.5\n\
+                   \n";
+        let mut parser = parser_from_str(csv).skip_bad_tables(true);
+
+        assert!(matches!(
+            parser.next().await,
+            Err(Error::TableSkipped { table: 3, .. })
+        ));
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_long("table"), Some(4));
+        assert_eq!(record.get_double("_value"), Some(2.5));
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_null_policy_skip() {
+        let mut parser = parser_from_str(CSV_WITH_NULLS).null_policy(NullPolicy::Skip);
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.columns().collect::<Vec<_>>(), ["name", "value"]);
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.columns().collect::<Vec<_>>(), ["name", "count"]);
+        assert_eq!(record.get_long("count"), Some(20));
+    }
+
+    #[tokio::test]
+    async fn test_null_policy_substitute() {
+        let mut parser =
+            parser_from_str(CSV_WITH_NULLS).null_policy(NullPolicy::Substitute(Value::Long(0)));
+
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_long("count"), Some(0));
+        match parser.next().await {
+            Err(Error::Parse { message }) => assert_eq!(
+                message,
+                "Substitute for null in double column 'value' is not a double value"
+            ),
+            other => panic!("unexpected result: {:?}", other.map(|r| r.is_some())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_null_policy_error() {
+        let mut parser = parser_from_str(CSV_WITH_NULLS).null_policy(NullPolicy::Error);
+
+        match parser.next().await {
+            Err(Error::Parse { message }) => {
+                assert_eq!(message, "Empty value for column 'count'")
+            }
+            other => panic!("unexpected result: {:?}", other.map(|r| r.is_some())),
+        }
+    }
+
     #[tokio::test]
     async fn test_parser_empty_input() {
         let csv = "";
@@ -708,6 +1608,69 @@ mod tests {
         assert!(parser.next().await.unwrap().is_none());
     }
 
+    #[tokio::test]
+    async fn test_parser_truncated_row() {
+        let csv = "#datatype,string,long,double\n#group,false,false,false\n#default,_result,,\n\
+            ,result,table,_value\n,,0,1.5\n,,0,2";
+        let mut parser = parser_from_str(csv).terminated(true);
+
+        assert!(parser.next().await.unwrap().is_some());
+        match parser.next().await {
+            Err(Error::Truncated { bytes, records }) => {
+                assert_eq!(bytes, csv.len() as u64);
+                assert_eq!(records, 1);
+            }
+            other => panic!("expected Truncated, got {other:?}"),
+        }
+
+        // Files need not end with a line break.
+        let mut parser = parser_from_str(csv);
+        assert_eq!(
+            parser.next_into(&mut FluxRecord::new(0)).await.ok(),
+            Some(true)
+        );
+        let record = parser.next().await.unwrap().unwrap();
+        assert_eq!(record.get_double("_value"), Some(2.0));
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parser_truncated_between_rows() {
+        let csv = "#datatype,string,long,double\n#group,false,false,false\n#default,_result,,\n\
+            ,result,table,_value\n,,0,1.5\n";
+        let mut parser = parser_from_str(csv).terminated(true);
+        assert!(parser.next().await.unwrap().is_some());
+        assert!(matches!(
+            parser.next().await,
+            Err(Error::Truncated { records: 1, .. })
+        ));
+
+        let complete = format!("{}\r\n", csv.replace('\n', "\r\n"));
+        let mut parser = parser_from_str(&complete).terminated(true);
+        assert!(parser.next().await.unwrap().is_some());
+        assert!(parser.next().await.unwrap().is_none());
+
+        // Responses without tables have nothing to terminate.
+        assert!(
+            parser_from_str("")
+                .terminated(true)
+                .next()
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parser_truncated_annotations() {
+        let csv = "#datatype,string,long,double\n#group,false,false,false\n";
+        let mut parser = parser_from_str(csv);
+        assert!(matches!(
+            parser.next().await,
+            Err(Error::Truncated { records: 0, .. })
+        ));
+    }
+
     #[tokio::test]
     async fn test_parser_empty_result_set() {
         // Only annotations, no data rows
@@ -820,6 +1783,71 @@ mod tests {
         assert!(parser.next().await.unwrap().is_none());
     }
 
+    #[tokio::test]
+    async fn test_parser_shares_schema_within_table() {
+        let csv = r#"#datatype,string,long
+#group,false,false
+#default,,
+,name,value
+,alice,10
+,bob,20
+
+#datatype,string,long
+#group,false,false
+#default,,
+,name,value
+,carol,30
+"#;
+        let mut parser = parser_from_str(csv);
+
+        let alice = parser.next().await.unwrap().unwrap();
+        let bob = parser.next().await.unwrap().unwrap();
+        let carol = parser.next().await.unwrap().unwrap();
+
+        assert!(Arc::ptr_eq(alice.schema(), bob.schema()));
+        assert!(!Arc::ptr_eq(bob.schema(), carol.schema()));
+        assert_eq!(alice.columns().collect::<Vec<_>>(), vec!["name", "value"]);
+    }
+
+    #[tokio::test]
+    async fn test_parser_next_into_reuses_record() {
+        let csv = r#"#datatype,string,long
+#group,false,false
+#default,,
+,name,value
+,alice,10
+,bob,20
+,carol,x
+"#;
+        let mut parser = parser_from_str(csv);
+        let mut record = FluxRecord::new(7);
+
+        assert!(parser.next_into(&mut record).await.unwrap());
+        assert_eq!(record.table, 0);
+        assert_eq!(record.get_string("name"), Some("alice".to_string()));
+        let buffer = record
+            .get("name")
+            .and_then(|v| v.as_string())
+            .unwrap()
+            .as_ptr();
+
+        assert!(parser.next_into(&mut record).await.unwrap());
+        assert_eq!(record.get_string("name"), Some("bob".to_string()));
+        assert_eq!(record.get_long("value"), Some(20));
+        // The string buffer of the previous row is reused.
+        assert_eq!(
+            record
+                .get("name")
+                .and_then(|v| v.as_string())
+                .unwrap()
+                .as_ptr(),
+            buffer
+        );
+
+        assert!(parser.next_into(&mut record).await.is_err());
+        assert!(record.is_empty());
+    }
+
     #[tokio::test]
     async fn test_parser_default_values() {
         let csv = r#"#datatype,string,long,double
@@ -867,12 +1895,12 @@ mod tests {
         assert_eq!(record.get_string("str"), Some("hello".to_string()));
         assert_eq!(record.get_long("lng"), Some(-42));
         assert_eq!(
-            record.values.get("ulng").and_then(|v| v.as_unsigned_long()),
+            record.get("ulng").and_then(|v| v.as_unsigned_long()),
             Some(u64::MAX)
         );
         assert_eq!(record.get_double("dbl"), Some(2.72));
         assert_eq!(record.get_bool("bl"), Some(true));
-        assert!(record.values.get("ts").and_then(|v| v.as_time()).is_some());
+        assert!(record.get("ts").and_then(|v| v.as_time()).is_some());
     }
 
     #[tokio::test]
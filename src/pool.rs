@@ -0,0 +1,242 @@
+//! Shared clients for many tenants.
+//!
+//! Services querying on behalf of many tenants would otherwise build one
+//! [`Client`] (and one HTTP connection pool) per tenant. A [`ClientPool`]
+//! hands out clients that all share a single [`Transport`], and caches
+//! organization IDs so they are looked up once per server and organization.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::pool::ClientPool;
+//!
+//! let pool = ClientPool::new();
+//! let client = pool.client(&tenant.url, &tenant.org, &tenant.token)?;
+//! let org_id = pool.org_id(&client).await?;
+//! let stream = client.query_stream(query).await?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::transport::{Transport, default_transport};
+
+/// Identifies one tenant's client.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TenantKey {
+    url: String,
+    org: String,
+    token: String,
+}
+
+#[derive(Default)]
+struct PoolState {
+    clients: HashMap<TenantKey, Client>,
+    org_ids: HashMap<(String, String), String>,
+}
+
+/// Hands out clients keyed by URL, organization and token, sharing one transport.
+///
+/// Cloning the pool is cheap; clones share their clients and caches.
+#[derive(Clone)]
+pub struct ClientPool {
+    transport: Arc<dyn Transport>,
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl ClientPool {
+    /// Create a pool using the default transport.
+    pub fn new() -> Self {
+        Self {
+            transport: default_transport(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Create a pool whose clients send requests through `transport`.
+    pub fn with_transport(transport: impl Transport) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            state: Arc::default(),
+        }
+    }
+
+    /// Get the client for a tenant, creating it on first use.
+    ///
+    /// Fails with [`Error::Config`](crate::Error::Config) if `url` is invalid.
+    pub fn client(&self, url: &str, org: &str, token: &str) -> Result<Client> {
+        let key = TenantKey {
+            url: url.to_string(),
+            org: org.to_string(),
+            token: token.to_string(),
+        };
+        let mut state = self.state.lock().expect("client pool lock poisoned");
+        if let Some(client) = state.clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = Client::from_parts(
+            self.transport.clone(),
+            url,
+            org.to_string(),
+            token.to_string(),
+        )?;
+        state.clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Get the organization ID of `client`, fetching it on first use.
+    ///
+    /// IDs are cached per server URL and organization name, so tenants with
+    /// different tokens for the same organization share one lookup.
+    pub async fn org_id(&self, client: &Client) -> Result<String> {
+        let key = (client.url().to_string(), client.org().to_string());
+        if let Some(id) = self
+            .state
+            .lock()
+            .expect("client pool lock poisoned")
+            .org_ids
+            .get(&key)
+        {
+            return Ok(id.clone());
+        }
+
+        let id = client.org_id().await?;
+        self.state
+            .lock()
+            .expect("client pool lock poisoned")
+            .org_ids
+            .insert(key, id.clone());
+        Ok(id)
+    }
+
+    /// Remove a tenant's client and cached organization ID.
+    ///
+    /// Returns true if the client was in the pool.
+    pub fn remove(&self, url: &str, org: &str, token: &str) -> bool {
+        let key = TenantKey {
+            url: url.to_string(),
+            org: org.to_string(),
+            token: token.to_string(),
+        };
+        let mut state = self.state.lock().expect("client pool lock poisoned");
+        let removed = state.clients.remove(&key);
+        if let Some(client) = &removed {
+            state
+                .org_ids
+                .remove(&(client.url().to_string(), org.to_string()));
+        }
+        removed.is_some()
+    }
+
+    /// Get the number of clients in the pool.
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .expect("client pool lock poisoned")
+            .clients
+            .len()
+    }
+
+    /// Returns true if the pool has no clients.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ClientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ClientPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().expect("client pool lock poisoned");
+        f.debug_struct("ClientPool")
+            .field("clients", &state.clients.len())
+            .field("org_ids", &state.org_ids.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::transport::{TransportRequest, TransportResponse};
+    use bytes::Bytes;
+    use futures::future::BoxFuture;
+    use futures::stream;
+    use http::StatusCode;
+    use http::header::HeaderMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answer organization lookups and count requests.
+    #[derive(Default)]
+    struct OrgTransport {
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl Transport for OrgTransport {
+        fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let org = request
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == "org")
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+            let body = format!(r#"{{"orgs":[{{"id":"id-{}","name":"{}"}}]}}"#, org, org);
+            Box::pin(futures::future::ready(Ok(TransportResponse {
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Box::pin(stream::iter([Ok(Bytes::from(body))])),
+            })))
+        }
+    }
+
+    #[test]
+    fn test_client_reused_per_tenant() {
+        let pool = ClientPool::with_transport(OrgTransport::default());
+        let a = pool.client("http://influx:8086", "acme", "t1").unwrap();
+        let again = pool.client("http://influx:8086", "acme", "t1").unwrap();
+        let b = pool.client("http://influx:8086", "acme", "t2").unwrap();
+
+        assert_eq!(a.org(), again.org());
+        assert_eq!(b.org(), "acme");
+        assert_eq!(pool.len(), 2);
+
+        assert!(pool.remove("http://influx:8086", "acme", "t1"));
+        assert!(!pool.remove("http://influx:8086", "acme", "t1"));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_url_is_error() {
+        let pool = ClientPool::new();
+        assert!(matches!(
+            pool.client("not a url", "acme", "t"),
+            Err(Error::Config(_))
+        ));
+        assert!(pool.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_org_id_cached() {
+        let transport = OrgTransport::default();
+        let requests = transport.requests.clone();
+        let pool = ClientPool::with_transport(transport);
+
+        let t1 = pool.client("http://influx:8086", "acme", "t1").unwrap();
+        let t2 = pool.client("http://influx:8086", "acme", "t2").unwrap();
+        let other = pool.client("http://influx:8086", "globex", "t3").unwrap();
+
+        assert_eq!(pool.org_id(&t1).await.unwrap(), "id-acme");
+        assert_eq!(pool.org_id(&t2).await.unwrap(), "id-acme");
+        assert_eq!(pool.org_id(&other).await.unwrap(), "id-globex");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
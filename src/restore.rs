@@ -0,0 +1,266 @@
+//! Restoring native backups through the `/api/v2/restore` endpoints.
+//!
+//! [`Client::restore`](crate::Client::restore) reads the newest
+//! [`BackupManifest`] in a directory written by
+//! [`Client::backup`](crate::Client::backup) and uploads its files, each
+//! streamed from disk:
+//!
+//! - A full restore replaces the server's KV and SQL stores with the
+//!   snapshots, then uploads every shard under its original ID. Use it to
+//!   rebuild a server from scratch.
+//! - With [`RestoreOptions::bucket`], only the named buckets are restored.
+//!   They are created from their backed-up metadata, which must not clash
+//!   with existing buckets, and their shards are uploaded under the IDs the
+//!   server assigns.
+//!
+//! Progress is recorded next to the manifest in `<prefix>.restore`. When a
+//! restore is interrupted, running it again on the same directory skips the
+//! steps and shards that already completed. The operator token issued by a
+//! full restore is kept apart in `<prefix>.restore-token`, readable only by
+//! its owner on Unix, until the restore completes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::backup::BackupManifest;
+use crate::error::Result;
+use crate::transport::ByteStream;
+
+/// Default for [`RestoreOptions::max_retries`].
+pub const DEFAULT_RESTORE_RETRIES: u32 = 3;
+
+/// Options for [`Client::restore_opts`](crate::Client::restore_opts).
+#[derive(Clone, Debug)]
+pub struct RestoreOptions {
+    buckets: Vec<String>,
+    max_retries: u32,
+}
+
+impl RestoreOptions {
+    /// Restore the whole server.
+    pub fn new() -> Self {
+        Self {
+            buckets: Vec::new(),
+            max_retries: DEFAULT_RESTORE_RETRIES,
+        }
+    }
+
+    /// Only restore bucket `name`; may be repeated.
+    ///
+    /// The KV and SQL snapshots are not restored in this mode.
+    pub fn bucket(mut self, name: impl Into<String>) -> Self {
+        self.buckets.push(name.into());
+        self
+    }
+
+    /// Upload a shard again up to `n` times after a retryable error (default:
+    /// [`DEFAULT_RESTORE_RETRIES`]).
+    ///
+    /// Retries wait as set by the client's
+    /// [`RetryPolicy`](crate::retry::RetryPolicy), or its defaults, including
+    /// the server's `Retry-After`.
+    pub fn max_retries(mut self, n: u32) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    pub(crate) fn retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub(crate) fn selected(&self, bucket: &serde_json::Value) -> bool {
+        self.is_full()
+            || bucket["bucketName"]
+                .as_str()
+                .is_some_and(|name| self.buckets.iter().any(|b| b == name))
+    }
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of [`Client::restore`](crate::Client::restore).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestoreSummary {
+    /// Operator token of the restored KV store, if the server issued one.
+    ///
+    /// The token the client was created with may no longer exist after a
+    /// full restore; use this one instead.
+    pub token: Option<String>,
+    /// Number of shards uploaded by this call.
+    pub shards: usize,
+    /// Number of shards already uploaded by an earlier, interrupted call.
+    pub resumed: usize,
+}
+
+/// Completed steps of a restore, saved after each step.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct RestoreState {
+    #[serde(skip)]
+    path: PathBuf,
+    pub(crate) kv: bool,
+    pub(crate) sql: bool,
+    /// Operator token of the restored KV store, saved by
+    /// [`save_token`](Self::save_token).
+    #[serde(skip)]
+    pub(crate) token: Option<String>,
+    /// Old to new shard IDs, by bucket ID, for buckets already created.
+    pub(crate) buckets: BTreeMap<String, BTreeMap<u64, u64>>,
+    /// Old IDs of the shards already uploaded.
+    pub(crate) shards: BTreeSet<u64>,
+}
+
+impl RestoreState {
+    /// Load the state saved for the manifest at `manifest`, if any.
+    pub(crate) async fn load(manifest: &Path) -> Result<Self> {
+        let path = manifest.with_extension("restore");
+        let mut state = match tokio::fs::read(&path).await {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+        state.path = path;
+        state.token = match tokio::fs::read_to_string(state.token_path()).await {
+            Ok(token) => Some(token),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(state)
+    }
+
+    fn token_path(&self) -> PathBuf {
+        self.path.with_extension("restore-token")
+    }
+
+    pub(crate) async fn save(&self) -> Result<()> {
+        // Replace the file atomically, so an interruption never leaves a
+        // truncated state behind.
+        let tmp = self.path.with_extension("restore.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(self)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+
+    /// Save the token, so that a resumed restore can still authenticate.
+    pub(crate) async fn save_token(&self) -> Result<()> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let tmp = self.path.with_extension("restore-token.tmp");
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&tmp).await?;
+        file.write_all(token.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, self.token_path()).await?;
+        Ok(())
+    }
+
+    /// Remove the saved token once the restore is complete.
+    pub(crate) async fn finish(&self) -> Result<()> {
+        match tokio::fs::remove_file(self.token_path()).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Find and read the newest manifest in `dir`.
+pub(crate) async fn latest_manifest(dir: &Path) -> Result<(PathBuf, BackupManifest)> {
+    let mut latest: Option<PathBuf> = None;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        // Prefixes are UTC timestamps, so names sort by backup time.
+        if path.extension().is_some_and(|x| x == "manifest")
+            && latest.as_ref().is_none_or(|l| path > *l)
+        {
+            latest = Some(path);
+        }
+    }
+    let path = latest.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No backup manifest in {}", dir.display()),
+        )
+    })?;
+    let manifest = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+    Ok((path, manifest))
+}
+
+/// Open `path` as a request body.
+pub(crate) async fn file_body(path: &Path) -> Result<ByteStream> {
+    let file = tokio::fs::File::open(path).await?;
+    Ok(Box::pin(ReaderStream::new(file)))
+}
+
+/// Read the old-to-new shard ID mapping from a `bucketMetadata` response.
+pub(crate) fn shard_mappings(response: &serde_json::Value) -> BTreeMap<u64, u64> {
+    response["shardMappings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| Some((m["oldId"].as_u64()?, m["newId"].as_u64()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_state_round_trip() {
+        let dir =
+            std::env::temp_dir().join(format!("influxdb-stream-restore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("20240101T000000Z.manifest");
+
+        let mut state = RestoreState::load(&manifest).await.unwrap();
+        assert!(!state.kv && state.shards.is_empty());
+        state.kv = true;
+        state.shards.insert(7);
+        state.save().await.unwrap();
+
+        let state = RestoreState::load(&manifest).await.unwrap();
+        assert!(state.kv);
+        assert!(state.shards.contains(&7));
+        assert!(dir.join("20240101T000000Z.restore").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_shard_mappings() {
+        let response = json!({
+            "id": "b2",
+            "shardMappings": [{"oldId": 1, "newId": 10}, {"oldId": 2, "newId": 11}],
+        });
+        let mappings = shard_mappings(&response);
+        assert_eq!(mappings.get(&1), Some(&10));
+        assert_eq!(mappings.len(), 2);
+    }
+
+    #[test]
+    fn test_bucket_selection() {
+        let bucket = json!({"bucketName": "sensors"});
+        assert!(RestoreOptions::new().selected(&bucket));
+        assert!(RestoreOptions::new().bucket("sensors").selected(&bucket));
+        assert!(!RestoreOptions::new().bucket("other").selected(&bucket));
+    }
+}
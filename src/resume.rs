@@ -0,0 +1,246 @@
+//! Automatic resumption of interrupted query streams.
+//!
+//! Long exports over unreliable links can fail part-way through. The adapter in
+//! this module re-issues the query starting just after the last `_time` that was
+//! yielded, so the consumer sees one continuous stream instead of starting over.
+//!
+//! Where the new query starts is chosen with [`ResumeFrom`]: just after the
+//! last `_time`, which never repeats a record but can miss records sharing
+//! that time, or at the last `_time`, which never misses one but repeats
+//! some.
+
+use std::future::Future;
+
+use async_stream::stream;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::{Stream, StreamExt};
+
+use crate::error::Result;
+use crate::types::FluxRecord;
+
+/// Where a resumed query starts, relative to the last `_time` yielded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResumeFrom {
+    /// One nanosecond after the last `_time`.
+    ///
+    /// No record is yielded twice, but records with the same `_time` as the
+    /// last one that had not arrived before the failure, such as those of
+    /// other series after `|> group()`, are lost.
+    #[default]
+    AfterLast,
+    /// At the last `_time`.
+    ///
+    /// No record is lost, but delivery is at-least-once: the records with
+    /// the last `_time` that were yielded before the failure are yielded
+    /// again. Consumers should deduplicate, or write to a sink where
+    /// rewriting a point is harmless, such as InfluxDB itself.
+    Last,
+}
+
+/// Resume a record stream after retryable failures.
+///
+/// Same as [`resume_from`] with [`ResumeFrom::AfterLast`].
+pub fn resume<F, Fut, S>(connect: F, max_retries: u32) -> impl Stream<Item = Result<FluxRecord>>
+where
+    F: FnMut(Option<DateTime<FixedOffset>>) -> Fut,
+    Fut: Future<Output = Result<S>>,
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    resume_from(connect, max_retries, ResumeFrom::AfterLast)
+}
+
+/// Resume a record stream after retryable failures, restarting at `from`.
+///
+/// `connect` is called with `None` for the first attempt and with the time to
+/// resume from (the last seen `_time`, plus one nanosecond with
+/// [`ResumeFrom::AfterLast`]) on each retry. It
+/// should return a stream for the query restricted to `range(start: ...)`.
+/// If no record has been yielded yet, retries are issued with `None` again.
+///
+/// At most `max_retries` retries are attempted over the lifetime of the stream.
+/// Once the budget is exhausted, or an error that is not
+/// [retryable](crate::Error::is_retryable) occurs, the error is yielded and the
+/// stream ends.
+///
+/// Resumption relies on records arriving in ascending `_time` order. Queries
+/// returning several tables should be regrouped and sorted (for example with
+/// `|> group() |> sort(columns: ["_time"])`), otherwise records from later
+/// tables may be skipped.
+pub fn resume_from<F, Fut, S>(
+    mut connect: F,
+    max_retries: u32,
+    from: ResumeFrom,
+) -> impl Stream<Item = Result<FluxRecord>>
+where
+    F: FnMut(Option<DateTime<FixedOffset>>) -> Fut,
+    Fut: Future<Output = Result<S>>,
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    let offset = match from {
+        ResumeFrom::AfterLast => TimeDelta::nanoseconds(1),
+        ResumeFrom::Last => TimeDelta::zero(),
+    };
+    stream! {
+        let mut retries = 0;
+        let mut resume_from = None;
+
+        loop {
+            let error = match connect(resume_from).await {
+                Ok(inner) => {
+                    let mut inner = std::pin::pin!(inner);
+                    let mut failure = None;
+
+                    while let Some(item) = inner.next().await {
+                        match item {
+                            Ok(record) => {
+                                if let Some(t) = record.time() {
+                                    resume_from = Some(*t + offset);
+                                }
+                                yield Ok(record);
+                            }
+                            Err(e) => {
+                                failure = Some(e);
+                                break;
+                            }
+                        }
+                    }
+
+                    match failure {
+                        Some(e) => e,
+                        None => break, // Completed without errors
+                    }
+                }
+                Err(e) => e,
+            };
+
+            if !error.is_retryable() || retries >= max_retries {
+                yield Err(error);
+                break;
+            }
+            retries += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::value::Value;
+    use futures::stream;
+    use std::sync::{Arc, Mutex};
+
+    fn record_at(ts: &str) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339(ts).unwrap()),
+        );
+        record
+    }
+
+    fn reset() -> Error {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset",
+        ))
+    }
+
+    type Attempt =
+        futures::future::Ready<Result<stream::Iter<std::vec::IntoIter<Result<FluxRecord>>>>>;
+    type Starts = Arc<Mutex<Vec<Option<DateTime<FixedOffset>>>>>;
+
+    /// Build a `connect` closure that serves the given attempts in order and
+    /// records the start time passed to each call.
+    fn scripted(
+        attempts: Vec<Vec<Result<FluxRecord>>>,
+    ) -> (impl FnMut(Option<DateTime<FixedOffset>>) -> Attempt, Starts) {
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let seen = starts.clone();
+        let mut attempts = attempts.into_iter();
+        let connect = move |start| {
+            seen.lock().unwrap().push(start);
+            let items = attempts.next().unwrap_or_default();
+            futures::future::ready(Ok(stream::iter(items)))
+        };
+        (connect, starts)
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_after_retryable_error() {
+        let (connect, starts) = scripted(vec![
+            vec![
+                Ok(record_at("2023-11-14T12:00:00Z")),
+                Ok(record_at("2023-11-14T12:00:01Z")),
+                Err(reset()),
+            ],
+            vec![Ok(record_at("2023-11-14T12:00:02Z"))],
+        ]);
+
+        let items: Vec<_> = resume(connect, 3).collect().await;
+        assert_eq!(items.len(), 3);
+        assert!(items.iter().all(|r| r.is_ok()));
+
+        let starts = starts.lock().unwrap();
+        assert_eq!(starts.len(), 2);
+        assert!(starts[0].is_none());
+        let expected = DateTime::parse_from_rfc3339("2023-11-14T12:00:01.000000001Z").unwrap();
+        assert_eq!(starts[1], Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_last_repeats_last_time() {
+        let (connect, starts) = scripted(vec![
+            vec![Ok(record_at("2023-11-14T12:00:01Z")), Err(reset())],
+            vec![
+                Ok(record_at("2023-11-14T12:00:01Z")),
+                Ok(record_at("2023-11-14T12:00:02Z")),
+            ],
+        ]);
+
+        let items: Vec<_> = resume_from(connect, 3, ResumeFrom::Last).collect().await;
+        assert_eq!(items.len(), 3);
+        let expected = DateTime::parse_from_rfc3339("2023-11-14T12:00:01Z").unwrap();
+        assert_eq!(starts.lock().unwrap()[1], Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_resume_retries_before_first_record() {
+        let (connect, starts) = scripted(vec![
+            vec![Err(reset())],
+            vec![Ok(record_at("2023-11-14T12:00:00Z"))],
+        ]);
+
+        let items: Vec<_> = resume(connect, 1).collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_ok());
+        assert_eq!(*starts.lock().unwrap(), vec![None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_non_retryable_error_ends_stream() {
+        let (connect, starts) = scripted(vec![vec![
+            Ok(record_at("2023-11-14T12:00:00Z")),
+            Err(Error::Csv("bad row".to_string())),
+        ]]);
+
+        let items: Vec<_> = resume(connect, 3).collect().await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[1], Err(Error::Csv(_))));
+        assert_eq!(starts.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resume_budget_exhausted() {
+        let (connect, starts) = scripted(vec![
+            vec![Err(reset())],
+            vec![Err(reset())],
+            vec![Err(reset())],
+        ]);
+
+        let items: Vec<_> = resume(connect, 2).collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(matches!(items[0], Err(Error::Io(_))));
+        assert_eq!(starts.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,225 @@
+//! Automatic retries of failed requests.
+//!
+//! With a [`RetryPolicy`] set through
+//! [`ClientBuilder::retry_policy`](crate::ClientBuilder::retry_policy), a
+//! client re-sends requests that fail with a
+//! [retryable](crate::Error::is_retryable) error, such as a refused
+//! connection, a DNS failure or a `502`/`503` response, waiting longer before
+//! each attempt. When the server asks for a delay with `Retry-After`, as
+//! InfluxDB Cloud does when an organization exceeds its rate limits, the
+//! client waits that long instead, up to a limit.
+//!
+//! By default only queries and other requests that read are retried; see
+//! [`RetryScope`]. Retries only happen before a response is accepted: once a
+//! query has yielded records, a failure mid-stream is reported as usual. See
+//! [`Client::query_stream_resumable`](crate::Client::query_stream_resumable)
+//! to pick such a query up where it stopped.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//! use influxdb_stream::Client;
+//! use influxdb_stream::retry::RetryPolicy;
+//!
+//! let client = Client::builder("http://localhost:8086", "my-org", "my-token")
+//!     .retry_policy(
+//!         RetryPolicy::new()
+//!             .max_attempts(5)
+//!             .backoff(Duration::from_millis(200), Duration::from_secs(5)),
+//!     )
+//!     .build()?;
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Default for [`RetryPolicy::max_attempts`].
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Default initial delay of [`RetryPolicy::backoff`].
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Default maximum delay of [`RetryPolicy::backoff`].
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Default for [`RetryPolicy::max_retry_after`].
+pub const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Requests a [`RetryPolicy`] applies to, set with [`RetryPolicy::scope`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetryScope {
+    /// Queries and other requests that only read, such as `GET` requests.
+    ///
+    /// Flux queries that write with `to()` are queries too: use a client
+    /// without a retry policy for them.
+    #[default]
+    Reads,
+    /// Every request, including writes, deletes and restores.
+    ///
+    /// A write whose response was lost is sent again; InfluxDB stores
+    /// identical points once.
+    All,
+}
+
+/// How often and how patiently a [`Client`](crate::Client) re-sends failed
+/// requests.
+///
+/// The delay starts at the initial backoff and doubles after every attempt,
+/// up to the maximum. With jitter, each delay is drawn at random between half
+/// and all of it, so that clients failing together do not retry in lockstep.
+///
+/// Requests with a streamed body, such as shard uploads of
+/// [`Client::restore`](crate::Client::restore), are sent once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retry_after: bool,
+    max_retry_after: Duration,
+    scope: RetryScope,
+}
+
+impl RetryPolicy {
+    /// Create a policy with the defaults: three attempts of requests that
+    /// read, waiting 100ms then 200ms, with jitter.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            jitter: true,
+            retry_after: true,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+            scope: RetryScope::default(),
+        }
+    }
+
+    /// Send a request at most `n` times in total, including the first
+    /// attempt (default: [`DEFAULT_MAX_ATTEMPTS`]). One disables retries.
+    pub fn max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = n.max(1);
+        self
+    }
+
+    /// Wait `initial` before the first retry, doubling up to `max` for the
+    /// following ones.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Randomize delays (default: true).
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Wait as long as the server's `Retry-After` header asks, instead of
+    /// the backoff delay (default: true).
+    ///
+    /// See [`Error::RateLimited`].
+    pub fn honor_retry_after(mut self, honor: bool) -> Self {
+        self.retry_after = honor;
+        self
+    }
+
+    /// Wait at most `max` when the server's `Retry-After` asks for longer
+    /// (default: [`DEFAULT_MAX_RETRY_AFTER`]).
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Retry the requests in `scope` (default: [`RetryScope::Reads`]).
+    pub fn scope(mut self, scope: RetryScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Get the number of attempts, including the first.
+    pub fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns true if requests are retried, given whether they only read.
+    pub(crate) fn covers(&self, read: bool) -> bool {
+        read || self.scope == RetryScope::All
+    }
+
+    /// Get the delay before retry number `retry` after `error`.
+    pub(crate) fn delay_after(&self, error: &Error, retry: u32) -> Duration {
+        match error.retry_after() {
+            Some(wait) if self.retry_after => wait.min(self.max_retry_after),
+            _ => self.delay(retry),
+        }
+    }
+
+    /// Get the delay before retry number `retry`, counted from zero.
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        if !self.jitter {
+            return delay;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = 0.5 + (random >> 11) as f64 / (1u64 << 53) as f64 / 2.0;
+        delay.mul_f64(fraction)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_doubles_up_to_max() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_millis(350))
+            .jitter(false);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(350));
+        assert_eq!(policy.delay(40), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_delay_after_retry_after() {
+        let limited = Error::RateLimited {
+            status: 429,
+            retry_after: Some(Duration::from_secs(30)),
+            message: "org exceeded its limit".to_string(),
+        };
+        let policy = RetryPolicy::new().jitter(false);
+        assert_eq!(policy.delay_after(&limited, 0), Duration::from_secs(30));
+        let capped = policy.clone().max_retry_after(Duration::from_secs(5));
+        assert_eq!(capped.delay_after(&limited, 0), Duration::from_secs(5));
+        let policy = policy.honor_retry_after(false);
+        assert_eq!(policy.delay_after(&limited, 0), DEFAULT_INITIAL_BACKOFF);
+    }
+
+    #[test]
+    fn test_delay_jitter_range() {
+        let policy = RetryPolicy::new().backoff(Duration::from_secs(1), Duration::from_secs(1));
+        for _ in 0..100 {
+            let delay = policy.delay(0);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Client-side scheduled queries.
+//!
+//! For environments where server-side tasks are not available, a
+//! [`Scheduler`] runs registered Flux queries periodically and streams their
+//! results to a [`JobSink`]. Each run produces a [`RunReport`], delivered to
+//! the callback set with [`Scheduler::on_report`].
+//!
+//! A job never overlaps with itself: if a run takes longer than the period,
+//! the ticks that passed in the meantime are skipped rather than queued.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::scheduler::{Job, JobSink, Schedule, Scheduler};
+//!
+//! let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+//! let handle = Scheduler::new(client)
+//!     .job(Job::new(
+//!         "cpu-5m",
+//!         Schedule::every(Duration::from_secs(300)),
+//!         |run| format!(
+//!             r#"from(bucket: "sensors") |> range(start: -5m, stop: {})"#,
+//!             run.scheduled.to_rfc3339(),
+//!         ),
+//!         JobSink::Channel(tx),
+//!     ))
+//!     .on_report(|report| {
+//!         if let Err(e) = &report.result {
+//!             eprintln!("{} failed: {}", report.job, e);
+//!         }
+//!     })
+//!     .start();
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use tokio::io::AsyncWrite;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::client::{Client, QueryClient};
+use crate::error::{Error, Result};
+use crate::sink::{CsvOptions, write_csv};
+use crate::types::FluxRecord;
+
+/// When a job runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    period: Duration,
+    offset: Duration,
+    aligned: bool,
+}
+
+impl Schedule {
+    /// Run at every multiple of `period` since the Unix epoch, like `every` in
+    /// an InfluxDB task.
+    ///
+    /// `Schedule::every(Duration::from_secs(3600))` runs at the top of every hour.
+    pub fn every(period: Duration) -> Self {
+        Self {
+            period: period.max(Duration::from_millis(1)),
+            offset: Duration::ZERO,
+            aligned: true,
+        }
+    }
+
+    /// Run immediately and then every `period`, without wall-clock alignment.
+    pub fn interval(period: Duration) -> Self {
+        Self {
+            aligned: false,
+            ..Self::every(period)
+        }
+    }
+
+    /// Delay every run by `offset`, for example to let late data arrive.
+    pub fn offset(mut self, offset: Duration) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Get the time until the first run, given the current wall-clock time.
+    fn first_delay(&self, now: DateTime<Utc>) -> Duration {
+        if !self.aligned {
+            return self.offset;
+        }
+        let period = self.period.as_nanos() as i128;
+        let now = now.timestamp_nanos_opt().unwrap_or_default() as i128;
+        let since_boundary = (now - self.offset.as_nanos() as i128).rem_euclid(period);
+        let wait = if since_boundary == 0 {
+            0
+        } else {
+            period - since_boundary
+        };
+        Duration::from_nanos(wait as u64)
+    }
+}
+
+/// Information about a run, passed to the job's query builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Run {
+    /// Wall-clock time the run was scheduled for, without the offset.
+    pub scheduled: DateTime<Utc>,
+    /// Scheduled time of the previous run of this job, if any.
+    pub previous: Option<DateTime<Utc>>,
+}
+
+/// Where the records of a job are sent.
+pub enum JobSink {
+    /// Call a function with every record.
+    Callback(Arc<dyn Fn(FluxRecord) + Send + Sync>),
+    /// Send every record to a channel. Runs wait for room in the channel.
+    Channel(mpsc::Sender<FluxRecord>),
+    /// Append every run's records to a writer as CSV.
+    Writer(Arc<Mutex<dyn AsyncWrite + Send + Unpin>>, CsvOptions),
+}
+
+impl std::fmt::Debug for JobSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobSink::Callback(_) => f.write_str("Callback"),
+            JobSink::Channel(_) => f.write_str("Channel"),
+            JobSink::Writer(_, options) => f.debug_tuple("Writer").field(options).finish(),
+        }
+    }
+}
+
+type QueryFn = Box<dyn Fn(&Run) -> String + Send + Sync>;
+
+/// A query registered with a [`Scheduler`].
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    query: QueryFn,
+    sink: JobSink,
+}
+
+impl Job {
+    /// Create a job that runs `query` on `schedule` and sends results to `sink`.
+    pub fn new<Q>(name: impl Into<String>, schedule: Schedule, query: Q, sink: JobSink) -> Self
+    where
+        Q: Fn(&Run) -> String + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule,
+            query: Box::new(query),
+            sink,
+        }
+    }
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Job")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .field("sink", &self.sink)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Outcome of one run of a job.
+#[derive(Debug)]
+pub struct RunReport {
+    /// Job name.
+    pub job: String,
+    /// The run that was executed.
+    pub run: Run,
+    /// Time taken by the run.
+    pub duration: Duration,
+    /// Number of records delivered to the sink.
+    pub rows: u64,
+    /// Whether the run completed; on error, records delivered before the
+    /// failure are included in `rows`.
+    pub result: Result<()>,
+}
+
+type ReportFn = Arc<dyn Fn(RunReport) + Send + Sync>;
+
+/// Runs [`Job`]s periodically.
+pub struct Scheduler<C = Client> {
+    client: C,
+    jobs: Vec<Job>,
+    on_report: Option<ReportFn>,
+}
+
+impl<C> Scheduler<C>
+where
+    C: QueryClient + Clone + 'static,
+{
+    /// Create a scheduler that runs queries with `client`.
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            jobs: Vec::new(),
+            on_report: None,
+        }
+    }
+
+    /// Register a job.
+    pub fn job(mut self, job: Job) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    /// Call `f` with the report of every run.
+    pub fn on_report<F>(mut self, f: F) -> Self
+    where
+        F: Fn(RunReport) + Send + Sync + 'static,
+    {
+        self.on_report = Some(Arc::new(f));
+        self
+    }
+
+    /// Start running jobs on spawned tasks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn start(self) -> SchedulerHandle {
+        let tasks = self
+            .jobs
+            .into_iter()
+            .map(|job| {
+                let client = self.client.clone();
+                let on_report = self.on_report.clone();
+                tokio::spawn(run_job(client, job, on_report))
+            })
+            .collect();
+        SchedulerHandle { tasks }
+    }
+}
+
+impl<C> std::fmt::Debug for Scheduler<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("jobs", &self.jobs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to a started [`Scheduler`]. Dropping it stops all jobs.
+#[derive(Debug)]
+pub struct SchedulerHandle {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SchedulerHandle {
+    /// Stop all jobs, cancelling runs in progress.
+    pub fn shutdown(self) {
+        drop(self);
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn run_job<C: QueryClient>(client: C, job: Job, on_report: Option<ReportFn>) {
+    let wall_start = Utc::now();
+    let delay = job.schedule.first_delay(wall_start);
+    let mut ticks = tokio::time::interval_at(Instant::now() + delay, job.schedule.period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let origin = Instant::now();
+    let mut previous = None;
+    loop {
+        let tick = ticks.tick().await;
+        // Derive the wall-clock time of the tick from the start of the job, so
+        // reports are stable even if the run itself starts late.
+        let elapsed = tick.saturating_duration_since(origin);
+        let scheduled = wall_start + chrono::Duration::from_std(elapsed).unwrap_or_default()
+            - chrono::Duration::from_std(job.schedule.offset).unwrap_or_default();
+        let run = Run {
+            scheduled,
+            previous,
+        };
+        previous = Some(scheduled);
+
+        let started = Instant::now();
+        let mut rows = 0;
+        let result = execute(&client, &job, &run, &mut rows).await;
+        if let Some(report) = &on_report {
+            report(RunReport {
+                job: job.name.clone(),
+                run,
+                duration: started.elapsed(),
+                rows,
+                result,
+            });
+        }
+    }
+}
+
+async fn execute<C: QueryClient>(client: &C, job: &Job, run: &Run, rows: &mut u64) -> Result<()> {
+    let mut stream = client.query_stream((job.query)(run)).await?;
+    match &job.sink {
+        JobSink::Callback(f) => {
+            while let Some(record) = stream.next().await {
+                f(record?);
+                *rows += 1;
+            }
+        }
+        JobSink::Channel(tx) => {
+            while let Some(record) = stream.next().await {
+                tx.send(record?).await.map_err(|_| {
+                    Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::BrokenPipe,
+                        "scheduler channel closed",
+                    ))
+                })?;
+                *rows += 1;
+            }
+        }
+        JobSink::Writer(writer, options) => {
+            let mut writer = writer.lock().await;
+            // Count rows as they pass so partial output is reported on error.
+            let counted = stream.inspect(|item| {
+                if item.is_ok() {
+                    *rows += 1;
+                }
+            });
+            write_csv(counted, &mut *writer, options).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RecordStream;
+    use futures::stream;
+    use std::sync::Mutex as StdMutex;
+
+    /// Yield two records per query and remember the queries.
+    #[derive(Clone, Default)]
+    struct TwoRecords {
+        queries: Arc<StdMutex<Vec<String>>>,
+        delay: Duration,
+    }
+
+    impl QueryClient for TwoRecords {
+        async fn query_stream(&self, query: impl Into<String> + Send) -> Result<RecordStream> {
+            self.queries.lock().unwrap().push(query.into());
+            tokio::time::sleep(self.delay).await;
+            let records = vec![Ok(FluxRecord::new(0)), Ok(FluxRecord::new(1))];
+            Ok(Box::pin(stream::iter(records)))
+        }
+    }
+
+    fn collect_reports() -> (
+        Arc<StdMutex<Vec<RunReport>>>,
+        impl Fn(RunReport) + Send + Sync,
+    ) {
+        let reports = Arc::new(StdMutex::new(Vec::new()));
+        let sink = reports.clone();
+        (reports, move |r| sink.lock().unwrap().push(r))
+    }
+
+    #[test]
+    fn test_first_delay_aligned() {
+        let now = DateTime::parse_from_rfc3339("2023-11-14T12:03:20Z")
+            .unwrap()
+            .to_utc();
+        let schedule = Schedule::every(Duration::from_secs(300));
+        assert_eq!(schedule.first_delay(now), Duration::from_secs(100));
+
+        let offset = schedule.offset(Duration::from_secs(30));
+        assert_eq!(offset.first_delay(now), Duration::from_secs(130));
+
+        let on_boundary = DateTime::parse_from_rfc3339("2023-11-14T12:05:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(schedule.first_delay(on_boundary), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval_runs_and_reports() {
+        let client = TwoRecords::default();
+        let (reports, on_report) = collect_reports();
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let handle = Scheduler::new(client.clone())
+            .job(Job::new(
+                "job",
+                Schedule::interval(Duration::from_secs(10)),
+                |run| format!("q {}", run.previous.is_some()),
+                JobSink::Channel(tx),
+            ))
+            .on_report(on_report)
+            .start();
+
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        handle.shutdown();
+
+        assert_eq!(
+            *client.queries.lock().unwrap(),
+            vec!["q false", "q true", "q true"]
+        );
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert!(reports.iter().all(|r| r.rows == 2 && r.result.is_ok()));
+
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overrunning_job_does_not_overlap() {
+        let client = TwoRecords {
+            delay: Duration::from_secs(25),
+            ..Default::default()
+        };
+        let counted = Arc::new(StdMutex::new(0));
+        let sink = counted.clone();
+
+        let _handle = Scheduler::new(client.clone())
+            .job(Job::new(
+                "slow",
+                Schedule::interval(Duration::from_secs(10)),
+                |_| "q".to_string(),
+                JobSink::Callback(Arc::new(move |_| *sink.lock().unwrap() += 1)),
+            ))
+            .start();
+
+        // Runs start at 0s and 30s (the ticks at 10s and 20s are skipped).
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert_eq!(client.queries.lock().unwrap().len(), 2);
+        assert_eq!(*counted.lock().unwrap(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_error_is_reported() {
+        let client = TwoRecords::default();
+        let (reports, on_report) = collect_reports();
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+
+        let handle = Scheduler::new(client)
+            .job(Job::new(
+                "closed",
+                Schedule::interval(Duration::from_secs(10)),
+                |_| "q".to_string(),
+                JobSink::Channel(tx),
+            ))
+            .on_report(on_report)
+            .start();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        drop(handle);
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(matches!(reports[0].result, Err(Error::Io(_))));
+    }
+}
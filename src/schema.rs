@@ -0,0 +1,226 @@
+//! Registry of measurements with known Rust types.
+//!
+//! Applications with a fixed schema can register each [`Measurement`] type
+//! with the bucket it lives in, then query it by type: the registry generates
+//! the Flux and the stream yields the mapped structs.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::schema::{SchemaRegistry, TimeRange};
+//!
+//! let registry = SchemaRegistry::new()
+//!     .register::<Cpu>("telegraf")
+//!     .register::<Mem>("telegraf");
+//!
+//! let mut stream = client
+//!     .query_typed::<Cpu>(&registry, &TimeRange::last(chrono::Duration::hours(1)))
+//!     .await?;
+//! while let Some(cpu) = stream.next().await {
+//!     let cpu: Cpu = cpu?;
+//! }
+//! ```
+
+use std::any::{TypeId, type_name};
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone};
+
+use crate::error::{Error, Result};
+use crate::flux::{Literal, ToFlux};
+use crate::typed::Measurement;
+
+/// Time bounds of a generated query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    start: String,
+    stop: Option<String>,
+}
+
+impl TimeRange {
+    /// Cover `[start, stop)`.
+    pub fn new<Tz: TimeZone>(start: DateTime<Tz>, stop: DateTime<Tz>) -> Self {
+        Self {
+            start: Literal(&start).to_string(),
+            stop: Some(Literal(&stop).to_string()),
+        }
+    }
+
+    /// Cover everything from `start` until now.
+    pub fn since<Tz: TimeZone>(start: DateTime<Tz>) -> Self {
+        Self {
+            start: Literal(&start).to_string(),
+            stop: None,
+        }
+    }
+
+    /// Cover the last `duration` until now.
+    pub fn last(duration: chrono::Duration) -> Self {
+        Self {
+            start: Literal(&-duration.abs()).to_string(),
+            stop: None,
+        }
+    }
+
+    /// Get the start bound as a Flux literal.
+    pub(crate) fn start(&self) -> &str {
+        &self.start
+    }
+
+    /// Get the stop bound as a Flux literal, or `None` for "until now".
+    pub(crate) fn stop(&self) -> Option<&str> {
+        self.stop.as_deref()
+    }
+
+    /// Render the `range()` call.
+    pub(crate) fn to_flux(&self) -> String {
+        match &self.stop {
+            Some(stop) => format!("range(start: {}, stop: {})", self.start, stop),
+            None => format!("range(start: {})", self.start),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    bucket: String,
+    type_id: TypeId,
+}
+
+/// Maps measurement names to the Rust types they are decoded into.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaRegistry {
+    entries: HashMap<&'static str, Entry>,
+}
+
+impl SchemaRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `T` as the type of its measurement, stored in `bucket`.
+    ///
+    /// Registering another type for the same measurement replaces it.
+    pub fn register<T: Measurement + 'static>(mut self, bucket: impl Into<String>) -> Self {
+        self.entries.insert(
+            T::MEASUREMENT,
+            Entry {
+                bucket: bucket.into(),
+                type_id: TypeId::of::<T>(),
+            },
+        );
+        self
+    }
+
+    /// Get the bucket a measurement is registered in.
+    pub fn bucket(&self, measurement: &str) -> Option<&str> {
+        self.entries.get(measurement).map(|e| e.bucket.as_str())
+    }
+
+    /// Get the names of all registered measurements.
+    pub fn measurements(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().copied()
+    }
+
+    /// Build the Flux query for `T` over `range`.
+    ///
+    /// The query filters on the measurement and pivots fields into columns,
+    /// so each record holds one point. Fails with
+    /// [`Error::Config`] if `T` is not the type registered for
+    /// its measurement.
+    pub fn query_for<T: Measurement + 'static>(&self, range: &TimeRange) -> Result<String> {
+        let entry = self
+            .entries
+            .get(T::MEASUREMENT)
+            .filter(|e| e.type_id == TypeId::of::<T>())
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "{} is not registered for measurement '{}'",
+                    type_name::<T>(),
+                    T::MEASUREMENT
+                ))
+            })?;
+
+        Ok(format!(
+            "from(bucket: {bucket})\n  |> {range}\n  |> filter(fn: (r) => r._measurement == {measurement})\n  |> pivot(rowKey: [\"_time\"], columnKey: [\"_field\"], valueColumn: \"_value\")",
+            bucket = Literal(entry.bucket.as_str()),
+            range = range.to_flux(),
+            measurement = Literal(T::MEASUREMENT),
+        ))
+    }
+}
+
+impl ToFlux for TimeRange {
+    fn fmt_flux(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_flux())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed::FromRecord;
+    use crate::types::FluxRecord;
+
+    struct Cpu;
+    struct OtherCpu;
+
+    impl FromRecord for Cpu {
+        fn from_record(_: &FluxRecord) -> Result<Self> {
+            Ok(Cpu)
+        }
+    }
+
+    impl Measurement for Cpu {
+        const MEASUREMENT: &'static str = "cpu";
+    }
+
+    impl FromRecord for OtherCpu {
+        fn from_record(_: &FluxRecord) -> Result<Self> {
+            Ok(OtherCpu)
+        }
+    }
+
+    impl Measurement for OtherCpu {
+        const MEASUREMENT: &'static str = "cpu";
+    }
+
+    #[test]
+    fn test_query_for_registered_type() {
+        let registry = SchemaRegistry::new().register::<Cpu>("telegraf");
+        assert_eq!(registry.bucket("cpu"), Some("telegraf"));
+
+        let query = registry
+            .query_for::<Cpu>(&TimeRange::last(chrono::Duration::hours(1)))
+            .unwrap();
+        assert!(query.starts_with("from(bucket: \"telegraf\")\n  |> range(start: -1h)"));
+        assert!(query.contains("r._measurement == \"cpu\""));
+        assert!(query.contains("pivot("));
+    }
+
+    #[test]
+    fn test_query_for_unregistered_type() {
+        let registry = SchemaRegistry::new().register::<Cpu>("telegraf");
+        let range = TimeRange::last(chrono::Duration::hours(1));
+        assert!(matches!(
+            registry.query_for::<OtherCpu>(&range),
+            Err(Error::Config(_))
+        ));
+        assert!(SchemaRegistry::new().query_for::<Cpu>(&range).is_err());
+    }
+
+    #[test]
+    fn test_time_range_bounds() {
+        let start = DateTime::parse_from_rfc3339("2023-11-14T00:00:00Z").unwrap();
+        let stop = DateTime::parse_from_rfc3339("2023-11-15T00:00:00Z").unwrap();
+        assert_eq!(
+            TimeRange::new(start, stop).to_flux(),
+            "range(start: 2023-11-14T00:00:00Z, stop: 2023-11-15T00:00:00Z)"
+        );
+        assert_eq!(
+            TimeRange::since(start).to_flux(),
+            "range(start: 2023-11-14T00:00:00Z)"
+        );
+    }
+}
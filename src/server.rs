@@ -0,0 +1,231 @@
+//! Server identification and API selection.
+
+use std::fmt;
+
+use http::HeaderMap;
+use serde::Deserialize;
+
+/// API generation a [`Client`](crate::Client) talks to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ApiVersion {
+    /// InfluxDB 2.x and Cloud Serverless: Flux over `/api/v2/query`, with
+    /// `Authorization: Token`.
+    #[default]
+    V2,
+    /// InfluxDB 3 (Core, Enterprise, Cloud Dedicated and Clustered): SQL over
+    /// `/api/v3/query_sql`, with `Authorization: Bearer`. Flux queries are
+    /// rejected before they are sent.
+    V3,
+}
+
+/// Kind of InfluxDB server, as detected by [`ServerInfo::flavor`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerFlavor {
+    /// InfluxDB OSS 1.8 or later, through its 2.x compatibility API. Tokens
+    /// have the form `username:password`.
+    Oss1,
+    /// InfluxDB OSS 2.x.
+    Oss2,
+    /// InfluxDB Cloud Serverless and the older TSM-based Cloud 2.
+    Cloud,
+    /// InfluxDB 3: Core, Enterprise, Cloud Dedicated or Clustered.
+    V3,
+}
+
+/// An API feature whose availability depends on the [`ServerFlavor`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+    /// Flux queries over `/api/v2/query`.
+    Flux,
+    /// SQL queries over `/api/v3/query_sql`.
+    Sql,
+    /// Organizations, stacks and templates.
+    Management,
+    /// Native backup and restore.
+    Backup,
+    /// Query parameters sent next to a Flux query.
+    QueryParams,
+}
+
+impl ServerFlavor {
+    /// Get the API generation the server speaks.
+    pub fn api_version(self) -> ApiVersion {
+        match self {
+            ServerFlavor::V3 => ApiVersion::V3,
+            _ => ApiVersion::V2,
+        }
+    }
+
+    /// Check whether the server offers `capability`.
+    pub fn supports(self, capability: Capability) -> bool {
+        use Capability::*;
+        match self {
+            ServerFlavor::Oss1 => matches!(capability, Flux),
+            ServerFlavor::Oss2 => matches!(capability, Flux | Management | Backup),
+            ServerFlavor::Cloud => matches!(capability, Flux | Management | QueryParams),
+            ServerFlavor::V3 => matches!(capability, Sql),
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Flux => "Flux querying",
+            Capability::Sql => "SQL querying",
+            Capability::Management => "The management API",
+            Capability::Backup => "Backup and restore",
+            Capability::QueryParams => "Parameterized querying",
+        })
+    }
+}
+
+impl fmt::Display for ServerFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ServerFlavor::Oss1 => "InfluxDB 1.x",
+            ServerFlavor::Oss2 => "InfluxDB OSS 2.x",
+            ServerFlavor::Cloud => "InfluxDB Cloud",
+            ServerFlavor::V3 => "InfluxDB 3",
+        })
+    }
+}
+
+/// Server details reported by [`Client::ping`](crate::Client::ping).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Server version, from the `X-Influxdb-Version` header.
+    pub version: Option<String>,
+    /// Build flavor, such as `OSS` or `Cloud`, from the `X-Influxdb-Build`
+    /// header.
+    pub build: Option<String>,
+}
+
+/// Health status reported by [`Client::health`](crate::Client::health).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Health {
+    /// Service name, usually `influxdb`.
+    pub name: String,
+    /// `pass` for a healthy server.
+    pub status: String,
+    /// Human-readable status, such as `ready for queries and writes`.
+    pub message: Option<String>,
+    /// Server version.
+    pub version: Option<String>,
+    /// Git commit of the server build.
+    pub commit: Option<String>,
+}
+
+impl Health {
+    /// Check whether the server reported itself as healthy.
+    pub fn is_pass(&self) -> bool {
+        self.status == "pass"
+    }
+}
+
+impl ServerInfo {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            version: header("x-influxdb-version"),
+            build: header("x-influxdb-build"),
+        }
+    }
+
+    /// Get the API generation matching the reported version.
+    ///
+    /// Servers that report a 3.x version, or a Dedicated or Clustered build,
+    /// use [`ApiVersion::V3`]; everything else, including servers that send
+    /// no version, uses [`ApiVersion::V2`].
+    pub fn api_version(&self) -> ApiVersion {
+        self.flavor().api_version()
+    }
+
+    /// Get the kind of server matching the reported version and build.
+    ///
+    /// Servers that send neither are taken for [`ServerFlavor::Oss2`].
+    pub fn flavor(&self) -> ServerFlavor {
+        let version = self.version.as_deref().map(|v| v.trim_start_matches('v'));
+        let build = self
+            .build
+            .as_deref()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if version.is_some_and(|v| v.starts_with("3."))
+            || build.contains("dedicated")
+            || build.contains("clustered")
+        {
+            ServerFlavor::V3
+        } else if version.is_some_and(|v| v.starts_with("1.")) {
+            ServerFlavor::Oss1
+        } else if build.contains("cloud") {
+            ServerFlavor::Cloud
+        } else {
+            ServerFlavor::Oss2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(version: Option<&str>, build: Option<&str>) -> ServerInfo {
+        ServerInfo {
+            version: version.map(str::to_string),
+            build: build.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_api_version_from_info() {
+        assert_eq!(
+            info(Some("v2.7.4"), Some("OSS")).api_version(),
+            ApiVersion::V2
+        );
+        assert_eq!(
+            info(Some("3.0.1"), Some("Core")).api_version(),
+            ApiVersion::V3
+        );
+        assert_eq!(
+            info(None, Some("Cloud Dedicated")).api_version(),
+            ApiVersion::V3
+        );
+        assert_eq!(info(None, None).api_version(), ApiVersion::V2);
+    }
+
+    #[test]
+    fn test_flavor_from_info() {
+        assert_eq!(info(Some("1.8.10"), None).flavor(), ServerFlavor::Oss1);
+        assert_eq!(
+            info(Some("v2.7.4"), Some("OSS")).flavor(),
+            ServerFlavor::Oss2
+        );
+        assert_eq!(info(None, Some("Cloud")).flavor(), ServerFlavor::Cloud);
+        assert_eq!(
+            info(None, Some("Cloud Dedicated")).flavor(),
+            ServerFlavor::V3
+        );
+        assert!(ServerFlavor::Oss2.supports(Capability::Backup));
+        assert!(!ServerFlavor::Cloud.supports(Capability::Backup));
+        assert!(!ServerFlavor::V3.supports(Capability::Flux));
+        assert!(ServerFlavor::Cloud.supports(Capability::QueryParams));
+        assert!(!ServerFlavor::Oss2.supports(Capability::QueryParams));
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Influxdb-Version", "v2.7.4".parse().unwrap());
+        let info = ServerInfo::from_headers(&headers);
+        assert_eq!(info.version.as_deref(), Some("v2.7.4"));
+        assert_eq!(info.build, None);
+    }
+}
@@ -0,0 +1,336 @@
+//! Parallel execution of time-sharded queries.
+//!
+//! A single HTTP response is parsed on one connection, which caps throughput
+//! well below what the server can deliver. [`TimeShards`] splits a time range
+//! into windows that are queried concurrently and merged back into one stream.
+
+use std::future::Future;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::stream;
+use futures::{Stream, StreamExt, TryStreamExt};
+
+use crate::client::RecordStream;
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// Splits a time range into windows that are queried concurrently.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::shard::TimeShards;
+///
+/// let shards = TimeShards::new(start, stop, 24).concurrency(4).ordered(true);
+/// let mut stream = client.query_stream_sharded(
+///     |start, stop| format!(
+///         r#"from(bucket: "sensors") |> range(start: {}, stop: {})"#,
+///         start.to_rfc3339(),
+///         stop.to_rfc3339(),
+///     ),
+///     &shards,
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct TimeShards {
+    start: DateTime<FixedOffset>,
+    stop: DateTime<FixedOffset>,
+    count: usize,
+    width: Option<TimeDelta>,
+    concurrency: usize,
+    ordered: bool,
+}
+
+impl TimeShards {
+    /// Split `[start, stop)` into `count` equally sized windows.
+    ///
+    /// By default all windows run concurrently and records are yielded in
+    /// arrival order.
+    pub fn new(start: DateTime<FixedOffset>, stop: DateTime<FixedOffset>, count: usize) -> Self {
+        let count = count.max(1);
+        Self {
+            start,
+            stop,
+            count,
+            width: None,
+            concurrency: count,
+            ordered: false,
+        }
+    }
+
+    /// Split `[start, stop)` into consecutive windows of `width`.
+    ///
+    /// The last window ends at `stop` and may be shorter. Defaults to one
+    /// window at a time, in time order, which keeps every request short
+    /// without adding load on the server.
+    ///
+    /// Fails with [`Error::Config`] if `width` is not positive.
+    pub fn every(
+        start: DateTime<FixedOffset>,
+        stop: DateTime<FixedOffset>,
+        width: TimeDelta,
+    ) -> Result<Self> {
+        if width <= TimeDelta::zero() {
+            return Err(Error::Config(format!(
+                "window width must be positive, got {}",
+                width
+            )));
+        }
+        let total = nanos(stop - start).max(0);
+        let count = (total + nanos(width) - 1) / nanos(width);
+        Ok(Self {
+            start,
+            stop,
+            count: usize::try_from(count).unwrap_or(usize::MAX).max(1),
+            width: Some(width),
+            concurrency: 1,
+            ordered: true,
+        })
+    }
+
+    /// Set the maximum number of windows queried at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Yield records window by window in time order instead of as they arrive.
+    ///
+    /// Later windows are still opened ahead of time (up to the concurrency
+    /// limit), but their responses are only read once all earlier windows have
+    /// been consumed.
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Get the `(start, stop)` bounds of each window, in time order.
+    ///
+    /// Windows are computed as they are iterated.
+    pub fn windows(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (DateTime<FixedOffset>, DateTime<FixedOffset>)> + Send + use<>
+    {
+        let Self {
+            start,
+            stop,
+            count,
+            width,
+            ..
+        } = *self;
+        let total = nanos(stop - start);
+        let at = move |i: usize| match width {
+            Some(width) => start + from_nanos(nanos(width) * i as i128),
+            None => start + from_nanos(total * i as i128 / count as i128),
+        };
+
+        (0..count).map(move |i| {
+            let end = if i == count - 1 { stop } else { at(i + 1) };
+            (at(i), end)
+        })
+    }
+
+    /// Run `connect` for every window and merge the resulting streams.
+    ///
+    /// A window that fails to start yields its error in place of its records;
+    /// the other windows are unaffected.
+    pub fn execute<F, Fut, S>(&self, connect: F) -> RecordStream
+    where
+        F: Fn(DateTime<FixedOffset>, DateTime<FixedOffset>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<S>> + Send + 'static,
+        S: Stream<Item = Result<FluxRecord>> + Send + 'static,
+    {
+        let opens = stream::iter(self.windows()).map(move |(start, stop)| connect(start, stop));
+
+        if self.ordered {
+            opens
+                .buffered(self.concurrency)
+                .flat_map(|opened| match opened {
+                    Ok(s) => s.boxed(),
+                    Err(e) => stream::once(async { Err(e) }).boxed(),
+                })
+                .boxed()
+        } else {
+            opens
+                .map(|open| stream::once(open).try_flatten().boxed())
+                .flatten_unordered(self.concurrency)
+                .boxed()
+        }
+    }
+}
+
+fn nanos(delta: TimeDelta) -> i128 {
+    delta.num_seconds() as i128 * 1_000_000_000 + delta.subsec_nanos() as i128
+}
+
+fn from_nanos(nanos: i128) -> TimeDelta {
+    TimeDelta::seconds((nanos / 1_000_000_000) as i64)
+        + TimeDelta::nanoseconds((nanos % 1_000_000_000) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::value::Value;
+    use futures::stream::BoxStream;
+
+    fn time(ts: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(ts).unwrap()
+    }
+
+    fn record_at(t: DateTime<FixedOffset>) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("_time".to_string(), Value::TimeRFC(t));
+        record
+    }
+
+    /// Serve two records per window: at its start and one second later.
+    async fn serve(
+        start: DateTime<FixedOffset>,
+        _stop: DateTime<FixedOffset>,
+    ) -> Result<BoxStream<'static, Result<FluxRecord>>> {
+        let records = vec![
+            Ok(record_at(start)),
+            Ok(record_at(start + TimeDelta::seconds(1))),
+        ];
+        Ok(stream::iter(records).boxed())
+    }
+
+    #[test]
+    fn test_windows_cover_range() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T01:00:00Z"),
+            4,
+        );
+        let windows: Vec<_> = shards.windows().collect();
+
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows[0].0, time("2023-11-14T00:00:00Z"));
+        assert_eq!(windows[0].1, time("2023-11-14T00:15:00Z"));
+        assert_eq!(windows[3].1, time("2023-11-14T01:00:00Z"));
+        for pair in windows.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn test_windows_uneven_split() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T00:00:00.000000010Z"),
+            3,
+        );
+        let windows: Vec<_> = shards.windows().collect();
+
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[1].0, time("2023-11-14T00:00:00.000000003Z"));
+        assert_eq!(windows[2].1, time("2023-11-14T00:00:00.000000010Z"));
+    }
+
+    #[test]
+    fn test_every_fixed_width() {
+        let shards = TimeShards::every(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T02:30:00Z"),
+            TimeDelta::hours(1),
+        )
+        .unwrap();
+        let windows: Vec<_> = shards.windows().collect();
+
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[1].0, time("2023-11-14T01:00:00Z"));
+        assert_eq!(windows[2].0, time("2023-11-14T02:00:00Z"));
+        assert_eq!(windows[2].1, time("2023-11-14T02:30:00Z"));
+
+        let empty = TimeShards::every(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T00:00:00Z"),
+            TimeDelta::hours(1),
+        )
+        .unwrap();
+        assert_eq!(empty.windows().len(), 1);
+    }
+
+    #[test]
+    fn test_every_rejects_empty_width() {
+        let start = time("2023-11-14T00:00:00Z");
+        let stop = time("2023-11-14T01:00:00Z");
+        for width in [TimeDelta::zero(), TimeDelta::seconds(-1)] {
+            assert!(matches!(
+                TimeShards::every(start, stop, width),
+                Err(Error::Config(_))
+            ));
+        }
+
+        let shards = TimeShards::every(start, stop, TimeDelta::nanoseconds(1)).unwrap();
+        assert_eq!(shards.windows().len(), 3_600_000_000_000);
+    }
+
+    #[test]
+    fn test_zero_count_is_single_window() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T01:00:00Z"),
+            0,
+        );
+        assert_eq!(shards.windows().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_ordered() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T04:00:00Z"),
+            4,
+        )
+        .concurrency(2)
+        .ordered(true);
+
+        let records: Vec<_> = shards.execute(serve).try_collect().await.unwrap();
+        let times: Vec<_> = records.iter().map(|r| *r.time().unwrap()).collect();
+
+        assert_eq!(times.len(), 8);
+        assert!(times.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn test_execute_unordered() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T04:00:00Z"),
+            4,
+        );
+
+        let records: Vec<_> = shards.execute(serve).try_collect().await.unwrap();
+        assert_eq!(records.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_execute_window_error() {
+        let shards = TimeShards::new(
+            time("2023-11-14T00:00:00Z"),
+            time("2023-11-14T02:00:00Z"),
+            2,
+        )
+        .ordered(true);
+        let first_stop = shards.windows().next().unwrap().1;
+
+        let items: Vec<_> = shards
+            .execute(move |start, stop| async move {
+                if start == first_stop {
+                    Err(Error::Csv("boom".to_string()))
+                } else {
+                    serve(start, stop).await
+                }
+            })
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_ok());
+        assert!(matches!(items[2], Err(Error::Csv(_))));
+    }
+}
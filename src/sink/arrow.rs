@@ -0,0 +1,258 @@
+//! Arrow IPC (Feather) output for record streams.
+//!
+//! Requires the `arrow` feature.
+
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{ArrowError, Schema};
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::error::{Error, Result};
+use crate::sink::batch::BatchBuilder;
+use crate::types::FluxRecord;
+
+/// Arrow IPC container format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpcFormat {
+    /// IPC streaming format, readable incrementally (`.arrows`).
+    #[default]
+    Stream,
+    /// IPC file format with a footer, also known as Feather v2 (`.arrow`, `.feather`).
+    File,
+}
+
+/// Options for [`write_arrow_ipc`].
+#[derive(Clone, Debug)]
+pub struct ArrowIpcOptions {
+    format: IpcFormat,
+    batch_size: usize,
+}
+
+impl ArrowIpcOptions {
+    /// Create options for the IPC streaming format with 8192-row batches.
+    pub fn new() -> Self {
+        Self {
+            format: IpcFormat::Stream,
+            batch_size: 8192,
+        }
+    }
+
+    /// Set the container format.
+    pub fn format(mut self, format: IpcFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the number of rows per record batch (default: 8192).
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows.max(1);
+        self
+    }
+}
+
+impl Default for ArrowIpcOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write every record of `stream` to `writer` in Arrow IPC format.
+///
+/// The schema is derived from the first record (see the [type
+/// mapping](crate::sink#columnar-output)). An IPC stream carries a single
+/// schema, so a record that does not fit it, such as one from a table with
+/// extra columns, fails with [`Error::Encode`]. Regroup or pivot the query
+/// output to get uniform tables.
+///
+/// Returns the number of rows written. An empty input still produces a valid
+/// file, with an empty schema.
+pub async fn write_arrow_ipc<S, W>(
+    stream: S,
+    mut writer: W,
+    options: &ArrowIpcOptions,
+) -> Result<u64>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    W: AsyncWrite + Unpin,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut ipc: Option<(IpcWriter, BatchBuilder)> = None;
+    let mut rows = 0u64;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+
+        let (ipc, batch) = match &mut ipc {
+            Some(state) => state,
+            None => {
+                let batch = BatchBuilder::for_record(&record);
+                let ipc_writer = IpcWriter::new(options.format, &batch.schema())?;
+                ipc.insert((ipc_writer, batch))
+            }
+        };
+
+        if !batch.accepts(&record) {
+            return Err(Error::Encode(format!(
+                "Record from table {} does not match the Arrow IPC schema",
+                record.table
+            )));
+        }
+        batch.push(&record)?;
+        rows += 1;
+
+        if batch.len() >= options.batch_size {
+            ipc.write(&batch.finish()?)?;
+            writer.write_all(&ipc.take_bytes()).await?;
+        }
+    }
+
+    let (mut ipc, mut batch) = match ipc {
+        Some(state) => state,
+        None => (
+            IpcWriter::new(options.format, &Schema::empty())?,
+            BatchBuilder::empty(),
+        ),
+    };
+    if !batch.is_empty() {
+        ipc.write(&batch.finish()?)?;
+    }
+    ipc.finish()?;
+    writer.write_all(&ipc.take_bytes()).await?;
+    writer.flush().await?;
+
+    Ok(rows)
+}
+
+/// Synchronous IPC encoder writing into an in-memory buffer.
+///
+/// The buffer is drained after every batch so memory stays bounded by the
+/// batch size while the bytes are written asynchronously.
+enum IpcWriter {
+    Stream(StreamWriter<Vec<u8>>),
+    File(FileWriter<Vec<u8>>),
+}
+
+impl IpcWriter {
+    fn new(format: IpcFormat, schema: &Schema) -> Result<Self> {
+        let writer = match format {
+            IpcFormat::Stream => {
+                Self::Stream(StreamWriter::try_new(Vec::new(), schema).map_err(encode_error)?)
+            }
+            IpcFormat::File => {
+                Self::File(FileWriter::try_new(Vec::new(), schema).map_err(encode_error)?)
+            }
+        };
+        Ok(writer)
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            Self::Stream(w) => w.write(batch),
+            Self::File(w) => w.write(batch),
+        }
+        .map_err(encode_error)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Stream(w) => w.finish(),
+            Self::File(w) => w.finish(),
+        }
+        .map_err(encode_error)
+    }
+
+    fn take_bytes(&mut self) -> Vec<u8> {
+        match self {
+            Self::Stream(w) => std::mem::take(w.get_mut()),
+            Self::File(w) => std::mem::take(w.get_mut()),
+        }
+    }
+}
+
+fn encode_error(e: ArrowError) -> Error {
+    Error::Encode(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use arrow_array::{Array, Int64Array};
+    use arrow_ipc::reader::{FileReader, StreamReader};
+    use futures::stream;
+    use std::io::Cursor;
+
+    fn record(table: i32, count: i64) -> FluxRecord {
+        let mut record = FluxRecord::new(table);
+        record.insert("count".to_string(), Value::Long(count));
+        record.insert("host".to_string(), Value::String("server1".to_string()));
+        record
+    }
+
+    async fn render(records: Vec<FluxRecord>, options: &ArrowIpcOptions) -> (u64, Vec<u8>) {
+        let mut out = Vec::new();
+        let rows = write_arrow_ipc(stream::iter(records.into_iter().map(Ok)), &mut out, options)
+            .await
+            .unwrap();
+        (rows, out)
+    }
+
+    #[tokio::test]
+    async fn test_write_arrow_ipc_stream() {
+        let records = (0..5).map(|i| record(0, i)).collect();
+        let (rows, out) = render(records, &ArrowIpcOptions::new().batch_size(2)).await;
+        assert_eq!(rows, 5);
+
+        let reader = StreamReader::try_new(Cursor::new(out), None).unwrap();
+        let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 3);
+
+        let counts: Vec<i64> = batches
+            .iter()
+            .flat_map(|b| {
+                let col = b
+                    .column_by_name("count")
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap()
+                    .clone();
+                (0..col.len()).map(move |i| col.value(i))
+            })
+            .collect();
+        assert_eq!(counts, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_write_arrow_ipc_file() {
+        let records = (0..3).map(|i| record(0, i)).collect();
+        let options = ArrowIpcOptions::new().format(IpcFormat::File);
+        let (_, out) = render(records, &options).await;
+
+        let reader = FileReader::try_new(Cursor::new(out), None).unwrap();
+        assert_eq!(reader.schema().fields().len(), 2);
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+    }
+
+    #[tokio::test]
+    async fn test_write_arrow_ipc_empty() {
+        let (rows, out) = render(Vec::new(), &ArrowIpcOptions::new()).await;
+        assert_eq!(rows, 0);
+
+        let reader = StreamReader::try_new(Cursor::new(out), None).unwrap();
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_write_arrow_ipc_schema_mismatch() {
+        let mut other = FluxRecord::new(1);
+        other.insert("count".to_string(), Value::Double(1.0.into()));
+        let input = stream::iter(vec![Ok(record(0, 1)), Ok(other)]);
+
+        let mut out = Vec::new();
+        let result = write_arrow_ipc(input, &mut out, &ArrowIpcOptions::new()).await;
+        assert!(matches!(result, Err(Error::Encode(_))));
+    }
+}
@@ -0,0 +1,306 @@
+//! Conversion of records into Arrow record batches.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    ArrayBuilder, BinaryBuilder, BooleanBuilder, DurationNanosecondBuilder, Float64Builder,
+    Int64Builder, StringBuilder, TimestampNanosecondBuilder, UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType as ArrowType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Accumulates records with a common schema into Arrow record batches.
+///
+/// The schema is inferred from the first record: one nullable field per
+/// column, typed after its value (null values become `Utf8`). Timestamps are
+/// stored as UTC nanoseconds.
+pub(crate) struct BatchBuilder {
+    schema: SchemaRef,
+    columns: BTreeMap<String, ColumnBuilder>,
+    rows: usize,
+}
+
+impl BatchBuilder {
+    /// Create a builder whose schema matches `record`.
+    pub(crate) fn for_record(record: &FluxRecord) -> Self {
+        let columns: BTreeMap<String, ColumnBuilder> = record
+            .iter()
+            .map(|(name, value)| (name.to_string(), ColumnBuilder::for_value(value)))
+            .collect();
+        let fields: Vec<Field> = columns
+            .iter()
+            .map(|(name, column)| Field::new(name, column.data_type(), true))
+            .collect();
+
+        Self {
+            schema: Arc::new(Schema::new(fields)),
+            columns,
+            rows: 0,
+        }
+    }
+
+    /// Create a builder with no columns.
+    #[cfg(feature = "arrow")]
+    pub(crate) fn empty() -> Self {
+        Self {
+            schema: Arc::new(Schema::empty()),
+            columns: BTreeMap::new(),
+            rows: 0,
+        }
+    }
+
+    /// Get the Arrow schema of the batches produced by this builder.
+    pub(crate) fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Returns true if `record` can be appended without changing the schema.
+    ///
+    /// Every column of the record must exist with a matching type (or be
+    /// null). Columns missing from the record are filled with nulls.
+    pub(crate) fn accepts(&self, record: &FluxRecord) -> bool {
+        record.iter().all(|(name, value)| {
+            self.columns
+                .get(name)
+                .is_some_and(|column| column.accepts(value))
+        })
+    }
+
+    /// Append a record. The caller must check [`accepts`](Self::accepts) first.
+    pub(crate) fn push(&mut self, record: &FluxRecord) -> Result<()> {
+        for (name, column) in self.columns.iter_mut() {
+            column.append(name, record.get(name))?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Number of rows appended since the last batch was finished.
+    pub(crate) fn len(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns true if no rows are pending.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Build a batch from the pending rows and reset the builder.
+    pub(crate) fn finish(&mut self) -> Result<RecordBatch> {
+        let arrays: Vec<ArrayRef> = self.columns.values_mut().map(|c| c.finish()).collect();
+        self.rows = 0;
+        RecordBatch::try_new(self.schema.clone(), arrays).map_err(|e| Error::Encode(e.to_string()))
+    }
+}
+
+/// Typed Arrow builder for one column.
+enum ColumnBuilder {
+    String(StringBuilder),
+    Double(Float64Builder),
+    Bool(BooleanBuilder),
+    Long(Int64Builder),
+    UnsignedLong(UInt64Builder),
+    Duration(DurationNanosecondBuilder),
+    Binary(BinaryBuilder),
+    Time(TimestampNanosecondBuilder),
+}
+
+impl ColumnBuilder {
+    fn for_value(value: &Value) -> Self {
+        match value {
+            Value::String(_) | Value::SharedString(_) | Value::Null => {
+                Self::String(StringBuilder::new())
+            }
+            Value::Double(_) => Self::Double(Float64Builder::new()),
+            Value::Bool(_) => Self::Bool(BooleanBuilder::new()),
+            Value::Long(_) => Self::Long(Int64Builder::new()),
+            Value::UnsignedLong(_) => Self::UnsignedLong(UInt64Builder::new()),
+            Value::Duration(_) => Self::Duration(DurationNanosecondBuilder::new()),
+            Value::Base64Binary(_) => Self::Binary(BinaryBuilder::new()),
+            Value::TimeRFC(_) => Self::Time(TimestampNanosecondBuilder::new().with_timezone("UTC")),
+        }
+    }
+
+    fn data_type(&self) -> ArrowType {
+        match self {
+            Self::String(_) => ArrowType::Utf8,
+            Self::Double(_) => ArrowType::Float64,
+            Self::Bool(_) => ArrowType::Boolean,
+            Self::Long(_) => ArrowType::Int64,
+            Self::UnsignedLong(_) => ArrowType::UInt64,
+            Self::Duration(_) => ArrowType::Duration(TimeUnit::Nanosecond),
+            Self::Binary(_) => ArrowType::Binary,
+            Self::Time(_) => ArrowType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (_, Value::Null)
+                | (Self::String(_), Value::String(_) | Value::SharedString(_))
+                | (Self::Double(_), Value::Double(_))
+                | (Self::Bool(_), Value::Bool(_))
+                | (Self::Long(_), Value::Long(_))
+                | (Self::UnsignedLong(_), Value::UnsignedLong(_))
+                | (Self::Duration(_), Value::Duration(_))
+                | (Self::Binary(_), Value::Base64Binary(_))
+                | (Self::Time(_), Value::TimeRFC(_))
+        )
+    }
+
+    fn append(&mut self, column: &str, value: Option<&Value>) -> Result<()> {
+        let value = value.filter(|v| !v.is_null());
+        match (self, value) {
+            (Self::String(b), Some(Value::String(s))) => b.append_value(s),
+            (Self::String(b), Some(Value::SharedString(s))) => b.append_value(s),
+            (Self::Double(b), Some(Value::Double(d))) => b.append_value(d.into_inner()),
+            (Self::Bool(b), Some(Value::Bool(v))) => b.append_value(*v),
+            (Self::Long(b), Some(Value::Long(v))) => b.append_value(*v),
+            (Self::UnsignedLong(b), Some(Value::UnsignedLong(v))) => b.append_value(*v),
+            (Self::Duration(b), Some(Value::Duration(d))) => {
+                b.append_value(d.num_nanoseconds().ok_or_else(|| out_of_range(column))?)
+            }
+            (Self::Binary(b), Some(Value::Base64Binary(v))) => b.append_value(v),
+            (Self::Time(b), Some(Value::TimeRFC(t))) => b.append_value(
+                t.timestamp_nanos_opt()
+                    .ok_or_else(|| out_of_range(column))?,
+            ),
+            (b, None) => b.append_null(),
+            (_, Some(other)) => {
+                return Err(Error::Encode(format!(
+                    "Unexpected value {:?} for column '{}'",
+                    other, column
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            Self::String(b) => b.append_null(),
+            Self::Double(b) => b.append_null(),
+            Self::Bool(b) => b.append_null(),
+            Self::Long(b) => b.append_null(),
+            Self::UnsignedLong(b) => b.append_null(),
+            Self::Duration(b) => b.append_null(),
+            Self::Binary(b) => b.append_null(),
+            Self::Time(b) => b.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Self::String(b) => ArrayBuilder::finish(b),
+            Self::Double(b) => ArrayBuilder::finish(b),
+            Self::Bool(b) => ArrayBuilder::finish(b),
+            Self::Long(b) => ArrayBuilder::finish(b),
+            Self::UnsignedLong(b) => ArrayBuilder::finish(b),
+            Self::Duration(b) => ArrayBuilder::finish(b),
+            Self::Binary(b) => ArrayBuilder::finish(b),
+            Self::Time(b) => ArrayBuilder::finish(b),
+        }
+    }
+}
+
+fn out_of_range(column: &str) -> Error {
+    Error::Encode(format!(
+        "Value for column '{}' does not fit in 64-bit nanoseconds",
+        column
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, Float64Array, StringArray, TimestampNanosecondArray};
+    use chrono::DateTime;
+    use ordered_float::OrderedFloat;
+
+    fn record(host: &str, value: Value) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-11-14T12:00:00+09:00").unwrap()),
+        );
+        record.insert("_value".to_string(), value);
+        record.insert("host".to_string(), Value::String(host.to_string()));
+        record
+    }
+
+    #[test]
+    fn test_batch_schema_inferred() {
+        let builder = BatchBuilder::for_record(&record("a", Value::Double(OrderedFloat(1.0))));
+        let schema = builder.schema();
+
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(
+            schema.field_with_name("_value").unwrap().data_type(),
+            &ArrowType::Float64
+        );
+        assert_eq!(
+            schema.field_with_name("host").unwrap().data_type(),
+            &ArrowType::Utf8
+        );
+    }
+
+    #[test]
+    fn test_batch_push_and_finish() {
+        let first = record("a", Value::Double(OrderedFloat(1.5)));
+        let mut builder = BatchBuilder::for_record(&first);
+        builder.push(&first).unwrap();
+        builder.push(&record("b", Value::Null)).unwrap();
+
+        let batch = builder.finish().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert!(builder.is_empty());
+
+        let values = batch
+            .column_by_name("_value")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(values.value(0), 1.5);
+        assert!(values.is_null(1));
+
+        let hosts = batch
+            .column_by_name("host")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(hosts.value(1), "b");
+
+        let times = batch
+            .column_by_name("_time")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .unwrap();
+        assert_eq!(times.value(0), 1_699_930_800_000_000_000);
+    }
+
+    #[test]
+    fn test_batch_accepts() {
+        let builder = BatchBuilder::for_record(&record("a", Value::Double(OrderedFloat(1.0))));
+
+        assert!(builder.accepts(&record("b", Value::Double(OrderedFloat(2.0)))));
+        assert!(builder.accepts(&record("b", Value::Null)));
+        assert!(!builder.accepts(&record("b", Value::Long(2))));
+
+        let mut extra = record("b", Value::Null);
+        extra.insert("region".to_string(), Value::String("us".to_string()));
+        assert!(!builder.accepts(&extra));
+
+        let mut fewer = FluxRecord::new(1);
+        fewer.insert("host".to_string(), Value::String("c".to_string()));
+        assert!(builder.accepts(&fewer));
+    }
+}
@@ -0,0 +1,292 @@
+//! CSV output for record streams.
+
+use std::sync::Arc;
+
+use csv_async::{AsyncWriter, AsyncWriterBuilder};
+use futures::{Stream, StreamExt};
+use tokio::io::AsyncWrite;
+
+use crate::error::{Result, csv_error};
+use crate::types::{DataType, FluxRecord, RecordSchema};
+use crate::value::Value;
+
+/// Options for [`write_csv`].
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    annotated: bool,
+    header: bool,
+    delimiter: u8,
+    columns: Option<Vec<String>>,
+}
+
+impl CsvOptions {
+    /// Create options for plain CSV with a header row and `,` delimiter.
+    pub fn new() -> Self {
+        Self {
+            annotated: false,
+            header: true,
+            delimiter: b',',
+            columns: None,
+        }
+    }
+
+    /// Write InfluxDB annotated CSV instead of plain CSV.
+    ///
+    /// Each block starts with `#datatype`, `#group` and `#default` rows. Records
+    /// do not carry table metadata, so data types are inferred from the first
+    /// record of each block (null values are written as `string`) and the
+    /// `#group` row marks every column as `false`.
+    pub fn annotated(mut self, annotated: bool) -> Self {
+        self.annotated = annotated;
+        self
+    }
+
+    /// Write header rows (default: `true`).
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the field delimiter (default: `,`).
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Write only these columns, in this order.
+    ///
+    /// Missing values are written as empty cells. Without this option the
+    /// columns of each record are written in name order, and a new header is
+    /// emitted whenever the set of columns changes between tables.
+    pub fn columns<I, T>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write every record of `stream` to `writer` as CSV.
+///
+/// Output is buffered and flushed once the stream ends. Returns the number of
+/// data rows written. The first error from the stream or the writer aborts
+/// the operation.
+pub async fn write_csv<S, W>(stream: S, writer: W, options: &CsvOptions) -> Result<u64>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    W: AsyncWrite + Unpin,
+{
+    let mut csv = AsyncWriterBuilder::new()
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .create_writer(writer);
+
+    let mut stream = std::pin::pin!(stream);
+    let mut schema: Option<Arc<RecordSchema>> = None;
+    let mut current: Option<Vec<String>> = None;
+    let mut rows = 0u64;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+
+        // The header only needs rebuilding when a record arrives with a new
+        // schema; records of the same table share theirs.
+        let same_schema = schema
+            .as_ref()
+            .is_some_and(|s| Arc::ptr_eq(s, record.schema()));
+        let changed = match &options.columns {
+            Some(columns) => current.is_none().then(|| columns.clone()),
+            None if same_schema => None,
+            None => {
+                schema = Some(record.schema().clone());
+                let columns: Vec<String> = record.columns().map(str::to_string).collect();
+                (current.as_ref() != Some(&columns)).then_some(columns)
+            }
+        };
+
+        if let Some(columns) = changed {
+            if options.header {
+                write_header(&mut csv, &record, &columns, options.annotated).await?;
+            }
+            current = Some(columns);
+        }
+
+        let columns = current.as_deref().unwrap_or_default();
+        let mut row = Vec::with_capacity(columns.len() + 1);
+        if options.annotated {
+            row.push(String::new());
+        }
+        row.extend(columns.iter().map(|c| {
+            record
+                .get(c)
+                .map(|v| v.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }));
+        write_row(&mut csv, &row).await?;
+        rows += 1;
+    }
+
+    csv.flush().await?;
+    Ok(rows)
+}
+
+/// Write the header (and annotations, if enabled) for a block of rows.
+async fn write_header<W: AsyncWrite + Unpin>(
+    csv: &mut AsyncWriter<W>,
+    record: &FluxRecord,
+    columns: &[String],
+    annotated: bool,
+) -> Result<()> {
+    if annotated {
+        let datatypes = columns.iter().map(|c| {
+            record
+                .get(c)
+                .and_then(Value::data_type)
+                .unwrap_or(DataType::String)
+                .to_string()
+        });
+        let group = columns.iter().map(|_| "false".to_string());
+        let default = columns.iter().map(|_| String::new());
+
+        write_row(csv, &annotation("#datatype", datatypes)).await?;
+        write_row(csv, &annotation("#group", group)).await?;
+        write_row(csv, &annotation("#default", default)).await?;
+        write_row(csv, &annotation("", columns.iter().cloned())).await?;
+    } else {
+        write_row(csv, columns).await?;
+    }
+    Ok(())
+}
+
+fn annotation(first: &str, cells: impl Iterator<Item = String>) -> Vec<String> {
+    std::iter::once(first.to_string()).chain(cells).collect()
+}
+
+async fn write_row<W: AsyncWrite + Unpin>(csv: &mut AsyncWriter<W>, row: &[String]) -> Result<()> {
+    csv.write_record(row)
+        .await
+        .map_err(|e| csv_error("CSV write error", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AnnotatedCsvParser;
+    use chrono::DateTime;
+    use futures::stream;
+    use ordered_float::OrderedFloat;
+    use std::io::Cursor;
+
+    fn record(table: i32, host: &str, value: f64) -> FluxRecord {
+        let mut record = FluxRecord::new(table);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-11-14T12:00:00Z").unwrap()),
+        );
+        record.insert("_value".to_string(), Value::Double(OrderedFloat(value)));
+        record.insert("host".to_string(), Value::String(host.to_string()));
+        record
+    }
+
+    async fn render(records: Vec<FluxRecord>, options: &CsvOptions) -> (u64, String) {
+        let mut out = Vec::new();
+        let rows = write_csv(stream::iter(records.into_iter().map(Ok)), &mut out, options)
+            .await
+            .unwrap();
+        (rows, String::from_utf8(out).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_plain() {
+        let (rows, out) = render(
+            vec![record(0, "server1", 1.5), record(0, "server2", 2.5)],
+            &CsvOptions::new(),
+        )
+        .await;
+
+        assert_eq!(rows, 2);
+        assert_eq!(
+            out,
+            "_time,_value,host\n\
+             2023-11-14T12:00:00Z,1.5,server1\n\
+             2023-11-14T12:00:00Z,2.5,server2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_escaping() {
+        let (_, out) = render(
+            vec![record(0, "rack \"a\", row 1", 1.0)],
+            &CsvOptions::new().columns(["host"]).header(false),
+        )
+        .await;
+
+        assert_eq!(out, "\"rack \"\"a\"\", row 1\"\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_columns_and_delimiter() {
+        let (_, out) = render(
+            vec![record(0, "server1", 1.5)],
+            &CsvOptions::new()
+                .columns(["host", "_value", "missing"])
+                .delimiter(b';'),
+        )
+        .await;
+
+        assert_eq!(out, "host;_value;missing\nserver1;1.5;\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_new_header_on_schema_change() {
+        let mut other = FluxRecord::new(1);
+        other.insert("count".to_string(), Value::Long(3));
+
+        let (rows, out) = render(vec![record(0, "server1", 1.5), other], &CsvOptions::new()).await;
+
+        assert_eq!(rows, 2);
+        assert_eq!(
+            out,
+            "_time,_value,host\n2023-11-14T12:00:00Z,1.5,server1\ncount\n3\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_annotated_roundtrip() {
+        let (_, out) = render(
+            vec![record(0, "server1", 1.5), record(0, "server2", 2.5)],
+            &CsvOptions::new().annotated(true),
+        )
+        .await;
+
+        let mut parser = AnnotatedCsvParser::new(Cursor::new(out.into_bytes()));
+        let first = parser.next().await.unwrap().unwrap();
+        assert_eq!(first.get_string("host"), Some("server1".to_string()));
+        assert_eq!(first.get_double("_value"), Some(1.5));
+        assert!(first.time().is_some());
+
+        let second = parser.next().await.unwrap().unwrap();
+        assert_eq!(second.get_string("host"), Some("server2".to_string()));
+        assert!(parser.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_stream_error() {
+        let input = stream::iter(vec![
+            Ok(record(0, "server1", 1.5)),
+            Err(crate::Error::Csv("boom".to_string())),
+        ]);
+        let mut out = Vec::new();
+
+        let result = write_csv(input, &mut out, &CsvOptions::new()).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,384 @@
+//! Line protocol output for record streams.
+//!
+//! Query results are turned back into the format InfluxDB ingests, so they
+//! can be replayed into another bucket or server with `influx write`.
+//!
+//! Tags are the string columns of the table's group key, except the reserved
+//! Flux columns, as InfluxDB groups query results by series. Records
+//! without a group key, such as those read without the `#group` annotation,
+//! use every string column except the reserved ones, like
+//! [`Checkpoint::group_key`](crate::checkpoint::Checkpoint::group_key).
+//! Records with a `_field` column write `_value` under that field name;
+//! pivoted records write every remaining column as a field.
+
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Columns that are never written as tags or fields.
+pub(crate) const RESERVED: &[&str] = &[
+    "result",
+    "table",
+    "_start",
+    "_stop",
+    "_time",
+    "_measurement",
+    "_field",
+    "_value",
+];
+
+/// Timestamp precision of written lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Precision {
+    /// Nanoseconds (`ns`), InfluxDB's default.
+    #[default]
+    Nanoseconds,
+    /// Microseconds (`us`).
+    Microseconds,
+    /// Milliseconds (`ms`).
+    Milliseconds,
+    /// Seconds (`s`).
+    Seconds,
+}
+
+impl Precision {
+    /// Get the value of the `precision` parameter of the write API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Precision::Nanoseconds => "ns",
+            Precision::Microseconds => "us",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+        }
+    }
+
+    pub(crate) fn divisor(self) -> i64 {
+        match self {
+            Precision::Nanoseconds => 1,
+            Precision::Microseconds => 1_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Seconds => 1_000_000_000,
+        }
+    }
+}
+
+/// Options for [`write_line_protocol`] and [`to_line_protocol`].
+#[derive(Clone, Debug, Default)]
+pub struct LineProtocolOptions {
+    tags: Option<Vec<String>>,
+    precision: Precision,
+}
+
+impl LineProtocolOptions {
+    /// Create options inferring tags, with nanosecond timestamps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write exactly these columns as tags, in this order.
+    ///
+    /// Other non-reserved columns of pivoted records become fields.
+    pub fn tags<I, T>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.tags = Some(tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the timestamp precision (default: nanoseconds).
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub(crate) fn timestamp_precision(&self) -> Precision {
+        self.precision
+    }
+
+    fn is_tag(&self, record: &FluxRecord, name: &str, value: &Value) -> bool {
+        match &self.tags {
+            Some(tags) => tags.iter().any(|t| t == name),
+            None => {
+                let schema = record.schema();
+                let grouped = !schema.has_group_key() || schema.is_group_key(name);
+                grouped && value.as_string().is_some() && !RESERVED.contains(&name)
+            }
+        }
+    }
+}
+
+/// Convert a record to one line of line protocol, without trailing newline.
+///
+/// Returns `Ok(None)` for records without a non-null field value, which line
+/// protocol cannot express. Fails with [`Error::Encode`] if the record has no
+/// `_measurement`.
+pub fn to_line_protocol(
+    record: &FluxRecord,
+    options: &LineProtocolOptions,
+) -> Result<Option<String>> {
+    let measurement = record
+        .get("_measurement")
+        .and_then(Value::as_string)
+        .ok_or_else(|| Error::Encode("Record has no _measurement column".to_string()))?;
+
+    let mut tags: Vec<(&str, &str)> = match &options.tags {
+        Some(names) => names
+            .iter()
+            .filter_map(|n| Some((n.as_str(), record.get(n)?.as_string()?)))
+            .collect(),
+        None => record
+            .iter()
+            .filter(|(name, value)| options.is_tag(record, name, value))
+            .filter_map(|(name, value)| Some((name, value.as_string()?)))
+            .collect(),
+    };
+    if options.tags.is_none() {
+        // Line protocol recommends sorted tags for write performance.
+        tags.sort_unstable();
+    }
+
+    let fields: Vec<(&str, &Value)> = match record.get("_field").and_then(Value::as_string) {
+        Some(field) => record
+            .get("_value")
+            .map(|v| (field, v))
+            .into_iter()
+            .collect(),
+        None => record
+            .iter()
+            .filter(|(name, value)| {
+                !RESERVED.contains(name) && !options.is_tag(record, name, value)
+            })
+            .collect(),
+    };
+
+    format_line(measurement, tags, fields, record.time(), options.precision)
+}
+
+/// Assemble a line from its parts, without trailing newline.
+///
+/// Empty tag values and fields that line protocol cannot express are left
+/// out; returns `Ok(None)` if no field remains.
+pub(crate) fn format_line<'a>(
+    measurement: &str,
+    tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+    fields: impl IntoIterator<Item = (&'a str, &'a Value)>,
+    time: Option<&DateTime<FixedOffset>>,
+    precision: Precision,
+) -> Result<Option<String>> {
+    let mut line = escape(measurement, &[',', ' ']);
+    for (key, value) in tags.into_iter().filter(|(_, v)| !v.is_empty()) {
+        line.push(',');
+        line.push_str(&escape(key, &[',', '=', ' ']));
+        line.push('=');
+        line.push_str(&escape(value, &[',', '=', ' ']));
+    }
+
+    let mut empty = true;
+    for (key, value) in fields {
+        let Some(value) = field_value(value) else {
+            continue;
+        };
+        line.push(if empty { ' ' } else { ',' });
+        line.push_str(&escape(key, &[',', '=', ' ']));
+        line.push('=');
+        line.push_str(&value);
+        empty = false;
+    }
+    if empty {
+        return Ok(None);
+    }
+
+    if let Some(time) = time {
+        let nanos = time
+            .timestamp_nanos_opt()
+            .ok_or_else(|| Error::Encode("_time does not fit in 64-bit nanoseconds".to_string()))?;
+        line.push(' ');
+        line.push_str(&nanos.div_euclid(precision.divisor()).to_string());
+    }
+    Ok(Some(line))
+}
+
+/// Write every record of `stream` to `writer` as line protocol.
+///
+/// Output is buffered and flushed once the stream ends. Returns the number of
+/// lines written; records without field values are skipped. The first error
+/// from the stream, the conversion or the writer aborts the operation.
+pub async fn write_line_protocol<S, W>(
+    stream: S,
+    writer: W,
+    options: &LineProtocolOptions,
+) -> Result<u64>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    W: AsyncWrite + Unpin,
+{
+    let mut writer = BufWriter::new(writer);
+    let mut stream = std::pin::pin!(stream);
+    let mut lines = 0u64;
+
+    while let Some(record) = stream.next().await {
+        if let Some(mut line) = to_line_protocol(&record?, options)? {
+            line.push('\n');
+            writer.write_all(line.as_bytes()).await?;
+            lines += 1;
+        }
+    }
+
+    writer.flush().await?;
+    Ok(lines)
+}
+
+/// Format a field value, or `None` if it cannot be written.
+fn field_value(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Double(d) if d.is_finite() => d.to_string(),
+        Value::Double(_) | Value::Null => return None,
+        Value::Long(n) => format!("{}i", n),
+        Value::UnsignedLong(n) => format!("{}u", n),
+        Value::Bool(b) => b.to_string(),
+        Value::Duration(d) => format!("{}i", d.num_nanoseconds()?),
+        Value::String(_) | Value::SharedString(_) => quote(value.as_string()?),
+        Value::TimeRFC(t) => quote(&t.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        Value::Base64Binary(b) => quote(&base64::engine::general_purpose::STANDARD.encode(b)),
+    })
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s, &['"']))
+}
+
+/// Backslash-escape `special` characters and backslashes before them.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) || (c == '\\' && special.contains(&'"')) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FluxColumn, RecordSchema};
+    use chrono::DateTime;
+    use futures::stream;
+    use ordered_float::OrderedFloat;
+    use std::sync::Arc;
+
+    fn record(field: Option<&str>, value: Value) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("result".to_string(), Value::String("_result".to_string()));
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-11-14T12:00:00Z").unwrap()),
+        );
+        record.insert(
+            "_measurement".to_string(),
+            Value::String("cpu load".to_string()),
+        );
+        record.insert("host".to_string(), Value::String("server,1".to_string()));
+        record.insert("dc".to_string(), Value::String("eu".to_string()));
+        if let Some(field) = field {
+            record.insert("_field".to_string(), Value::String(field.to_string()));
+            record.insert("_value".to_string(), value);
+        }
+        record
+    }
+
+    #[test]
+    fn test_line_from_field_record() {
+        let line = to_line_protocol(
+            &record(Some("usage"), Value::Double(OrderedFloat(0.5))),
+            &LineProtocolOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            line.as_deref(),
+            Some("cpu\\ load,dc=eu,host=server\\,1 usage=0.5 1699963200000000000")
+        );
+    }
+
+    #[test]
+    fn test_line_tags_from_group_key() {
+        let record = record(Some("usage"), Value::Double(OrderedFloat(0.5)));
+        let columns: Vec<_> = record
+            .columns()
+            .map(|name| FluxColumn {
+                name: name.to_string(),
+                group: ["_measurement", "_field", "host"].contains(&name),
+                ..FluxColumn::new()
+            })
+            .collect();
+        let grouped = FluxRecord::from_parts(
+            0,
+            Arc::new(RecordSchema::from_columns(&columns)),
+            record.values().to_vec(),
+        );
+
+        assert_eq!(
+            to_line_protocol(&grouped, &LineProtocolOptions::new())
+                .unwrap()
+                .as_deref(),
+            Some("cpu\\ load,host=server\\,1 usage=0.5 1699963200000000000")
+        );
+    }
+
+    #[test]
+    fn test_line_from_pivoted_record() {
+        let mut pivoted = record(None, Value::Null);
+        pivoted.insert("count".to_string(), Value::Long(3));
+        pivoted.insert("note".to_string(), Value::String("say \"hi\"".to_string()));
+
+        let options = LineProtocolOptions::new()
+            .tags(["host"])
+            .precision(Precision::Seconds);
+        assert_eq!(
+            to_line_protocol(&pivoted, &options).unwrap().as_deref(),
+            Some(
+                "cpu\\ load,host=server\\,1 dc=\"eu\",count=3i,note=\"say \\\"hi\\\"\" 1699963200"
+            )
+        );
+    }
+
+    #[test]
+    fn test_line_without_fields_or_measurement() {
+        let options = LineProtocolOptions::new();
+        assert_eq!(
+            to_line_protocol(&record(Some("x"), Value::Null), &options).unwrap(),
+            None
+        );
+        assert!(matches!(
+            to_line_protocol(&FluxRecord::new(0), &options),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_write_line_protocol() {
+        let records = vec![
+            Ok(record(Some("a"), Value::Long(1))),
+            Ok(record(Some("b"), Value::Null)),
+            Ok(record(Some("c"), Value::Bool(true))),
+        ];
+        let mut out = Vec::new();
+
+        let lines =
+            write_line_protocol(stream::iter(records), &mut out, &LineProtocolOptions::new())
+                .await
+                .unwrap();
+        assert_eq!(lines, 2);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 2);
+        assert!(out.ends_with("c=true 1699963200000000000\n"));
+    }
+}
@@ -0,0 +1,50 @@
+//! Terminal operations that write record streams to files and writers.
+//!
+//! Sinks consume a `Stream<Item = Result<FluxRecord>>` to completion, writing
+//! each record as it arrives. The most common ones are also available as
+//! methods through [`RecordStreamExt`](crate::adapters::RecordStreamExt).
+//!
+//! # Columnar output
+//!
+//! The `parquet` feature enables [`parquet::write_parquet`] and the `arrow`
+//! feature enables [`arrow::write_arrow_ipc`]. Schemas are
+//! derived from the records themselves, with one nullable column per record
+//! column:
+//!
+//! | Value          | Arrow type                   |
+//! |----------------|------------------------------|
+//! | `String`       | `Utf8`                       |
+//! | `Double`       | `Float64`                    |
+//! | `Bool`         | `Boolean`                    |
+//! | `Long`         | `Int64`                      |
+//! | `UnsignedLong` | `UInt64`                     |
+//! | `Duration`     | `Duration(Nanosecond)`       |
+//! | `Base64Binary` | `Binary`                     |
+//! | `TimeRFC`      | `Timestamp(Nanosecond, UTC)` |
+//! | `Null`         | `Utf8`                       |
+//!
+//! For typed streams, the `serde-arrow` feature enables
+//! [`serde_arrow::record_batches`], which builds batches from the `serde`
+//! representation of each item instead.
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(any(feature = "arrow", feature = "parquet"))]
+mod batch;
+pub mod csv;
+pub mod line_protocol;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "serde-arrow")]
+pub mod serde_arrow;
+
+#[cfg(feature = "arrow")]
+pub use arrow::{ArrowIpcOptions, IpcFormat, write_arrow_ipc};
+pub use csv::{CsvOptions, write_csv};
+pub use line_protocol::{LineProtocolOptions, Precision, to_line_protocol, write_line_protocol};
+#[cfg(feature = "parquet")]
+pub use parquet::{ParquetOptions, ParquetSummary, write_parquet};
+#[cfg(feature = "prometheus")]
+pub use prometheus::{RemoteWriteOptions, remote_write_requests};
@@ -0,0 +1,344 @@
+//! Parquet output for record streams.
+//!
+//! Requires the `parquet` feature.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use futures::{Stream, StreamExt};
+use parquet::arrow::AsyncArrowWriter;
+use parquet::file::properties::WriterProperties;
+
+pub use parquet::basic::Compression;
+
+use crate::error::{Error, Result};
+use crate::sink::batch::BatchBuilder;
+use crate::types::FluxRecord;
+
+/// Options for [`write_parquet`].
+#[derive(Clone, Debug)]
+pub struct ParquetOptions {
+    dir: PathBuf,
+    prefix: String,
+    batch_size: usize,
+    row_group_size: usize,
+    max_file_bytes: Option<u64>,
+    max_file_span: Option<TimeDelta>,
+    compression: Compression,
+}
+
+impl ParquetOptions {
+    /// Write files into `dir`, which is created if missing.
+    ///
+    /// Files are named `{prefix}-{n:05}.parquet`, with the prefix defaulting
+    /// to `part`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            prefix: "part".to_string(),
+            batch_size: 8192,
+            row_group_size: 1024 * 1024,
+            max_file_bytes: None,
+            max_file_span: None,
+            compression: Compression::SNAPPY,
+        }
+    }
+
+    /// Set the file name prefix.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the number of records buffered before they are encoded (default: 8192).
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows.max(1);
+        self
+    }
+
+    /// Set the maximum number of rows per row group (default: 1048576).
+    pub fn row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = rows.max(1);
+        self
+    }
+
+    /// Start a new file once the current one reaches roughly `bytes`.
+    ///
+    /// The size includes data buffered for the row group in progress, so files
+    /// may exceed the limit by up to one batch.
+    pub fn max_file_bytes(mut self, bytes: u64) -> Self {
+        self.max_file_bytes = Some(bytes);
+        self
+    }
+
+    /// Start a new file once records span more than `span` of `_time`.
+    ///
+    /// The span is measured from the first record written to the file, which
+    /// gives time-partitioned files for time-ordered streams.
+    pub fn max_file_span(mut self, span: TimeDelta) -> Self {
+        self.max_file_span = Some(span);
+        self
+    }
+
+    /// Set the compression codec (default: Snappy).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        self.dir
+            .join(format!("{}-{:05}.parquet", self.prefix, index))
+    }
+}
+
+/// Files and rows written by [`write_parquet`].
+#[derive(Clone, Debug, Default)]
+pub struct ParquetSummary {
+    /// Paths of the files written, in order.
+    pub files: Vec<PathBuf>,
+    /// Total number of rows written.
+    pub rows: u64,
+}
+
+/// Write every record of `stream` to Parquet files.
+///
+/// The schema of each file is derived from its first record (see
+/// [`RecordBatch` conversion](crate::sink) for the type mapping). A new file
+/// is started when a record does not fit the current schema, for example when
+/// a table with different columns begins, and when the size or time limits in
+/// `options` are reached.
+pub async fn write_parquet<S>(stream: S, options: &ParquetOptions) -> Result<ParquetSummary>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    tokio::fs::create_dir_all(&options.dir).await?;
+
+    let mut stream = std::pin::pin!(stream);
+    let mut summary = ParquetSummary::default();
+    let mut file: Option<ParquetFile> = None;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+
+        let rotate = match &file {
+            Some(f) => f.must_rotate(&record, options),
+            None => true,
+        };
+        if rotate {
+            if let Some(f) = file.take() {
+                f.close().await?;
+            }
+            let path = options.path(summary.files.len());
+            file = Some(ParquetFile::create(&path, &record, options).await?);
+            summary.files.push(path);
+        }
+
+        if let Some(f) = file.as_mut() {
+            f.push(&record, options).await?;
+            summary.rows += 1;
+        }
+    }
+
+    if let Some(f) = file {
+        f.close().await?;
+    }
+
+    Ok(summary)
+}
+
+/// A Parquet file being written.
+struct ParquetFile {
+    writer: AsyncArrowWriter<tokio::fs::File>,
+    batch: BatchBuilder,
+    first_time: Option<DateTime<FixedOffset>>,
+}
+
+impl ParquetFile {
+    async fn create(path: &Path, record: &FluxRecord, options: &ParquetOptions) -> Result<Self> {
+        let batch = BatchBuilder::for_record(record);
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(options.row_group_size)
+            .set_compression(options.compression)
+            .build();
+        let file = tokio::fs::File::create(path).await?;
+        let writer =
+            AsyncArrowWriter::try_new(file, batch.schema(), Some(props)).map_err(encode_error)?;
+
+        Ok(Self {
+            writer,
+            batch,
+            first_time: None,
+        })
+    }
+
+    fn must_rotate(&self, record: &FluxRecord, options: &ParquetOptions) -> bool {
+        if !self.batch.accepts(record) {
+            return true;
+        }
+        if let Some(max) = options.max_file_bytes {
+            let size = self.writer.bytes_written() + self.writer.in_progress_size();
+            if size as u64 >= max {
+                return true;
+            }
+        }
+        if let (Some(span), Some(first), Some(t)) =
+            (options.max_file_span, self.first_time, record.time())
+        {
+            if *t - first >= span {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn push(&mut self, record: &FluxRecord, options: &ParquetOptions) -> Result<()> {
+        self.batch.push(record)?;
+        if self.first_time.is_none() {
+            self.first_time = record.time().copied();
+        }
+        if self.batch.len() >= options.batch_size {
+            self.flush_batch().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_batch(&mut self) -> Result<()> {
+        if !self.batch.is_empty() {
+            let batch = self.batch.finish()?;
+            self.writer.write(&batch).await.map_err(encode_error)?;
+        }
+        Ok(())
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.flush_batch().await?;
+        self.writer.close().await.map_err(encode_error)?;
+        Ok(())
+    }
+}
+
+fn encode_error(e: parquet::errors::ParquetError) -> Error {
+    Error::Encode(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use futures::stream;
+    use ordered_float::OrderedFloat;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn record(minute: u32, value: f64) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert(
+            "_time".to_string(),
+            Value::TimeRFC(
+                DateTime::parse_from_rfc3339(&format!("2023-11-14T12:{:02}:00Z", minute)).unwrap(),
+            ),
+        );
+        record.insert("_value".to_string(), Value::Double(OrderedFloat(value)));
+        record
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "influxdb-stream-parquet-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_rows(path: &Path) -> usize {
+        let file = std::fs::File::open(path).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_write_parquet_single_file() {
+        let dir = temp_dir("single");
+        let records = (0..10).map(|i| Ok(record(i, i as f64)));
+
+        let summary = write_parquet(
+            stream::iter(records),
+            &ParquetOptions::new(&dir).batch_size(3),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(summary.rows, 10);
+        assert_eq!(summary.files, vec![dir.join("part-00000.parquet")]);
+        assert_eq!(read_rows(&summary.files[0]), 10);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_parquet_rotates_on_span() {
+        let dir = temp_dir("span");
+        let records = (0..6).map(|i| Ok(record(i, i as f64)));
+        let options = ParquetOptions::new(&dir)
+            .prefix("cpu")
+            .max_file_span(TimeDelta::minutes(2));
+
+        let summary = write_parquet(stream::iter(records), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files.len(), 3);
+        assert_eq!(summary.files[2], dir.join("cpu-00002.parquet"));
+        for file in &summary.files {
+            assert_eq!(read_rows(file), 2);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_parquet_rotates_on_size() {
+        let dir = temp_dir("size");
+        let records = (0..3).map(|i| Ok(record(i, i as f64)));
+        let options = ParquetOptions::new(&dir).batch_size(1).max_file_bytes(1);
+
+        let summary = write_parquet(stream::iter(records), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files.len(), 3);
+        assert_eq!(summary.rows, 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_parquet_rotates_on_schema_change() {
+        let dir = temp_dir("schema");
+        let mut other = FluxRecord::new(1);
+        other.insert("count".to_string(), Value::Long(1));
+        let records = vec![Ok(record(0, 1.0)), Ok(other)];
+
+        let summary = write_parquet(stream::iter(records), &ParquetOptions::new(&dir))
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_parquet_empty_stream() {
+        let dir = temp_dir("empty");
+        let summary = write_parquet(stream::empty(), &ParquetOptions::new(&dir))
+            .await
+            .unwrap();
+
+        assert!(summary.files.is_empty());
+        assert_eq!(summary.rows, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
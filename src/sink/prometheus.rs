@@ -0,0 +1,355 @@
+//! Prometheus remote-write output for record streams.
+//!
+//! [`remote_write_requests`] turns records into snappy-compressed
+//! `WriteRequest` bodies for the Prometheus remote-write protocol (version
+//! 0.1.0), so InfluxDB data can be backfilled into Prometheus, Mimir, Thanos
+//! or VictoriaMetrics. Send each body as a `POST` with the [`HEADERS`].
+//!
+//! Each record becomes one sample:
+//!
+//! - the metric name (`__name__`) is `<_measurement>_<_field>`;
+//! - every other string column of the table's group key, except the reserved
+//!   Flux columns, becomes a label, so each InfluxDB series maps to one
+//!   Prometheus series; records without a group key, such as those read
+//!   without the `#group` annotation, use every string column;
+//! - `_value` becomes the sample value (booleans as `0` and `1`);
+//! - `_time` becomes the sample timestamp, truncated to milliseconds.
+//!
+//! Names are changed to match Prometheus' rules, with invalid characters
+//! replaced by `_`. Records without a numeric `_value`, a `_time` or a
+//! `_measurement` are skipped.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use futures::TryStreamExt;
+//! use influxdb_stream::sink::prometheus::{HEADERS, RemoteWriteOptions, remote_write_requests};
+//!
+//! let records = client.query_stream(query).await?;
+//! let mut bodies = std::pin::pin!(remote_write_requests(records, RemoteWriteOptions::new()));
+//! while let Some(body) = bodies.try_next().await? {
+//!     let mut request = http.post("http://mimir:9009/api/v1/push").body(body);
+//!     for (name, value) in HEADERS {
+//!         request = request.header(*name, *value);
+//!     }
+//!     request.send().await?.error_for_status()?;
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use async_stream::try_stream;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+
+use crate::error::{Error, Result};
+use crate::sink::line_protocol::RESERVED;
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Headers required on remote-write requests.
+pub const HEADERS: &[(&str, &str)] = &[
+    ("Content-Encoding", "snappy"),
+    ("Content-Type", "application/x-protobuf"),
+    ("X-Prometheus-Remote-Write-Version", "0.1.0"),
+];
+
+/// Default number of samples per request.
+pub const DEFAULT_BATCH_SIZE: usize = 2_000;
+
+/// Options for [`remote_write_requests`].
+#[derive(Clone, Debug)]
+pub struct RemoteWriteOptions {
+    batch_size: usize,
+    labels: Vec<(String, String)>,
+}
+
+impl Default for RemoteWriteOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            labels: Vec::new(),
+        }
+    }
+}
+
+impl RemoteWriteOptions {
+    /// Create options with the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of samples per request (default: [`DEFAULT_BATCH_SIZE`]).
+    pub fn batch_size(mut self, samples: usize) -> Self {
+        self.batch_size = samples.max(1);
+        self
+    }
+
+    /// Add a label to every series, such as `source="influxdb"`.
+    ///
+    /// Record columns of the same name take precedence.
+    pub fn label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels
+            .push((sanitize(&name.into(), false), value.into()));
+        self
+    }
+}
+
+/// Labels of one series, sorted by name as Prometheus requires.
+type Labels = Vec<(String, String)>;
+
+/// A sample: value and timestamp in milliseconds.
+type Sample = (f64, i64);
+
+/// Convert the records of `stream` into remote-write request bodies.
+///
+/// Each body holds up to the configured batch size of samples, grouped by
+/// series. The first error from the stream ends it.
+pub fn remote_write_requests<S>(
+    stream: S,
+    options: RemoteWriteOptions,
+) -> impl Stream<Item = Result<Bytes>>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+{
+    try_stream! {
+        let mut stream = std::pin::pin!(stream);
+        let mut batch: BTreeMap<Labels, Vec<Sample>> = BTreeMap::new();
+        let mut samples = 0;
+
+        while let Some(record) = stream.next().await {
+            let Some((labels, sample)) = to_sample(&record?, &options) else {
+                continue;
+            };
+            batch.entry(labels).or_default().push(sample);
+            samples += 1;
+            if samples >= options.batch_size {
+                yield compress(&encode_write_request(&std::mem::take(&mut batch)))?;
+                samples = 0;
+            }
+        }
+        if samples > 0 {
+            yield compress(&encode_write_request(&batch))?;
+        }
+    }
+}
+
+/// Get the labels and sample of a record, or `None` if it has no sample.
+fn to_sample(record: &FluxRecord, options: &RemoteWriteOptions) -> Option<(Labels, Sample)> {
+    let value = match record.value()? {
+        Value::Bool(b) => f64::from(u8::from(*b)),
+        other => other.as_f64()?,
+    };
+    let timestamp = record.time()?.timestamp_millis();
+
+    let mut name = record.measurement_str()?.to_string();
+    if let Some(field) = record.field_str() {
+        name.push('_');
+        name.push_str(field);
+    }
+
+    let schema = record.schema();
+    let grouped = |column| !schema.has_group_key() || schema.is_group_key(column);
+    let mut labels: BTreeMap<String, String> = options.labels.iter().cloned().collect();
+    for (column, value) in record.iter() {
+        let label = grouped(column) && !RESERVED.contains(&column);
+        if let Some(value) = value.as_string().filter(|_| label) {
+            labels.insert(sanitize(column, false), value.to_string());
+        }
+    }
+    labels.insert("__name__".to_string(), sanitize(&name, true));
+    labels.retain(|_, value| !value.is_empty());
+
+    Some((labels.into_iter().collect(), (value, timestamp)))
+}
+
+/// Make `name` a valid metric name (`[a-zA-Z_:][a-zA-Z0-9_:]*`) or, without
+/// `metric`, label name (no `:`).
+fn sanitize(name: &str, metric: bool) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+            ':' if metric => c,
+            _ => '_',
+        })
+        .collect();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn compress(message: &[u8]) -> Result<Bytes> {
+    snap::raw::Encoder::new()
+        .compress_vec(message)
+        .map(Bytes::from)
+        .map_err(|e| Error::Encode(format!("Snappy compression failed: {}", e)))
+}
+
+/// Encode a `prometheus.WriteRequest` protobuf message.
+fn encode_write_request(batch: &BTreeMap<Labels, Vec<Sample>>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (labels, samples) in batch {
+        let mut series = Vec::new();
+        for (name, value) in labels {
+            let mut label = Vec::new();
+            put_bytes(&mut label, 1, name.as_bytes());
+            put_bytes(&mut label, 2, value.as_bytes());
+            put_bytes(&mut series, 1, &label);
+        }
+        for (value, timestamp) in samples {
+            let mut sample = Vec::new();
+            put_key(&mut sample, 1, 1);
+            sample.extend_from_slice(&value.to_le_bytes());
+            put_key(&mut sample, 2, 0);
+            put_varint(&mut sample, *timestamp as u64);
+            put_bytes(&mut series, 2, &sample);
+        }
+        put_bytes(&mut out, 1, &series);
+    }
+    out
+}
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_key(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    put_varint(out, (field << 3) | wire_type);
+}
+
+/// Write a length-delimited field.
+fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_key(out, field, 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FluxColumn, RecordSchema};
+    use chrono::DateTime;
+    use futures::{TryStreamExt, stream};
+    use ordered_float::OrderedFloat;
+    use std::sync::Arc;
+
+    fn record(host: &str, value: Value) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("result", Value::from("_result"));
+        record.insert(
+            "_time",
+            Value::TimeRFC(DateTime::parse_from_rfc3339("2023-11-14T12:00:00.5Z").unwrap()),
+        );
+        record.insert("_measurement", Value::from("cpu"));
+        record.insert("_field", Value::from("usage.idle"));
+        record.insert("host", Value::from(host));
+        record.insert("_value", value);
+        record
+    }
+
+    #[test]
+    fn test_to_sample() {
+        let options = RemoteWriteOptions::new().label("source", "influx");
+        let (labels, sample) =
+            to_sample(&record("a", Value::Double(OrderedFloat(0.5))), &options).unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                ("__name__".to_string(), "cpu_usage_idle".to_string()),
+                ("host".to_string(), "a".to_string()),
+                ("source".to_string(), "influx".to_string()),
+            ]
+        );
+        assert_eq!(sample, (0.5, 1_699_963_200_500));
+
+        assert_eq!(
+            to_sample(&record("a", Value::Bool(true)), &options)
+                .unwrap()
+                .1
+                .0,
+            1.0
+        );
+        assert!(to_sample(&record("a", Value::from("up")), &options).is_none());
+    }
+
+    #[test]
+    fn test_labels_from_group_key() {
+        let mut record = record("a", Value::Double(OrderedFloat(0.5)));
+        record.insert("message", Value::from("ok"));
+        let columns: Vec<_> = record
+            .columns()
+            .map(|name| FluxColumn {
+                name: name.to_string(),
+                group: ["_measurement", "_field", "host"].contains(&name),
+                ..FluxColumn::new()
+            })
+            .collect();
+        let grouped = FluxRecord::from_parts(
+            0,
+            Arc::new(RecordSchema::from_columns(&columns)),
+            record.values().to_vec(),
+        );
+
+        let labels = |record| to_sample(record, &RemoteWriteOptions::new()).unwrap().0;
+        assert_eq!(
+            labels(&grouped),
+            vec![
+                ("__name__".to_string(), "cpu_usage_idle".to_string()),
+                ("host".to_string(), "a".to_string()),
+            ]
+        );
+        assert_eq!(labels(&record).len(), 3);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("http.requests:rate", true), "http_requests:rate");
+        assert_eq!(sanitize("a:b", false), "a_b");
+        assert_eq!(sanitize("5xx", false), "_5xx");
+    }
+
+    #[test]
+    fn test_encode_write_request() {
+        let mut batch = BTreeMap::new();
+        batch.insert(
+            vec![("__name__".to_string(), "up".to_string())],
+            vec![(1.0, 1_000)],
+        );
+        let encoded = encode_write_request(&batch);
+
+        let mut expected = vec![0x0a, 0x1e]; // timeseries, 30 bytes
+        expected.extend([0x0a, 0x0e]); // label, 14 bytes
+        expected.extend([0x0a, 0x08]);
+        expected.extend(b"__name__");
+        expected.extend([0x12, 0x02]);
+        expected.extend(b"up");
+        expected.extend([0x12, 0x0c, 0x09]); // sample, 12 bytes; double value
+        expected.extend(1.0f64.to_le_bytes());
+        expected.extend([0x10, 0xe8, 0x07]); // timestamp 1000
+        assert_eq!(encoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_remote_write_requests_batches() {
+        let records = (0..5).map(|i| Ok(record(&format!("h{}", i % 2), Value::Long(i))));
+        let options = RemoteWriteOptions::new().batch_size(2);
+
+        let bodies: Vec<Bytes> = remote_write_requests(stream::iter(records), options)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(bodies.len(), 3);
+
+        let decoded = snap::raw::Decoder::new()
+            .decompress_vec(&bodies[0])
+            .unwrap();
+        assert!(decoded.windows(2).any(|w| w == b"h0"));
+        assert!(decoded.windows(2).any(|w| w == b"h1"));
+    }
+}
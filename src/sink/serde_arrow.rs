@@ -0,0 +1,200 @@
+//! Arrow record batches from typed streams, via `serde`.
+//!
+//! Requires the `serde-arrow` feature. Any `Serialize` type works, so the
+//! structs produced by [`typed`](crate::typed) or
+//! [`Client::query_typed`](crate::Client::query_typed) can be turned into
+//! columnar data without a second mapping.
+//!
+//! ```ignore
+//! use influxdb_stream::sink::serde_arrow::{fields_for, record_batches};
+//!
+//! let fields = fields_for::<Cpu>()?;
+//! let stream = client.query_typed::<Cpu>(&registry, &range).await?;
+//! let mut batches = std::pin::pin!(record_batches(stream, fields, 8192));
+//! while let Some(batch) = batches.next().await {
+//!     let batch: RecordBatch = batch?;
+//! }
+//! ```
+
+use arrow_array::RecordBatch;
+use arrow_schema::FieldRef;
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_arrow::ArrayBuilder;
+use serde_arrow::schema::{SchemaLike, TracingOptions};
+
+use crate::error::{Error, Result};
+
+/// Derive Arrow fields from the `serde` representation of `T`.
+///
+/// Every field is nullable and strings map to `LargeUtf8`. chrono timestamps
+/// serialize as strings too, so build the fields by hand (for example with
+/// [`SchemaLike`]) to store them as Arrow timestamps.
+pub fn fields_for<T: DeserializeOwned>() -> Result<Vec<FieldRef>> {
+    let options = TracingOptions::default().allow_null_fields(true);
+    Vec::<FieldRef>::from_type::<T>(options).map_err(encode_error)
+}
+
+/// Group `stream` into record batches of at most `batch_size` rows.
+///
+/// Each item is serialized into `fields` as it arrives, so at most one batch
+/// is held in memory. Errors from `stream` end it after a last batch of the
+/// rows received so far, then the error. An item that does not fit the
+/// fields ends the stream with [`Error::Encode`] alone, as part of it may
+/// already be in the batch. An empty input yields no batches.
+pub fn record_batches<S, T>(
+    stream: S,
+    fields: Vec<FieldRef>,
+    batch_size: usize,
+) -> impl Stream<Item = Result<RecordBatch>>
+where
+    S: Stream<Item = Result<T>>,
+    T: Serialize,
+{
+    let batch_size = batch_size.max(1);
+    stream! {
+        let mut builder = match ArrayBuilder::from_arrow(&fields) {
+            Ok(builder) => builder,
+            Err(e) => {
+                yield Err(encode_error(e));
+                return;
+            }
+        };
+        let mut rows = 0usize;
+
+        let mut stream = std::pin::pin!(stream);
+        while let Some(item) = stream.next().await {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    if rows > 0 {
+                        yield builder.to_record_batch().map_err(encode_error);
+                    }
+                    yield Err(e);
+                    return;
+                }
+            };
+            if let Err(e) = builder.push(&item) {
+                yield Err(encode_error(e));
+                return;
+            }
+            rows += 1;
+
+            if rows >= batch_size {
+                rows = 0;
+                yield builder.to_record_batch().map_err(encode_error);
+            }
+        }
+
+        if rows > 0 {
+            yield builder.to_record_batch().map_err(encode_error);
+        }
+    }
+}
+
+/// Collect all of `stream` into a single record batch.
+///
+/// **Warning**: holds the entire result in memory. Prefer
+/// [`record_batches`] for large results.
+pub async fn to_record_batch<S, T>(stream: S, fields: &[FieldRef]) -> Result<RecordBatch>
+where
+    S: Stream<Item = Result<T>>,
+    T: Serialize,
+{
+    let mut builder = ArrayBuilder::from_arrow(fields).map_err(encode_error)?;
+    let mut stream = std::pin::pin!(stream);
+    while let Some(item) = stream.next().await {
+        builder.push(&item?).map_err(encode_error)?;
+    }
+    builder.to_record_batch().map_err(encode_error)
+}
+
+fn encode_error(e: serde_arrow::Error) -> Error {
+    Error::Encode(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, Float64Array, LargeStringArray};
+    use futures::{TryStreamExt, stream};
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Cpu {
+        host: String,
+        usage: f64,
+        core: Option<i64>,
+    }
+
+    fn cpus(n: usize) -> impl Stream<Item = Result<Cpu>> {
+        stream::iter((0..n).map(|i| {
+            Ok(Cpu {
+                host: format!("host{}", i),
+                usage: i as f64 / 2.0,
+                core: None,
+            })
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_record_batches() {
+        let fields = fields_for::<Cpu>().unwrap();
+        let batches: Vec<RecordBatch> = record_batches(cpus(5), fields, 2)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let rows: Vec<usize> = batches.iter().map(|b| b.num_rows()).collect();
+        assert_eq!(rows, vec![2, 2, 1]);
+
+        let batch = &batches[1];
+        let hosts = batch
+            .column_by_name("host")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<LargeStringArray>()
+            .unwrap();
+        assert_eq!(hosts.value(0), "host2");
+        let usage = batch
+            .column_by_name("usage")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(usage.value(1), 1.5);
+        assert!(batch.column_by_name("core").unwrap().is_null(0));
+    }
+
+    #[tokio::test]
+    async fn test_record_batches_stops_on_error() {
+        let fields = fields_for::<Cpu>().unwrap();
+        let input = cpus(3).chain(stream::iter(vec![
+            Err(Error::Csv("bad".to_string())),
+            Ok(Cpu {
+                host: "late".to_string(),
+                usage: 0.0,
+                core: None,
+            }),
+        ]));
+        let items: Vec<Result<RecordBatch>> = record_batches(input, fields, 2).collect().await;
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap().num_rows(), 2);
+        assert_eq!(items[1].as_ref().unwrap().num_rows(), 1);
+        assert!(matches!(items[2], Err(Error::Csv(_))));
+    }
+
+    #[tokio::test]
+    async fn test_to_record_batch() {
+        let fields = fields_for::<Cpu>().unwrap();
+        let batch = to_record_batch(cpus(3), &fields).await.unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), 3);
+
+        let empty = to_record_batch(cpus(0), &fields).await.unwrap();
+        assert_eq!(empty.num_rows(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Records from InfluxDB 3 SQL query responses.
+//!
+//! `/api/v3/query_sql` answers in JSON Lines: one object per row, keyed by
+//! column name. Values keep their JSON type; the `time` column, which
+//! InfluxDB 3 writes as an RFC 3339 timestamp without offset, becomes a
+//! [`Value::TimeRFC`] in UTC.
+
+use std::sync::Arc;
+
+use async_stream::stream;
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ordered_float::OrderedFloat;
+use tokio::io::AsyncBufReadExt;
+use tokio_util::io::StreamReader;
+
+use crate::client::RecordStream;
+use crate::error::Result;
+use crate::transport::ByteStream;
+use crate::types::{FluxRecord, RecordSchema};
+use crate::value::Value;
+
+/// Stream the rows of a JSON Lines response body.
+pub(crate) fn jsonl_records(body: ByteStream) -> RecordStream {
+    let reader: StreamReader<ByteStream, Bytes> = StreamReader::new(body);
+    Box::pin(stream! {
+        let mut lines = reader.lines();
+        let mut schema = None;
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => {
+                    let record = parse_row(&line, &mut schema);
+                    let failed = record.is_err();
+                    yield record;
+                    if failed {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Parse one JSON Lines row into a record.
+///
+/// Rows with the same columns as the previous one share its `schema`; rows
+/// leave out null columns, so a result's schema can change between rows.
+pub(crate) fn parse_row(line: &str, schema: &mut Option<Arc<RecordSchema>>) -> Result<FluxRecord> {
+    let row: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)?;
+    let schema = match schema.as_ref().filter(|s| s.names().iter().eq(row.keys())) {
+        Some(same) => Arc::clone(same),
+        None => Arc::clone(schema.insert(Arc::new(RecordSchema::new(row.keys())))),
+    };
+    let values = row
+        .into_iter()
+        .map(|(name, value)| {
+            if name == "time" {
+                time_value(&value).unwrap_or_else(|| json_value(value))
+            } else {
+                json_value(value)
+            }
+        })
+        .collect();
+    Ok(FluxRecord::from_parts(0, schema, values))
+}
+
+fn json_value(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Long(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::UnsignedLong(u)
+            } else {
+                Value::Double(OrderedFloat(n.as_f64().unwrap_or(f64::NAN)))
+            }
+        }
+        serde_json::Value::String(s) => Value::String(s),
+        other => Value::String(other.to_string()),
+    }
+}
+
+fn time_value(value: &serde_json::Value) -> Option<Value> {
+    let s = value.as_str()?;
+    let time = match DateTime::parse_from_rfc3339(s) {
+        Ok(time) => time,
+        Err(_) => NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+            .ok()?
+            .and_utc()
+            .fixed_offset(),
+    };
+    Some(Value::TimeRFC(time.with_timezone(&Utc).fixed_offset()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{StreamExt, TryStreamExt, stream};
+
+    #[test]
+    fn test_parse_row_types() {
+        let record = parse_row(
+            r#"{"host":"server1","usage":0.5,"count":3,"ok":true,"note":null,"time":"2024-01-02T03:04:05.123"}"#,
+            &mut None,
+        )
+        .unwrap();
+
+        assert_eq!(record.get_string("host"), Some("server1".to_string()));
+        assert_eq!(record.get_double("usage"), Some(0.5));
+        assert_eq!(record.get_long("count"), Some(3));
+        assert_eq!(record.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(record.get("note"), Some(&Value::Null));
+        let time = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.123Z").unwrap();
+        assert_eq!(record.get("time"), Some(&Value::TimeRFC(time)));
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_records() {
+        let body = "{\"n\":1}\n\n{\"n\":2}\n";
+        let chunks = body
+            .as_bytes()
+            .chunks(3)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        let records: Vec<_> = jsonl_records(Box::pin(stream::iter(chunks)))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get_long("n"), Some(2));
+        assert!(Arc::ptr_eq(records[0].schema(), records[1].schema()));
+    }
+
+    #[test]
+    fn test_parse_row_schema_changes() {
+        let mut schema = None;
+        let first = parse_row(r#"{"host":"a","n":1}"#, &mut schema).unwrap();
+        let second = parse_row(r#"{"host":"b"}"#, &mut schema).unwrap();
+        assert_eq!(second.columns().collect::<Vec<_>>(), ["host"]);
+        assert_eq!(first.get_long("n"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_invalid_row() {
+        let chunks = vec![Ok(Bytes::from_static(b"{\"n\":1}\nnot json\n{\"n\":3}\n"))];
+        let items: Vec<_> = jsonl_records(Box::pin(stream::iter(chunks)))
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+}
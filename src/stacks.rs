@@ -0,0 +1,284 @@
+//! Stacks and templates.
+//!
+//! An InfluxDB template describes a set of resources, such as buckets,
+//! tasks, checks and dashboards, as a list of JSON or YAML objects. A stack
+//! records which resources a template installed, so applying a newer
+//! version of the template to the same stack updates or removes them instead
+//! of creating duplicates.
+//!
+//! ```ignore
+//! use influxdb_stream::stacks::TemplateApply;
+//!
+//! let stack = client.create_stack("monitoring").await?;
+//! let apply = TemplateApply::remote("https://example.com/monitoring.yml").stack(&stack.id);
+//!
+//! // Review the changes before installing them.
+//! let preview = client.apply_template(&apply.clone().dry_run(true)).await?;
+//! println!("{}", preview.diff);
+//! client.apply_template(&apply).await?;
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// A stack, as returned by the `/api/v2/stacks` endpoints.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stack {
+    /// Stack ID.
+    pub id: String,
+    /// ID of the organization owning the stack.
+    #[serde(rename = "orgID")]
+    pub org_id: String,
+    /// Creation time, as an RFC 3339 timestamp.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// Changes to the stack, oldest first.
+    #[serde(default)]
+    pub events: Vec<StackEvent>,
+}
+
+impl Stack {
+    /// Get the most recent event, which describes the current state.
+    pub fn latest(&self) -> Option<&StackEvent> {
+        self.events.last()
+    }
+
+    /// Get the current name of the stack.
+    pub fn name(&self) -> Option<&str> {
+        self.latest().map(|e| e.name.as_str())
+    }
+
+    /// Get the resources currently managed by the stack.
+    pub fn resources(&self) -> &[StackResource] {
+        self.latest().map_or(&[], |e| &e.resources)
+    }
+}
+
+/// A change to a [`Stack`], such as its creation or a template being applied.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StackEvent {
+    /// Event type, such as `create`, `update` or `uninstall`.
+    pub event_type: String,
+    /// Stack name at the time of the event.
+    pub name: String,
+    /// Stack description at the time of the event.
+    pub description: Option<String>,
+    /// Template URLs the stack was applied from.
+    pub sources: Vec<String>,
+    /// Template URLs associated with the stack.
+    pub urls: Vec<String>,
+    /// Resources managed by the stack after the event.
+    pub resources: Vec<StackResource>,
+    /// Time of the event, as an RFC 3339 timestamp.
+    pub updated_at: Option<String>,
+}
+
+/// A resource installed by a stack.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StackResource {
+    /// Template API version of the resource, such as `influxdata.com/v2alpha1`.
+    pub api_version: String,
+    /// ID of the resource on the server.
+    #[serde(rename = "resourceID")]
+    pub resource_id: String,
+    /// Resource kind, such as `Bucket`, `Task` or `Dashboard`.
+    pub kind: String,
+    /// `metadata.name` of the resource in the template.
+    pub template_meta_name: String,
+}
+
+/// Request body for [`Client::apply_template`](crate::Client::apply_template).
+///
+/// Templates can be given inline, as the parsed list of template objects, or
+/// as URLs the server fetches itself. Both can be combined.
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateApply {
+    #[serde(rename = "orgID", skip_serializing_if = "String::is_empty")]
+    pub(crate) org_id: String,
+    #[serde(rename = "stackID", skip_serializing_if = "Option::is_none")]
+    stack_id: Option<String>,
+    dry_run: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    templates: Vec<TemplateContents>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remotes: Vec<TemplateRemote>,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    env_refs: serde_json::Map<String, Json>,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    secrets: serde_json::Map<String, Json>,
+}
+
+impl std::fmt::Debug for TemplateApply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TemplateApply")
+            .field("org_id", &self.org_id)
+            .field("stack_id", &self.stack_id)
+            .field("dry_run", &self.dry_run)
+            .field("templates", &self.templates)
+            .field("remotes", &self.remotes)
+            .field("env_refs", &self.env_refs)
+            .field("secrets", &self.secrets.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TemplateContents {
+    contents: Json,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TemplateRemote {
+    url: String,
+}
+
+impl TemplateApply {
+    /// Apply an inline template: a JSON array of template objects.
+    pub fn new(template: Json) -> Self {
+        Self::default().template(template)
+    }
+
+    /// Apply the template the server downloads from `url`.
+    pub fn remote(url: impl Into<String>) -> Self {
+        Self::default().remote_url(url)
+    }
+
+    /// Also apply an inline template.
+    pub fn template(mut self, template: Json) -> Self {
+        self.templates.push(TemplateContents { contents: template });
+        self
+    }
+
+    /// Also apply the template at `url`.
+    pub fn remote_url(mut self, url: impl Into<String>) -> Self {
+        self.remotes.push(TemplateRemote { url: url.into() });
+        self
+    }
+
+    /// Install into the stack `id`, updating the resources it already
+    /// manages. Without a stack, the server creates a new one.
+    pub fn stack(mut self, id: impl Into<String>) -> Self {
+        self.stack_id = Some(id.into());
+        self
+    }
+
+    /// Only report what would change (default: `false`).
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set the value of the template environment reference `name`.
+    pub fn env_ref(mut self, name: impl Into<String>, value: impl Into<Json>) -> Self {
+        self.env_refs.insert(name.into(), value.into());
+        self
+    }
+
+    /// Provide the secret `name` referenced by the template.
+    pub fn secret(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.secrets.insert(name.into(), Json::String(value.into()));
+        self
+    }
+}
+
+/// Result of [`Client::apply_template`](crate::Client::apply_template).
+///
+/// The diff and summary are kept as JSON; their shape depends on the kinds
+/// of resources in the template.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TemplateSummary {
+    /// ID of the stack the template was applied to.
+    #[serde(rename = "stackID")]
+    pub stack_id: Option<String>,
+    /// Template sources that were applied.
+    pub sources: Vec<String>,
+    /// Changes between the installed resources and the template.
+    pub diff: Json,
+    /// Resources in the template, by kind.
+    pub summary: Json,
+    /// Resources the server could not apply.
+    pub errors: Vec<Json>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct StackList {
+    pub(crate) stacks: Vec<Stack>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NewStack<'a> {
+    #[serde(rename = "orgID")]
+    pub(crate) org_id: &'a str,
+    pub(crate) name: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ExportRequest<'a> {
+    #[serde(rename = "stackID")]
+    pub(crate) stack_id: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_template_apply_body() {
+        let apply = TemplateApply::new(json!([{"kind": "Bucket"}]))
+            .remote_url("https://example.com/t.yml")
+            .stack("0a1b")
+            .env_ref("bucket-name", "sensors")
+            .secret("token", "s3cr3t");
+
+        let body = serde_json::to_value(&apply).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "stackID": "0a1b",
+                "dryRun": false,
+                "templates": [{"contents": [{"kind": "Bucket"}]}],
+                "remotes": [{"url": "https://example.com/t.yml"}],
+                "envRefs": {"bucket-name": "sensors"},
+                "secrets": {"token": "s3cr3t"},
+            })
+        );
+
+        let debug = format!("{:?}", apply);
+        assert!(debug.contains("\"token\""));
+        assert!(!debug.contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_stack_latest_event() {
+        let stack: Stack = serde_json::from_value(json!({
+            "id": "0a1b",
+            "orgID": "org1",
+            "createdAt": "2024-01-01T00:00:00Z",
+            "events": [
+                {"eventType": "create", "name": "old", "resources": []},
+                {
+                    "eventType": "update",
+                    "name": "monitoring",
+                    "resources": [{
+                        "apiVersion": "influxdata.com/v2alpha1",
+                        "resourceID": "b1",
+                        "kind": "Bucket",
+                        "templateMetaName": "sensors",
+                    }],
+                },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(stack.name(), Some("monitoring"));
+        assert_eq!(stack.resources().len(), 1);
+        assert_eq!(stack.resources()[0].kind, "Bucket");
+    }
+}
@@ -0,0 +1,712 @@
+//! In-process mock InfluxDB server for tests.
+//!
+//! Requires the `testing` feature. [`MockServer`] listens on a local port and
+//! answers every request with the next queued [`MockResponse`], so streaming
+//! logic can be tested against canned annotated CSV, error tables, slow or
+//! truncated bodies and rate limiting without a real InfluxDB.
+//!
+//! For code written against [`QueryClient`], [`FakeClient`] serves preset
+//! records without any network I/O.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use influxdb_stream::testing::{MockResponse, MockServer};
+//!
+//! let server = MockServer::start().await?;
+//! server.enqueue(MockResponse::too_many_requests(Some(1)));
+//! server.enqueue(MockResponse::csv(CANNED_CSV).chunked(64));
+//!
+//! let client = server.client();
+//! let err = client.query_stream("buckets()").await.err().unwrap();
+//! assert!(err.is_retryable());
+//! let records = client.query("buckets()").await?;
+//! assert_eq!(server.requests().len(), 2);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use futures::stream;
+
+use crate::client::{Client, QueryClient, RecordStream};
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+
+/// Organization name used by [`MockServer::client`].
+pub const MOCK_ORG: &str = "test-org";
+
+/// Token used by [`MockServer::client`].
+pub const MOCK_TOKEN: &str = "test-token";
+
+/// A canned HTTP response served by [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    chunk_size: Option<usize>,
+    chunk_delay: Duration,
+    truncate_after: Option<usize>,
+}
+
+impl MockResponse {
+    /// Respond with `status` and `body`.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            chunk_size: None,
+            chunk_delay: Duration::ZERO,
+            truncate_after: None,
+        }
+    }
+
+    /// Respond with `200 OK` and an annotated CSV body.
+    ///
+    /// The body is served as given. End it with a blank line, as InfluxDB
+    /// does, or the client reports the response as
+    /// [truncated](crate::Error::Truncated).
+    pub fn csv(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200, body).header("Content-Type", "text/csv; charset=utf-8")
+    }
+
+    /// Respond with an annotated CSV error table, as InfluxDB does for
+    /// errors raised while the query runs.
+    pub fn error_table(message: &str, reference: Option<&str>) -> Self {
+        let body = format!(
+            "#datatype,string,string\n#group,true,true\n#default,,\n,error,reference\n,{},{}\n\n",
+            csv_field(message),
+            csv_field(reference.unwrap_or_default()),
+        );
+        Self::csv(body)
+    }
+
+    /// Respond with an error status and a JSON body in the InfluxDB API format.
+    pub fn api_error(status: u16, message: &str) -> Self {
+        let body = serde_json::json!({ "code": "invalid", "message": message }).to_string();
+        Self::new(status, body).header("Content-Type", "application/json")
+    }
+
+    /// Respond with `429 Too Many Requests`, optionally with a `Retry-After` in seconds.
+    pub fn too_many_requests(retry_after_secs: Option<u64>) -> Self {
+        let response = Self::api_error(429, "too many requests");
+        match retry_after_secs {
+            Some(secs) => response.header("Retry-After", secs.to_string()),
+            None => response,
+        }
+    }
+
+    /// Add a response header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send the body with chunked transfer encoding, `size` bytes per chunk.
+    pub fn chunked(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+
+    /// Wait `delay` before each chunk, to simulate a slow server.
+    ///
+    /// Implies chunked encoding; without [`chunked`](Self::chunked) the body
+    /// is sent as a single delayed chunk.
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Close the connection after `bytes` bytes of the body, simulating a dropped connection.
+    pub fn truncate_after(mut self, bytes: usize) -> Self {
+        self.truncate_after = Some(bytes);
+        self
+    }
+}
+
+/// A request received by [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// HTTP method, e.g. `POST`.
+    pub method: String,
+    /// Request target, including the query string.
+    pub target: String,
+    /// Request headers, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// Request body.
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Get the first header with the given (case-insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get the request path without the query string.
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Get the body as UTF-8 text.
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    responses: VecDeque<MockResponse>,
+    fallback: Option<MockResponse>,
+    requests: Vec<MockRequest>,
+}
+
+/// Mock InfluxDB HTTP server running on a local port.
+///
+/// Queued responses are served in order, one per request. When the queue is
+/// empty the [fallback](Self::set_fallback) is served, or `404 Not Found` if
+/// none is set. The server stops when dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start a server on a random local port.
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, shared.clone()));
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// Get the base URL of the server.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Create a client for this server using [`MOCK_ORG`] and [`MOCK_TOKEN`].
+    pub fn client(&self) -> Client {
+        Client::new(self.url(), MOCK_ORG, MOCK_TOKEN)
+    }
+
+    /// Queue a response for the next unanswered request.
+    pub fn enqueue(&self, response: MockResponse) {
+        self.state
+            .lock()
+            .expect("mock server lock poisoned")
+            .responses
+            .push_back(response);
+    }
+
+    /// Set the response served once the queue is empty.
+    pub fn set_fallback(&self, response: MockResponse) {
+        self.state
+            .lock()
+            .expect("mock server lock poisoned")
+            .fallback = Some(response);
+    }
+
+    /// Get the requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state
+            .lock()
+            .expect("mock server lock poisoned")
+            .requests
+            .clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+type ErrorFactory = Arc<dyn Fn() -> Error + Send + Sync>;
+
+#[derive(Default)]
+struct FakeState {
+    default: Vec<FluxRecord>,
+    by_query: HashMap<String, Vec<FluxRecord>>,
+    error: Option<ErrorFactory>,
+    queries: Vec<String>,
+}
+
+/// In-memory [`QueryClient`] yielding preset records.
+///
+/// Every query yields the default records, unless records were registered
+/// for that exact query with [`respond_to`](Self::respond_to). Clones share
+/// their configuration and the log of received queries.
+#[derive(Clone, Default)]
+pub struct FakeClient {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeClient {
+    /// Create a fake that yields `records` for every query.
+    pub fn new(records: Vec<FluxRecord>) -> Self {
+        let client = Self::default();
+        client
+            .state
+            .lock()
+            .expect("fake client lock poisoned")
+            .default = records;
+        client
+    }
+
+    /// Yield `records` for queries equal to `query`.
+    pub fn respond_to(self, query: impl Into<String>, records: Vec<FluxRecord>) -> Self {
+        self.state
+            .lock()
+            .expect("fake client lock poisoned")
+            .by_query
+            .insert(query.into(), records);
+        self
+    }
+
+    /// End every stream with an error produced by `error`, after its records.
+    pub fn then_error<F>(self, error: F) -> Self
+    where
+        F: Fn() -> Error + Send + Sync + 'static,
+    {
+        self.state.lock().expect("fake client lock poisoned").error = Some(Arc::new(error));
+        self
+    }
+
+    /// Get the queries received so far.
+    pub fn queries(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .expect("fake client lock poisoned")
+            .queries
+            .clone()
+    }
+}
+
+impl std::fmt::Debug for FakeClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().expect("fake client lock poisoned");
+        f.debug_struct("FakeClient")
+            .field("default", &state.default.len())
+            .field("by_query", &state.by_query.len())
+            .field("queries", &state.queries)
+            .finish()
+    }
+}
+
+impl QueryClient for FakeClient {
+    async fn query_stream(&self, query: impl Into<String> + Send) -> Result<RecordStream> {
+        let query = query.into();
+        let mut state = self.state.lock().expect("fake client lock poisoned");
+        let records = state.by_query.get(&query).unwrap_or(&state.default).clone();
+        let error = state.error.as_ref().map(|make| make());
+        state.queries.push(query);
+
+        let items = records.into_iter().map(Ok).chain(error.map(Err));
+        Ok(Box::pin(stream::iter(items.collect::<Vec<_>>())))
+    }
+}
+
+async fn serve(socket: TcpStream, state: Arc<Mutex<State>>) {
+    let mut socket = BufReader::new(socket);
+    let Ok(Some(request)) = read_request(&mut socket).await else {
+        return;
+    };
+
+    let response = {
+        let mut state = state.lock().expect("mock server lock poisoned");
+        state.requests.push(request);
+        state
+            .responses
+            .pop_front()
+            .or_else(|| state.fallback.clone())
+            .unwrap_or_else(|| MockResponse::api_error(404, "no mock response queued"))
+    };
+
+    let _ = write_response(socket.get_mut(), &response).await;
+}
+
+async fn read_request(socket: &mut BufReader<TcpStream>) -> std::io::Result<Option<MockRequest>> {
+    let mut line = String::new();
+    if socket.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        socket.read_line(&mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let chunked = headers
+        .iter()
+        .any(|(n, v)| n == "transfer-encoding" && v.to_ascii_lowercase().contains("chunked"));
+    let body = if chunked {
+        read_chunked(socket).await?
+    } else {
+        let length = headers
+            .iter()
+            .find(|(n, _)| n == "content-length")
+            .and_then(|(_, v)| v.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        socket.read_exact(&mut body).await?;
+        body
+    };
+
+    Ok(Some(MockRequest {
+        method,
+        target,
+        headers,
+        body,
+    }))
+}
+
+/// Decode a `Transfer-Encoding: chunked` body, discarding any trailers.
+async fn read_chunked(socket: &mut BufReader<TcpStream>) -> std::io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        socket.read_line(&mut line).await?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid chunk size: {:?}", size),
+            )
+        })?;
+        if size == 0 {
+            break;
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        socket.read_exact(&mut body[start..]).await?;
+        line.clear();
+        socket.read_line(&mut line).await?;
+    }
+    loop {
+        line.clear();
+        if socket.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            return Ok(body);
+        }
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, response: &MockResponse) -> std::io::Result<()> {
+    let chunked = response.chunk_size.is_some() || !response.chunk_delay.is_zero();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status)
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if chunked {
+        head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+    } else {
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", response.body.len()));
+    }
+    socket.write_all(head.as_bytes()).await?;
+
+    let limit = response.truncate_after.unwrap_or(usize::MAX);
+    let body = &response.body[..response.body.len().min(limit)];
+
+    if chunked {
+        let size = response.chunk_size.unwrap_or(response.body.len().max(1));
+        for chunk in body.chunks(size) {
+            tokio::time::sleep(response.chunk_delay).await;
+            socket
+                .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                .await?;
+            socket.write_all(chunk).await?;
+            socket.write_all(b"\r\n").await?;
+            socket.flush().await?;
+        }
+        if response.truncate_after.is_none() {
+            socket.write_all(b"0\r\n\r\n").await?;
+        }
+    } else {
+        socket.write_all(body).await?;
+    }
+    socket.flush().await?;
+    socket.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::retry::RetryPolicy;
+    use futures::StreamExt;
+
+    const CSV: &str = "#datatype,string,long,double\n\
+                       #group,false,false,false\n\
+                       #default,_result,,\n\
+                       ,result,table,_value\n\
+                       ,,0,1.5\n\
+                       ,,0,2.5\n\
+                       ,,0,3.5\n\n";
+
+    #[tokio::test]
+    async fn test_fake_client() {
+        let fake = FakeClient::new(vec![FluxRecord::new(0)])
+            .respond_to("special", vec![FluxRecord::new(1), FluxRecord::new(2)]);
+
+        async fn count(client: &impl QueryClient, query: &str) -> usize {
+            client.query(query).await.unwrap().len()
+        }
+        assert_eq!(count(&fake, "anything").await, 1);
+        assert_eq!(count(&fake, "special").await, 2);
+        assert_eq!(fake.queries(), vec!["anything", "special"]);
+    }
+
+    #[tokio::test]
+    async fn test_fake_client_error() {
+        let fake =
+            FakeClient::new(vec![FluxRecord::new(0)]).then_error(|| Error::Csv("boom".to_string()));
+
+        let items: Vec<_> = fake.query_stream("q").await.unwrap().collect().await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[1], Err(Error::Csv(_))));
+        assert!(fake.query("q").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_serves_csv_and_records_request() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::csv(CSV));
+
+        let records = server.client().query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].get_double("_value"), Some(3.5));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path(), "/api/v2/query");
+        assert_eq!(
+            requests[0].header("Authorization"),
+            Some("Token test-token")
+        );
+        assert!(requests[0].body_text().contains("buckets()"));
+    }
+
+    #[tokio::test]
+    async fn test_chunked_slow_body() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(
+            MockResponse::csv(CSV)
+                .chunked(7)
+                .chunk_delay(Duration::from_millis(1)),
+        );
+
+        let records = server.client().query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_decodes_chunked_request_body() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::csv(CSV));
+
+        let mut socket = TcpStream::connect(server.addr).await.unwrap();
+        socket
+            .write_all(
+                b"POST /api/v2/write HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5\r\ncpu v\r\n7;ext=1\r\nalue=1i\r\n0\r\nExpires: never\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        socket.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+
+        assert_eq!(server.requests()[0].body_text(), "cpu value=1i");
+    }
+
+    #[tokio::test]
+    async fn test_error_table() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::error_table("bucket not found", Some("42")));
+
+        let err = server.client().query("buckets()").await.unwrap_err();
+        assert!(matches!(err, Error::QueryError { message, .. } if message == "bucket not found"));
+    }
+
+    #[tokio::test]
+    async fn test_too_many_requests_is_retryable() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::too_many_requests(Some(3)));
+
+        let err = server
+            .client()
+            .query_stream("buckets()")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.is_retryable());
+        assert_eq!(err.status(), Some(429));
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(3)));
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_waits_for_retry_after() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::too_many_requests(Some(1)));
+        server.enqueue(MockResponse::api_error(503, "maintenance").header("Retry-After", "0"));
+        server.enqueue(MockResponse::csv(CSV));
+        let client = Client::builder(server.url(), MOCK_ORG, MOCK_TOKEN)
+            .retry_policy(RetryPolicy::new().backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        assert_eq!(client.query("buckets()").await.unwrap().len(), 3);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_retries_unavailable() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::api_error(503, "unavailable"));
+        server.enqueue(MockResponse::api_error(502, "bad gateway"));
+        server.enqueue(MockResponse::csv(CSV));
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let client = Client::builder(server.url(), MOCK_ORG, MOCK_TOKEN)
+            .retry_policy(policy.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(client.query("buckets()").await.unwrap().len(), 3);
+        assert_eq!(server.requests().len(), 3);
+
+        server.enqueue(MockResponse::api_error(503, "unavailable"));
+        server.enqueue(MockResponse::api_error(400, "bad query"));
+        let err = client.query("buckets()").await.unwrap_err();
+        assert_eq!(err.status(), Some(400));
+
+        let client = Client::builder(server.url(), MOCK_ORG, MOCK_TOKEN)
+            .retry_policy(policy.max_attempts(2))
+            .build()
+            .unwrap();
+        server.set_fallback(MockResponse::api_error(503, "unavailable"));
+        let err = client.query("buckets()").await.unwrap_err();
+        assert_eq!(err.status(), Some(503));
+        assert_eq!(server.requests().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_scope() {
+        use crate::retry::RetryScope;
+        use crate::write::Precision;
+
+        let server = MockServer::start().await.unwrap();
+        let policy = RetryPolicy::new().backoff(Duration::ZERO, Duration::ZERO);
+        let client = Client::builder(server.url(), MOCK_ORG, MOCK_TOKEN)
+            .retry_policy(policy.clone())
+            .build()
+            .unwrap();
+        server.enqueue(MockResponse::api_error(503, "unavailable"));
+        let err = client
+            .write_lines("bucket", Precision::Seconds, "cpu n=1i")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(503));
+        assert_eq!(server.requests().len(), 1);
+
+        let client = Client::builder(server.url(), MOCK_ORG, MOCK_TOKEN)
+            .retry_policy(policy.scope(RetryScope::All))
+            .build()
+            .unwrap();
+        server.enqueue(MockResponse::api_error(503, "unavailable"));
+        server.enqueue(MockResponse::new(204, ""));
+        client
+            .write_lines("bucket", Precision::Seconds, "cpu n=1i")
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_body_fails_stream() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockResponse::csv(CSV).chunked(16).truncate_after(70));
+
+        let mut stream = server.client().query_stream("buckets()").await.unwrap();
+        let mut last = None;
+        while let Some(item) = stream.next().await {
+            last = Some(item);
+        }
+        let err = last.unwrap().unwrap_err();
+        assert!(err.is_retryable(), "unexpected error: {err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_and_not_found() {
+        let server = MockServer::start().await.unwrap();
+        let err = server.client().query("buckets()").await.unwrap_err();
+        assert_eq!(err.status(), Some(404));
+
+        server.set_fallback(MockResponse::csv(CSV));
+        assert_eq!(server.client().query("a").await.unwrap().len(), 3);
+        assert_eq!(server.client().query("b").await.unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,216 @@
+//! Failover between replicated InfluxDB endpoints.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::future::BoxFuture;
+use url::Url;
+
+use super::{Transport, TransportRequest, TransportResponse};
+use crate::error::Result;
+
+/// [`Transport`] that sends requests to the first of several endpoints that
+/// accepts them.
+///
+/// Requests are built for the first endpoint, the primary. When one fails
+/// with a [retryable](crate::Error::is_retryable) error or a `5xx` status,
+/// it is sent again to the next endpoint, until one answers or all have been
+/// tried. The endpoint that answered is used first for the following
+/// requests, so a failed primary is not waited on every time; it is only
+/// tried again once the others fail.
+///
+/// Only the start of a request fails over: a response body interrupted
+/// mid-stream is reported as usual. Requests with a streamed body are only
+/// sent to the current endpoint.
+pub struct FailoverTransport {
+    inner: Arc<dyn Transport>,
+    endpoints: Vec<Url>,
+    current: AtomicUsize,
+}
+
+impl FailoverTransport {
+    /// Send requests for `endpoints[0]` through `inner`, failing over to the
+    /// other `endpoints` in order.
+    pub fn new(inner: impl Transport, endpoints: impl IntoIterator<Item = Url>) -> Self {
+        Self::from_arc(Arc::new(inner), endpoints)
+    }
+
+    pub(crate) fn from_arc(
+        inner: Arc<dyn Transport>,
+        endpoints: impl IntoIterator<Item = Url>,
+    ) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|mut url| {
+                if !url.path().ends_with('/') {
+                    url.set_path(&format!("{}/", url.path()));
+                }
+                url
+            })
+            .collect();
+        Self {
+            inner,
+            endpoints,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the endpoint requests are sent to first.
+    pub fn current_endpoint(&self) -> Option<&Url> {
+        self.endpoints.get(self.current.load(Ordering::Relaxed))
+    }
+
+    /// Move `url` from the primary to endpoint `index`, if it is under the
+    /// primary.
+    fn rebase(&self, url: &Url, index: usize) -> Url {
+        let primary = self.endpoints[0].as_str();
+        url.as_str()
+            .strip_prefix(primary)
+            .and_then(|path| self.endpoints[index].join(path).ok())
+            .unwrap_or_else(|| url.clone())
+    }
+}
+
+impl Transport for FailoverTransport {
+    fn send(&self, mut request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+        Box::pin(async move {
+            let count = self.endpoints.len();
+            let first = self.current.load(Ordering::Relaxed);
+            if count < 2 || request.body_stream.is_some() {
+                if count > 0 {
+                    request.url = self.rebase(&request.url, first);
+                }
+                return self.inner.send(request).await;
+            }
+
+            let mut last = None;
+            for offset in 0..count {
+                let index = (first + offset) % count;
+                let mut attempt = request.try_clone().expect("buffered body");
+                attempt.url = self.rebase(&request.url, index);
+                let result = self.inner.send(attempt).await;
+                let failed = match &result {
+                    Ok(response) => response.status.is_server_error(),
+                    Err(e) => e.is_retryable(),
+                };
+                if !failed {
+                    self.current.store(index, Ordering::Relaxed);
+                    return result;
+                }
+                last = Some(result);
+            }
+            last.expect("at least two endpoints")
+        })
+    }
+}
+
+impl std::fmt::Debug for FailoverTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailoverTransport")
+            .field("endpoints", &self.endpoints)
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::stream;
+    use http::{HeaderMap, Method, StatusCode};
+    use std::sync::Mutex;
+
+    /// Refuses connections to `down`, answers everything else, and records
+    /// the URLs it was sent.
+    struct Hosts {
+        down: Mutex<Vec<&'static str>>,
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl Transport for Arc<Hosts> {
+        fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            self.sent.lock().unwrap().push(request.url.to_string());
+            let host = request.url.host_str().unwrap_or_default().to_string();
+            let down = self.down.lock().unwrap().contains(&host.as_str());
+            Box::pin(async move {
+                if down {
+                    return Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into());
+                }
+                Ok(TransportResponse {
+                    status: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                    body: Box::pin(stream::empty()),
+                })
+            })
+        }
+    }
+
+    fn request() -> TransportRequest {
+        TransportRequest {
+            method: Method::POST,
+            url: "http://primary.invalid:8086/api/v2/query?org=o"
+                .parse()
+                .unwrap(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            body_stream: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_replica() {
+        let hosts = Arc::new(Hosts {
+            down: Mutex::new(vec!["primary.invalid"]),
+            sent: Mutex::new(Vec::new()),
+        });
+        let transport = FailoverTransport::new(
+            hosts.clone(),
+            [
+                "http://primary.invalid:8086".parse().unwrap(),
+                "http://replica.invalid:8086/influx".parse().unwrap(),
+            ],
+        );
+
+        transport.send(request()).await.unwrap();
+        transport.send(request()).await.unwrap();
+        assert_eq!(
+            *hosts.sent.lock().unwrap(),
+            [
+                "http://primary.invalid:8086/api/v2/query?org=o",
+                "http://replica.invalid:8086/influx/api/v2/query?org=o",
+                "http://replica.invalid:8086/influx/api/v2/query?org=o",
+            ]
+        );
+        assert_eq!(
+            transport.current_endpoint().unwrap().as_str(),
+            "http://replica.invalid:8086/influx/"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_endpoints_down() {
+        let hosts = Arc::new(Hosts {
+            down: Mutex::new(vec!["primary.invalid", "replica.invalid"]),
+            sent: Mutex::new(Vec::new()),
+        });
+        let transport = FailoverTransport::new(
+            hosts.clone(),
+            [
+                "http://primary.invalid:8086".parse().unwrap(),
+                "http://replica.invalid:8086".parse().unwrap(),
+            ],
+        );
+
+        let err = transport.send(request()).await.err().unwrap();
+        assert!(err.is_retryable());
+        assert_eq!(hosts.sent.lock().unwrap().len(), 2);
+
+        hosts.down.lock().unwrap().clear();
+        transport.send(request()).await.unwrap();
+        assert_eq!(
+            transport.current_endpoint().unwrap().host_str(),
+            Some("primary.invalid")
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! [`Transport`] built directly on hyper and rustls.
+//!
+//! Requires the `hyper` feature. Together with `default-features = false`,
+//! this drops reqwest and its dependencies for services that only need to
+//! stream queries.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use futures::future::BoxFuture;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyDataStream, BodyExt, Full, StreamBody};
+use hyper::body::Frame;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use super::{
+    ConnectionOptions, TlsConfig, Transport, TransportRequest, TransportResponse, tls_error,
+};
+use crate::error::{Error, Result};
+
+type RequestBody = UnsyncBoxBody<Bytes, std::io::Error>;
+
+/// [`Transport`] sending HTTP/1.1 requests with hyper, over rustls for
+/// `https` URLs.
+///
+/// Server certificates are verified against the bundled Mozilla root
+/// certificates, and those of the [`TlsConfig`] in the options. Error
+/// statuses are returned to the client as-is and reported as
+/// [`Error::Status`].
+#[derive(Clone, Debug)]
+pub struct HyperTransport {
+    http: Client<HttpsConnector<HttpConnector>, RequestBody>,
+}
+
+impl HyperTransport {
+    /// Create a transport with a connection pool of its own.
+    pub fn new() -> Self {
+        Self::with_options(&ConnectionOptions::default())
+    }
+
+    /// Create a transport whose connection pool is configured by `options`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [TLS settings](ConnectionOptions::tls) are invalid; see
+    /// [`try_with_options`](Self::try_with_options).
+    pub fn with_options(options: &ConnectionOptions) -> Self {
+        Self::try_with_options(options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [`with_options`](Self::with_options), failing with
+    /// [`Error::Config`] if the TLS settings are invalid.
+    pub fn try_with_options(options: &ConnectionOptions) -> Result<Self> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_keepalive(options.tcp_keepalive);
+        http.set_connect_timeout(options.connect_timeout);
+        let connector = HttpsConnectorBuilder::new();
+        let connector = if options.tls.is_default() {
+            connector.with_webpki_roots()
+        } else {
+            connector.with_tls_config(rustls_config(&options.tls)?)
+        };
+        let connector = connector
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(http);
+
+        let mut builder = Client::builder(TokioExecutor::new());
+        builder.pool_timer(TokioTimer::new());
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max);
+        }
+        Ok(Self {
+            http: builder.build(connector),
+        })
+    }
+}
+
+/// Build a rustls configuration trusting the bundled roots and `tls`.
+fn rustls_config(tls: &TlsConfig) -> Result<rustls::ClientConfig> {
+    let mut roots =
+        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for pem in &tls.roots {
+        let certs = CertificateDer::pem_slice_iter(pem)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(tls_error)?;
+        if certs.is_empty() {
+            return Err(tls_error("no certificate in root certificate PEM"));
+        }
+        for cert in certs {
+            roots.add(cert).map_err(tls_error)?;
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?
+        .with_root_certificates(roots);
+    match &tls.identity {
+        Some((cert, key)) => {
+            let chain = CertificateDer::pem_slice_iter(cert)
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(tls_error)?;
+            let key = PrivateKeyDer::from_pem_slice(key).map_err(tls_error)?;
+            builder.with_client_auth_cert(chain, key).map_err(tls_error)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+        Box::pin(async move {
+            let mut http_request = http::Request::builder()
+                .method(request.method)
+                .uri(request.url.as_str())
+                .body(match request.body_stream {
+                    Some(stream) => StreamBody::new(stream.map_ok(Frame::data)).boxed_unsync(),
+                    None => Full::new(request.body)
+                        .map_err(|never| match never {})
+                        .boxed_unsync(),
+                })
+                .map_err(|e| Error::Config(format!("Invalid request: {}", e)))?;
+            *http_request.headers_mut() = request.headers;
+
+            let response = self
+                .http
+                .request(http_request)
+                .await
+                .map_err(std::io::Error::other)?;
+            let (parts, body) = response.into_parts();
+
+            Ok(TransportResponse {
+                status: parts.status,
+                headers: parts.headers,
+                body: Box::pin(BodyDataStream::new(body).map_err(std::io::Error::other)),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Accept one connection and answer it with `response`, returning the request head.
+    async fn serve_once(response: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.unwrap();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_hyper_transport_streams_query() {
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nConnection: close\r\n\r\n\
+             #datatype,string,long\n#group,false,false\n#default,_result,\n,result,n\n,,1\n,,2\n\n",
+        )
+        .await;
+        let client = Client::with_transport(HyperTransport::new(), url, "org", "token");
+
+        let records = client.query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get_long("n"), Some(2));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/v2/query?org=org HTTP/1.1"));
+        assert!(request.contains("authorization: Token token"));
+    }
+
+    #[tokio::test]
+    async fn test_hyper_transport_with_options() {
+        let (url, _server) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nConnection: close\r\n\r\n\
+             #datatype,string,long\n#group,false,false\n#default,_result,\n,result,n\n,,1\n\n",
+        )
+        .await;
+        let options = ConnectionOptions::new()
+            .pool_idle_timeout(std::time::Duration::from_secs(5))
+            .pool_max_idle_per_host(1)
+            .tcp_keepalive(std::time::Duration::from_secs(10));
+        let transport = HyperTransport::with_options(&options);
+        let client = Client::with_transport(transport, url, "org", "token");
+
+        assert_eq!(client.query("buckets()").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hyper_transport_error_status() {
+        let (url, _server) = serve_once(
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 26\r\nConnection: close\r\n\r\n\
+             {\"message\":\"unauthorized\"}",
+        )
+        .await;
+        let client = Client::with_transport(HyperTransport::new(), url, "org", "token");
+
+        match client.query("buckets()").await.unwrap_err() {
+            Error::Status { status, message } => {
+                assert_eq!(status, 401);
+                assert_eq!(message, "unauthorized");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hyper_transport_connection_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = Client::with_transport(HyperTransport::new(), url, "org", "token");
+
+        let err = client.query("buckets()").await.unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+        assert!(err.is_retryable());
+    }
+}
@@ -0,0 +1,518 @@
+//! HTTP transport used by the client.
+//!
+//! [`Client`](crate::Client) builds requests and parses responses, but hands
+//! the actual HTTP exchange to a [`Transport`]. The default is
+//! `ReqwestTransport`, or `HyperTransport` when only the `hyper` feature is
+//! enabled; implement the trait to use another HTTP stack or to feed byte
+//! streams to the client directly in tests.
+
+#[cfg(not(any(feature = "reqwest", feature = "hyper")))]
+compile_error!("influxdb-stream needs an HTTP backend: enable the `reqwest` or `hyper` feature");
+
+mod failover;
+#[cfg(feature = "hyper")]
+mod hyper;
+mod refresh;
+mod throttle;
+
+#[cfg(feature = "hyper")]
+pub use self::hyper::HyperTransport;
+pub use failover::FailoverTransport;
+pub use refresh::RefreshingTransport;
+pub use throttle::ThrottledTransport;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::Stream;
+#[cfg(feature = "reqwest")]
+use futures::TryStreamExt;
+use futures::future::BoxFuture;
+use http::header::HeaderMap;
+use http::{Method, StatusCode};
+use url::Url;
+
+use crate::error::Result;
+
+/// Stream of response body chunks.
+pub type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// An HTTP request to be sent by a [`Transport`].
+pub struct TransportRequest {
+    /// Request method.
+    pub method: Method,
+    /// Full request URL, including the query string.
+    pub url: Url,
+    /// Request headers, including `Authorization`.
+    pub headers: HeaderMap,
+    /// Request body.
+    pub body: Bytes,
+    /// Body to stream instead of `body`, for uploads too large to buffer,
+    /// such as [restoring](crate::Client::restore) shard snapshots.
+    ///
+    /// Transports that cannot stream may collect it into `body` first.
+    pub body_stream: Option<ByteStream>,
+}
+
+impl TransportRequest {
+    /// Copy the request to send it again, unless its body is streamed.
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        if self.body_stream.is_some() {
+            return None;
+        }
+        Some(Self {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            body_stream: None,
+        })
+    }
+}
+
+impl std::fmt::Debug for TransportRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportRequest")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &Redacted(&self.headers))
+            .field("body_len", &self.body.len())
+            .field("body_stream", &self.body_stream.is_some())
+            .finish()
+    }
+}
+
+/// Headers shown with the values of sensitive ones, such as credentials,
+/// left out.
+struct Redacted<'a>(&'a HeaderMap);
+
+impl std::fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.0 {
+            if value.is_sensitive() {
+                map.entry(name, &format_args!("<redacted>"));
+            } else {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
+}
+
+/// An HTTP response returned by a [`Transport`].
+pub struct TransportResponse {
+    /// Response status.
+    pub status: StatusCode,
+    /// Response headers.
+    pub headers: HeaderMap,
+    /// Response body, read incrementally.
+    pub body: ByteStream,
+}
+
+impl std::fmt::Debug for TransportResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sends HTTP requests on behalf of the client.
+///
+/// Responses with an error status may either be returned as-is, in which case
+/// the client reports them as [`Error::Status`](crate::Error::Status), or be
+/// turned into an error by the transport itself.
+pub trait Transport: Send + Sync + 'static {
+    /// Send `request` and return the response once its headers are received.
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>>;
+}
+
+/// Connection pool and TCP settings of the built-in transports.
+///
+/// Unset options keep the defaults of the HTTP stack. Long-running streams
+/// behind load balancers that drop idle connections usually want a
+/// [`tcp_keepalive`](Self::tcp_keepalive) shorter than the balancer's idle
+/// timeout, and a [`pool_idle_timeout`](Self::pool_idle_timeout) that retires
+/// pooled connections before the balancer silently does.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    tls: TlsConfig,
+}
+
+impl ConnectionOptions {
+    /// Create options that keep the HTTP stack's defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Close pooled connections that have been idle for `timeout`.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host in the pool.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Send TCP keepalive probes after `interval` of inactivity.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Give up connecting to the server after `timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the TLS settings of `https` connections.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Replace the connection pool once it is `lifetime` old.
+    ///
+    /// New connections resolve the host again, so long-lived clients follow
+    /// DNS changes of the InfluxDB endpoint. See [`RefreshingTransport`].
+    pub fn max_lifetime(mut self, lifetime: Duration) -> Self {
+        self.max_lifetime = Some(lifetime);
+        self
+    }
+}
+
+/// TLS settings of the built-in transports, for servers behind private CAs
+/// or gateways requiring mutual TLS.
+///
+/// Certificates and keys are given in PEM form and checked when the
+/// transport is built. The TLS library is chosen with the `rustls` and
+/// `native-tls` features; the `hyper` transport always uses rustls.
+///
+/// # Example
+///
+/// ```ignore
+/// use influxdb_stream::Client;
+/// use influxdb_stream::transport::TlsConfig;
+///
+/// let tls = TlsConfig::new()
+///     .root_certificates_pem(std::fs::read("/etc/influx/ca.pem")?)
+///     .client_identity_pem(
+///         std::fs::read("/etc/influx/client.pem")?,
+///         std::fs::read("/etc/influx/client.key")?,
+///     );
+/// let client = Client::builder("https://influx.corp:8086", "my-org", "my-token")
+///     .tls(tls)
+///     .build()?;
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    roots: Vec<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl TlsConfig {
+    /// Create settings that verify servers against the bundled roots.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also trust the CA certificates in `pem`; may be repeated.
+    pub fn root_certificates_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.roots.push(pem.into());
+        self
+    }
+
+    /// Present the certificate chain in `cert_pem`, with its PKCS#8 private
+    /// key in `key_pem`, to servers asking for a client certificate.
+    pub fn client_identity_pem(
+        mut self,
+        cert_pem: impl Into<Vec<u8>>,
+        key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.identity = Some((cert_pem.into(), key_pem.into()));
+        self
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.roots.is_empty() && self.identity.is_none()
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("roots", &self.roots.len())
+            .field("identity", &self.identity.is_some())
+            .finish()
+    }
+}
+
+fn tls_error(e: impl std::fmt::Display) -> crate::Error {
+    crate::Error::Config(format!("Invalid TLS configuration: {}", e))
+}
+
+/// Create the transport used by clients that are not given one.
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    #[cfg(feature = "reqwest")]
+    return Arc::new(ReqwestTransport::default());
+    #[cfg(not(feature = "reqwest"))]
+    return Arc::new(HyperTransport::new());
+}
+
+/// Create the default transport with connection `options` applied.
+pub(crate) fn transport_with(options: &ConnectionOptions) -> Result<Arc<dyn Transport>> {
+    if let Some(lifetime) = options.max_lifetime {
+        let options = options.clone();
+        return Ok(Arc::new(RefreshingTransport::new(lifetime, move || {
+            pooled_transport(&options)
+        })?));
+    }
+    Ok(Arc::new(pooled_transport(options)?))
+}
+
+#[cfg(feature = "reqwest")]
+fn pooled_transport(options: &ConnectionOptions) -> Result<ReqwestTransport> {
+    ReqwestTransport::with_options(options)
+}
+
+#[cfg(not(feature = "reqwest"))]
+fn pooled_transport(options: &ConnectionOptions) -> Result<HyperTransport> {
+    HyperTransport::try_with_options(options)
+}
+
+/// Apply `tls` to a reqwest client.
+#[cfg(all(feature = "reqwest", any(feature = "rustls", feature = "native-tls")))]
+fn reqwest_tls(
+    mut builder: reqwest::ClientBuilder,
+    tls: &TlsConfig,
+) -> Result<reqwest::ClientBuilder> {
+    for pem in &tls.roots {
+        let certs = reqwest::Certificate::from_pem_bundle(pem).map_err(tls_error)?;
+        if certs.is_empty() {
+            return Err(tls_error("no certificate in root certificate PEM"));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some((cert, key)) = &tls.identity {
+        // reqwest prefers native-tls when both backends are enabled.
+        #[cfg(feature = "native-tls")]
+        let identity = reqwest::Identity::from_pkcs8_pem(cert, key);
+        #[cfg(not(feature = "native-tls"))]
+        let identity = reqwest::Identity::from_pem(&[key.as_slice(), cert.as_slice()].concat());
+        builder = builder.identity(identity.map_err(tls_error)?);
+    }
+    Ok(builder)
+}
+
+#[cfg(all(
+    feature = "reqwest",
+    not(any(feature = "rustls", feature = "native-tls"))
+))]
+fn reqwest_tls(_: reqwest::ClientBuilder, _: &TlsConfig) -> Result<reqwest::ClientBuilder> {
+    Err(tls_error(
+        "settings require the rustls or native-tls feature",
+    ))
+}
+
+/// [`Transport`] backed by a [`reqwest::Client`].
+///
+/// Error statuses are reported as [`Error::Http`](crate::Error::Http), so that
+/// the underlying `reqwest::Error` is available to callers. `429` and `503`
+/// responses are passed on instead, so that the client can read their
+/// `Retry-After` header; see [`Error::RateLimited`](crate::Error::RateLimited).
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// Create a transport using `http` for all requests.
+    pub fn new(http: reqwest::Client) -> Self {
+        Self { http }
+    }
+
+    /// Create a transport with its own reqwest client, configured by `options`.
+    pub fn with_options(options: &ConnectionOptions) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().tcp_keepalive(options.tcp_keepalive);
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if !options.tls.is_default() {
+            builder = reqwest_tls(builder, &options.tls)?;
+        }
+        Ok(Self::new(builder.build()?))
+    }
+
+    /// Get the underlying reqwest client.
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Transport for ReqwestTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+        Box::pin(async move {
+            let body = match request.body_stream {
+                Some(stream) => reqwest::Body::wrap_stream(stream),
+                None => request.body.into(),
+            };
+            let response = self
+                .http
+                .request(request.method, request.url)
+                .headers(request.headers)
+                .body(body)
+                .send()
+                .await?;
+            let response = match response.status() {
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => response,
+                _ => response.error_for_status()?,
+            };
+
+            Ok(TransportResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: Box::pin(response.bytes_stream().map_err(std::io::Error::other)),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::error::Error;
+    use futures::stream;
+    use std::sync::{Arc, Mutex};
+
+    /// Requests recorded by a test transport.
+    pub(crate) type Requests = Arc<Mutex<Vec<TransportRequest>>>;
+
+    /// Serve a fixed status and body in small chunks, recording requests.
+    pub(crate) struct StaticTransport {
+        status: StatusCode,
+        body: Bytes,
+        requests: Requests,
+    }
+
+    impl StaticTransport {
+        /// Answer every request with `status` and `body`.
+        pub(crate) fn new(status: StatusCode, body: impl Into<Bytes>) -> (Self, Requests) {
+            let requests = Requests::default();
+            let transport = Self {
+                status,
+                body: body.into(),
+                requests: requests.clone(),
+            };
+            (transport, requests)
+        }
+    }
+
+    impl Transport for StaticTransport {
+        fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            self.requests.lock().unwrap().push(request);
+            let chunks: Vec<_> = self
+                .body
+                .chunks(5)
+                .map(|c| Ok(Bytes::copy_from_slice(c)))
+                .collect();
+            Box::pin(futures::future::ready(Ok(TransportResponse {
+                status: self.status,
+                headers: HeaderMap::new(),
+                body: Box::pin(stream::iter(chunks)),
+            })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_transport_streams_body() {
+        let csv =
+            "#datatype,string,long\n#group,false,false\n#default,_result,\n,result,n\n,,1\n,,2\n\n";
+        let (transport, requests) = StaticTransport::new(StatusCode::OK, csv);
+        let client =
+            Client::with_transport(transport, "http://influx.invalid:8086", "org", "token");
+
+        let records = client.query("buckets()").await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get_long("n"), Some(2));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[0].url.path(), "/api/v2/query");
+        assert_eq!(requests[0].url.query(), Some("org=org"));
+        assert_eq!(requests[0].headers["authorization"], "Token token");
+    }
+
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    fn test_tls_config() -> TlsConfig {
+        let fixture = |name: &str| {
+            std::fs::read(format!(
+                "{}/tests/fixtures/tls/{name}",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap()
+        };
+        TlsConfig::new()
+            .root_certificates_pem(fixture("ca.pem"))
+            .client_identity_pem(fixture("client.pem"), fixture("client.key"))
+    }
+
+    #[test]
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    fn test_builder_tls() {
+        let client = Client::builder("https://influx.invalid:8086", "o", "t")
+            .tls(test_tls_config())
+            .build();
+        assert!(client.is_ok(), "unexpected error: {:?}", client.err());
+
+        let err = Client::builder("https://influx.invalid:8086", "o", "t")
+            .tls(TlsConfig::new().root_certificates_pem("not a certificate"))
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Config(_)), "unexpected error: {err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_error_status_from_transport() {
+        let (transport, _) = StaticTransport::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            r#"{"code":"unavailable","message":"shutting down"}"#,
+        );
+        let client = Client::with_transport(transport, "http://influx.invalid:8086", "o", "t");
+
+        let err = client.query("buckets()").await.unwrap_err();
+        assert!(err.is_retryable());
+        match err {
+            Error::Status { status, message } => {
+                assert_eq!(status, 503);
+                assert_eq!(message, "shutting down");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Periodic replacement of a transport's connection pool.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+
+use super::{Transport, TransportRequest, TransportResponse};
+use crate::error::Result;
+
+type Factory = dyn Fn() -> Result<Arc<dyn Transport>> + Send + Sync;
+
+/// [`Transport`] that recreates its inner transport once it reaches a maximum
+/// age.
+///
+/// Pooled connections otherwise live as long as the server keeps them open,
+/// so a long-lived client never notices that a DNS name now points somewhere
+/// else (for example after a blue/green switch of the InfluxDB endpoint).
+/// Replacing the transport starts a fresh pool, whose connections resolve
+/// the host again.
+///
+/// Requests already in flight, including streams still being read, keep
+/// using the transport they were sent with.
+pub struct RefreshingTransport {
+    factory: Box<Factory>,
+    max_lifetime: Duration,
+    current: Mutex<(Instant, Arc<dyn Transport>)>,
+}
+
+impl RefreshingTransport {
+    /// Create a transport calling `factory` now and whenever the current
+    /// transport is older than `max_lifetime`.
+    pub fn new<T, F>(max_lifetime: Duration, factory: F) -> Result<Self>
+    where
+        T: Transport,
+        F: Fn() -> Result<T> + Send + Sync + 'static,
+    {
+        let factory: Box<Factory> = Box::new(move || Ok(Arc::new(factory()?)));
+        let current = factory()?;
+        Ok(Self {
+            factory,
+            max_lifetime,
+            current: Mutex::new((Instant::now(), current)),
+        })
+    }
+
+    /// Get the maximum age of a transport.
+    pub fn max_lifetime(&self) -> Duration {
+        self.max_lifetime
+    }
+
+    /// Get the current transport, replacing it first if it is too old.
+    fn current(&self) -> Result<Arc<dyn Transport>> {
+        let mut current = self.current.lock().expect("transport lock poisoned");
+        if current.0.elapsed() >= self.max_lifetime {
+            *current = (Instant::now(), (self.factory)()?);
+        }
+        Ok(current.1.clone())
+    }
+}
+
+impl Transport for RefreshingTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+        let transport = self.current();
+        Box::pin(async move { transport?.send(request).await })
+    }
+}
+
+impl std::fmt::Debug for RefreshingTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RefreshingTransport")
+            .field("max_lifetime", &self.max_lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::{StreamExt, stream};
+    use http::{HeaderMap, Method, StatusCode};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers every request with the generation it was created in.
+    struct Generation(usize);
+
+    impl Transport for Generation {
+        fn send(&self, _: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            let body = Bytes::from(self.0.to_string());
+            Box::pin(async move {
+                Ok(TransportResponse {
+                    status: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                    body: Box::pin(stream::iter([Ok(body)])),
+                })
+            })
+        }
+    }
+
+    async fn generation(transport: &RefreshingTransport) -> String {
+        let request = TransportRequest {
+            method: Method::GET,
+            url: "http://influx.invalid/ping".parse().unwrap(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            body_stream: None,
+        };
+        let mut body = transport.send(request).await.unwrap().body;
+        let chunk = body.next().await.unwrap().unwrap();
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transport_replaced_after_max_lifetime() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let counter = built.clone();
+        let transport = RefreshingTransport::new(Duration::from_secs(60), move || {
+            Ok(Generation(counter.fetch_add(1, Ordering::SeqCst)))
+        })
+        .unwrap();
+
+        assert_eq!(generation(&transport).await, "0");
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(generation(&transport).await, "0");
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(generation(&transport).await, "1");
+        assert_eq!(built.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_factory_error_on_create() {
+        let result = RefreshingTransport::new(Duration::from_secs(1), || {
+            Err::<Generation, _>(crate::Error::Config("no TLS backend".to_string()))
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Download bandwidth limiting.
+
+use std::sync::{Arc, Mutex};
+
+use async_stream::stream;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use tokio::time::sleep_until;
+
+use super::{ByteStream, Transport, TransportRequest, TransportResponse};
+use crate::adapters::throttle::RateLimiter;
+use crate::error::Result;
+
+/// [`Transport`] that caps the rate at which response bodies are read.
+///
+/// A single budget of `bytes_per_sec` is shared by every response, so a
+/// client running several exports at once stays under the cap in total.
+/// Reading is paused once a body chunk exceeds the budget, which lets the TCP
+/// receive window close and slows the sender down instead of buffering the
+/// excess. Request bodies are not limited.
+pub struct ThrottledTransport {
+    inner: Arc<dyn Transport>,
+    bytes_per_sec: u64,
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl ThrottledTransport {
+    /// Limit the responses of `inner` to `bytes_per_sec`. Zero disables the limit.
+    pub fn new(inner: impl Transport, bytes_per_sec: u64) -> Self {
+        Self::from_arc(Arc::new(inner), bytes_per_sec)
+    }
+
+    pub(crate) fn from_arc(inner: Arc<dyn Transport>, bytes_per_sec: u64) -> Self {
+        Self {
+            inner,
+            bytes_per_sec,
+            limiter: Arc::new(Mutex::new(RateLimiter::new(bytes_per_sec))),
+        }
+    }
+
+    /// Get the configured rate in bytes per second.
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+}
+
+impl Transport for ThrottledTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+        Box::pin(async move {
+            let mut response = self.inner.send(request).await?;
+            response.body = throttle(response.body, self.limiter.clone());
+            Ok(response)
+        })
+    }
+}
+
+impl std::fmt::Debug for ThrottledTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThrottledTransport")
+            .field("bytes_per_sec", &self.bytes_per_sec)
+            .finish_non_exhaustive()
+    }
+}
+
+fn throttle(mut body: ByteStream, limiter: Arc<Mutex<RateLimiter>>) -> ByteStream {
+    Box::pin(stream! {
+        while let Some(chunk) = body.next().await {
+            let wait = match &chunk {
+                Ok(bytes) => limiter
+                    .lock()
+                    .expect("rate limiter lock poisoned")
+                    .consume(bytes.len() as u64),
+                Err(_) => None,
+            };
+            yield chunk;
+            if let Some(until) = wait {
+                sleep_until(until).await;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::stream;
+    use http::{HeaderMap, Method, StatusCode};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    /// Answers every request with ten chunks of 100 bytes.
+    struct Chunks;
+
+    impl Transport for Chunks {
+        fn send(&self, _: TransportRequest) -> BoxFuture<'_, Result<TransportResponse>> {
+            let chunks = (0..10).map(|_| Ok(Bytes::from(vec![0u8; 100])));
+            Box::pin(futures::future::ready(Ok(TransportResponse {
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Box::pin(stream::iter(chunks)),
+            })))
+        }
+    }
+
+    async fn download(transport: &ThrottledTransport) -> usize {
+        let request = TransportRequest {
+            method: Method::POST,
+            url: "http://influx.invalid/api/v2/query".parse().unwrap(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            body_stream: None,
+        };
+        let body = transport.send(request).await.unwrap().body;
+        body.map(|chunk| chunk.unwrap().len())
+            .fold(0, |a, n| async move { a + n })
+            .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_rate_limited() {
+        let transport = ThrottledTransport::new(Chunks, 1_000);
+        let start = Instant::now();
+
+        assert_eq!(download(&transport).await, 1_000);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_budget_shared_between_responses() {
+        let transport = ThrottledTransport::new(Chunks, 1_000);
+        let start = Instant::now();
+
+        let (a, b) = tokio::join!(download(&transport), download(&transport));
+        assert_eq!(a + b, 2_000);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+}
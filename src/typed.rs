@@ -0,0 +1,262 @@
+//! Conversion of records into application types.
+//!
+//! Implement [`FromRecord`] for a struct to turn query results into typed
+//! values, and [`Measurement`] to tie it to a measurement so it can be
+//! queried through a [`SchemaRegistry`](crate::schema::SchemaRegistry).
+//!
+//! # Example
+//!
+//! ```
+//! use influxdb_stream::FluxRecord;
+//! use influxdb_stream::typed::{FromRecord, Measurement, field};
+//!
+//! struct Cpu {
+//!     host: String,
+//!     usage_user: f64,
+//! }
+//!
+//! impl FromRecord for Cpu {
+//!     fn from_record(record: &FluxRecord) -> influxdb_stream::Result<Self> {
+//!         Ok(Self {
+//!             host: field(record, "host")?,
+//!             usage_user: field(record, "usage_user")?,
+//!         })
+//!     }
+//! }
+//!
+//! impl Measurement for Cpu {
+//!     const MEASUREMENT: &'static str = "cpu";
+//! }
+//! ```
+
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use chrono::{DateTime, FixedOffset};
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::error::{Error, Result};
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+/// Boxed stream of typed values.
+pub type TypedStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+/// A type that can be built from a record.
+pub trait FromRecord: Sized {
+    /// Convert `record`, failing if required columns are missing or mistyped.
+    fn from_record(record: &FluxRecord) -> Result<Self>;
+}
+
+impl FromRecord for FluxRecord {
+    fn from_record(record: &FluxRecord) -> Result<Self> {
+        Ok(record.clone())
+    }
+}
+
+/// A [`FromRecord`] type stored as one measurement.
+///
+/// Queries for a measurement pivot fields into columns, so a record holds
+/// the `_time`, tags and one column per field of a point.
+pub trait Measurement: FromRecord {
+    /// Name of the measurement.
+    const MEASUREMENT: &'static str;
+}
+
+/// A type that can be extracted from a single value.
+pub trait FromValue: Sized {
+    /// Convert `value`, or return `None` if it has another type.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.string()
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_double()
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_long()
+    }
+}
+
+impl FromValue for u64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_unsigned_long()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl FromValue for DateTime<FixedOffset> {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_time().copied()
+    }
+}
+
+impl FromValue for chrono::Duration {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_duration().copied()
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromValue for time::OffsetDateTime {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.to_offset_datetime()
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromValue for time::Duration {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.to_time_duration()
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_binary().map(<[u8]>::to_vec)
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    /// Null values become `None`; other values must convert to `T`.
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            v => T::from_value(v).map(Some),
+        }
+    }
+}
+
+/// Get column `name` of `record` as `T`.
+///
+/// A missing column is an error unless `T` is an `Option`, in which case it
+/// becomes `None`.
+pub fn field<T: FromValue>(record: &FluxRecord, name: &str) -> Result<T> {
+    let value = record.get(name).unwrap_or(&Value::Null);
+    T::from_value(value).ok_or_else(|| Error::Parse {
+        message: match record.get(name) {
+            Some(v) => format!("column '{}' has unexpected value {:?}", name, v),
+            None => format!("missing column '{}'", name),
+        },
+    })
+}
+
+pin_project! {
+    /// Stream returned by [`RecordStreamExt::typed`](crate::adapters::RecordStreamExt::typed).
+    pub struct Typed<S, T> {
+        #[pin]
+        stream: S,
+        _type: PhantomData<fn() -> T>,
+    }
+}
+
+impl<S, T> Typed<S, T> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<S, T> Stream for Typed<S, T>
+where
+    S: Stream<Item = Result<FluxRecord>>,
+    T: FromRecord,
+{
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.project().stream.poll_next(cx));
+        Poll::Ready(item.map(|r| r.and_then(|record| T::from_record(&record))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::RecordStreamExt;
+    use futures::{StreamExt, stream};
+    use ordered_float::OrderedFloat;
+
+    #[derive(Debug, PartialEq)]
+    struct Cpu {
+        host: String,
+        usage: f64,
+        core: Option<i64>,
+    }
+
+    impl FromRecord for Cpu {
+        fn from_record(record: &FluxRecord) -> Result<Self> {
+            Ok(Self {
+                host: field(record, "host")?,
+                usage: field(record, "usage")?,
+                core: field(record, "core")?,
+            })
+        }
+    }
+
+    fn record(usage: Option<f64>) -> FluxRecord {
+        let mut record = FluxRecord::new(0);
+        record.insert("host".to_string(), Value::String("a".to_string()));
+        if let Some(usage) = usage {
+            record.insert("usage".to_string(), Value::Double(OrderedFloat(usage)));
+        }
+        record
+    }
+
+    #[test]
+    fn test_field_conversions() {
+        let record = record(Some(1.5));
+        assert_eq!(field::<String>(&record, "host").unwrap(), "a");
+        assert_eq!(field::<Option<f64>>(&record, "usage").unwrap(), Some(1.5));
+        assert_eq!(field::<Option<f64>>(&record, "missing").unwrap(), None);
+        assert!(matches!(
+            field::<i64>(&record, "usage"),
+            Err(Error::Parse { .. })
+        ));
+        assert!(field::<f64>(&record, "missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_typed_stream() {
+        let input = stream::iter(vec![Ok(record(Some(1.5))), Ok(record(None))]);
+        let items: Vec<Result<Cpu>> = input.typed::<Cpu>().collect().await;
+
+        assert_eq!(
+            items[0].as_ref().unwrap(),
+            &Cpu {
+                host: "a".to_string(),
+                usage: 1.5,
+                core: None
+            }
+        );
+        assert!(items[1].is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! Core types for InfluxDB Flux query results.
 
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
 
 use crate::error::Error;
 use crate::value::Value;
@@ -114,13 +115,107 @@ impl FluxTableMetadata {
     }
 }
 
+/// Column names shared by the records of one table.
+///
+/// Records store their values in column order and look names up here, so a
+/// table's names are allocated once rather than once per row. Schemas of
+/// parsed records also tell which columns are part of the table's group key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecordSchema {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    group: Vec<bool>,
+}
+
+impl RecordSchema {
+    /// Create a schema with the given column names, in order.
+    ///
+    /// If a name repeats, lookups find its last position.
+    pub fn new<I, N>(names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<String>,
+    {
+        let mut schema = Self::default();
+        for name in names {
+            schema.push(name.into());
+        }
+        schema
+    }
+
+    /// Create a schema with the names and group-key flags of `columns`.
+    pub fn from_columns(columns: &[FluxColumn]) -> Self {
+        let mut schema = Self::default();
+        for column in columns {
+            schema.push_column(column.name.clone(), column.group);
+        }
+        schema
+    }
+
+    /// Returns true if column `name` is part of the group key of its table.
+    pub fn is_group_key(&self, name: &str) -> bool {
+        self.index_of(name).is_some_and(|i| self.group[i])
+    }
+
+    /// Returns true if any column is part of the group key.
+    ///
+    /// Records read without the `#group` annotation, and records built by
+    /// hand, have no group key.
+    pub fn has_group_key(&self) -> bool {
+        self.group.contains(&true)
+    }
+
+    /// Get the position of column `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Get the column names, in order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Get the number of columns.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true if the schema has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn push(&mut self, name: String) -> usize {
+        self.push_column(name, false)
+    }
+
+    pub(crate) fn push_column(&mut self, name: String, group: bool) -> usize {
+        let position = self.names.len();
+        self.index.insert(name.clone(), position);
+        self.names.push(name);
+        self.group.push(group);
+        position
+    }
+}
+
+/// Get the schema of records without columns, shared to avoid allocating it
+/// for every new record.
+fn empty_schema() -> Arc<RecordSchema> {
+    static EMPTY: LazyLock<Arc<RecordSchema>> = LazyLock::new(Arc::default);
+    EMPTY.clone()
+}
+
 /// A single record (row) from a Flux query result.
-#[derive(Clone, Debug)]
+///
+/// Values are stored by position in a [`RecordSchema`] shared with the other
+/// records of the same table; the name-based accessors look positions up in
+/// the schema.
+#[derive(Clone)]
 pub struct FluxRecord {
     /// Table index this record belongs to.
     pub table: i32,
-    /// Column name to value mapping.
-    pub values: BTreeMap<String, Value>,
+    schema: Arc<RecordSchema>,
+    values: Vec<Value>,
 }
 
 impl FluxRecord {
@@ -128,38 +223,168 @@ impl FluxRecord {
     pub fn new(table: i32) -> Self {
         Self {
             table,
-            values: BTreeMap::new(),
+            schema: empty_schema(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Create a record from values in the column order of `schema`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of values differs from the number of columns.
+    pub fn from_parts(table: i32, schema: Arc<RecordSchema>, values: Vec<Value>) -> Self {
+        assert_eq!(
+            schema.len(),
+            values.len(),
+            "record values do not match the schema"
+        );
+        Self {
+            table,
+            schema,
+            values,
+        }
+    }
+
+    /// Remove all columns, keeping the value buffer for reuse.
+    pub fn clear(&mut self) {
+        self.schema = empty_schema();
+        self.values.clear();
+    }
+
+    /// Prepare to overwrite this record with a row of `schema`.
+    ///
+    /// The returned values keep their previous contents, so the caller must
+    /// leave exactly one value per column of `schema`.
+    pub(crate) fn refill(&mut self, table: i32, schema: &Arc<RecordSchema>) -> &mut Vec<Value> {
+        self.table = table;
+        if !Arc::ptr_eq(&self.schema, schema) {
+            self.schema = schema.clone();
+        }
+        &mut self.values
+    }
+
+    /// Get the schema of this record.
+    pub fn schema(&self) -> &Arc<RecordSchema> {
+        &self.schema
+    }
+
+    /// Get the values in column order.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Get the values in column order, for in-place changes.
+    pub fn values_mut(&mut self) -> &mut [Value] {
+        &mut self.values
+    }
+
+    /// Get the number of columns.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the record has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns true if the record has column `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.schema.index_of(name).is_some()
+    }
+
+    /// Iterate over column names and values, in column order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.schema
+            .names
+            .iter()
+            .map(String::as_str)
+            .zip(&self.values)
+    }
+
+    /// Iterate over column names, in column order.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.schema.names.iter().map(String::as_str)
+    }
+
+    /// Set column `name` to `value`, returning the previous value.
+    ///
+    /// Adding a new column copies the schema if it is shared with other
+    /// records, so prefer [`FluxRecord::from_parts`] when building many
+    /// records with the same columns.
+    pub fn insert(&mut self, name: impl Into<String>, value: Value) -> Option<Value> {
+        let name = name.into();
+        match self.schema.index_of(&name) {
+            Some(i) => Some(std::mem::replace(&mut self.values[i], value)),
+            None => {
+                Arc::make_mut(&mut self.schema).push(name);
+                self.values.push(value);
+                None
+            }
         }
     }
 
     /// Get a value by column name.
     pub fn get(&self, name: &str) -> Option<&Value> {
-        self.values.get(name)
+        self.schema.index_of(name).map(|i| &self.values[i])
+    }
+
+    /// Get a mutable value by column name.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.schema.index_of(name).map(|i| &mut self.values[i])
     }
 
     /// Get value as string.
+    ///
+    /// This allocates a copy; prefer [`get_str`](Self::get_str) when a
+    /// borrowed string will do.
     pub fn get_string(&self, name: &str) -> Option<String> {
-        self.values.get(name).and_then(|v| v.string())
+        self.get(name).and_then(|v| v.string())
+    }
+
+    /// Get value as a borrowed string.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|v| v.as_string())
     }
 
     /// Get value as f64.
     pub fn get_double(&self, name: &str) -> Option<f64> {
-        self.values.get(name).and_then(|v| v.as_double())
+        self.get(name).and_then(|v| v.as_double())
     }
 
     /// Get value as i64.
     pub fn get_long(&self, name: &str) -> Option<i64> {
-        self.values.get(name).and_then(|v| v.as_long())
+        self.get(name).and_then(|v| v.as_long())
     }
 
     /// Get value as bool.
     pub fn get_bool(&self, name: &str) -> Option<bool> {
-        self.values.get(name).and_then(|v| v.as_bool())
+        self.get(name).and_then(|v| v.as_bool())
     }
 
     /// Get the timestamp (_time field).
     pub fn time(&self) -> Option<&chrono::DateTime<chrono::FixedOffset>> {
-        self.values.get("_time").and_then(|v| v.as_time())
+        self.get("_time").and_then(|v| v.as_time())
+    }
+
+    /// Get the timestamp (_time field) as a [`time::OffsetDateTime`].
+    #[cfg(feature = "time")]
+    pub fn offset_datetime(&self) -> Option<time::OffsetDateTime> {
+        self.get("_time").and_then(|v| v.to_offset_datetime())
+    }
+
+    /// Get the timestamp (_time field) in nanoseconds since the Unix epoch.
+    ///
+    /// Works for records parsed with
+    /// [`raw_times`](crate::parser::AnnotatedCsvParser::raw_times), where
+    /// `_time` holds a `Long`, as well as for regular `TimeRFC` values.
+    pub fn time_nanos(&self) -> Option<i64> {
+        match self.get("_time")? {
+            Value::Long(n) => Some(*n),
+            Value::TimeRFC(t) => t.timestamp_nanos_opt(),
+            _ => None,
+        }
     }
 
     /// Get the measurement name (_measurement field).
@@ -167,14 +392,61 @@ impl FluxRecord {
         self.get_string("_measurement")
     }
 
+    /// Get the measurement name (_measurement field) without copying it.
+    pub fn measurement_str(&self) -> Option<&str> {
+        self.get_str("_measurement")
+    }
+
     /// Get the field name (_field).
     pub fn field(&self) -> Option<String> {
         self.get_string("_field")
     }
 
+    /// Get the field name (_field) without copying it.
+    pub fn field_str(&self) -> Option<&str> {
+        self.get_str("_field")
+    }
+
     /// Get the field value (_value).
     pub fn value(&self) -> Option<&Value> {
-        self.values.get("_value")
+        self.get("_value")
+    }
+
+    /// Estimate the memory used by this record, in bytes.
+    ///
+    /// This counts the record itself and its heap-allocated values. Column
+    /// names live in the schema shared by the whole table and are not
+    /// counted, nor is allocator overhead, so it is a lower bound suitable for
+    /// memory budgets rather than an exact figure.
+    pub fn estimated_size(&self) -> usize {
+        let heap: usize = self
+            .values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => s.capacity(),
+                Value::Base64Binary(b) => b.capacity(),
+                _ => 0,
+            })
+            .sum();
+        std::mem::size_of::<Self>() + self.values.capacity() * std::mem::size_of::<Value>() + heap
+    }
+}
+
+impl std::fmt::Debug for FluxRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FluxRecord")
+            .field("table", &self.table)
+            .field("values", &DebugValues(self))
+            .finish()
+    }
+}
+
+/// Formats record values as a name-to-value map.
+struct DebugValues<'a>(&'a FluxRecord);
+
+impl std::fmt::Debug for DebugValues<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.0.iter()).finish()
     }
 }
 
@@ -302,15 +574,13 @@ mod tests {
     fn test_flux_record_new() {
         let record = FluxRecord::new(5);
         assert_eq!(record.table, 5);
-        assert!(record.values.is_empty());
+        assert!(record.is_empty());
     }
 
     #[test]
     fn test_flux_record_get() {
         let mut record = FluxRecord::new(0);
-        record
-            .values
-            .insert("key".to_string(), Value::String("value".to_string()));
+        record.insert("key".to_string(), Value::String("value".to_string()));
 
         assert!(record.get("key").is_some());
         assert_eq!(record.get("key"), Some(&Value::String("value".to_string())));
@@ -320,25 +590,33 @@ mod tests {
     #[test]
     fn test_flux_record_get_string() {
         let mut record = FluxRecord::new(0);
-        record
-            .values
-            .insert("name".to_string(), Value::String("alice".to_string()));
-        record.values.insert("count".to_string(), Value::Long(42));
+        record.insert("name".to_string(), Value::String("alice".to_string()));
+        record.insert("count".to_string(), Value::Long(42));
 
         assert_eq!(record.get_string("name"), Some("alice".to_string()));
         assert_eq!(record.get_string("count"), None); // Not a string
         assert_eq!(record.get_string("nonexistent"), None);
     }
 
+    #[test]
+    fn test_flux_record_get_str() {
+        let mut record = FluxRecord::new(0);
+        record.insert("_measurement", Value::SharedString(Arc::from("cpu")));
+        record.insert("_field", Value::String("usage".to_string()));
+        record.insert("count", Value::Long(42));
+
+        assert_eq!(record.get_str("_field"), Some("usage"));
+        assert_eq!(record.get_str("count"), None);
+        assert_eq!(record.get_str("nonexistent"), None);
+        assert_eq!(record.measurement_str(), Some("cpu"));
+        assert_eq!(record.field_str(), Some("usage"));
+    }
+
     #[test]
     fn test_flux_record_get_double() {
         let mut record = FluxRecord::new(0);
-        record
-            .values
-            .insert("value".to_string(), Value::Double(OrderedFloat::from(2.72)));
-        record
-            .values
-            .insert("name".to_string(), Value::String("test".to_string()));
+        record.insert("value".to_string(), Value::Double(OrderedFloat::from(2.72)));
+        record.insert("name".to_string(), Value::String("test".to_string()));
 
         assert_eq!(record.get_double("value"), Some(2.72));
         assert_eq!(record.get_double("name"), None); // Not a double
@@ -348,10 +626,8 @@ mod tests {
     #[test]
     fn test_flux_record_get_long() {
         let mut record = FluxRecord::new(0);
-        record.values.insert("count".to_string(), Value::Long(-42));
-        record
-            .values
-            .insert("name".to_string(), Value::String("test".to_string()));
+        record.insert("count".to_string(), Value::Long(-42));
+        record.insert("name".to_string(), Value::String("test".to_string()));
 
         assert_eq!(record.get_long("count"), Some(-42));
         assert_eq!(record.get_long("name"), None); // Not a long
@@ -361,10 +637,8 @@ mod tests {
     #[test]
     fn test_flux_record_get_bool() {
         let mut record = FluxRecord::new(0);
-        record.values.insert("flag".to_string(), Value::Bool(true));
-        record
-            .values
-            .insert("name".to_string(), Value::String("test".to_string()));
+        record.insert("flag".to_string(), Value::Bool(true));
+        record.insert("name".to_string(), Value::String("test".to_string()));
 
         assert_eq!(record.get_bool("flag"), Some(true));
         assert_eq!(record.get_bool("name"), None); // Not a bool
@@ -375,9 +649,7 @@ mod tests {
     fn test_flux_record_time() {
         let mut record = FluxRecord::new(0);
         let dt = DateTime::parse_from_rfc3339("2023-11-14T12:00:00Z").unwrap();
-        record
-            .values
-            .insert("_time".to_string(), Value::TimeRFC(dt));
+        record.insert("_time".to_string(), Value::TimeRFC(dt));
 
         assert!(record.time().is_some());
         assert_eq!(record.time().unwrap().year(), 2023);
@@ -392,9 +664,7 @@ mod tests {
     #[test]
     fn test_flux_record_measurement() {
         let mut record = FluxRecord::new(0);
-        record
-            .values
-            .insert("_measurement".to_string(), Value::String("cpu".to_string()));
+        record.insert("_measurement".to_string(), Value::String("cpu".to_string()));
 
         assert_eq!(record.measurement(), Some("cpu".to_string()));
     }
@@ -408,7 +678,7 @@ mod tests {
     #[test]
     fn test_flux_record_field() {
         let mut record = FluxRecord::new(0);
-        record.values.insert(
+        record.insert(
             "_field".to_string(),
             Value::String("temperature".to_string()),
         );
@@ -425,7 +695,7 @@ mod tests {
     #[test]
     fn test_flux_record_value() {
         let mut record = FluxRecord::new(0);
-        record.values.insert(
+        record.insert(
             "_value".to_string(),
             Value::Double(OrderedFloat::from(25.5)),
         );
@@ -442,4 +712,55 @@ mod tests {
         let record = FluxRecord::new(0);
         assert!(record.value().is_none());
     }
+
+    #[test]
+    fn test_flux_record_insert_keeps_column_order() {
+        let mut record = FluxRecord::new(0);
+        assert_eq!(record.insert("b", Value::Long(1)), None);
+        assert_eq!(record.insert("a", Value::Long(2)), None);
+        assert_eq!(record.insert("b", Value::Long(3)), Some(Value::Long(1)));
+
+        let entries: Vec<_> = record.iter().collect();
+        assert_eq!(
+            entries,
+            vec![("b", &Value::Long(3)), ("a", &Value::Long(2))]
+        );
+        assert_eq!(record.len(), 2);
+        assert!(record.contains("a"));
+    }
+
+    #[test]
+    fn test_flux_record_from_parts_shares_schema() {
+        let schema = Arc::new(RecordSchema::new(["_time", "_value"]));
+        let first = FluxRecord::from_parts(0, schema.clone(), vec![Value::Null, Value::Long(1)]);
+        let mut second =
+            FluxRecord::from_parts(0, schema.clone(), vec![Value::Null, Value::Long(2)]);
+
+        assert_eq!(second.get_long("_value"), Some(2));
+        *second.get_mut("_value").unwrap() = Value::Long(5);
+        assert_eq!(second.get_long("_value"), Some(5));
+        assert!(Arc::ptr_eq(first.schema(), second.schema()));
+
+        // Adding a column copies the schema for that record only.
+        second.insert("host", Value::String("a".to_string()));
+        assert_eq!(schema.len(), 2);
+        assert_eq!(second.schema().index_of("host"), Some(2));
+        assert!(!first.contains("host"));
+    }
+
+    #[test]
+    #[should_panic(expected = "record values do not match the schema")]
+    fn test_flux_record_from_parts_length_mismatch() {
+        let schema = Arc::new(RecordSchema::new(["_value"]));
+        FluxRecord::from_parts(0, schema, Vec::new());
+    }
+
+    #[test]
+    fn test_flux_record_estimated_size() {
+        let empty = FluxRecord::new(0).estimated_size();
+        let mut record = FluxRecord::new(0);
+        record.insert("host".to_string(), Value::String("x".repeat(100)));
+
+        assert!(record.estimated_size() >= empty + 104);
+    }
 }
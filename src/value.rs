@@ -1,16 +1,30 @@
 //! Value types for InfluxDB Flux query results.
 
-use chrono::{DateTime, FixedOffset};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, SecondsFormat};
 use ordered_float::OrderedFloat;
 
+use crate::types::DataType;
+
 /// Represents a value in an InfluxDB Flux query result.
 ///
 /// This enum covers all data types that can appear in InfluxDB annotated CSV responses.
-#[derive(Clone, Debug, PartialEq)]
+///
+/// `String` and `SharedString` compare equal when their text is equal.
+#[derive(Clone, Debug)]
 pub enum Value {
     /// String value.
     String(String),
 
+    /// String value sharing its allocation with other values.
+    ///
+    /// Produced by [`RecordStreamExt::intern_strings`](crate::adapters::RecordStreamExt::intern_strings);
+    /// the string accessors treat it like `String`.
+    SharedString(Arc<str>),
+
     /// 64-bit floating point value.
     Double(OrderedFloat<f64>),
 
@@ -37,20 +51,33 @@ pub enum Value {
 }
 
 impl Value {
-    /// Returns the value as a string reference if it is a `String` variant.
+    /// Returns the annotated CSV data type of the value, or `None` for `Null`.
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::String(_) | Value::SharedString(_) => Some(DataType::String),
+            Value::Double(_) => Some(DataType::Double),
+            Value::Bool(_) => Some(DataType::Bool),
+            Value::Long(_) => Some(DataType::Long),
+            Value::UnsignedLong(_) => Some(DataType::UnsignedLong),
+            Value::Duration(_) => Some(DataType::Duration),
+            Value::Base64Binary(_) => Some(DataType::Base64Binary),
+            Value::TimeRFC(_) => Some(DataType::TimeRFC),
+            Value::Null => None,
+        }
+    }
+
+    /// Returns the value as a string reference if it is a `String` or `SharedString` variant.
     pub fn as_string(&self) -> Option<&str> {
         match self {
             Value::String(s) => Some(s),
+            Value::SharedString(s) => Some(s),
             _ => None,
         }
     }
 
-    /// Returns the value as an owned string if it is a `String` variant.
+    /// Returns the value as an owned string if it is a `String` or `SharedString` variant.
     pub fn string(&self) -> Option<String> {
-        match self {
-            Value::String(s) => Some(s.clone()),
-            _ => None,
-        }
+        self.as_string().map(str::to_string)
     }
 
     /// Returns the value as a f64 if it is a `Double` variant.
@@ -85,6 +112,18 @@ impl Value {
         }
     }
 
+    /// Returns the value as a f64 if it is numeric (`Double`, `Long` or `UnsignedLong`).
+    ///
+    /// Integers beyond 2^53 lose precision in the conversion.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Double(f) => Some(f.into_inner()),
+            Value::Long(i) => Some(*i as f64),
+            Value::UnsignedLong(u) => Some(*u as f64),
+            _ => None,
+        }
+    }
+
     /// Returns the value as a chrono::Duration if it is a `Duration` variant.
     pub fn as_duration(&self) -> Option<&chrono::Duration> {
         match self {
@@ -113,12 +152,66 @@ impl Value {
     pub fn is_null(&self) -> bool {
         matches!(self, Value::Null)
     }
+
+    /// Returns the value as a [`time::OffsetDateTime`], keeping its offset,
+    /// if it is a `TimeRFC` variant.
+    #[cfg(feature = "time")]
+    pub fn to_offset_datetime(&self) -> Option<time::OffsetDateTime> {
+        let t = self.as_time()?;
+        let nanos =
+            i128::from(t.timestamp()) * 1_000_000_000 + i128::from(t.timestamp_subsec_nanos());
+        let offset = time::UtcOffset::from_whole_seconds(t.offset().local_minus_utc()).ok()?;
+        Some(
+            time::OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                .ok()?
+                .to_offset(offset),
+        )
+    }
+
+    /// Returns the value as a [`time::Duration`] if it is a `Duration` variant.
+    #[cfg(feature = "time")]
+    pub fn to_time_duration(&self) -> Option<time::Duration> {
+        let d = self.as_duration()?;
+        Some(time::Duration::new(d.num_seconds(), d.subsec_nanos()))
+    }
+
+    /// Render the value as text, the way InfluxDB writes it in annotated CSV.
+    ///
+    /// Unlike [`Display`](std::fmt::Display), which is meant for people, the
+    /// output is always a complete textual form of the value:
+    ///
+    /// | Value          | Text                                               |
+    /// |----------------|----------------------------------------------------|
+    /// | `String`       | Unchanged, without quotes or escaping              |
+    /// | `Double`       | Shortest form that parses back to the same number  |
+    /// | `Bool`         | `true` or `false`                                  |
+    /// | `Long`         | Decimal                                            |
+    /// | `UnsignedLong` | Decimal                                            |
+    /// | `Duration`     | Nanoseconds with an `ns` suffix, such as `1500ns`  |
+    /// | `Base64Binary` | Standard base64 with padding                       |
+    /// | `TimeRFC`      | RFC 3339 with as many fractional digits as needed and `Z` for UTC |
+    /// | `Null`         | Empty string                                       |
+    ///
+    /// Strings are borrowed rather than copied.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        match self {
+            Value::String(s) => Cow::Borrowed(s),
+            Value::SharedString(s) => Cow::Borrowed(s),
+            Value::Base64Binary(b) => {
+                Cow::Owned(base64::engine::general_purpose::STANDARD.encode(b))
+            }
+            Value::TimeRFC(t) => Cow::Owned(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            Value::Null => Cow::Borrowed(""),
+            other => Cow::Owned(other.to_string()),
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::String(s) => write!(f, "{}", s),
+            Value::SharedString(s) => write!(f, "{}", s),
             Value::Double(d) => write!(f, "{}", d),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Long(i) => write!(f, "{}", i),
@@ -131,6 +224,102 @@ impl std::fmt::Display for Value {
     }
 }
 
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Double(OrderedFloat(v))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Long(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::UnsignedLong(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<chrono::Duration> for Value {
+    fn from(v: chrono::Duration) -> Self {
+        Value::Duration(v)
+    }
+}
+
+impl From<DateTime<FixedOffset>> for Value {
+    fn from(v: DateTime<FixedOffset>) -> Self {
+        Value::TimeRFC(v)
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for Value {
+    type Error = crate::error::Error;
+
+    /// Convert to a `TimeRFC` value with the same offset.
+    ///
+    /// Fails for offsets of a day or more, which `time` allows but chrono
+    /// does not, and for dates outside chrono's range.
+    fn try_from(v: time::OffsetDateTime) -> Result<Self, Self::Error> {
+        let out_of_range = || crate::error::Error::Parse {
+            message: format!("time {} is out of range for a TimeRFC value", v),
+        };
+        let offset = FixedOffset::east_opt(v.offset().whole_seconds()).ok_or_else(out_of_range)?;
+        let utc = DateTime::from_timestamp(v.unix_timestamp(), v.nanosecond())
+            .ok_or_else(out_of_range)?;
+        Ok(Value::TimeRFC(utc.with_timezone(&offset)))
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::Duration> for Value {
+    fn from(v: time::Duration) -> Self {
+        Value::Duration(
+            chrono::Duration::seconds(v.whole_seconds())
+                + chrono::Duration::nanoseconds(v.subsec_nanoseconds().into()),
+        )
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Double(a), Value::Double(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Long(a), Value::Long(b)) => a == b,
+            (Value::UnsignedLong(a), Value::UnsignedLong(b)) => a == b,
+            (Value::Duration(a), Value::Duration(b)) => a == b,
+            (Value::Base64Binary(a), Value::Base64Binary(b)) => a == b,
+            (Value::TimeRFC(a), Value::TimeRFC(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            _ => match (self.as_string(), other.as_string()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,6 +338,65 @@ mod tests {
         assert_eq!(Value::Null.as_string(), None);
     }
 
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time_crate_conversions() {
+        let t = DateTime::parse_from_rfc3339("2023-11-14T12:30:45.123456789+09:00").unwrap();
+        let odt = Value::TimeRFC(t).to_offset_datetime().unwrap();
+        assert_eq!(
+            odt.unix_timestamp_nanos(),
+            i128::from(t.timestamp_nanos_opt().unwrap())
+        );
+        assert_eq!(odt.offset().whole_hours(), 9);
+        assert_eq!(Value::try_from(odt).unwrap(), Value::TimeRFC(t));
+        let offset = time::UtcOffset::from_hms(25, 0, 0).unwrap();
+        assert!(Value::try_from(odt.to_offset(offset)).is_err());
+
+        let d = chrono::Duration::nanoseconds(-1_500_000_000);
+        let td = Value::Duration(d).to_time_duration().unwrap();
+        assert_eq!(td, time::Duration::milliseconds(-1500));
+        assert_eq!(Value::from(td), Value::Duration(d));
+        assert_eq!(Value::Long(1).to_offset_datetime(), None);
+    }
+
+    #[test]
+    fn test_to_string_lossy() {
+        assert_eq!(
+            Value::Base64Binary(b"Hello".to_vec()).to_string_lossy(),
+            "SGVsbG8="
+        );
+        assert_eq!(Value::Null.to_string_lossy(), "");
+        assert_eq!(Value::Long(-4).to_string_lossy(), "-4");
+        assert_eq!(Value::Double(OrderedFloat(1.5)).to_string_lossy(), "1.5");
+        assert_eq!(
+            Value::Duration(chrono::Duration::microseconds(2)).to_string_lossy(),
+            "2000ns"
+        );
+        let t = DateTime::parse_from_rfc3339("2023-11-14T12:30:45.123456789Z").unwrap();
+        assert_eq!(
+            Value::TimeRFC(t).to_string_lossy(),
+            "2023-11-14T12:30:45.123456789Z"
+        );
+        let t = DateTime::parse_from_rfc3339("2023-11-14T12:30:45+09:00").unwrap();
+        assert_eq!(
+            Value::TimeRFC(t).to_string_lossy(),
+            "2023-11-14T12:30:45+09:00"
+        );
+
+        let s = Value::String("hello".to_string());
+        assert!(matches!(s.to_string_lossy(), Cow::Borrowed("hello")));
+    }
+
+    #[test]
+    fn test_shared_string() {
+        let shared = Value::SharedString(Arc::from("hello"));
+        assert_eq!(shared.as_string(), Some("hello"));
+        assert_eq!(shared.to_string(), "hello");
+        assert_eq!(shared, Value::String("hello".to_string()));
+        assert_ne!(shared, Value::String("world".to_string()));
+        assert_ne!(shared, Value::Null);
+    }
+
     #[test]
     fn test_string() {
         let v = Value::String("hello".to_string());
@@ -210,6 +458,18 @@ mod tests {
         assert_eq!(Value::Null.as_unsigned_long(), None);
     }
 
+    #[test]
+    fn test_as_f64() {
+        assert_eq!(Value::Double(OrderedFloat::from(1.5)).as_f64(), Some(1.5));
+        assert_eq!(Value::Long(-3).as_f64(), Some(-3.0));
+        assert_eq!(Value::UnsignedLong(7).as_f64(), Some(7.0));
+
+        // Non-numeric types return None
+        assert_eq!(Value::String("1".to_string()).as_f64(), None);
+        assert_eq!(Value::Bool(true).as_f64(), None);
+        assert_eq!(Value::Null.as_f64(), None);
+    }
+
     #[test]
     fn test_as_duration() {
         let dur = chrono::Duration::nanoseconds(1_000_000_000);
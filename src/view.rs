@@ -0,0 +1,203 @@
+//! Column access by position for hot loops.
+//!
+//! [`FluxRecord::get`] looks each name up in the record's schema. When the
+//! same few columns are read from millions of records, a [`RecordView`]
+//! resolves the names once per table and then reads values by position:
+//!
+//! ```ignore
+//! use influxdb_stream::view::RecordView;
+//!
+//! let mut view = RecordView::new();
+//! let host = view.column("host");
+//! let value = view.column("_value");
+//!
+//! let mut reader = client.query_reader(query).await?;
+//! let mut record = FluxRecord::new(0);
+//! while reader.next_into(&mut record).await? {
+//!     let row = view.bind(&record);
+//!     if row.get_str(host) == Some("server1") {
+//!         total += row.get_double(value).unwrap_or_default();
+//!     }
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::types::{FluxRecord, RecordSchema};
+use crate::value::Value;
+
+/// Handle to a column registered with [`RecordView::column`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Column(usize);
+
+/// A set of columns resolved against the schema of the current table.
+///
+/// Records of the same table share their [`RecordSchema`], so binding a
+/// record only compares a pointer; the positions are looked up again when a
+/// record of another table arrives.
+#[derive(Clone, Debug, Default)]
+pub struct RecordView {
+    names: Vec<String>,
+    positions: Vec<Option<usize>>,
+    schema: Option<Arc<RecordSchema>>,
+}
+
+impl RecordView {
+    /// Create a view without columns.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register column `name` and get its handle.
+    ///
+    /// Registering the same name twice returns the same handle.
+    pub fn column(&mut self, name: impl Into<String>) -> Column {
+        let name = name.into();
+        if let Some(i) = self.names.iter().position(|n| *n == name) {
+            return Column(i);
+        }
+        self.names.push(name);
+        // Resolve the new column on the next bind.
+        self.schema = None;
+        Column(self.names.len() - 1)
+    }
+
+    /// Get the name of a registered column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `column` was registered with another view.
+    pub fn name(&self, column: Column) -> &str {
+        &self.names[column.0]
+    }
+
+    /// Bind `record`, re-resolving the columns if its schema changed.
+    pub fn bind<'a>(&'a mut self, record: &'a FluxRecord) -> BoundRecord<'a> {
+        let current = self
+            .schema
+            .as_ref()
+            .is_some_and(|schema| Arc::ptr_eq(schema, record.schema()));
+        if !current {
+            let schema = record.schema();
+            self.positions = self.names.iter().map(|n| schema.index_of(n)).collect();
+            self.schema = Some(schema.clone());
+        }
+        BoundRecord {
+            positions: &self.positions,
+            values: record.values(),
+        }
+    }
+}
+
+/// A record bound to a [`RecordView`], read by [`Column`] handles.
+///
+/// Getters return `None` for columns the record does not have, and for
+/// handles that do not belong to the view.
+#[derive(Clone, Copy, Debug)]
+pub struct BoundRecord<'a> {
+    positions: &'a [Option<usize>],
+    values: &'a [Value],
+}
+
+impl<'a> BoundRecord<'a> {
+    /// Get the value of `column`.
+    pub fn get(&self, column: Column) -> Option<&'a Value> {
+        let position = (*self.positions.get(column.0)?)?;
+        self.values.get(position)
+    }
+
+    /// Get the value of `column` as a borrowed string.
+    pub fn get_str(&self, column: Column) -> Option<&'a str> {
+        self.get(column).and_then(|v| v.as_string())
+    }
+
+    /// Get the value of `column` as f64.
+    pub fn get_double(&self, column: Column) -> Option<f64> {
+        self.get(column).and_then(|v| v.as_double())
+    }
+
+    /// Get the value of `column` as i64.
+    pub fn get_long(&self, column: Column) -> Option<i64> {
+        self.get(column).and_then(|v| v.as_long())
+    }
+
+    /// Get the value of `column` as u64.
+    pub fn get_unsigned_long(&self, column: Column) -> Option<u64> {
+        self.get(column).and_then(|v| v.as_unsigned_long())
+    }
+
+    /// Get the value of `column` as bool.
+    pub fn get_bool(&self, column: Column) -> Option<bool> {
+        self.get(column).and_then(|v| v.as_bool())
+    }
+
+    /// Get the value of `column` as a timestamp.
+    pub fn get_time(&self, column: Column) -> Option<&'a DateTime<FixedOffset>> {
+        self.get(column).and_then(|v| v.as_time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ordered_float::OrderedFloat;
+
+    fn record(table: i32, columns: &[(&str, Value)]) -> FluxRecord {
+        let mut record = FluxRecord::new(table);
+        for (name, value) in columns {
+            record.insert(name.to_string(), value.clone());
+        }
+        record
+    }
+
+    #[test]
+    fn test_view_reads_by_position() {
+        let mut view = RecordView::new();
+        let host = view.column("host");
+        let value = view.column("_value");
+        let missing = view.column("missing");
+        assert_eq!(view.column("host"), host);
+        assert_eq!(view.name(value), "_value");
+
+        let record = record(
+            0,
+            &[
+                ("_value", Value::Double(OrderedFloat(1.5))),
+                ("host", Value::String("server1".to_string())),
+            ],
+        );
+        let row = view.bind(&record);
+        assert_eq!(row.get_str(host), Some("server1"));
+        assert_eq!(row.get_double(value), Some(1.5));
+        assert_eq!(row.get(missing), None);
+        assert_eq!(row.get_long(value), None);
+    }
+
+    #[test]
+    fn test_view_rebinds_on_schema_change() {
+        let mut view = RecordView::new();
+        let n = view.column("n");
+
+        let first = record(0, &[("n", Value::Long(1)), ("m", Value::Long(2))]);
+        let second = record(1, &[("m", Value::Long(3)), ("n", Value::Long(4))]);
+        assert_eq!(view.bind(&first).get_long(n), Some(1));
+        assert_eq!(view.bind(&second).get_long(n), Some(4));
+
+        // Records sharing a schema reuse the resolved positions.
+        let mut third = second.clone();
+        *third.values_mut().last_mut().unwrap() = Value::Long(5);
+        assert_eq!(view.bind(&third).get_long(n), Some(5));
+    }
+
+    #[test]
+    fn test_view_column_added_after_bind() {
+        let mut view = RecordView::new();
+        let record = record(0, &[("a", Value::Long(1)), ("b", Value::Long(2))]);
+        view.bind(&record);
+
+        let b = view.column("b");
+        assert_eq!(view.bind(&record).get_long(b), Some(2));
+    }
+}
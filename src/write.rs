@@ -0,0 +1,413 @@
+//! Writing points to InfluxDB.
+//!
+//! [`WriteApi`] buffers points or records as line protocol and posts them to
+//! `/api/v2/write` in batches. It implements [`futures::Sink`] for both
+//! [`Point`] and [`FluxRecord`], so write pipelines compose with the usual
+//! combinators:
+//!
+//! ```ignore
+//! use futures::{SinkExt, StreamExt};
+//!
+//! // Copy a query result into another bucket.
+//! let mut writer = client.write_api("archive");
+//! let mut records = client.query_stream(query).await?;
+//! writer.send_all(&mut records).await?;
+//! writer.close().await?;
+//! ```
+//!
+//! Only one request is in flight at a time: while it runs, new items fill the
+//! next batch, and the sink stops accepting items once that batch is full.
+//! Call [`WriteApi::close`] (or [`flush`](WriteApi::flush)) at the end, or
+//! the last partial batch is dropped with the writer.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use chrono::{DateTime, FixedOffset};
+use futures::Sink;
+use futures::future::BoxFuture;
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::sink::line_protocol::{self, LineProtocolOptions};
+use crate::types::FluxRecord;
+use crate::value::Value;
+
+pub use crate::sink::line_protocol::Precision;
+
+/// Default number of lines per write request.
+pub const DEFAULT_BATCH_SIZE: usize = 5_000;
+
+/// A single point to write: a measurement, tags, fields and a timestamp.
+///
+/// # Example
+///
+/// ```
+/// use influxdb_stream::write::{Point, Precision};
+///
+/// let point = Point::new("cpu").tag("host", "server1").field("usage", 0.5);
+/// assert_eq!(
+///     point.to_line_protocol(Precision::Nanoseconds).unwrap(),
+///     "cpu,host=server1 usage=0.5"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    measurement: String,
+    tags: BTreeMap<String, String>,
+    fields: BTreeMap<String, Value>,
+    time: Option<DateTime<FixedOffset>>,
+}
+
+impl Point {
+    /// Create a point in `measurement` with no tags, fields or timestamp.
+    pub fn new(measurement: impl Into<String>) -> Self {
+        Self {
+            measurement: measurement.into(),
+            tags: BTreeMap::new(),
+            fields: BTreeMap::new(),
+            time: None,
+        }
+    }
+
+    /// Set tag `key` to `value`.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set field `key` to `value`.
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the timestamp. Without one, the server uses its receive time.
+    pub fn timestamp(mut self, time: DateTime<FixedOffset>) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Get the measurement name.
+    pub fn measurement(&self) -> &str {
+        &self.measurement
+    }
+
+    /// Get the tags, sorted by key.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Get the fields, sorted by key.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Get the timestamp, if one is set.
+    pub fn time(&self) -> Option<&DateTime<FixedOffset>> {
+        self.time.as_ref()
+    }
+
+    /// Convert the point to one line of line protocol, without trailing newline.
+    ///
+    /// Fails with [`Error::Encode`] if the point has no field that line
+    /// protocol can express (null and non-finite values are left out).
+    pub fn to_line_protocol(&self, precision: Precision) -> Result<String> {
+        line_protocol::format_line(
+            &self.measurement,
+            self.tags(),
+            self.fields(),
+            self.time.as_ref(),
+            precision,
+        )?
+        .ok_or_else(|| {
+            Error::Encode(format!(
+                "Point in measurement '{}' has no fields",
+                self.measurement
+            ))
+        })
+    }
+}
+
+/// Settings for a [`WriteApi`].
+#[derive(Clone, Debug)]
+pub struct WriteOptions {
+    batch_size: usize,
+    line_protocol: LineProtocolOptions,
+    v1: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            line_protocol: LineProtocolOptions::default(),
+            v1: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Create options with the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of lines per request (default: [`DEFAULT_BATCH_SIZE`]).
+    pub fn batch_size(mut self, lines: usize) -> Self {
+        self.batch_size = lines.max(1);
+        self
+    }
+
+    /// Set the timestamp precision (default: nanoseconds).
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.line_protocol = self.line_protocol.precision(precision);
+        self
+    }
+
+    /// Write to the InfluxDB 1.x `/write` endpoint (default: `false`).
+    ///
+    /// The bucket passed to [`Client::write_api_opts`] is then read as
+    /// `database` or `database/retention-policy`, like the bucket names of
+    /// InfluxDB 2's 1.x compatibility API, so the same code can ingest into
+    /// 1.8 servers. With authentication enabled, create the client with
+    /// `username:password` as its token; see [`Client::write_lines_v1`].
+    pub fn v1_endpoint(mut self, v1: bool) -> Self {
+        self.v1 = v1;
+        self
+    }
+
+    /// Set how records written through `Sink<FluxRecord>` are converted.
+    ///
+    /// The precision of `options` replaces the one set with
+    /// [`precision`](Self::precision).
+    pub fn line_protocol(mut self, options: LineProtocolOptions) -> Self {
+        self.line_protocol = options;
+        self
+    }
+}
+
+/// Batched writer for one bucket, created by [`Client::write_api`].
+///
+/// Records without a field value are skipped, like in
+/// [`write_line_protocol`](crate::sink::write_line_protocol); points without
+/// fields fail with [`Error::Encode`].
+pub struct WriteApi {
+    client: Client,
+    bucket: String,
+    options: WriteOptions,
+    buffer: String,
+    lines: usize,
+    in_flight: Option<BoxFuture<'static, Result<()>>>,
+}
+
+impl WriteApi {
+    pub(crate) fn new(client: Client, bucket: String, options: WriteOptions) -> Self {
+        Self {
+            client,
+            bucket,
+            options,
+            buffer: String::new(),
+            lines: 0,
+            in_flight: None,
+        }
+    }
+
+    /// Get the bucket written to.
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Get the number of lines waiting for the next request.
+    pub fn buffered(&self) -> usize {
+        self.lines
+    }
+
+    /// Send the buffered lines and wait for every request to finish.
+    pub async fn flush(&mut self) -> Result<()> {
+        std::future::poll_fn(|cx| self.poll_flush_inner(cx)).await
+    }
+
+    /// Flush the writer before dropping it.
+    ///
+    /// Provided on the type because [`SinkExt::close`](futures::SinkExt::close)
+    /// needs the item type spelled out, as `WriteApi` is a sink of both
+    /// points and records.
+    pub async fn close(mut self) -> Result<()> {
+        self.flush().await
+    }
+
+    fn push_line(&mut self, line: Option<String>) {
+        if let Some(line) = line {
+            self.buffer.push_str(&line);
+            self.buffer.push('\n');
+            self.lines += 1;
+        }
+    }
+
+    /// Wait for the running request, if any.
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(request) = &mut self.in_flight {
+            let result = ready!(request.as_mut().poll(cx));
+            self.in_flight = None;
+            result?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Send the buffered lines; the caller must have waited for the previous request.
+    fn start_request(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let body = std::mem::take(&mut self.buffer);
+        self.lines = 0;
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let precision = self.options.line_protocol.timestamp_precision();
+        let v1 = self.options.v1;
+        self.in_flight = Some(Box::pin(async move {
+            if v1 {
+                let (database, retention_policy) = match bucket.split_once('/') {
+                    Some((database, rp)) => (database, Some(rp)),
+                    None => (bucket.as_str(), None),
+                };
+                client
+                    .write_lines_v1(database, retention_policy, precision, body)
+                    .await
+            } else {
+                client.write_lines(&bucket, precision, body).await
+            }
+        }));
+    }
+
+    fn poll_ready_inner(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.lines >= self.options.batch_size {
+            ready!(self.poll_in_flight(cx))?;
+            self.start_request();
+        }
+        // Drive the running request while the next batch fills, instead of
+        // only once that batch is full.
+        match self.poll_in_flight(cx) {
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_flush_inner(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_in_flight(cx))?;
+        self.start_request();
+        self.poll_in_flight(cx)
+    }
+}
+
+impl Sink<Point> for WriteApi {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_ready_inner(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, point: Point) -> Result<()> {
+        let this = self.get_mut();
+        let line = point.to_line_protocol(this.options.line_protocol.timestamp_precision())?;
+        this.push_line(Some(line));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+}
+
+impl Sink<FluxRecord> for WriteApi {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_ready_inner(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, record: FluxRecord) -> Result<()> {
+        let this = self.get_mut();
+        let line = line_protocol::to_line_protocol(&record, &this.options.line_protocol)?;
+        this.push_line(line);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+}
+
+impl std::fmt::Debug for WriteApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteApi")
+            .field("bucket", &self.bucket)
+            .field("options", &self.options)
+            .field("buffered", &self.lines)
+            .field("in_flight", &self.in_flight.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::StaticTransport;
+    use futures::SinkExt;
+    use http::StatusCode;
+
+    #[test]
+    fn test_point_to_line_protocol() {
+        let time = DateTime::parse_from_rfc3339("2023-11-14T12:00:00Z").unwrap();
+        let point = Point::new("cpu load")
+            .tag("region", "eu")
+            .tag("host", "a b")
+            .field("usage", 0.5)
+            .field("count", 3i64)
+            .field("ok", true)
+            .timestamp(time);
+
+        assert_eq!(
+            point.to_line_protocol(Precision::Seconds).unwrap(),
+            "cpu\\ load,host=a\\ b,region=eu count=3i,ok=true,usage=0.5 1699963200"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_sent_while_next_fills() {
+        let (transport, requests) = StaticTransport::new(StatusCode::NO_CONTENT, "");
+        let client =
+            Client::with_transport(transport, "http://influx.invalid:8086", "org", "token");
+        let mut api = client.write_api_opts("bucket", WriteOptions::new().batch_size(2));
+
+        for i in 0..3i64 {
+            api.feed(Point::new("cpu").field("n", i)).await.unwrap();
+        }
+        // Feeding the third point sent the first batch and saw it complete.
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert!(api.in_flight.is_none());
+        assert_eq!(api.buffered(), 1);
+
+        api.close().await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_point_without_fields() {
+        let point = Point::new("cpu").field("usage", Value::Null);
+        assert!(matches!(
+            point.to_line_protocol(Precision::Nanoseconds),
+            Err(Error::Encode(_))
+        ));
+    }
+}